@@ -26,6 +26,7 @@ mod builtin {
     pub(crate) const PRINT: FuncDesc = FuncDesc {
         code: FuncCode::Native(NativeFuncPtrEx(print)),
         constants: vec![],
+        tuple_shapes: vec![],
         num_params: 1,
         stack_size: 1,
     };
@@ -38,6 +39,7 @@ mod builtin {
     pub(crate) const PRINTLN: FuncDesc = FuncDesc {
         code: FuncCode::Native(NativeFuncPtrEx(println)),
         constants: vec![],
+        tuple_shapes: vec![],
         num_params: 1,
         stack_size: 1,
     };
@@ -125,6 +127,12 @@ impl<'a> AstInfo<'a> {
                     ItemData::Func(func) => {
                         self.collect_block(&func.body, stmt_id);
                     }
+
+                    ItemData::Trait(_) => {}
+
+                    ItemData::Impl(imp) => {
+                        self.collect_block(&imp.methods, stmt_id);
+                    }
                 }
             }
 
@@ -134,6 +142,8 @@ impl<'a> AstInfo<'a> {
                 }
             }
 
+            StmtData::Delete (delete) => { self.collect_expr(&delete.target, stmt_id); }
+
             StmtData::Expr (expr) => { self.collect_expr(expr, stmt_id); }
 
             StmtData::Empty => (),
@@ -230,6 +240,11 @@ impl<'a> AstInfo<'a> {
             }
 
             ExprData::Env => {}
+
+            ExprData::Implements (implements) => {
+                self.collect_expr(&implements.value, expr_id);
+                self.collect_expr(&implements.traitt, expr_id);
+            }
         }
     }
 
@@ -261,7 +276,9 @@ impl<'a> CodeInfo<'a> {
         let mut p = kibi::Parser::new(&tokens);
         let mut ast = Box::new(p.parse_module(kibi::SourcePos { line: 1, column: 1 }).unwrap());
 
-        let mut i = kibi::infer::Infer::new();
+        let mut diags = kibi::Diagnostics::new();
+
+        let mut i = kibi::infer::Infer::new(&mut diags);
         i.assign_ids(&mut ast);
         i.infer(&mut ast);
 
@@ -270,10 +287,12 @@ impl<'a> CodeInfo<'a> {
 
         let ast_info = unsafe { core::mem::transmute(ast_info) };
 
-        let mut builder = kibi::bbir_builder::Builder::new();
+        let mut builder = kibi::bbir_builder::Builder::new(&mut diags);
         builder.build(&ast);
         let (funcs, items, debug_info) = builder.krate.build();
 
+        print!("{}", diags.render("<explorer>", source));
+
         return CodeInfo {
             tokens,
             ast,
@@ -337,12 +356,12 @@ impl TokenClass {
             FatArrow |
             ColonEq => TokenClass::Default,
 
-            KwLet | KwVar |
+            KwLet | KwVar | KwDel |
             KwDo | KwIf | KwElif | KwElse | KwWhile |
             KwFor | KwIn |
             KwBreak | KwContinue | KwReturn |
             KwEnd |
-            KwFn |
+            KwFn | KwTrait | KwImpl | KwImplements |
             KwAnd | KwOr | KwNot |
             KwEnv => TokenClass::Keyword,
 
@@ -489,11 +508,15 @@ impl CodeView {
         self.info = unsafe { core::mem::transmute(info) };
 
         self.vm = kibi::Vm::new();
-        self.vm.add_func("print", builtin::PRINT);
-        self.vm.add_func("println", builtin::PRINTLN);
-        self.vm.load_crate(0, &self.info.funcs.inner(), &self.info.items.inner());
-        self.vm.set_instr_limit(0);
-        self.vm.call(0, 0, &[]).unwrap();
+        self.vm.add_func("print", builtin::PRINT).unwrap();
+        self.vm.add_func("println", builtin::PRINTLN).unwrap();
+        match self.vm.load_crate(0, &self.info.funcs.inner(), &self.info.items.inner()) {
+            Ok(()) => {
+                self.vm.set_instr_limit(0);
+                self.vm.call(0, 0, &[]).unwrap();
+            }
+            Err(e) => println!("link error: {e}"),
+        }
 
         self.update_instrs();
 
@@ -982,7 +1005,7 @@ impl Slider {
 
 
 impl CodeView {
-    fn render_reg(&self, func: kibi::FunctionId, pc: u16, reg: u8, gui: &mut Gui) {
+    fn render_reg(&self, func: kibi::FunctionId, pc: u32, reg: u32, gui: &mut Gui) {
         let _ = (func, pc);
 
         gui.widget_text(Key::Counter,
@@ -1089,8 +1112,15 @@ impl CodeView {
                     text(format!("c{index}"), TokenClass::Default.color(), self, gui);
                 }
 
+                LoadGlobal { dst, slot } => {
+                    self.render_reg(func_id, pc + 1, *dst, gui);
+                    text(format!(", "), TokenClass::Default.color(), self, gui);
+                    text(format!("g{slot}"), TokenClass::Default.color(), self, gui);
+                }
+
                 ListNew  { dst, values } |
-                TupleNew { dst, values } => {
+                TupleNew { dst, values } |
+                TupleNewNamed { dst, shape: _, values } => {
                     let _ = (dst, values);
                     text(format!("..."), TokenClass::Comment.color(), self, gui);
                 }
@@ -1106,6 +1136,11 @@ impl CodeView {
                     text(format!("..."), TokenClass::Comment.color(), self, gui);
                 }
 
+                DeletePath { base, keys } => {
+                    let _ = (base, keys);
+                    text(format!("..."), TokenClass::Comment.color(), self, gui);
+                }
+
 
                 Jump { target } => {
                     text(format!("{target}"), TokenClass::Default.color(), self, gui);
@@ -1116,14 +1151,43 @@ impl CodeView {
                     text(format!(", {target}"), TokenClass::Default.color(), self, gui);
                 }
 
+                JumpC2 { target, src1, src2, value } => {
+                    self.render_reg(func_id, pc, *src1, gui);
+                    text(format!(", "), TokenClass::Default.color(), self, gui);
+                    self.render_reg(func_id, pc, *src2, gui);
+                    text(format!(", {value}, {target}"), TokenClass::Default.color(), self, gui);
+                }
+
                 Call { dst, func, args } => {
                     let _ = (dst, func, args);
                     text(format!("..."), TokenClass::Comment.color(), self, gui);
                 }
 
+                TailCall { func, args } => {
+                    let _ = (func, args);
+                    text(format!("..."), TokenClass::Comment.color(), self, gui);
+                }
+
                 Ret { src } => {
                     self.render_reg(func_id, pc, *src, gui);
                 }
+
+                LoadMethod { dst, traitt, recv, method } => {
+                    self.render_reg(func_id, pc + 1, *dst, gui);
+                    text(format!(", "), TokenClass::Default.color(), self, gui);
+                    self.render_reg(func_id, pc, *traitt, gui);
+                    text(format!(", "), TokenClass::Default.color(), self, gui);
+                    self.render_reg(func_id, pc, *recv, gui);
+                    text(format!(", m{method}"), TokenClass::Default.color(), self, gui);
+                }
+
+                Implements { dst, src, traitt } => {
+                    self.render_reg(func_id, pc + 1, *dst, gui);
+                    text(format!(", "), TokenClass::Default.color(), self, gui);
+                    self.render_reg(func_id, pc, *src, gui);
+                    text(format!(", "), TokenClass::Default.color(), self, gui);
+                    self.render_reg(func_id, pc, *traitt, gui);
+                }
             }
             text(format!("\n"), TokenClass::Default.color(), self, gui);
         });
@@ -13,6 +13,7 @@ mod builtin {
     pub(crate) const PRINT: FuncDesc = FuncDesc {
         code: FuncCode::Native(NativeFuncPtrEx(print)),
         constants: vec![],
+        tuple_shapes: vec![],
         num_params: 1,
         stack_size: 1,
     };
@@ -25,6 +26,7 @@ mod builtin {
     pub(crate) const PRINTLN: FuncDesc = FuncDesc {
         code: FuncCode::Native(NativeFuncPtrEx(println)),
         constants: vec![],
+        tuple_shapes: vec![],
         num_params: 1,
         stack_size: 1,
     };
@@ -35,40 +37,186 @@ mod builtin {
 fn main() {
     let mut vm = Vm::new();
 
-    vm.add_func("print", builtin::PRINT);
-    vm.add_func("println", builtin::PRINTLN);
+    vm.add_func("print", builtin::PRINT).unwrap();
+    vm.add_func("println", builtin::PRINTLN).unwrap();
     vm.add_func("quit", FuncDesc {
         code: FuncCode::Native(NativeFuncPtrEx(|_| std::process::exit(0))),
         constants: vec![],
+        tuple_shapes: vec![],
         num_params: 0,
         stack_size: 0,
-    });
+    }).unwrap();
+
+    // usage: repl [-A|-W|-D lint]... [-R linear|graph] [-O0|-O1]
+    //  [--dump-after=pass]... [--time-passes] [--dot=dir] [--emit=file.kbtf] [path]
+    // `path` can also be a `.kbtf` file, which is run without compiling.
+    let mut lints = lint::LintConfig::new();
+    let mut reg_alloc = None;
+    let mut opt_level = OptLevel::default();
+    let mut dump_after = vec![];
+    let mut time_passes = false;
+    let mut dot_dir = None;
+    let mut emit_path = None;
+    let mut path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let level = match arg.as_str() {
+            "-R" => {
+                reg_alloc = match args.next().as_deref() {
+                    Some("linear") => Some(codegen::RegAllocKind::LinearScan),
+                    Some("graph")  => Some(codegen::RegAllocKind::GraphColoring),
+                    name => {
+                        println!("unknown register allocator {name:?}");
+                        return;
+                    }
+                };
+                continue;
+            }
+            "-O0" => { opt_level = OptLevel::O0; continue }
+            "-O1" => { opt_level = OptLevel::O1; continue }
+            "--time-passes" => { time_passes = true; continue }
+            _ if arg.starts_with("--dot=") => {
+                dot_dir = Some(arg["--dot=".len()..].to_string());
+                continue;
+            }
+            _ if arg.starts_with("--emit=") => {
+                emit_path = Some(arg["--emit=".len()..].to_string());
+                continue;
+            }
+            _ if arg.starts_with("--dump-after=") => {
+                dump_after.push(arg["--dump-after=".len()..].to_string());
+                continue;
+            }
+            "-A" => lint::LintLevel::Allow,
+            "-W" => lint::LintLevel::Warn,
+            "-D" => lint::LintLevel::Deny,
+            _ => {
+                assert!(path.is_none());
+                path = Some(arg);
+                continue;
+            }
+        };
+        let name = args.next().unwrap();
+        if !lints.set_by_name(&name, level) {
+            println!("unknown lint {name:?}");
+            return;
+        }
+    }
+
+    let new_pass_manager = || {
+        let mut passes = PassManager::new(opt_level);
+        for name in &dump_after {
+            if !passes.dump_after(name) {
+                println!("unknown pass {name:?}");
+                std::process::exit(1);
+            }
+        }
+        if time_passes {
+            passes.enable_timing();
+        }
+        passes
+    };
+
+    if let Some(path) = path.as_ref().filter(|path| path.ends_with(".kbtf")) {
+        let bytes = std::fs::read(path).unwrap();
+
+        let t0 = std::time::Instant::now();
+        let krate = match read_kbtf(&bytes) {
+            Ok(krate) => krate,
+            Err(e) => {
+                println!("{path}: {e}");
+                return;
+            }
+        };
+        if let Err(e) = vm.load_crate(0, krate.funcs.inner(), krate.items.inner()) {
+            println!("link error: {e}");
+            return;
+        }
+        let dt_load = t0.elapsed();
+
+        let t0 = std::time::Instant::now();
+        vm.call(0,  0, &[]).unwrap();
+        let dt_run = t0.elapsed();
+
+        println!("load:    {:?}", dt_load);
+        println!("run:     {:?}", dt_run);
 
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() > 1 {
-        assert_eq!(args.len(), 2);
+        return;
+    }
 
-        let path = &args[1];
+    if let Some(path) = &path {
         let source = std::fs::read_to_string(path).unwrap();
 
         let t0 = std::time::Instant::now();
-        let mut module = parser::parse_module(source.as_bytes()).unwrap();
+        let mut module = match parser::parse_module(source.as_bytes()) {
+            Ok(module) => module,
+            Err(e) => {
+                let mut out = String::new();
+                Diagnostic::from(&e).render(path, &source, &mut out);
+                print!("{out}");
+                return;
+            }
+        };
         let dt_parse = t0.elapsed();
 
+        let mut diags = Diagnostics::new();
+
         let t0 = std::time::Instant::now();
-        let mut infer = infer::Infer::new();
+        let mut infer = infer::Infer::new(&mut diags);
         infer.assign_ids(&mut module);
         infer.infer(&mut module);
         let dt_infer = t0.elapsed();
 
         let t0 = std::time::Instant::now();
-        let mut builder = bbir_builder::Builder::new();
+        let mut builder = bbir_builder::Builder::new(&mut diags);
         builder.build(&module);
-        let (funcs, items, _) = builder.krate.build();
+        let mut krate = builder.krate;
+        krate.set_reg_alloc(reg_alloc.unwrap_or_default());
+        lint::lint(&lints, &vm.global_names(), &module, &krate, &mut diags);
+        if let Some(dir) = &dot_dir {
+            if let Err(e) = krate.write_dot_files(std::path::Path::new(dir)) {
+                println!("failed to write dot files: {e}");
+            }
+        }
+        let mut passes = new_pass_manager();
+        let (funcs, items, debug_info) = krate.build_ex(&mut passes);
         let dt_compile = t0.elapsed();
 
+        print!("{}", passes.take_dumps());
+        if let Some(report) = passes.timing_report() {
+            print!("{report}");
+        }
+
+        if reg_alloc.is_some() {
+            let mut num_regs  = 0;
+            let mut num_words = 0;
+            for func in funcs.iter() {
+                num_regs += func.stack_size;
+                if let FuncCode::ByteCode(code) = &func.code {
+                    num_words += code.len();
+                }
+            }
+            println!("registers: {num_regs}, code: {num_words} words");
+        }
+
+        print!("{}", diags.render(path, &source));
+        if diags.has_errors() {
+            return;
+        }
+
+        if let Some(emit_path) = &emit_path {
+            let bytes = write_kbtf(funcs.inner(), items.inner(), Some(debug_info.inner())).unwrap();
+            if let Err(e) = std::fs::write(emit_path, bytes) {
+                println!("failed to write {emit_path}: {e}");
+            }
+        }
+
         let t0 = std::time::Instant::now();
-        vm.load_crate(0, funcs.inner(), items.inner());
+        if let Err(e) = vm.load_crate(0, funcs.inner(), items.inner()) {
+            println!("link error: {e}");
+            return;
+        }
         vm.call(0,  0, &[]).unwrap();
         let dt_run = t0.elapsed();
 
@@ -139,18 +287,34 @@ fn main() {
 
         let mut module = item::Module { source: ast.source, block: expr::Block { stmts: vec![ast.to_stmt()] } };
 
-        let mut infer = infer::Infer::new();
+        let mut diags = Diagnostics::new();
+
+        let mut infer = infer::Infer::new(&mut diags);
         infer.assign_ids(&mut module);
         infer.infer(&mut module);
 
-        let mut builder = bbir_builder::Builder::new();
+        let mut builder = bbir_builder::Builder::new(&mut diags);
         builder.build(&module);
-        let (funcs, items, _) = builder.krate.build();
+        let mut krate = builder.krate;
+        krate.set_reg_alloc(reg_alloc.unwrap_or_default());
+        lint::lint(&lints, &vm.global_names(), &module, &krate, &mut diags);
+        let mut passes = new_pass_manager();
+        let (funcs, items, _) = krate.build_ex(&mut passes);
+        print!("{}", passes.take_dumps());
+
+        print!("{}", diags.render("<repl>", buffer.trim()));
         buffer.clear();
+        if diags.has_errors() {
+            continue;
+        }
 
 
+        if let Err(e) = vm.load_crate(0, funcs.inner(), items.inner()) {
+            println!("link error: {e}");
+            continue;
+        }
+
         running.store(true, core::sync::atomic::Ordering::SeqCst);
-        vm.load_crate(0, funcs.inner(), items.inner());
         let result = vm.call(0,  0, &[]);
         running.store(false, core::sync::atomic::Ordering::SeqCst);
 
@@ -1,112 +1,342 @@
-// @todo-robust: this file should be generated from a table.
+use crate::kbtf::Constant;
+
+
+// the opcode table.
+// each opcode has the name of its builder method, its operands & the
+// `InstrData` it decodes to. operands are `name: slot kind`, in encoding order:
+//  `C1`, `C2`, `C3` & `U16` are slots of the instruction word and come first.
+//  `Extra` is one extra word. `List` is one extra word per item, the number
+//  of items is the `Count` operand. it must be last.
+// the operand names are the builder's parameters & the `InstrData` fields.
+// `args: [a, b]` collects single operands into a list field.
+// the builder methods, `InstrData::decode` & `Instr::encode` are generated.
+// `emit`, the raw decoder & dump are driven by the operand lists.
+// duplicate opcode numbers show up as unreachable patterns in `name`,
+// and the vm's `run` match is exhaustive over the numbers.
+macro_rules! define_opcodes {
+    ($($name:ident = $value:literal, $method:ident,
+        [$($op:ident: $slot:ident $kind:ident),*]
+        => $variant:ident { $($field:ident $(: [$($elem:ident),*])?),* };)*) => {
+        $(pub const $name: u8 = $value;)*
+
+        pub const fn name(opcode: u8) -> &'static str {
+            match opcode {
+                $($name => stringify!($method),)*
+                _ => unreachable!()
+            }
+        }
 
-pub mod opcode {
-    pub const NOP:              u8 = 1;
-    pub const UNREACHABLE:      u8 = 2;
+        pub const fn operands(opcode: u8) -> &'static [Operand] {
+            match opcode {
+                $($name => &[$(Operand { slot: Slot::$slot, kind: OperandKind::$kind }),*],)*
+                _ => unreachable!()
+            }
+        }
+
+        pub const fn is_valid(opcode: u8) -> bool {
+            matches!(opcode, $($name)|*)
+        }
+
+        impl ByteCodeBuilder {
+            $(builder_fn!($name $method [] [] [] $($op: $slot $kind),*);)*
+        }
+
+        impl InstrData {
+            // `raw` must be valid, see `ByteCodeDecoder::next_raw`.
+            // returns `None` for invalid path keys.
+            pub fn decode(raw: &RawInstr) -> Option<InstrData> {
+                let operands = &mut raw.operands.iter();
+                match raw.opcode {
+                    $($name => {
+                        $(decode_operand!(operands $op: $slot $kind);)*
+                        Some(InstrData::$variant { $($field $(: vec![$($elem),*])?),* })
+                    })*
+                    _ => unreachable!()
+                }
+            }
+        }
+
+        impl Instr {
+            // jump targets are encoded as is.
+            pub fn encode(&self, b: &mut ByteCodeBuilder) {
+                match self.opcode {
+                    $($name => {
+                        let InstrData::$variant { $($field),* } = &self.data else { unreachable!() };
+                        $($(let [$($elem),*] = &$field[..] else { unreachable!() };)?)*
+                        encode_operands!(b $method [] $($op: $slot $kind),*);
+                    })*
+                    _ => unreachable!()
+                }
+            }
+        }
+    };
+}
 
-    pub const COPY:             u8 = 3;
-    pub const SWAP:             u8 = 4;
+// the rust type of an operand.
+macro_rules! operand_type {
+    (Reg)    => { u32 };
+    (Bool)   => { bool };
+    (Int)    => { i16 };
+    (Const)  => { u32 };
+    (Global) => { u32 };
+    (Shape)  => { u32 };
+    (Method) => { u32 };
+    (Target) => { u32 };
+    (Base)   => { PathBase };
+    (Key)    => { PathKey };
+}
+
+// a builder method. counts are the length of the list.
+macro_rules! builder_fn {
+    ($opcode:ident $method:ident [$($param:tt)*] [$($operand:tt)*] []) => {
+        pub fn $method(&mut self, $($param)*) {
+            self.emit($opcode, &[$($operand)*], core::iter::empty());
+        }
+    };
+    ($opcode:ident $method:ident [$($param:tt)*] [$($operand:tt)*] [$list:ident]) => {
+        pub fn $method(&mut self, $($param)*) {
+            self.emit($opcode, &[$($operand)*], $list.iter().map(|v| v.to_operand()));
+        }
+    };
+
+    ($opcode:ident $method:ident [$($param:tt)*] [$($operand:tt)*] [$($list:tt)*]
+        $op:ident: $slot:ident Count $(, $($rest:tt)*)?) => {
+        builder_fn!($opcode $method [$($param)*] [$($operand)*] [$($list)*] $($($rest)*)?);
+    };
+    ($opcode:ident $method:ident [$($param:tt)*] [$($operand:tt)*] [$($list:tt)*]
+        $op:ident: List $kind:ident $(, $($rest:tt)*)?) => {
+        builder_fn!($opcode $method [$($param)* $op: &[operand_type!($kind)],] [$($operand)*] [$op] $($($rest)*)?);
+    };
+    ($opcode:ident $method:ident [$($param:tt)*] [$($operand:tt)*] [$($list:tt)*]
+        $op:ident: $slot:ident $kind:ident $(, $($rest:tt)*)?) => {
+        builder_fn!($opcode $method [$($param)* $op: operand_type!($kind),] [$($operand)* $op.to_operand(),] [$($list)*] $($($rest)*)?);
+    };
+}
 
-    pub const LOAD_NIL:         u8 = 5;
-    pub const LOAD_BOOL:        u8 = 6;
-    pub const LOAD_INT:         u8 = 7;
-    pub const LOAD_CONST:       u8 = 8;
-    pub const LOAD_ENV:         u8 = 9;
+// binds the next operand of a `RawInstr` to its name.
+macro_rules! decode_operand {
+    ($operands:ident $op:ident: $slot:ident Count) => {
+        $operands.next()?;
+    };
+    ($operands:ident $op:ident: List $kind:ident) => {
+        let $op = $operands.by_ref()
+            .map(|o| <operand_type!($kind)>::from_operand(o.value))
+            .collect::<Option<Vec<_>>>()?;
+    };
+    ($operands:ident $op:ident: $slot:ident $kind:ident) => {
+        let $op = <operand_type!($kind)>::from_operand($operands.next()?.value)?;
+    };
+}
 
-    pub const LIST_NEW:         u8 = 10;
+// calls the builder method with the bound operands.
+macro_rules! encode_operands {
+    ($b:ident $method:ident [$($arg:tt)*]) => {
+        $b.$method($($arg)*)
+    };
+    ($b:ident $method:ident [$($arg:tt)*] $op:ident: $slot:ident Count $(, $($rest:tt)*)?) => {
+        encode_operands!($b $method [$($arg)*] $($($rest)*)?)
+    };
+    ($b:ident $method:ident [$($arg:tt)*] $op:ident: List $kind:ident $(, $($rest:tt)*)?) => {
+        encode_operands!($b $method [$($arg)* $op,] $($($rest)*)?)
+    };
+    ($b:ident $method:ident [$($arg:tt)*] $op:ident: $slot:ident $kind:ident $(, $($rest:tt)*)?) => {
+        encode_operands!($b $method [$($arg)* *$op,] $($($rest)*)?)
+    };
+}
 
-    pub const TUPLE_NEW:        u8 = 11;
-    pub const LOAD_UNIT:        u8 = 12;
+pub mod opcode {
+    use super::{Operand, Slot, OperandKind, OperandValue, ByteCodeBuilder, Instr, InstrData, RawInstr, PathBase, PathKey};
+
+    define_opcodes! {
+        NOP             = 1,  nop,             [] => Nop {};
+        UNREACHABLE     = 2,  unreachable,     [] => Unreachable {};
+
+        COPY            = 3,  copy,            [dst: C1 Reg, src: C2 Reg] => Copy { dst, src };
+        SWAP            = 4,  swap,            [dst: C1 Reg, src: C2 Reg] => Swap { dst, src };
+
+        LOAD_NIL        = 5,  load_nil,        [dst: C1 Reg] => LoadNil { dst };
+        LOAD_BOOL       = 6,  load_bool,       [dst: C1 Reg, value: C2 Bool] => LoadBool { dst, value };
+        LOAD_INT        = 7,  load_int,        [dst: C1 Reg, value: U16 Int] => LoadInt { dst, value };
+        LOAD_CONST      = 8,  load_const,      [dst: C1 Reg, index: U16 Const] => LoadConst { dst, index };
+        LOAD_ENV        = 9,  load_env,        [dst: C1 Reg] => LoadEnv { dst };
+        // `slot` is an index into the env. only emitted by the linker.
+        LOAD_GLOBAL     = 43, load_global,     [dst: C1 Reg, slot: U16 Global] => LoadGlobal { dst, slot };
+
+        LIST_NEW        = 10, list_new,        [dst: C1 Reg, len: U16 Count, values: List Reg] => ListNew { dst, values };
+
+        TUPLE_NEW       = 11, tuple_new,       [dst: C1 Reg, len: U16 Count, values: List Reg] => TupleNew { dst, values };
+        LOAD_UNIT       = 12, load_unit,       [dst: C1 Reg] => LoadUnit { dst };
+
+        MAP_NEW         = 13, map_new,         [dst: C1 Reg] => MapNew { dst };
+
+        READ_PATH       = 14, read_path,       [dst: C1 Reg, base: C2 Base, len: C3 Count, keys: List Key] => ReadPath { dst, base, keys };
+        WRITE_PATH      = 15, write_path,      [base: C1 Base, len: C2 Count, value: C3 Reg, keys: List Key] => WritePath { base, keys, value };
+        WRITE_PATH_DEF  = 16, write_path_def,  [base: C1 Base, len: C2 Count, value: C3 Reg, keys: List Key] => WritePath { base, keys, value };
+
+        ADD             = 17, add,             [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        SUB             = 18, sub,             [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        MUL             = 19, mul,             [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        DIV             = 20, div,             [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        FLOOR_DIV       = 21, floor_div,       [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        REM             = 22, rem,             [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        ADD_INT         = 23, add_int,         [dst: C1 Reg, value: U16 Int] => AddInt { dst, value };
+        NEGATE          = 24, negate,          [dst: C1 Reg, src: C2 Reg] => Op1 { dst, src };
+
+        NOT             = 25, not,             [dst: C1 Reg, src: C2 Reg] => Op1 { dst, src };
+
+        CMP_EQ          = 26, cmp_eq,          [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        CMP_NE          = 27, cmp_ne,          [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        CMP_LE          = 28, cmp_le,          [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        CMP_LT          = 29, cmp_lt,          [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        CMP_GE          = 30, cmp_ge,          [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        CMP_GT          = 31, cmp_gt,          [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+
+        JUMP            = 32, jump,            [target: U16 Target] => Jump { target };
+        JUMP_TRUE       = 33, jump_true,       [src: C1 Reg, target: U16 Target] => JumpC1 { target, src };
+        JUMP_FALSE      = 34, jump_false,      [src: C1 Reg, target: U16 Target] => JumpC1 { target, src };
+        JUMP_NIL        = 35, jump_nil,        [src: C1 Reg, target: U16 Target] => JumpC1 { target, src };
+        JUMP_NOT_NIL    = 36, jump_not_nil,    [src: C1 Reg, target: U16 Target] => JumpC1 { target, src };
+
+        CALL            = 37, call,            [dst: C1 Reg, func: C2 Reg, len: Extra Count, args: List Reg] => Call { dst, func, args };
+        RET             = 38, ret,             [src: C1 Reg] => Ret { src };
+
+        LOAD_METHOD     = 39, load_method,     [dst: C1 Reg, traitt: C2 Reg, recv: C3 Reg, method: Extra Method] => LoadMethod { dst, traitt, recv, method };
+        IMPLEMENTS      = 40, implements,      [dst: C1 Reg, src: C2 Reg, traitt: C3 Reg] => Implements { dst, src, traitt };
+
+        TUPLE_NEW_NAMED = 41, tuple_new_named, [dst: C1 Reg, len: U16 Count, shape: Extra Shape, values: List Reg] => TupleNewNamed { dst, shape, values };
+
+        DELETE_PATH     = 42, delete_path,     [base: C1 Base, len: C2 Count, keys: List Key] => DeletePath { base, keys };
+
+        // specialized for numbers.
+        // if an operand isn't a number, the instruction behaves like
+        // its generic version. arithmetic & comparisons are then
+        // patched to the generic version (deoptimized).
+        ADD_NUM         = 44, add_num,         [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        SUB_NUM         = 45, sub_num,         [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        MUL_NUM         = 46, mul_num,         [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+
+        CMP_EQ_NUM      = 47, cmp_eq_num,      [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        CMP_NE_NUM      = 48, cmp_ne_num,      [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        CMP_LE_NUM      = 49, cmp_le_num,      [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        CMP_LT_NUM      = 50, cmp_lt_num,      [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        CMP_GE_NUM      = 51, cmp_ge_num,      [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+        CMP_GT_NUM      = 52, cmp_gt_num,      [dst: C1 Reg, src1: C2 Reg, src2: C3 Reg] => Op2 { dst, src1, src2 };
+
+        // jump if the comparison result is equal to the bool.
+        JUMP_EQ_NUM     = 53, jump_eq_num,     [src1: C1 Reg, src2: C2 Reg, value: C3 Bool, target: Extra Target] => JumpC2 { target, src1, src2, value };
+        JUMP_NE_NUM     = 54, jump_ne_num,     [src1: C1 Reg, src2: C2 Reg, value: C3 Bool, target: Extra Target] => JumpC2 { target, src1, src2, value };
+        JUMP_LE_NUM     = 55, jump_le_num,     [src1: C1 Reg, src2: C2 Reg, value: C3 Bool, target: Extra Target] => JumpC2 { target, src1, src2, value };
+        JUMP_LT_NUM     = 56, jump_lt_num,     [src1: C1 Reg, src2: C2 Reg, value: C3 Bool, target: Extra Target] => JumpC2 { target, src1, src2, value };
+        JUMP_GE_NUM     = 57, jump_ge_num,     [src1: C1 Reg, src2: C2 Reg, value: C3 Bool, target: Extra Target] => JumpC2 { target, src1, src2, value };
+        JUMP_GT_NUM     = 58, jump_gt_num,     [src1: C1 Reg, src2: C2 Reg, value: C3 Bool, target: Extra Target] => JumpC2 { target, src1, src2, value };
+
+        // reuses the current frame, instead of `call` & `ret`.
+        TAIL_CALL       = 59, tail_call,       [func: C1 Reg, len: Extra Count, args: List Reg] => TailCall { func, args };
+
+        // calls with a fixed number of args, emitted by the peephole pass.
+        // the first arg is the third operand, the rest are extras.
+        CALL0           = 61, call0,           [dst: C1 Reg, func: C2 Reg] => Call { dst, func, args: [] };
+        CALL1           = 62, call1,           [dst: C1 Reg, func: C2 Reg, arg0: C3 Reg] => Call { dst, func, args: [arg0] };
+        CALL2           = 63, call2,           [dst: C1 Reg, func: C2 Reg, arg0: C3 Reg, arg1: Extra Reg] => Call { dst, func, args: [arg0, arg1] };
+        CALL3           = 64, call3,           [dst: C1 Reg, func: C2 Reg, arg0: C3 Reg, arg1: Extra Reg, arg2: Extra Reg] => Call { dst, func, args: [arg0, arg1, arg2] };
+    }
+
+    // the operand bytes are the high bytes of the next instruction's operands.
+    // see `WideInstrWord`. it's a prefix, not an instruction, so it isn't in the table.
+    pub const WIDE:             u8 = 60;
+
+    pub const END:              u8 = 65;
 
-    pub const MAP_NEW:          u8 = 13;
+    pub const EXTRA:            u8 = 255;
+}
 
-    pub const READ_PATH:        u8 = 14;
-    pub const WRITE_PATH:       u8 = 15;
-    pub const WRITE_PATH_DEF:   u8 = 16;
 
-    pub const ADD:              u8 = 17;
-    pub const SUB:              u8 = 18;
-    pub const MUL:              u8 = 19;
-    pub const DIV:              u8 = 20;
-    pub const FLOOR_DIV:        u8 = 21;
-    pub const REM:              u8 = 22;
-    pub const ADD_INT:          u8 = 23;
-    pub const NEGATE:           u8 = 24;
+// the encoding of an operand's value.
+trait OperandValue: Sized {
+    fn to_operand(self) -> u32;
+    fn from_operand(value: u32) -> Option<Self>;
+}
 
-    pub const NOT:              u8 = 25;
+impl OperandValue for u32 {
+    fn to_operand(self) -> u32 { self }
+    fn from_operand(value: u32) -> Option<Self> { Some(value) }
+}
 
-    pub const CMP_EQ:           u8 = 26;
-    pub const CMP_NE:           u8 = 27;
-    pub const CMP_LE:           u8 = 28;
-    pub const CMP_LT:           u8 = 29;
-    pub const CMP_GE:           u8 = 30;
-    pub const CMP_GT:           u8 = 31;
+impl OperandValue for bool {
+    fn to_operand(self) -> u32 { self as u32 }
+    fn from_operand(value: u32) -> Option<Self> { Some(value != 0) }
+}
 
-    pub const JUMP:             u8 = 32;
-    pub const JUMP_TRUE:        u8 = 33;
-    pub const JUMP_FALSE:       u8 = 34;
-    pub const JUMP_NIL:         u8 = 35;
-    pub const JUMP_NOT_NIL:     u8 = 36;
+impl OperandValue for i16 {
+    fn to_operand(self) -> u32 { self as u16 as u32 }
+    fn from_operand(value: u32) -> Option<Self> { Some(value as u16 as i16) }
+}
 
-    pub const CALL:             u8 = 37;
-    pub const RET:              u8 = 38;
+impl OperandValue for PathBase {
+    fn to_operand(self) -> u32 { self.0 }
+    fn from_operand(value: u32) -> Option<Self> { Some(PathBase(value)) }
+}
 
-    pub const END:              u8 = 39;
+impl OperandValue for PathKey {
+    fn to_operand(self) -> u32 { self.encode() }
+    fn from_operand(value: u32) -> Option<Self> { PathKey::from_extra(value) }
+}
 
-    pub const EXTRA:            u8 = 255;
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Slot {
+    C1,
+    C2,
+    C3,
+    U16,
+    Extra,
+    List,
+}
 
-    pub const fn name(opcode: u8) -> &'static str {
-        match opcode {
-            NOP                 => "nop",
-            UNREACHABLE         => "unreachable",
-            COPY                => "copy",
-            SWAP                => "swap",
-            LOAD_NIL            => "load_nil",
-            LOAD_BOOL           => "load_bool",
-            LOAD_INT            => "load_int",
-            LOAD_CONST          => "load_const",
-            LOAD_ENV            => "load_env",
-            LIST_NEW            => "list_new",
-            TUPLE_NEW           => "tuple_new",
-            LOAD_UNIT           => "load_unit",
-            MAP_NEW             => "map_new",
-            READ_PATH           => "read_path",
-            WRITE_PATH          => "write_path",
-            WRITE_PATH_DEF      => "write_path_def",
-            ADD                 => "add",
-            SUB                 => "sub",
-            MUL                 => "mul",
-            DIV                 => "div",
-            FLOOR_DIV           => "floor_div",
-            REM                 => "rem",
-            ADD_INT             => "add_int",
-            NEGATE              => "negate",
-            NOT                 => "not",
-            CMP_EQ              => "cmp_eq",
-            CMP_NE              => "cmp_ne",
-            CMP_LE              => "cmp_le",
-            CMP_LT              => "cmp_lt",
-            CMP_GE              => "cmp_ge",
-            CMP_GT              => "cmp_gt",
-            JUMP                => "jump",
-            JUMP_TRUE           => "jump_true",
-            JUMP_FALSE          => "jump_false",
-            JUMP_NIL            => "jump_nil",
-            JUMP_NOT_NIL        => "jump_not_nil",
-            CALL                => "call",
-            RET                 => "ret",
-            0 | 39..=255 => unreachable!()
-        }
+impl Slot {
+    #[inline(always)]
+    pub fn in_word(self) -> bool {
+        !matches!(self, Slot::Extra | Slot::List)
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OperandKind {
+    Reg,
+    Bool,
+    Int,    // i16.
+    Const,
+    Global,
+    Shape,
+    Method,
+    Target,
+    Count,  // of the `List`.
+    Base,   // see `PathBase`.
+    Key,    // see `PathKey`.
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Operand {
+    pub slot: Slot,
+    pub kind: OperandKind,
+}
+
 
 #[derive(Clone, Copy, Debug)]
 #[repr(transparent)]
 pub struct InstrWord(u32);
 
 impl InstrWord {
+    #[inline(always)]
+    pub const fn from_value(value: u32) -> InstrWord {
+        InstrWord(value)
+    }
+
+    #[inline(always)]
+    pub const fn value(self) -> u32 {
+        self.0
+    }
+
     #[inline(always)]
     pub fn opcode(self) -> u32 {
         let opcode = self.0 & 0xff;
@@ -206,55 +436,148 @@ impl InstrWord {
     pub fn u16(self) -> u32 {
         (self.0 >> 16) & 0xffff
     }
+
+    // extras have a 24 bit payload.
+    #[inline(always)]
+    pub fn encode_extra(v: u32) -> InstrWord {
+        debug_assert!(v < (1 << 24));
+        InstrWord(opcode::EXTRA as u32 | v << 8)
+    }
+
+    #[inline(always)]
+    pub fn extra(self) -> u32 {
+        self.0 >> 8
+    }
+
+    #[inline(always)]
+    pub fn patch_extra(&mut self, v: u32) {
+        debug_assert!(v < (1 << 24));
+        self.0 &= 0xff;
+        self.0 |= v << 8;
+    }
+
+    // the payload of a `wide` prefix.
+    #[inline(always)]
+    pub fn encode_wide(wide: u32) -> InstrWord {
+        debug_assert!(wide < (1 << 24));
+        InstrWord(opcode::WIDE as u32 | wide << 8)
+    }
+
+    #[inline(always)]
+    pub fn wide(self) -> u32 {
+        self.0 >> 8
+    }
+}
+
+
+// an instruction & its `wide` prefix (or 0).
+// the prefix holds the high bytes of the operands:
+//  bits 0..8 extend `c1`, bits 8..16 extend `c2`, bits 16..24 extend `c3`.
+//  `u16` operands occupy the `c2` & `c3` slots, so they're extended by bits 8..24.
+#[derive(Clone, Copy, Debug)]
+pub struct WideInstrWord {
+    pub word: InstrWord,
+    pub wide: u32,
+}
+
+impl WideInstrWord {
+    #[inline(always)]
+    pub fn opcode(self) -> u32 {
+        self.word.opcode()
+    }
+
+    #[inline(always)]
+    pub fn c1(self) -> u32 {
+        self.word._c1() | (self.wide & 0xff) << 8
+    }
+
+    #[inline(always)]
+    pub fn c2(self) -> (u32, u32) {
+        (self.c1(), self.word._c2() | (self.wide >> 8 & 0xff) << 8)
+    }
+
+    #[inline(always)]
+    pub fn c3(self) -> (u32, u32, u32) {
+        let (c1, c2) = self.c2();
+        (c1, c2, self.word._c3() | (self.wide >> 16 & 0xff) << 8)
+    }
+
+    #[inline(always)]
+    pub fn c1_bool(self) -> (u32, bool) {
+        (self.c1(), self.word._bool2())
+    }
+
+    #[inline(always)]
+    pub fn c1u16(self) -> (u32, u32) {
+        (self.c1(), self.u16())
+    }
+
+    #[inline(always)]
+    pub fn u16(self) -> u32 {
+        self.word.u16() | (self.wide >> 8 & 0xffff) << 16
+    }
 }
 
 
 
 #[derive(Clone, Copy, PartialEq, Debug)]
-pub struct PathBase(u8);
+pub struct PathBase(u32);
 
 impl PathBase {
     pub const ITEMS: PathBase = PathBase(254);
     pub const ENV:   PathBase = PathBase(255);
 
     #[inline(always)]
-    pub const fn value(self) -> u8 { self.0 }
+    pub const fn value(self) -> u32 { self.0 }
 
+    // registers 254 & 255 can't be used as path bases.
     #[inline(always)]
-    pub fn reg(reg: u8) -> PathBase {
-        assert!(reg < 254);
+    pub fn reg(reg: u32) -> PathBase {
+        assert!(!Self::is_reserved(reg));
         PathBase(reg)
     }
+
+    #[inline(always)]
+    pub const fn is_reserved(reg: u32) -> bool {
+        reg == Self::ITEMS.0 || reg == Self::ENV.0
+    }
 }
 
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum PathKey {
-    Field { string: u16 },
-    Index { reg:    u8  },
+    Field { string: u32 },
+    Index { reg:    u32 },
 }
 
 impl PathKey {
-    pub const TYPE_FIELD: u8 = 1;
-    pub const TYPE_INDEX: u8 = 2;
+    pub const TYPE_FIELD: u32 = 1;
+    pub const TYPE_INDEX: u32 = 2;
 
-    pub fn encode(self) -> (u8, u16) {
-        match self {
+    // the kind is in the low 2 bits of the extra's payload.
+    pub fn encode(self) -> u32 {
+        let (kind, value) = match self {
             PathKey::Field { string } => (PathKey::TYPE_FIELD, string),
-            PathKey::Index { reg    } => (PathKey::TYPE_INDEX, reg as u16),
-        }
+            PathKey::Index { reg    } => (PathKey::TYPE_INDEX, reg),
+        };
+        assert!(value < (1 << 22));
+        value << 2 | kind
     }
 
     pub fn decode(instr: InstrWord) -> PathKey {
-        let (kind, value) = instr.c1u16();
-        if kind == Self::TYPE_FIELD as u32 {
-            PathKey::Field { string: value as u16 }
+        Self::from_extra(instr.extra()).unwrap()
+    }
+
+    pub fn from_extra(extra: u32) -> Option<PathKey> {
+        let (kind, value) = (extra & 3, extra >> 2);
+        if kind == Self::TYPE_FIELD {
+            Some(PathKey::Field { string: value })
         }
-        else if kind == Self::TYPE_INDEX as u32 {
-            PathKey::Index { reg: value.try_into().unwrap() }
+        else if kind == Self::TYPE_INDEX {
+            Some(PathKey::Index { reg: value })
         }
         else {
-            unimplemented!()
+            None
         }
     }
 }
@@ -271,255 +594,147 @@ impl core::fmt::Display for PathKey {
 
 pub struct ByteCodeBuilder {
     buffer: Vec<InstrWord>,
+    wide_jumps: bool,
 }
 
 impl ByteCodeBuilder {
     pub fn new() -> Self {
         ByteCodeBuilder {
             buffer: vec![],
+            wide_jumps: false,
         }
     }
 
-    pub fn nop(&mut self) {
-        self.buffer.push(InstrWord::encode_op(opcode::NOP));
-    }
-
-    pub fn unreachable(&mut self) {
-        self.buffer.push(InstrWord::encode_op(opcode::UNREACHABLE));
-    }
-
-    pub fn copy(&mut self, dst: u8, src: u8) {
-        self.buffer.push(InstrWord::encode_c2(opcode::COPY, dst, src));
-    }
-
-    pub fn swap(&mut self, dst: u8, src: u8) {
-        self.buffer.push(InstrWord::encode_c2(opcode::SWAP, dst, src));
-    }
-
-
-    pub fn load_nil(&mut self, dst: u8) {
-        self.buffer.push(InstrWord::encode_c1(opcode::LOAD_NIL, dst));
-    }
-
-    pub fn load_bool(&mut self, dst: u8, value: bool) {
-        self.buffer.push(InstrWord::encode_c2(opcode::LOAD_BOOL, dst, value as u8));
-    }
-
-    pub fn load_int(&mut self, dst: u8, value: i16) {
-        self.buffer.push(InstrWord::encode_c1u16(opcode::LOAD_INT, dst, value as u16));
-    }
-
-    pub fn load_const(&mut self, dst: u8, index: u16) {
-        self.buffer.push(InstrWord::encode_c1u16(opcode::LOAD_CONST, dst, index));
+    // jumps always get a `wide` prefix,
+    // so targets beyond 16 bits can be patched in later.
+    pub fn set_wide_jumps(&mut self, wide_jumps: bool) {
+        self.wide_jumps = wide_jumps;
     }
 
-    pub fn load_env(&mut self, dst: u8) {
-        self.buffer.push(InstrWord::encode_c1(opcode::LOAD_ENV, dst));
-    }
-
-
-    pub fn list_new(&mut self, dst: u8, values: &[u8]) {
-        assert!(values.len() < 128);
-        self.buffer.push(InstrWord::encode_c1u16(opcode::LIST_NEW, dst, values.len() as u16));
-        for v in values {
-            self.buffer.push(InstrWord::encode_u16(opcode::EXTRA, *v as u16));
-        }
+    #[inline(always)]
+    pub fn current_offset(&self) -> usize {
+        self.buffer.len()
     }
 
 
-    pub fn tuple_new(&mut self, dst: u8, values: &[u8]) {
-        assert!(values.len() < 128);
-        self.buffer.push(InstrWord::encode_c1u16(opcode::TUPLE_NEW, dst, values.len() as u16));
-        for v in values {
-            self.buffer.push(InstrWord::encode_u16(opcode::EXTRA, *v as u16));
-        }
-    }
-
-    pub fn load_unit(&mut self, dst: u8) {
-        self.buffer.push(InstrWord::encode_c1(opcode::LOAD_UNIT, dst));
+    pub fn build(self) -> Vec<InstrWord> {
+        assert!(self.buffer.len() < (1 << 24));
+        self.buffer
     }
 
 
-    pub fn map_new(&mut self, dst: u8) {
-        self.buffer.push(InstrWord::encode_c1(opcode::MAP_NEW, dst));
-    }
+    // encodes `op` as described by the opcode table.
+    // `operands` are in table order, without the `Count`,
+    // which is the length of `list`.
+    // operands that don't fit into their slot get a `wide` prefix.
+    pub fn emit(&mut self, op: u8, operands: &[u32], mut list: impl ExactSizeIterator<Item = u32>) {
+        let table = opcode::operands(op);
 
+        let len = list.len() as u32;
+        let mut values = operands.iter().copied();
+        let mut next = |kind: OperandKind| {
+            if kind == OperandKind::Count { len }
+            else { values.next().unwrap() }
+        };
 
-    pub fn read_path(&mut self, dst: u8, base: PathBase, keys: &[PathKey]) {
-        assert!(keys.len() < 128);
-        self.buffer.push(InstrWord::encode_c3(opcode::READ_PATH, dst, base.0, keys.len() as u8));
-        for key in keys {
-            let (kind, value) = key.encode();
-            self.buffer.push(InstrWord::encode_c1u16(opcode::EXTRA, kind, value));
+        let mut c = [0; 3];
+        let mut u16 = None;
+        let mut force_wide = false;
+        let num_slots = table.iter().take_while(|o| o.slot.in_word()).count();
+        for operand in &table[..num_slots] {
+            let value = next(operand.kind);
+            match operand.slot {
+                Slot::C1  => c[0] = value,
+                Slot::C2  => c[1] = value,
+                Slot::C3  => c[2] = value,
+                Slot::U16 => u16 = Some(value),
+                Slot::Extra | Slot::List => unreachable!()
+            }
+            force_wide |= operand.kind == OperandKind::Target && self.wide_jumps;
         }
-    }
 
-    pub fn write_path(&mut self, base: PathBase, keys: &[PathKey], value: u8, is_define: bool) {
-        assert!(keys.len() < 128);
-        let op = if is_define { opcode::WRITE_PATH_DEF } else { opcode::WRITE_PATH };
-        self.buffer.push(InstrWord::encode_c3(op, base.0, keys.len() as u8, value));
-        for key in keys {
-            let (kind, value) = key.encode();
-            self.buffer.push(InstrWord::encode_c1u16(opcode::EXTRA, kind, value));
+        assert!(c[0] < (1 << 16) && c[1] < (1 << 16) && c[2] < (1 << 16));
+        let (wide, word) = match u16 {
+            Some(v) => ((c[0] >> 8) | (v >> 16) << 8,
+                        InstrWord::encode_c1u16(op, c[0] as u8, v as u16)),
+            None    => ((c[0] >> 8) | (c[1] >> 8) << 8 | (c[2] >> 8) << 16,
+                        InstrWord::encode_c3(op, c[0] as u8, c[1] as u8, c[2] as u8)),
+        };
+        if wide != 0 || force_wide {
+            assert!(wide < (1 << 24));
+            self.buffer.push(InstrWord::encode_wide(wide));
         }
-    }
-
-
-    pub fn add(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::ADD, dst, src1, src2));
-    }
+        self.buffer.push(word);
 
-    pub fn sub(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::SUB, dst, src1, src2));
-    }
-
-    pub fn mul(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::MUL, dst, src1, src2));
-    }
-
-    pub fn div(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::DIV, dst, src1, src2));
-    }
-
-    pub fn floor_div(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::FLOOR_DIV, dst, src1, src2));
-    }
-
-    pub fn rem(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::REM, dst, src1, src2));
-    }
-
-    pub fn add_int(&mut self, dst: u8, value: i16) {
-        self.buffer.push(InstrWord::encode_c1u16(opcode::ADD_INT, dst, value as u16));
-    }
-
-    pub fn negate(&mut self, dst: u8, src: u8) {
-        self.buffer.push(InstrWord::encode_c2(opcode::NEGATE, dst, src));
-    }
-
-
-    pub fn not(&mut self, dst: u8, src: u8) {
-        self.buffer.push(InstrWord::encode_c2(opcode::NOT, dst, src));
-    }
-
-
-
-    pub fn cmp_eq(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::CMP_EQ, dst, src1, src2));
-    }
-
-    pub fn cmp_ne(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::CMP_NE, dst, src1, src2));
-    }
-
-    pub fn cmp_le(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::CMP_LE, dst, src1, src2));
-    }
-
-    pub fn cmp_lt(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::CMP_LT, dst, src1, src2));
-    }
-
-    pub fn cmp_ge(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::CMP_GE, dst, src1, src2));
-    }
-
-    pub fn cmp_gt(&mut self, dst: u8, src1: u8, src2: u8) {
-        self.buffer.push(InstrWord::encode_c3(opcode::CMP_GT, dst, src1, src2));
-    }
-
-
-    pub fn jump(&mut self, target: u16) {
-        self.buffer.push(InstrWord::encode_u16(opcode::JUMP, target));
-    }
-
-    pub fn jump_true(&mut self, src: u8, target: u16) {
-        self.buffer.push(InstrWord::encode_c1u16(opcode::JUMP_TRUE, src, target));
-    }
-
-    pub fn jump_false(&mut self, src: u8, target: u16) {
-        self.buffer.push(InstrWord::encode_c1u16(opcode::JUMP_FALSE, src, target));
-    }
-
-    pub fn jump_nil(&mut self, src: u8, target: u16) {
-        self.buffer.push(InstrWord::encode_c1u16(opcode::JUMP_NIL, src, target));
-    }
-
-    pub fn jump_non_nil(&mut self, src: u8, target: u16) {
-        self.buffer.push(InstrWord::encode_c1u16(opcode::JUMP_NOT_NIL, src, target));
-    }
-
-
-    pub fn call(&mut self, dst: u8, func: u8, args: &[u8]) {
-        assert!(args.len() < 128);
-        self.buffer.push(InstrWord::encode_c2(opcode::CALL, dst, func));
-        self.buffer.push(InstrWord::encode_u16(opcode::EXTRA, args.len() as u16));
-        for arg in args {
-            self.buffer.push(InstrWord::encode_u16(opcode::EXTRA, *arg as u16));
+        for operand in &table[num_slots..] {
+            match operand.slot {
+                Slot::Extra => {
+                    let value = next(operand.kind);
+                    self.buffer.push(InstrWord::encode_extra(value));
+                }
+                Slot::List => {
+                    for value in list.by_ref() {
+                        self.buffer.push(InstrWord::encode_extra(value));
+                    }
+                }
+                _ => unreachable!()
+            }
         }
-    }
-
-    pub fn ret(&mut self, src: u8) {
-        self.buffer.push(InstrWord::encode_c1(opcode::RET, src));
-    }
-
-
-
-    #[inline(always)]
-    pub fn current_offset(&self) -> usize {
-        self.buffer.len()
-    }
-
-
-    pub fn build(self) -> Vec<InstrWord> {
-        assert!(self.buffer.len() < (1 << 16));
-        self.buffer
+        debug_assert!(values.next().is_none());
     }
 }
 
 
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Instr {
-    pub pc:     u16,
+    pub pc:     u32,
     pub opcode: u8,
     pub data:   InstrData,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum InstrData {
     Nop,
     Unreachable,
 
-    Copy                { dst: u8, src: u8 },
-    Swap                { dst: u8, src: u8 },
+    Copy                { dst: u32, src: u32 },
+    Swap                { dst: u32, src: u32 },
+
+    LoadNil             { dst: u32 },
+    LoadBool            { dst: u32, value: bool },
+    LoadInt             { dst: u32, value: i16 },
+    LoadConst           { dst: u32, index: u32 },
+    LoadEnv             { dst: u32 },
+    LoadGlobal          { dst: u32, slot: u32 },
 
-    LoadNil             { dst: u8 },
-    LoadBool            { dst: u8, value: bool },
-    LoadInt             { dst: u8, value: i16 },
-    LoadConst           { dst: u8, index: u16 },
-    LoadEnv             { dst: u8 },
+    ListNew             { dst: u32, values: Vec<u32>, },
 
-    ListNew             { dst: u8, values: Vec<u8>, },
+    TupleNew            { dst: u32, values: Vec<u32> },
+    TupleNewNamed       { dst: u32, shape: u32, values: Vec<u32> },
+    LoadUnit            { dst: u32 },
 
-    TupleNew            { dst: u8, values: Vec<u8> },
-    LoadUnit            { dst: u8 },
+    MapNew              { dst: u32 }, 
 
-    MapNew              { dst: u8 }, 
+    ReadPath            { dst: u32, base: PathBase, keys: Vec<PathKey> },
+    WritePath           { base: PathBase, keys: Vec<PathKey>, value: u32 },
+    DeletePath          { base: PathBase, keys: Vec<PathKey> },
 
-    ReadPath            { dst: u8, base: PathBase, keys: Vec<PathKey> },
-    WritePath           { base: PathBase, keys: Vec<PathKey>, value: u8 },
+    Op1                 { dst: u32, src: u32 },
+    Op2                 { dst: u32, src1: u32, src2: u32 },
 
-    Op1                 { dst: u8, src: u8 },
-    Op2                 { dst: u8, src1: u8, src2: u8 },
+    AddInt              { dst: u32, value: i16 },
 
-    AddInt              { dst: u8, value: i16 },
+    Jump                { target: u32 },
+    JumpC1              { target: u32, src: u32 },
+    JumpC2              { target: u32, src1: u32, src2: u32, value: bool },
 
-    Jump                { target: u16 },
-    JumpC1              { target: u16, src: u8 },
+    Call                { dst: u32, func: u32, args: Vec<u32> },
+    TailCall            { func: u32, args: Vec<u32> },
+    Ret                 { src: u32 },
 
-    Call                { dst: u8, func: u8, args: Vec<u8> },
-    Ret                 { src: u8 },
+    LoadMethod          { dst: u32, traitt: u32, recv: u32, method: u32 },
+    Implements          { dst: u32, src: u32, traitt: u32 },
 }
 
 impl Instr {
@@ -544,180 +759,78 @@ impl<'a> ByteCodeDecoder<'a> {
         self.pc >= self.code.len()
     }
 
-    fn next_instr(&mut self) -> Option<(InstrWord, u16)> {
+    pub fn offset(&self) -> usize {
+        self.pc
+    }
+
+    fn next_instr(&mut self) -> Option<(InstrWord, u32)> {
         let instr = *self.code.get(self.pc)?;
-        let pc    = self.pc as u16;
+        let pc    = self.pc as u32;
         self.pc += 1;
         Some((instr, pc))
     }
 
     fn next_instr_extra(&mut self) -> Option<InstrWord> {
         let (extra, _) = self.next_instr()?;
-        assert_eq!(extra.opcode() as u8, crate::bytecode::opcode::EXTRA);
-        Some(extra)
+        (extra.0 as u8 == opcode::EXTRA).then_some(extra)
     }
 
-    pub fn next(&mut self) -> Option<Instr> {
-        let (instr, pc) = self.next_instr()?;
-        let opcode = instr.opcode() as u8;
-
-        use crate::bytecode::opcode::*;
-        let data = match opcode {
-            NOP => {
-                InstrData::Nop
-            }
-
-            UNREACHABLE => {
-                InstrData::Unreachable
-            }
-
-
-            COPY => {
-                let (dst, src) = instr.c2();
-                InstrData::Copy { dst: dst as u8, src: src as u8 }
-            }
-
-            SWAP => {
-                let (dst, src) = instr.c2();
-                InstrData::Swap { dst: dst as u8, src: src as u8 }
-            }
-
-
-            LOAD_NIL => {
-                let dst = instr.c1();
-                InstrData::LoadNil { dst: dst as u8 }
-            }
-
-            LOAD_BOOL => {
-                let (dst, value) = instr.c1_bool();
-                InstrData::LoadBool { dst: dst as u8, value }
-            }
-
-            LOAD_INT => {
-                let (dst, value) = instr.c1u16();
-                let value = value as u16 as i16;
-                InstrData::LoadInt { dst: dst as u8, value }
-            }
-
-            LOAD_CONST => {
-                let (dst, index) = instr.c1u16();
-                InstrData::LoadConst { dst: dst as u8, index: index as u16 }
-            }
-
-            LOAD_ENV => {
-                let dst = instr.c1();
-                InstrData::LoadEnv { dst: dst as u8 }
-            }
-
-
-            LIST_NEW => {
-                let (dst, len) = instr.c1u16();
-
-                let mut values = Vec::with_capacity(len as usize);
-                for _ in 0..len {
-                    let v = self.next_instr_extra()?;
-                    values.push(v.u16() as u8);
-                }
-
-                InstrData::ListNew { dst: dst as u8, values }
-            }
-            
-
-            TUPLE_NEW => {
-                let (dst, len) = instr.c1u16();
-
-                let mut values = Vec::with_capacity(len as usize);
-                for _ in 0..len {
-                    let v = self.next_instr_extra()?;
-                    values.push(v.u16() as u8);
-                }
-
-                InstrData::TupleNew { dst: dst as u8, values }
-            }
-
-            LOAD_UNIT => {
-                let dst = instr.c1();
-                InstrData::LoadUnit { dst: dst as u8 }
-            }
-
-
-            MAP_NEW => {
-                let dst = instr.c1();
-                InstrData::MapNew { dst: dst as u8 }
-            }
-
-
-            READ_PATH => {
-                let (dst, base, num_keys) = instr.c3();
-                let mut keys = Vec::with_capacity(num_keys as usize);
-                for _ in 0..num_keys {
-                    keys.push(PathKey::decode(self.next_instr_extra()?));
-                }
-                InstrData::ReadPath { dst: dst as u8, base: PathBase(base as u8), keys }
-            }
-
-            WRITE_PATH | WRITE_PATH_DEF => {
-                let (base, num_keys, value) = instr.c3();
-                let mut keys = Vec::with_capacity(num_keys as usize);
-                for _ in 0..num_keys {
-                    keys.push(PathKey::decode(self.next_instr_extra()?));
+    // decodes the next instruction using only the opcode table.
+    // returns `None` for malformed code.
+    pub fn next_raw(&mut self) -> Option<RawInstr> {
+        let (mut word, pc) = self.next_instr()?;
+        let mut wide = 0;
+        if word.0 as u8 == opcode::WIDE {
+            wide = word.wide();
+            (word, _) = self.next_instr()?;
+        }
+        let at     = self.pc as u32 - 1;
+        let opcode = word.0 as u8;
+        if !opcode::is_valid(opcode) {
+            return None;
+        }
+        let instr = WideInstrWord { word, wide };
+
+        let table = opcode::operands(opcode);
+        let mut operands = Vec::with_capacity(table.len());
+        let mut count = 0;
+        for &Operand { slot, kind } in table {
+            let value = match slot {
+                Slot::C1  => instr.c1(),
+                Slot::C2  => instr.c2().1,
+                Slot::C3  => instr.c3().2,
+                Slot::U16 => instr.u16(),
+
+                Slot::Extra => {
+                    let at = self.pc as u32;
+                    let value = self.next_instr_extra()?.extra();
+                    if kind == OperandKind::Count { count = value }
+                    operands.push(RawOperand { slot, kind, value, at });
+                    continue;
                 }
-                InstrData::WritePath { base: PathBase(base as u8), keys, value: value as u8 }
-            }
-
-
-            NEGATE | NOT => {
-                let (dst, src) = instr.c2();
-                InstrData::Op1 { dst: dst as u8, src: src as u8 }
-            }
-
-            ADD | SUB | MUL | DIV | FLOOR_DIV | REM |
-            CMP_EQ | CMP_NE | CMP_LE | CMP_LT | CMP_GE | CMP_GT => {
-                let (dst, src1, src2) = instr.c3();
-                InstrData::Op2 { dst: dst as u8, src1: src1 as u8, src2: src2 as u8 }
-            }
-
-            ADD_INT => {
-                let (dst, value) = instr.c1u16();
-                let value = value as u16 as i16;
-                InstrData::AddInt { dst: dst as u8, value }
-            }
-
-
-            JUMP => {
-                let target = instr.u16();
-                InstrData::Jump { target: target as u16 }
-            }
 
-            JUMP_TRUE | JUMP_FALSE | JUMP_NIL | JUMP_NOT_NIL => {
-                let (src, target) = instr.c1u16();
-                InstrData::JumpC1 { target: target as u16, src: src as u8 }
-            }
-
-
-            CALL => {
-                let (dst, func) = instr.c2();
-                let num_args = self.next_instr_extra()?.u16();
-
-                let mut args = Vec::with_capacity(num_args as usize);
-                for _ in 0..num_args {
-                    let arg = self.next_instr_extra()?;
-                    args.push(arg.u16() as u8);
+                Slot::List => {
+                    for _ in 0..count {
+                        let at = self.pc as u32;
+                        let value = self.next_instr_extra()?.extra();
+                        operands.push(RawOperand { slot, kind, value, at });
+                    }
+                    continue;
                 }
+            };
+            if kind == OperandKind::Count { count = value }
+            operands.push(RawOperand { slot, kind, value, at });
+        }
+        Some(RawInstr { pc, at, opcode, operands })
+    }
 
-                InstrData::Call { dst: dst as u8, func: func as u8, args }
-            }
-
-            RET => {
-                let src = instr.c1();
-                InstrData::Ret { src: src as u8 }
-            }
+    // `pc` is the offset of the `wide` prefix, if there is one.
+    pub fn next(&mut self) -> Option<Instr> {
+        let raw = self.next_raw()?;
+        let opcode = raw.opcode;
 
-            // @todo-speed: this inserts a check to reduce dispatch table size.
-            //  may want an unreachable_unchecked() in release.
-            0 | END ..= 255 => unreachable!()
-        };
-        Some(Instr { pc, opcode, data })
+        let data = InstrData::decode(&raw)?;
+        Some(Instr { pc: raw.pc, opcode, data })
     }
 
     pub fn decode(code: &[InstrWord]) -> Option<Vec<Instr>> {
@@ -728,289 +841,288 @@ impl<'a> ByteCodeDecoder<'a> {
         }
         decoder.done().then_some(instrs)
     }
+
+    pub fn decode_raw(code: &[InstrWord]) -> Option<Vec<RawInstr>> {
+        let mut decoder = ByteCodeDecoder::new(code);
+        let mut instrs = vec![];
+        while let Some(instr) = decoder.next_raw() {
+            instrs.push(instr);
+        }
+        decoder.done().then_some(instrs)
+    }
 }
 
 
-pub fn dump(code: &[InstrWord]) {
-    let mut pc = 0;
+// an instruction decoded using only the opcode table.
+// the operands are in table order, lists are expanded.
+#[derive(Clone, Debug)]
+pub struct RawInstr {
+    pub pc:       u32, // of the `wide` prefix, if there is one.
+    pub at:       u32, // of the instruction word.
+    pub opcode:   u8,
+    pub operands: Vec<RawOperand>,
+}
 
-    macro_rules! next_instr {
-        () => {{
-            let instr = code[pc];
-            pc += 1;
-            instr
-        }};
-    }
+#[derive(Clone, Copy, Debug)]
+pub struct RawOperand {
+    pub slot:  Slot,
+    pub kind:  OperandKind,
+    pub value: u32,
+    pub at:    u32, // the word that holds the operand.
+}
 
-    macro_rules! next_instr_extra {
-        () => {{
-            let extra = next_instr!();
-            assert_eq!(extra.opcode() as u8, crate::bytecode::opcode::EXTRA);
-            extra
-        }};
+impl RawInstr {
+    #[inline(always)]
+    pub fn name(&self) -> &'static str {
+        opcode::name(self.opcode)
     }
 
-    while pc < code.len() {
-        print!("{:02}: ", pc);
+    #[inline(always)]
+    pub fn is_wide(&self) -> bool {
+        self.pc != self.at
+    }
+}
 
-        let instr  = next_instr!();
-        let opcode = instr.opcode() as u8;
+impl core::fmt::Display for RawInstr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.is_wide() { write!(f, "wide ")? }
+        write!(f, "  {}", self.name())?;
 
-        use crate::bytecode::opcode::*;
-        match opcode {
-            NOP => {
-                println!("  nop");
+        let mut sep = " ";
+        for operand in &self.operands {
+            if operand.slot == Slot::List || operand.kind == OperandKind::Count {
+                continue;
             }
+            write!(f, "{}{}", sep, operand)?;
+            sep = ", ";
+        }
 
-            UNREACHABLE => {
-                println!("  unreachable");
+        if opcode::operands(self.opcode).iter().any(|o| o.slot == Slot::List) {
+            write!(f, "{}[", sep)?;
+            let items = self.operands.iter().filter(|o| o.slot == Slot::List);
+            for (i, operand) in items.enumerate() {
+                if i > 0 { write!(f, ", ")? }
+                write!(f, "{}", operand)?;
             }
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+}
 
-
-            COPY => {
-                let (dst, src) = instr.c2();
-                println!("  copy r{}, r{}", dst, src);
+impl core::fmt::Display for RawOperand {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let value = self.value;
+        match self.kind {
+            OperandKind::Reg    => write!(f, "r{}", value),
+            OperandKind::Bool   => write!(f, "{}", value != 0),
+            OperandKind::Int    => write!(f, "{}", value as u16 as i16),
+            OperandKind::Const  => write!(f, "c{}", value),
+            OperandKind::Global => write!(f, "g{}", value),
+            OperandKind::Shape  => write!(f, "s{}", value),
+            OperandKind::Method => write!(f, "m{}", value),
+            OperandKind::Target => write!(f, "{}", value),
+            OperandKind::Count  => write!(f, "#{}", value),
+
+            OperandKind::Base => {
+                if      value == PathBase::ITEMS.0 { write!(f, "ITEMS") }
+                else if value == PathBase::ENV.0   { write!(f, "ENV") }
+                else                               { write!(f, "r{}", value) }
             }
 
-            SWAP => {
-                let (dst, src) = instr.c2();
-                println!("  swap r{}, r{}", dst, src);
+            OperandKind::Key => match PathKey::from_extra(value) {
+                Some(key) => write!(f, "{}", key),
+                None      => write!(f, "?{}", value),
             }
+        }
+    }
+}
 
 
-            LOAD_NIL => {
-                let dst = instr.c1();
-                println!("  load_nil r{}", dst);
-            }
+pub fn dump(code: &[InstrWord]) {
+    let mut decoder = ByteCodeDecoder::new(code);
+    while !decoder.done() {
+        let pc = decoder.offset();
+        let Some(instr) = decoder.next_raw() else {
+            println!("{:02}:   <invalid>", pc);
+            return;
+        };
+        println!("{:02}: {}", pc, instr);
+    }
+}
 
-            LOAD_BOOL => {
-                let (dst, value) = instr.c1_bool();
-                println!("  load_bool r{}, {}", dst, value);
-            }
 
-            LOAD_INT => {
-                let (dst, value) = instr.c1u16();
-                let value = value as u16 as i16 as f64;
-                println!("  load_int r{}, {}", dst, value);
-            }
 
-            LOAD_CONST => {
-                let (dst, index) = instr.c1u16();
-                println!("  load_const r{}, c{}", dst, index);
-            }
 
-            LOAD_ENV => {
-                let dst = instr.c1();
-                println!("  load_env r{}", dst);
-            }
+#[derive(Clone, Debug)]
+pub struct ByteCodeError {
+    pub pc:      u32,
+    pub message: String,
+}
 
+impl core::fmt::Display for ByteCodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}: {}", self.pc, self.message)
+    }
+}
 
-            LIST_NEW => {
-                let (dst, len) = instr.c1u16();
-                print!("  list_new r{}, [", dst);
 
-                for i in 0..len {
-                    let v = next_instr_extra!();
-                    print!("r{}", v.u16());
-                    if i < len - 1 {
-                        print!(", ");
-                    }
-                }
+// checks that the vm can run `code` without going out of bounds.
+// `run` only `debug_assert!`s operands, so untrusted code must pass this first.
+pub fn verify_bytecode(code: &[InstrWord], constants: &[Constant], tuple_shapes: &[Vec<String>], stack_size: u32) -> Result<(), ByteCodeError> {
+    let error = |pc: u32, message: String| Err(ByteCodeError { pc, message });
 
-                println!("]");
+    let mut decoder = ByteCodeDecoder::new(code);
+    let mut instrs = vec![];
+    while !decoder.done() {
+        let pc = decoder.offset();
+        let Some(instr) = decoder.next_raw() else {
+            let mut op = code[pc].0 as u8;
+            if op == opcode::WIDE && pc + 1 < code.len() {
+                op = code[pc + 1].0 as u8;
             }
-            
-
-            TUPLE_NEW => {
-                let (dst, len) = instr.c1u16();
-                print!("  tuple_new r{}, [", dst);
-
-                for i in 0..len {
-                    let v = next_instr_extra!();
-                    print!("r{}", v.u16());
-                    if i < len - 1 {
-                        print!(", ");
-                    }
+            let message =
+                if op == opcode::EXTRA { "unexpected extra word".into() }
+                else if !opcode::is_valid(op) { format!("invalid opcode {}", op) }
+                else { format!("{} is missing extra words", opcode::name(op)) };
+            return error(pc as u32, message);
+        };
+        instrs.push(instr);
+    }
+
+    let Some(last) = instrs.last() else {
+        return error(0, "empty code".into());
+    };
+    if !matches!(last.opcode, opcode::RET | opcode::JUMP | opcode::TAIL_CALL | opcode::UNREACHABLE) {
+        return error(last.pc, "execution can run past the end".into());
+    }
+
+    for instr in &instrs {
+        for operand in &instr.operands {
+            let value = operand.value;
+            let valid = match operand.kind {
+                OperandKind::Reg    => value < stack_size,
+                OperandKind::Bool   => value <= 1,
+                OperandKind::Const  => (value as usize) < constants.len(),
+                OperandKind::Shape  => (value as usize) < tuple_shapes.len(),
+                OperandKind::Base   => value < stack_size || PathBase::is_reserved(value),
+
+                // jumps can't enter an instruction's extras or skip its prefix.
+                OperandKind::Target => instrs.binary_search_by_key(&value, |instr| instr.pc).is_ok(),
+
+                OperandKind::Key => match PathKey::from_extra(value) {
+                    Some(PathKey::Field { string }) =>
+                        matches!(constants.get(string as usize), Some(Constant::String { .. })),
+                    Some(PathKey::Index { reg })    => reg < stack_size,
+                    None => false,
                 }
 
-                println!("]");
-            }
-
-            LOAD_UNIT => {
-                let dst = instr.c1();
-                println!("  tuple_new r{}, []", dst);
-            }
-
-
-            MAP_NEW => {
-                let dst = instr.c1();
-                println!("  map_new r{}", dst);
-            }
+                // checked at runtime.
+                OperandKind::Int | OperandKind::Count | OperandKind::Method => true,
 
-
-            READ_PATH => {
-                let (dst, base, num_keys) = instr.c3();
-                print!("  read_path r{}, ", dst);
-                if      base == 254 { print!("ITEMS[") }
-                else if base == 255 { print!("ENV[") }
-                else                { print!("r{}[", base) };
-                for i in 0..num_keys {
-                    print!("{}", PathKey::decode(next_instr_extra!()));
-                    if i < num_keys-1 { print!(", "); }
-                }
-                println!("]");
-            }
-
-            WRITE_PATH | WRITE_PATH_DEF => {
-                let is_def = opcode == WRITE_PATH_DEF;
-                let (base, num_keys, value) = instr.c3();
-                print!("  write_path");
-                if is_def { print!("(d)") }
-                print!(" ");
-                if      base == 254 { print!("ITEMS[") }
-                else if base == 255 { print!("ENV[") }
-                else                { print!("r{}[", base) };
-                for i in 0..num_keys {
-                    print!("{}", PathKey::decode(next_instr_extra!()));
-                    if i < num_keys-1 { print!(", "); }
+                // env slots are only known to the linker.
+                OperandKind::Global => {
+                    return error(instr.pc, format!("{} is only emitted by the linker", instr.name()));
                 }
-                println!("], r{}", value);
-            }
-
-
-            ADD => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  add r{}, r{}, r{}", dst, src1, src2);
-            }
-
-            SUB => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  sub r{}, r{}, r{}", dst, src1, src2);
-            }
-
-            MUL => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  mul r{}, r{}, r{}", dst, src1, src2);
-            }
-
-            DIV => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  div r{}, r{}, r{}", dst, src1, src2);
-            }
-
-            FLOOR_DIV => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  floor_div r{}, r{}, r{}", dst, src1, src2);
-            }
-
-            REM => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  rem r{}, r{}, r{}", dst, src1, src2);
-            }
-
-            ADD_INT => {
-                let (dst, value) = instr.c1u16();
-                println!("  add_int r{}, {}", dst, value);
-            }
-
-            NEGATE => {
-                let (dst, src) = instr.c2();
-                println!("  negate r{}, r{}", dst, src);
-            }
-
-
-            NOT => {
-                let (dst, src) = instr.c2();
-                println!("  or r{}, r{}", dst, src);
-            }
-
-
-            CMP_EQ => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  cmp_eq r{}, r{}, r{}", dst, src1, src2);
-            }
-
-            CMP_NE => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  cmp_ne r{}, r{}, r{}", dst, src1, src2);
-            }
-
-            CMP_LE => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  cmp_le r{}, r{}, r{}", dst, src1, src2);
+            };
+            if !valid {
+                return error(instr.pc, format!("{}: invalid {:?} operand {}", instr.name(), operand.kind, value));
             }
+        }
 
-            CMP_LT => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  cmp_lt r{}, r{}, r{}", dst, src1, src2);
-            }
+        let is_path = opcode::operands(instr.opcode).iter().any(|o| o.kind == OperandKind::Key);
+        if is_path && !instr.operands.iter().any(|o| o.kind == OperandKind::Key) {
+            return error(instr.pc, format!("{} has no keys", instr.name()));
+        }
 
-            CMP_GE => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  cmp_ge r{}, r{}, r{}", dst, src1, src2);
+        // items are indexed by number.
+        let base = instr.operands.iter().find(|o| o.kind == OperandKind::Base);
+        if base.is_some_and(|o| o.value == PathBase::ITEMS.0) {
+            let key = instr.operands.iter().find(|o| o.kind == OperandKind::Key).unwrap();
+            if !matches!(PathKey::from_extra(key.value), Some(PathKey::Index { .. })) {
+                return error(instr.pc, format!("{}: items need an index key", instr.name()));
             }
+        }
 
-            CMP_GT => {
-                let (dst, src1, src2) = instr.c3();
-                println!("  cmp_gt r{}, r{}, r{}", dst, src1, src2);
+        match instr.opcode {
+            // @temp-no-env-access.
+            opcode::LOAD_ENV => {
+                return error(instr.pc, "load_env is not supported".into());
             }
 
-
-            JUMP => {
-                let target = instr.u16();
-                println!("  jump {}", target);
+            // the shape's names & the values are zipped.
+            opcode::TUPLE_NEW_NAMED => {
+                let len = instr.operands.iter().filter(|o| o.slot == Slot::List).count();
+                let shape = instr.operands.iter().find(|o| o.kind == OperandKind::Shape).unwrap().value;
+                if len == 0 || len != tuple_shapes[shape as usize].len() {
+                    return error(instr.pc, format!("tuple_new_named: {} values for shape s{}", len, shape));
+                }
             }
 
-            JUMP_TRUE => {
-                let (src, target) = instr.c1u16();
-                println!("  jump_true r{}, {}", src, target);
-            }
+            _ => (),
+        }
+    }
 
-            JUMP_FALSE => {
-                let (src, target) = instr.c1u16();
-                println!("  jump_false r{}, {}", src, target);
-            }
+    Ok(())
+}
 
-            JUMP_NIL => {
-                let (src, target) = instr.c1u16();
-                println!("  jump_nil r{}, {}", src, target);
-            }
+#[cfg(test)]
+mod tests {
+    use crate::bytecode::*;
 
-            JUMP_NOT_NIL => {
-                let (src, target) = instr.c1u16();
-                println!("  jump_not_nil r{}, {}", src, target);
+    #[test]
+    fn opcode_table_roundtrip() {
+        for op in 1..opcode::END {
+            if op == opcode::WIDE {
+                continue;
             }
 
-
-            CALL => {
-                let (dst, func) = instr.c2();
-
-                let num_args = next_instr_extra!();
-                let num_args = num_args.u16();
-
-                print!("  call r{}, r{}, [", dst, func);
-
-                for i in 0..num_args {
-                    let arg = next_instr_extra!();
-                    print!("r{}", arg.u16());
-                    if i < num_args - 1 {
-                        print!(", ");
-                    }
+            // large values need a `wide` prefix.
+            let table = opcode::operands(op);
+            let mut operands = vec![];
+            for (i, operand) in table.iter().enumerate() {
+                let value = match operand.kind {
+                    OperandKind::Count => continue,
+                    OperandKind::Bool  => 1,
+                    OperandKind::Int   => -5i16 as u16 as u32,
+                    OperandKind::Base  => 300,
+                    OperandKind::Key   => PathKey::Index { reg: 7 }.encode(),
+                    _ if operand.slot == Slot::U16 => 70000 + i as u32,
+                    _ => 300 + i as u32,
+                };
+                if operand.slot != Slot::List {
+                    operands.push(value);
                 }
-
-                println!("]");
             }
-
-            RET => {
-                let src = instr.c1();
-                println!("  ret r{}", src);
+            let has_list = table.iter().any(|o| o.slot == Slot::List);
+            let list: &[u32] = if has_list { &[PathKey::Field { string: 3 }.encode(), 9] } else { &[] };
+
+            let mut b = ByteCodeBuilder::new();
+            b.emit(op, &operands, list.iter().copied());
+            b.nop();
+            let code = b.build();
+
+            let instrs = ByteCodeDecoder::decode_raw(&code).unwrap();
+            assert_eq!(instrs.len(), 2);
+            assert_eq!(instrs[0].opcode, op);
+            assert_eq!(instrs[1].opcode, opcode::NOP);
+
+            let mut decoded = instrs[0].operands.iter().filter(|o| o.kind != OperandKind::Count && o.slot != Slot::List);
+            for value in &operands {
+                assert_eq!(decoded.next().unwrap().value, *value, "{}", opcode::name(op));
             }
+            let items = instrs[0].operands.iter().filter(|o| o.slot == Slot::List).map(|o| o.value).collect::<Vec<_>>();
+            assert_eq!(items, list);
 
-            // @todo-speed: this inserts a check to reduce dispatch table size.
-            //  may want an unreachable_unchecked() in release.
-            0 | END ..= 255 => unreachable!()
+            // the structured decoder & the builder methods agree with the table.
+            let mut b = ByteCodeBuilder::new();
+            for instr in ByteCodeDecoder::decode(&code).unwrap() {
+                instr.encode(&mut b);
+            }
+            let encoded = b.build();
+            assert_eq!(encoded.iter().map(|w| w.0).collect::<Vec<_>>(),
+                       code.iter().map(|w| w.0).collect::<Vec<_>>(),
+                       "{}", opcode::name(op));
         }
     }
 }
-
-
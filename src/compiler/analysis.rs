@@ -1,6 +1,7 @@
 use derive_more::{Deref, Display};
 use crate::index_vec::*;
 use crate::macros::define_id;
+use crate::ValueKind;
 use super::*;
 
 
@@ -39,6 +40,57 @@ pub struct DominanceFrontiers {
 }
 
 
+// ### loops ###
+
+define_id!(LoopId, OptLoopId, "loop{}");
+
+// natural loop.
+#[derive(Debug)]
+pub struct Loop {
+    pub header:   BlockId,
+    pub parent:   OptLoopId,
+    pub children: Vec<LoopId>,
+    // includes the header & the blocks of nested loops.
+    pub blocks:   Vec<BlockId>,
+    // sources of the back edges to the header.
+    pub latches:  Vec<BlockId>,
+    // (inside, outside) edges.
+    pub exits:    Vec<(BlockId, BlockId)>,
+}
+
+// loop nesting forest.
+// only reducible loops: a back edge must target a block that dominates its source.
+pub struct LoopForest {
+    pub loops: IndexVec<LoopId, Loop>,
+    pub roots: Vec<LoopId>,
+    // innermost loop.
+    pub block_loops: IndexVec<BlockId, OptLoopId>,
+}
+
+
+// ### value kinds ###
+
+// the runtime kind of each instruction's value, if it's always the same.
+// unlike the types from `infer`, these are proven.
+#[derive(Deref)]
+pub struct ValueKinds {
+    pub kinds: IndexVec<InstrId, Option<ValueKind>>,
+}
+
+
+// ### interference ###
+
+// ssa values interfere, if one is live at the definition of the other.
+// (which then dominates the other's definition)
+pub struct Interference<'a> {
+    fun:   &'a Function,
+    idoms: &'a ImmediateDominators,
+    live:  BlockLiveInOut,
+    // position in the block.
+    positions: IndexVec<InstrId, u32>,
+}
+
+
 // ### block order ###
 
 #[derive(Deref)]
@@ -130,6 +182,48 @@ impl ImmediateDominators {
     }
 }
 
+impl LoopForest {
+    #[inline(always)]
+    pub fn num_loops(&self) -> usize { self.loops.len() }
+
+    #[inline(always)]
+    pub fn innermost(&self, bb: BlockId) -> Option<LoopId> {
+        self.block_loops[bb].to_option()
+    }
+
+    pub fn contains(&self, lp: LoopId, bb: BlockId) -> bool {
+        let mut at = self.block_loops[bb];
+        while let Some(current) = at.to_option() {
+            if current == lp {
+                return true;
+            }
+            at = self.loops[current].parent;
+        }
+        false
+    }
+
+    pub fn depth(&self, bb: BlockId) -> usize {
+        let mut result = 0;
+        let mut at = self.block_loops[bb];
+        while let Some(current) = at.to_option() {
+            result += 1;
+            at = self.loops[current].parent;
+        }
+        result
+    }
+
+    // parents before children.
+    pub fn pre_order(&self) -> Vec<LoopId> {
+        let mut result = Vec::with_capacity(self.loops.len());
+        let mut stack: Vec<LoopId> = self.roots.iter().rev().copied().collect();
+        while let Some(lp) = stack.pop() {
+            result.push(lp);
+            stack.extend(self.loops[lp].children.iter().rev().copied());
+        }
+        result
+    }
+}
+
 
 impl Function {
     pub fn predecessors(&self) -> Predecessors {
@@ -189,9 +283,15 @@ impl Function {
                 let preds = &preds[bb_id];
                 let bb = post_indices.get_unck(bb_id);
 
-                let mut new_dom = post_indices.get_unck(preds[0]);
+                // start from the first processed pred.
+                // block ids don't need to be in reverse post order,
+                // eg after licm guarded a loop.
+                let Some(first) = preds.iter().position(|pred|
+                    post_indices.get(*pred).is_some_and(|pred| doms[pred].is_some()))
+                else { continue };
+                let mut new_dom = post_indices.get_unck(preds[first]);
 
-                for pred_id in preds.iter().skip(1).copied() {
+                for pred_id in preds.iter().skip(first + 1).copied() {
                     let Some(pred) = post_indices.get(pred_id) else { continue };
 
                     // intersect.
@@ -270,6 +370,213 @@ impl Function {
     }
 
 
+    pub fn loop_forest(&self, preds: &Predecessors, post_order: &PostOrder, idoms: &ImmediateDominators) -> LoopForest {
+        let mut loops: IndexVec<LoopId, Loop> = index_vec![];
+        let mut block_loops = index_vec![OptLoopId::NONE; self.num_blocks()];
+
+        // inner headers come before outer headers in post order.
+        for header in post_order.iter().copied() {
+            let latches: Vec<BlockId> = preds[header].iter().copied()
+                .filter(|pred| !idoms.is_unreachable(*pred) && idoms.is_dominated_by(*pred, header))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+
+            let lp = LoopId::from_usize(loops.len());
+            loops.push(Loop {
+                header,
+                parent:   None.into(),
+                children: vec![],
+                blocks:   vec![],
+                latches:  latches.clone(),
+                exits:    vec![],
+            });
+            block_loops[header] = lp.some();
+
+            // walk backwards from the latches.
+            let mut stack = latches;
+            while let Some(bb) = stack.pop() {
+                if bb == header || idoms.is_unreachable(bb) {
+                    continue;
+                }
+
+                if let Some(mut inner) = block_loops[bb].to_option() {
+                    // continue at the header of the outermost nested loop.
+                    while let Some(parent) = loops[inner].parent.to_option() {
+                        inner = parent;
+                    }
+                    if inner == lp {
+                        continue;
+                    }
+
+                    loops[inner].parent = lp.some();
+                    loops[lp].children.push(inner);
+                    stack.extend(preds[loops[inner].header].iter().copied());
+                }
+                else {
+                    block_loops[bb] = lp.some();
+                    stack.extend(preds[bb].iter().copied());
+                }
+            }
+        }
+
+        // blocks.
+        for bb in self.block_ids() {
+            let mut at = block_loops[bb];
+            while let Some(lp) = at.to_option() {
+                loops[lp].blocks.push(bb);
+                at = loops[lp].parent;
+            }
+        }
+
+        let mut forest = LoopForest {
+            roots: loops.iter().enumerate()
+                .filter(|(_, lp)| lp.parent.is_none())
+                .map(|(i, _)| LoopId::from_usize(i)).collect(),
+            loops,
+            block_loops,
+        };
+
+        // exits.
+        for lp in 0..forest.loops.len() {
+            let lp = LoopId::from_usize(lp);
+
+            let mut exits = vec![];
+            for bb in forest.loops[lp].blocks.iter().copied() {
+                self.block_successors(bb, |succ| {
+                    if !forest.contains(lp, succ) {
+                        exits.push((bb, succ));
+                    }
+                });
+            }
+            forest.loops[lp].exits = exits;
+        }
+
+        forest
+    }
+
+
+    // optimistic: values in loops start out unknown.
+    // operations that fail for other kinds (arithmetic, not)
+    // have a known kind, if they produce a value at all.
+    pub fn value_kinds(&self, post_order: &PostOrder) -> ValueKinds {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Lattice {
+            Undef,
+            Kind(ValueKind),
+            Varying,
+        }
+
+        impl Lattice {
+            fn meet(self, other: Lattice) -> Lattice {
+                match (self, other) {
+                    (Lattice::Undef, x) | (x, Lattice::Undef) => x,
+                    (Lattice::Kind(a), Lattice::Kind(b)) if a == b => self,
+                    _ => Lattice::Varying,
+                }
+            }
+        }
+
+        let mut values = index_vec![Lattice::Undef; self.num_instrs()];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for bb in post_order.iter().rev().copied() {
+                self.block_instrs(bb, |instr| {
+                    use InstrData::*;
+                    let value = match instr.data {
+                        Copy { src } |
+                        ParallelCopy { src, copy_id: _ } => values[src],
+
+                        Phi { map_id } => {
+                            map_id.get(self).iter()
+                            .fold(Lattice::Undef, |acc, (_, src)| acc.meet(values[*src]))
+                        }
+
+                        LoadNil                 => Lattice::Kind(ValueKind::Nil),
+                        LoadBool   { value: _ } => Lattice::Kind(ValueKind::Bool),
+                        LoadInt    { value: _ } |
+                        LoadFloat  { value: _ } => Lattice::Kind(ValueKind::Number),
+                        LoadString { id: _ }    => Lattice::Kind(ValueKind::String),
+
+                        ListNew { values: _ }   => Lattice::Kind(ValueKind::List),
+                        TupleNew { values: _ }  |
+                        TupleNewNamed { shape: _, values: _ } => Lattice::Kind(ValueKind::Tuple),
+                        TupleNew0               => Lattice::Kind(ValueKind::Unit),
+
+                        Op1 { op, src: _ } => {
+                            use self::Op1::*;
+                            match op {
+                                Not    => Lattice::Kind(ValueKind::Bool),
+                                Negate => Lattice::Kind(ValueKind::Number),
+                            }
+                        }
+
+                        Op2 { op, src1: _, src2: _ } => {
+                            use self::Op2::*;
+                            match op {
+                                Add | Sub | Mul | Div | FloorDiv | Rem => Lattice::Kind(ValueKind::Number),
+                                CmpEq | CmpNe | CmpLe | CmpLt | CmpGe | CmpGt => Lattice::Kind(ValueKind::Bool),
+                                And | Or | OrElse => Lattice::Varying,
+                            }
+                        }
+
+                        Implements { src: _, traitt: _ } => Lattice::Kind(ValueKind::Bool),
+
+                        Param { id: _ } |
+                        Local { id: _ } |
+                        GetLocal { src: _ } |
+                        SetLocal { dst: _, src: _ } |
+                        LoadEnv |
+                        ReadPath { path_id: _ } |
+                        WritePath { path_id: _, value: _, is_def: _ } |
+                        DeletePath { path_id: _ } |
+                        Call { func: _, args_id: _ } |
+                        LoadMethod { traitt: _, recv: _, method: _ } |
+                        Jump { target: _ } |
+                        SwitchBool { src: _, on_true: _, on_false: _ } |
+                        SwitchNil { src: _, on_nil: _, on_non_nil: _ } |
+                        Return { src: _ } => Lattice::Varying,
+                    };
+
+                    if value != values[instr.id()] {
+                        values[instr.id()] = value;
+                        changed = true;
+                    }
+                });
+            }
+        }
+
+        let kinds = values.iter().map(|value| match value {
+            Lattice::Kind(kind) => Some(*kind),
+            Lattice::Undef | Lattice::Varying => None,
+        }).collect();
+
+        ValueKinds { kinds }
+    }
+
+
+    // requires ssa.
+    pub fn interference<'a>(&'a self, post_order: &PostOrder, idoms: &'a ImmediateDominators) -> Interference<'a> {
+        let gen_kill = self.block_gen_kill();
+        let live = self.block_live_in_out(post_order, &gen_kill);
+
+        let mut positions = index_vec![0; self.num_instrs()];
+        for bb in self.block_ids() {
+            let mut position = 0;
+            self.block_instrs(bb, |instr| {
+                positions[instr.id()] = position;
+                position += 1;
+            });
+        }
+
+        Interference { fun: self, idoms, live, positions }
+    }
+
+
     pub fn block_order_dominators_first(&self, idoms: &ImmediateDominators, dom_tree: &DomTree) -> BlockOrder {
         fn visit(bb: BlockId, order: &mut Vec<BlockId>, visited: &mut IndexVec<BlockId, bool>,
             fun: &Function, idom: &ImmediateDominators, dom_tree: &DomTree,
@@ -509,6 +816,60 @@ impl Function {
 }
 
 
+impl<'a> Interference<'a> {
+    pub fn interfere(&self, a: InstrId, b: InstrId) -> bool {
+        if a == b {
+            return false;
+        }
+
+        let a_instr = a.get(self.fun);
+        let b_instr = b.get(self.fun);
+        let a_bb = a_instr.bb().unwrap();
+        let b_bb = b_instr.bb().unwrap();
+
+        // phis of a block are defined at the same time.
+        if a_bb == b_bb && a_instr.is_phi() && b_instr.is_phi() {
+            return true;
+        }
+
+        let (def, other) =
+            if self.dominates(a, a_bb, b, b_bb)      { (a, b) }
+            else if self.dominates(b, b_bb, a, a_bb) { (b, a) }
+            else { return false };
+
+        self.live_after(def, other)
+    }
+
+    fn dominates(&self, a: InstrId, a_bb: BlockId, b: InstrId, b_bb: BlockId) -> bool {
+        if a_bb == b_bb { self.positions[a] < self.positions[b] }
+        else            { self.idoms.is_dominated_by(b_bb, a_bb) }
+    }
+
+    // whether `value` is live right after `def`.
+    fn live_after(&self, value: InstrId, def: InstrId) -> bool {
+        let def_instr = def.get(self.fun);
+        let bb = def_instr.bb().unwrap();
+
+        if def_instr.is_phi() {
+            return self.live.live_ins[bb][value];
+        }
+
+        if self.live.live_outs[bb][value] {
+            return true;
+        }
+
+        let mut used = false;
+        let mut at = def_instr.next();
+        while let Some(instr) = at.to_option() {
+            let instr = instr.get(self.fun);
+            instr.args(self.fun, |arg| used |= arg == value);
+            at = instr.next();
+        }
+        used
+    }
+}
+
+
 impl BlockOrder {
     pub fn block_begins_and_instr_indices(&self, fun: &Function) -> (BlockBegins, InstrIndices) {
         let mut block_begins  = index_vec![InstrIndex::NONE; fun.num_blocks()];
@@ -541,3 +902,81 @@ impl crate::index_vec::Key for InstrIndex {
 }
 
 
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn loop_forest() {
+        // bb1: outer loop, bb3: inner self loop, bb5: sibling self loop.
+        let mut krate = Crate::new();
+        let mut fun = krate.new_function();
+        bbir_text::parse_function(&mut fun, "
+            local l0 \"c\"
+            bb0:
+              i0 := param l0
+              i1    jump bb1
+            bb1:
+              i2    switch_bool i0, bb2, bb5
+            bb2:
+              i3    jump bb3
+            bb3:
+              i4    switch_bool i0, bb3, bb4
+            bb4:
+              i5    jump bb1
+            bb5:
+              i6    switch_bool i0, bb5, bb6
+            bb6:
+              i7    return i0
+        ").unwrap();
+
+        let preds      = fun.predecessors();
+        let post_order = fun.post_order();
+        let indices    = fun.post_order_indices(&post_order);
+        let idoms      = fun.immediate_dominators(&preds, &post_order, &indices);
+        let forest     = fun.loop_forest(&preds, &post_order, &idoms);
+
+        let bb = BlockId::from_usize;
+        assert_eq!(forest.num_loops(), 3);
+        let outer   = forest.innermost(bb(1)).unwrap();
+        let inner   = forest.innermost(bb(3)).unwrap();
+        let sibling = forest.innermost(bb(5)).unwrap();
+
+        let mut roots = forest.roots.clone();
+        roots.sort();
+        let mut expected = vec![outer, sibling];
+        expected.sort();
+        assert_eq!(roots, expected);
+
+        let lp = &forest.loops[outer];
+        assert_eq!(lp.children, [inner]);
+        let mut blocks = lp.blocks.clone();
+        blocks.sort();
+        assert_eq!(blocks, [bb(1), bb(2), bb(3), bb(4)]);
+        assert_eq!(lp.latches, [bb(4)]);
+        assert_eq!(lp.exits, [(bb(1), bb(5))]);
+
+        let lp = &forest.loops[inner];
+        assert_eq!(lp.parent.to_option(), Some(outer));
+        assert_eq!(lp.blocks, [bb(3)]);
+        assert_eq!(lp.latches, [bb(3)]);
+        assert_eq!(lp.exits, [(bb(3), bb(4))]);
+
+        assert!(forest.loops[sibling].parent.is_none());
+
+        assert_eq!(forest.innermost(bb(2)), Some(outer));
+        assert_eq!(forest.innermost(bb(6)), None);
+        assert_eq!(forest.depth(bb(3)), 2);
+        assert_eq!(forest.depth(bb(4)), 1);
+        assert_eq!(forest.depth(bb(0)), 0);
+        assert!(forest.contains(outer, bb(3)));
+        assert!(!forest.contains(inner, bb(2)));
+
+        let order = forest.pre_order();
+        assert_eq!(order.len(), 3);
+        let pos = |lp| order.iter().position(|at| *at == lp).unwrap();
+        assert!(pos(outer) < pos(inner));
+    }
+}
@@ -94,6 +94,16 @@ impl Op1 {
             Negate => { "negate" }
         }
     }
+
+    // inverse of `str`.
+    pub fn parse(name: &str) -> Option<Op1> {
+        use self::Op1::*;
+        Some(match name {
+            "not"    => Not,
+            "negate" => Negate,
+            _ => return None,
+        })
+    }
 }
 
 
@@ -120,6 +130,29 @@ impl Op2 {
         }
     }
 
+    // inverse of `str`.
+    pub fn parse(name: &str) -> Option<Op2> {
+        use Op2::*;
+        Some(match name {
+            "add"       => Add,
+            "sub"       => Sub,
+            "mul"       => Mul,
+            "div"       => Div,
+            "floor_div" => FloorDiv,
+            "rem"       => Rem,
+            "and"       => And,
+            "or"        => Or,
+            "or_else"   => OrElse,
+            "cmp_eq"    => CmpEq,
+            "cmp_ne"    => CmpNe,
+            "cmp_le"    => CmpLe,
+            "cmp_lt"    => CmpLt,
+            "cmp_ge"    => CmpGe,
+            "cmp_gt"    => CmpGt,
+            _ => return None,
+        })
+    }
+
     #[inline]
     pub fn is_cancelling(self) -> bool {
         use Op2::*;
@@ -130,6 +163,17 @@ impl Op2 {
             CmpEq | CmpNe | CmpLe | CmpLt | CmpGe | CmpGt => false,
         }
     }
+
+    #[inline]
+    pub fn is_comparison(self) -> bool {
+        use Op2::*;
+        match self {
+            CmpEq | CmpNe | CmpLe | CmpLt | CmpGe | CmpGt => true,
+
+            Add | Sub | Mul | Div | FloorDiv | Rem |
+            And | Or | OrElse => false,
+        }
+    }
 }
 
 
@@ -174,6 +218,8 @@ pub struct Item<'a> {
 pub enum ItemData<'a> {
     Module          (item::Module<'a>),
     Func            (item::Func<'a>),
+    Trait           (item::Trait<'a>),
+    Impl            (item::Impl<'a>),
 }
 
 impl<'a> Item<'a> {
@@ -197,6 +243,7 @@ pub struct Stmt<'a> {
 pub enum StmtData<'a> {
     Item            (Item<'a>),
     Local           (expr::Local<'a>),
+    Delete          (expr::Delete<'a>),
     Expr            (Expr<'a>),
     Empty,
 }
@@ -241,6 +288,7 @@ pub enum ExprData<'a> {
     Continue        (Box<expr::Continue<'a>>),
     Return          (expr::Return<'a>),
     Env,
+    Implements      (Box<expr::Implements<'a>>),
 }
 
 impl<'a> Expr<'a> {
@@ -269,7 +317,38 @@ pub mod item {
 
     #[derive(Clone, Copy, Debug)]
     pub struct FuncParam<'a> {
-        pub name: &'a str,
+        pub source: SourceRange,
+        pub name:   &'a str,
+    }
+
+
+    #[derive(Clone, Debug)]
+    pub struct Trait<'a> {
+        pub name:    &'a str,
+        pub methods: Vec<TraitMethod<'a>>,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct TraitMethod<'a> {
+        pub name:   &'a str,
+        pub params: Vec<FuncParam<'a>>,
+    }
+
+
+    #[derive(Clone, Debug)]
+    pub struct Impl<'a> {
+        pub traitt:  &'a str,
+        pub ty:      &'a str,
+        pub methods: Vec<Stmt<'a>>, // func items.
+        pub info:    Option<ImplInfo>,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct ImplInfo {
+        pub traitt:  ItemId,
+        pub kind:    crate::ValueKind,
+        // in the order of the trait's methods.
+        pub methods: Vec<ItemId>,
     }
 
 
@@ -328,10 +407,17 @@ pub mod expr {
     }
 
 
+    #[derive(Clone, Debug)]
+    pub struct Delete<'a> {
+        pub target: Expr<'a>, // a `Field` or `Index`.
+    }
+
+
 
     #[derive(Clone, Debug)]
     pub struct Tuple<'a> {
         pub values: Vec<Expr<'a>>,
+        pub names:  Vec<&'a str>, // empty for positional tuples.
     }
 
     #[derive(Clone, Debug)]
@@ -437,6 +523,13 @@ pub mod expr {
     pub struct Field<'a> {
         pub base: Expr<'a>,
         pub name: &'a str,
+        pub info: Option<FieldInfo>,
+    }
+
+    // set by infer, if `base` is known to be a named tuple.
+    #[derive(Clone, Copy, Debug)]
+    pub struct FieldInfo {
+        pub index: u32,
     }
 
     #[derive(Clone, Debug)]
@@ -449,6 +542,28 @@ pub mod expr {
     pub struct Call<'a> {
         pub func: Expr<'a>,
         pub args: Vec<Expr<'a>>,
+        pub info: Option<CallInfo>,
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    pub enum CallTarget {
+        Value,
+        // `Trait.method(recv, ..)`, where the impl is known.
+        StaticMethod  { func: ItemId },
+        // `Trait.method(recv, ..)`, dispatched on `recv` at runtime.
+        DynamicMethod { traitt: ItemId, method: u32 },
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    pub struct CallInfo {
+        pub target: CallTarget,
+    }
+
+
+    #[derive(Clone, Debug)]
+    pub struct Implements<'a> {
+        pub value:  Expr<'a>,
+        pub traitt: Expr<'a>,
     }
 
 
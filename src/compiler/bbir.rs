@@ -3,7 +3,8 @@ use derive_more::{Deref, DerefMut, Display};
 use crate::index_vec::*;
 use crate::macros::define_id;
 use super::{ItemId, NodeId, OptNodeId, Op1, Op2};
-use super::{opt, transform};
+use super::opt;
+use super::pass::{PassManager, OptLevel, Analyses, Analysis};
 
 
 
@@ -58,15 +59,20 @@ pub enum InstrData {
 
     TupleNew { values: InstrListId },
     TupleNew0,
+    TupleNewNamed { shape: TupleShapeId, values: InstrListId },
 
     ReadPath { path_id: PathId },
     WritePath { path_id: PathId, value: InstrId, is_def: bool },
+    DeletePath { path_id: PathId },
 
     Call { func: InstrId, args_id: InstrListId },
 
     Op1         { op: Op1, src: InstrId },
     Op2         { op: Op2, src1: InstrId, src2: InstrId },
 
+    LoadMethod  { traitt: InstrId, recv: InstrId, method: u32 },
+    Implements  { src: InstrId, traitt: InstrId },
+
     Jump        { target: BlockId },
     SwitchBool  { src: InstrId, on_true: BlockId, on_false: BlockId },
     SwitchNil   { src: InstrId, on_nil: BlockId, on_non_nil: BlockId },
@@ -111,7 +117,7 @@ pub enum PathBase {
     Instr(InstrId),
 }
 
-#[derive(Clone, Copy, Debug, Display)]
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq, Hash)]
 pub enum PathKey {
     Field(StringId),
     Index(InstrId),
@@ -174,6 +180,7 @@ pub struct Function {
     blocks:         IndexVec<BlockId,       Block>,
     locals:         IndexVec<LocalId,       Local>,
     strings:        IndexVec<StringId,      String>,
+    tuple_shapes:   IndexVec<TupleShapeId,  Vec<StringId>>,
 
     last_parallel_copy_id: u32,
 
@@ -186,6 +193,7 @@ pub struct Function {
 }
 
 define_id!(StringId, "str{}");
+define_id!(TupleShapeId, "shape{}");
 
 
 
@@ -194,6 +202,10 @@ define_id!(StringId, "str{}");
 pub struct Crate {
     functions: IndexVec<FunctionId, &'static RefCell<Function>>,
     items:     IndexVec<ItemId, Item>,
+    // the value kinds `infer` expects, by node.
+    // only hints, the codegen can't rely on them.
+    node_kinds: Vec<Option<crate::ValueKind>>,
+    reg_alloc:  super::codegen::RegAllocKind,
 }
 
 #[derive(Clone, Debug)]
@@ -205,6 +217,22 @@ pub struct Item {
 pub enum ItemData {
     None,
     Func    (FunctionId),
+    Trait   (Trait),
+    Impl    (Impl),
+}
+
+#[derive(Clone, Debug)]
+pub struct Trait {
+    pub name:    String,
+    pub methods: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Impl {
+    pub traitt:  ItemId,
+    pub kind:    crate::ValueKind,
+    // in the order of the trait's methods.
+    pub methods: Vec<ItemId>,
 }
 
 
@@ -276,6 +304,7 @@ impl<'a> core::fmt::Display for InstrFmt<'a> {
 
             TupleNew { values } => write!(f, "tuple_new {}", values.get(fun)),
             TupleNew0 => write!(f, "tuple_new []"),
+            TupleNewNamed { shape, values } => write!(f, "tuple_new_named {:?} {}", shape.get(fun), values.get(fun)),
 
             ReadPath { path_id } => write!(f, "read_path {}", path_id.get(fun)),
 
@@ -285,11 +314,16 @@ impl<'a> core::fmt::Display for InstrFmt<'a> {
                 write!(f, " {} {}", path_id.get(fun), value)
             }
 
+            DeletePath { path_id } => write!(f, "delete_path {}", path_id.get(fun)),
+
             Call { func, args_id } => write!(f, "call {}, {}", func, args_id.get(fun)),
 
             Op1 { op, src }        => { write!(f, "{} {}",     op.str(), src) }
             Op2 { op, src1, src2 } => { write!(f, "{} {}, {}", op.str(), src1, src2) }
 
+            LoadMethod { traitt, recv, method } => { write!(f, "load_method {}, {}, {}", traitt, recv, method) }
+            Implements { src, traitt }          => { write!(f, "implements {}, {}", src, traitt) }
+
             Jump       { target }                  => { write!(f, "jump {}", target) }
             SwitchBool { src, on_true, on_false }  => { write!(f, "switch_bool {}, {}, {}", src, on_true, on_false) }
             SwitchNil  { src, on_nil, on_non_nil } => { write!(f, "switch_nil {}, {}, {}", src, on_nil, on_non_nil) }
@@ -359,11 +393,15 @@ impl InstrData {
             ListNew { values: _ } |
             TupleNew { values: _ } |
             TupleNew0 |
+            TupleNewNamed { shape: _, values: _ } |
             ReadPath { path_id: _ } |
             WritePath { path_id: _, value: _, is_def: _ } |
+            DeletePath { path_id: _ } |
             Call { func: _, args_id: _ } |
             Op1 { op: _, src: _ } |
-            Op2 { op: _, src1: _, src2: _ } => false,
+            Op2 { op: _, src1: _, src2: _ } |
+            LoadMethod { traitt: _, recv: _, method: _ } |
+            Implements { src: _, traitt: _ } => false,
         }
     }
 
@@ -386,11 +424,15 @@ impl InstrData {
             ListNew { values: _ } |
             TupleNew { values: _ } |
             TupleNew0 |
+            TupleNewNamed { shape: _, values: _ } |
             ReadPath { path_id: _ } |
             WritePath { path_id: _, value: _, is_def: _ } |
+            DeletePath { path_id: _ } |
             Call { func: _, args_id: _ } |
             Op1 { op: _, src: _ } |
-            Op2 { op: _, src1: _, src2: _ } => true,
+            Op2 { op: _, src1: _, src2: _ } |
+            LoadMethod { traitt: _, recv: _, method: _ } |
+            Implements { src: _, traitt: _ } => true,
 
             SetLocal { dst: _, src: _ } |
             Jump { target: _ } |
@@ -400,6 +442,49 @@ impl InstrData {
         }
     }
 
+    // no side effects & can't raise errors, so unused pure instructions can be removed.
+    // the ops are pure for some operands, see `opt::dead_code_elim`.
+    #[inline(always)]
+    pub fn is_pure(&self) -> bool {
+        use InstrData::*;
+        match self {
+            Copy { src: _ } |
+            Phi { map_id: _ } |
+            ParallelCopy { src: _, copy_id: _ } |
+            Local { id: _ } |
+            GetLocal { src: _ } |
+            LoadNil |
+            LoadBool { value: _ } |
+            LoadInt { value: _ } |
+            LoadFloat { value: _ } |
+            LoadString { id: _ } |
+            LoadEnv |
+            ListNew { values: _ } |
+            TupleNew { values: _ } |
+            TupleNew0 |
+            TupleNewNamed { shape: _, values: _ } |
+            Op2 { op: super::Op2::CmpEq | super::Op2::CmpNe, src1: _, src2: _ } => true,
+
+            // codegen assigns the param registers.
+            Param { id: _ } |
+            SetLocal { dst: _, src: _ } |
+            // missing keys.
+            ReadPath { path_id: _ } |
+            WritePath { path_id: _, value: _, is_def: _ } |
+            DeletePath { path_id: _ } |
+            Call { func: _, args_id: _ } |
+            // type errors.
+            Op1 { op: _, src: _ } |
+            Op2 { op: _, src1: _, src2: _ } |
+            LoadMethod { traitt: _, recv: _, method: _ } |
+            Implements { src: _, traitt: _ } |
+            Jump { target: _ } |
+            SwitchBool { src: _, on_true: _, on_false: _ } |
+            SwitchNil  { src: _, on_nil: _, on_non_nil: _ } |
+            Return { src: _ } => false,
+        }
+    }
+
 
     pub fn args<F: FnMut(InstrId)>(&self, fun: &Function, mut f: F) {
         use InstrData::*;
@@ -427,15 +512,20 @@ impl InstrData {
 
             TupleNew { values } => { values.each(fun, f) }
             TupleNew0 => (),
+            TupleNewNamed { shape: _, values } => { values.each(fun, f) }
 
             ReadPath { path_id } => { path_id.each_instr(fun, f) }
             WritePath { path_id, value, is_def: _ } => { path_id.each_instr(fun, &mut f); f(*value) }
+            DeletePath { path_id } => { path_id.each_instr(fun, f) }
 
             Call { func, args_id } => { f(*func); args_id.each(fun, f) }
 
             Op1 { op: _, src }        => { f(*src) }
             Op2 { op: _, src1, src2 } => { f(*src1); f(*src2) }
 
+            LoadMethod { traitt, recv, method: _ } => { f(*traitt); f(*recv) }
+            Implements { src, traitt }             => { f(*src); f(*traitt) }
+
             Jump       { target: _ } => (),
             SwitchBool { src, on_true: _, on_false: _ }  => { f(*src) }
             SwitchNil  { src, on_nil: _, on_non_nil: _ } => { f(*src) }
@@ -476,15 +566,20 @@ impl InstrData {
 
             TupleNew { values } => { values.each_mut(fun, f) }
             TupleNew0 => (),
+            TupleNewNamed { shape: _, values } => { values.each_mut(fun, f) }
 
             ReadPath { path_id } => { path_id.each_instr_mut(fun, f) }
             WritePath { path_id, value, is_def: _ } => { path_id.each_instr_mut(fun, &mut f); f(fun, value) }
+            DeletePath { path_id } => { path_id.each_instr_mut(fun, f) }
 
             Call { func, args_id } => { f(fun, func); args_id.each_mut(fun, f) }
 
             Op1 { op: _, src }        => { f(fun, src) }
             Op2 { op: _, src1, src2 } => { f(fun, src1); f(fun, src2) }
 
+            LoadMethod { traitt, recv, method: _ } => { f(fun, traitt); f(fun, recv) }
+            Implements { src, traitt }             => { f(fun, src); f(fun, traitt) }
+
             Jump       { target: _ } => (),
             SwitchBool { src, on_true: _, on_false: _ }  => { f(fun, src) }
             SwitchNil  { src, on_nil: _, on_non_nil: _ } => { f(fun, src) }
@@ -679,6 +774,7 @@ impl Function {
             instr_lists: index_vec![],
             locals:     index_vec![],
             strings:    index_vec![],
+            tuple_shapes: index_vec![],
             last_parallel_copy_id: 0,
             param_cursor: None.into(),
             num_params:   0,
@@ -708,6 +804,16 @@ impl Function {
     pub fn block_ids(&self) -> BlockIdIter { BlockIdIter { at: 0, end: self.blocks.len() as u32 } }
 
 
+    #[inline(always)]
+    pub fn num_phi_maps(&self) -> usize { self.phi_maps.len() }
+
+    #[inline(always)]
+    pub fn num_instr_lists(&self) -> usize { self.instr_lists.len() }
+
+    #[inline(always)]
+    pub fn num_paths(&self) -> usize { self.paths.len() }
+
+
     #[inline(always)]
     pub fn num_params(&self) -> usize { self.num_params as usize }
 
@@ -730,6 +836,23 @@ impl Function {
         }
     }
 
+    #[inline(always)]
+    pub fn num_tuple_shapes(&self) -> usize { self.tuple_shapes.len() }
+
+    pub fn add_tuple_shape(&mut self, names: &[&str]) -> TupleShapeId {
+        let names: Vec<StringId> = names.iter().map(|name| self.add_string(name)).collect();
+
+        let found = self.tuple_shapes.iter().position(|s| *s == names);
+        if let Some(index) = found {
+            TupleShapeId(index as u32)
+        }
+        else {
+            let id = self.tuple_shapes.len() as u32;
+            self.tuple_shapes.push(names);
+            TupleShapeId(id)
+        }
+    }
+
     #[inline]
     pub fn new_parallel_copy_id(&mut self) -> u32 {
         self.last_parallel_copy_id += 1;
@@ -761,8 +884,7 @@ impl Function {
 
 
     pub fn new_phi(&mut self, source: SourceInfoIn, map: &[PhiEntry]) -> InstrId {
-        let map_id = PhiMapId(self.phi_maps.len() as u32);
-        self.phi_maps.push(PhiMapImpl { map: map.into() });
+        let map_id = self.new_phi_map(map);
         self.new_instr(source, InstrData::Phi { map_id })
     }
 
@@ -782,8 +904,7 @@ impl Function {
 
 
     pub fn new_call(&mut self, source: SourceInfoIn, func: InstrId, args: &[InstrId]) -> InstrId {
-        let args_id = InstrListId(self.instr_lists.len() as u32);
-        self.instr_lists.push(InstrListImpl { values: args.into() });
+        let args_id = self.new_instr_list(args);
         self.new_instr(source, InstrData::Call { func, args_id })
     }
 
@@ -791,6 +912,25 @@ impl Function {
     // @todo: set_call
 
 
+    pub fn new_phi_map(&mut self, map: &[PhiEntry]) -> PhiMapId {
+        let id = PhiMapId(self.phi_maps.len() as u32);
+        self.phi_maps.push(PhiMapImpl { map: map.into() });
+        id
+    }
+
+    pub fn new_instr_list(&mut self, values: &[InstrId]) -> InstrListId {
+        let id = InstrListId(self.instr_lists.len() as u32);
+        self.instr_lists.push(InstrListImpl { values: values.into() });
+        id
+    }
+
+    pub fn new_path(&mut self, base: PathBase, keys: &[PathKey]) -> PathId {
+        let id = PathId(self.paths.len() as u32);
+        self.paths.push(PathImpl { base, keys: keys.into() });
+        id
+    }
+
+
     pub fn all_args<F: FnMut(InstrId)>(&self, mut f: F) {
         for block in &self.blocks {
             let mut at = block.first;
@@ -1053,6 +1193,16 @@ impl Function {
 
         id
     }
+
+    // only the local, without a `Param` or `Local` instruction.
+    pub fn new_local_id(&mut self, name: &str, source: NodeId) -> LocalId {
+        let id = LocalId(self.locals.len() as u32);
+        self.locals.push(Local { id, name: name.into(), source });
+        id
+    }
+
+    #[inline(always)]
+    pub fn local_name(&self, id: LocalId) -> &str { &self.locals[id].name }
 }
 
 
@@ -1073,6 +1223,44 @@ impl Function {
         self.current_block = bb;
     }
 
+    // for functions that weren't built with `new_param` & `new_local`.
+    // recomputes the param & local cursors from the entry block.
+    pub fn reset_builder_state(&mut self) {
+        self.num_params   = 0;
+        self.param_cursor = None.into();
+        self.local_cursor = None.into();
+
+        let mut at = self.blocks[BlockId::ENTRY].first;
+        while let Some(id) = at.to_option() {
+            let instr = &self.instrs[id];
+            match instr.data {
+                InstrData::Param { id: _ } => {
+                    assert!(self.local_cursor == self.param_cursor);
+                    self.num_params  += 1;
+                    self.param_cursor = id.some();
+                    self.local_cursor = id.some();
+                }
+                InstrData::Local { id: _ } => {
+                    self.local_cursor = id.some();
+                }
+                _ => break,
+            }
+            at = instr.next;
+        }
+
+        let mut last_parallel_copy_id = 0;
+        for bb in self.block_ids() {
+            self.block_instrs(bb, |instr| {
+                if let InstrData::ParallelCopy { src: _, copy_id } = instr.data {
+                    last_parallel_copy_id = last_parallel_copy_id.max(copy_id);
+                }
+            });
+        }
+        self.last_parallel_copy_id = last_parallel_copy_id;
+
+        self.current_block = BlockId::ENTRY;
+    }
+
 
     pub fn add_instr(&mut self, source: SourceInfoIn, data: InstrData) -> InstrId {
         assert!(self.instrs.len() < u32::MAX as usize / 2);
@@ -1120,8 +1308,7 @@ impl Function {
     }
 
     pub fn instr_phi(&mut self, source: SourceInfoIn, map: &[PhiEntry]) -> InstrId {
-        let map_id = PhiMapId(self.phi_maps.len() as u32);
-        self.phi_maps.push(PhiMapImpl { map: map.into() });
+        let map_id = self.new_phi_map(map);
         self.add_instr(source, InstrData::Phi { map_id })
     }
 
@@ -1167,8 +1354,7 @@ impl Function {
 
     #[inline]
     pub fn instr_list_new(&mut self, source: SourceInfoIn, values: &[InstrId]) -> InstrId {
-        let values_id = InstrListId(self.instr_lists.len() as u32);
-        self.instr_lists.push(InstrListImpl { values: values.into() });
+        let values_id = self.new_instr_list(values);
         self.add_instr(source, InstrData::ListNew { values: values_id })
     }
 
@@ -1177,12 +1363,17 @@ impl Function {
             self.add_instr(source, InstrData::TupleNew0)
         }
         else {
-            let values_id = InstrListId(self.instr_lists.len() as u32);
-            self.instr_lists.push(InstrListImpl { values: values.into() });
+            let values_id = self.new_instr_list(values);
             self.add_instr(source, InstrData::TupleNew { values: values_id })
         }
     }
 
+    pub fn instr_tuple_new_named(&mut self, source: SourceInfoIn, shape: TupleShapeId, values: &[InstrId]) -> InstrId {
+        assert!(!values.is_empty());
+        let values_id = self.new_instr_list(values);
+        self.add_instr(source, InstrData::TupleNewNamed { shape, values: values_id })
+    }
+
     #[inline]
     pub fn instr_load_unit(&mut self, source: SourceInfoIn) -> InstrId {
         self.add_instr(source, InstrData::TupleNew0)
@@ -1191,23 +1382,27 @@ impl Function {
     #[inline]
     pub fn instr_read_path(&mut self, source: SourceInfoIn, base: PathBase, keys: &[PathKey]) -> InstrId {
         assert!(keys.len() > 0);
-        let path_id = PathId(self.paths.len() as u32);
-        self.paths.push(PathImpl { base, keys: keys.into() });
+        let path_id = self.new_path(base, keys);
         self.add_instr(source, InstrData::ReadPath { path_id })
     }
 
     #[inline]
     pub fn instr_write_path(&mut self, source: SourceInfoIn, base: PathBase, keys: &[PathKey], value: InstrId, is_def: bool) -> InstrId {
         assert!(keys.len() > 0);
-        let path_id = PathId(self.paths.len() as u32);
-        self.paths.push(PathImpl { base, keys: keys.into() });
+        let path_id = self.new_path(base, keys);
         self.add_instr(source, InstrData::WritePath { path_id, value, is_def })
     }
 
+    #[inline]
+    pub fn instr_delete_path(&mut self, source: SourceInfoIn, base: PathBase, keys: &[PathKey]) -> InstrId {
+        assert!(!keys.is_empty());
+        let path_id = self.new_path(base, keys);
+        self.add_instr(source, InstrData::DeletePath { path_id })
+    }
+
     #[inline]
     pub fn instr_call(&mut self, source: SourceInfoIn, func: InstrId, args: &[InstrId]) -> InstrId {
-        let args_id = InstrListId(self.instr_lists.len() as u32);
-        self.instr_lists.push(InstrListImpl { values: args.into() });
+        let args_id = self.new_instr_list(args);
         self.add_instr(source, InstrData::Call { func, args_id })
     }
 
@@ -1221,6 +1416,16 @@ impl Function {
         self.add_instr(source, InstrData::Op2 { op, src1, src2 })
     }
 
+    #[inline]
+    pub fn instr_load_method(&mut self, source: SourceInfoIn, traitt: InstrId, recv: InstrId, method: u32) -> InstrId {
+        self.add_instr(source, InstrData::LoadMethod { traitt, recv, method })
+    }
+
+    #[inline]
+    pub fn instr_implements(&mut self, source: SourceInfoIn, src: InstrId, traitt: InstrId) -> InstrId {
+        self.add_instr(source, InstrData::Implements { src, traitt })
+    }
+
     #[inline]
     pub fn instr_jump(&mut self, source: OptNodeId, target: BlockId) -> InstrId {
         self.add_instr((source, None.into()), InstrData::Jump { target })
@@ -1390,143 +1595,340 @@ impl Function {
             self.retain_block_instr(BlockId(bb), &mut f);
         }
     }
+
+    // a copy of `data` with its own lists, paths & phi maps,
+    // so the copy's args can be replaced independently.
+    pub fn clone_instr_data(&mut self, data: InstrData) -> InstrData {
+        let list = |this: &mut Function, id: InstrListId| {
+            let values = this.instr_lists[id].to_vec();
+            this.new_instr_list(&values)
+        };
+        let path = |this: &mut Function, id: PathId| {
+            let PathImpl { base, keys } = this.paths[id].clone();
+            this.new_path(base, &keys)
+        };
+
+        use InstrData::*;
+        match data {
+            Phi { map_id } => {
+                let map = self.phi_maps[map_id].map.clone();
+                Phi { map_id: self.new_phi_map(&map) }
+            }
+
+            ListNew  { values } => ListNew  { values: list(self, values) },
+            TupleNew { values } => TupleNew { values: list(self, values) },
+            TupleNewNamed { shape, values } => TupleNewNamed { shape, values: list(self, values) },
+
+            ReadPath   { path_id } => ReadPath { path_id: path(self, path_id) },
+            WritePath  { path_id, value, is_def } => WritePath { path_id: path(self, path_id), value, is_def },
+            DeletePath { path_id } => DeletePath { path_id: path(self, path_id) },
+
+            Call { func, args_id } => Call { func, args_id: list(self, args_id) },
+
+            data => data,
+        }
+    }
 }
 
 
-// other
+// cfg
 impl Function {
-    pub fn slow_integrity_check(&self) {
-        let preds = self.predecessors();
-        let post_order = self.post_order();
-        let post_indices = self.post_order_indices(&post_order);
-        let idom = self.immediate_dominators(&preds, &post_order, &post_indices);
+    // retargets `bb`'s terminator.
+    // does not update phis.
+    pub fn replace_successor(&mut self, bb: BlockId, old: BlockId, new: BlockId) {
+        let terminator = self.block_terminator(bb).unwrap();
+        let retarget = |target: &mut BlockId| if *target == old { *target = new };
+
+        use InstrData::*;
+        match &mut self.instrs[terminator].data {
+            Jump { target } => retarget(target),
+            SwitchBool { src: _, on_true, on_false }  => { retarget(on_true); retarget(on_false) }
+            SwitchNil  { src: _, on_nil, on_non_nil } => { retarget(on_nil); retarget(on_non_nil) }
+            _ => unreachable!()
+        }
+    }
+
+    // for when the edge from `old` to `bb` now comes from `new`.
+    pub fn replace_phi_pred(&mut self, bb: BlockId, old: BlockId, new: BlockId) {
+        let mut phi = self.block_first_phi(bb);
+        while let Some(at) = phi.to_option() {
+            let InstrData::Phi { map_id } = self.instrs[at].data else { break };
+            for (from, _) in self.phi_maps[map_id].iter_mut() {
+                if *from == old { *from = new }
+            }
+            phi = self.instrs[at].next;
+        }
+    }
+
+    // critical edges go from a block with multiple successors
+    // to a block with multiple predecessors.
+    // there's no block, where code for just that edge could go.
+    pub fn has_critical_edges(&self, preds: &super::Predecessors) -> bool {
+        self.block_ids().any(|bb| {
+            let mut succs = vec![];
+            self.block_successors(bb, |succ| if !succs.contains(&succ) { succs.push(succ) });
+            succs.len() > 1 && succs.iter().any(|succ| preds[*succ].len() > 1)
+        })
+    }
 
-        let mut visited = index_vec![false; self.instrs.len()];
+    // inserts an empty block on each critical edge.
+    // returns whether any edges were split.
+    pub fn split_critical_edges(&mut self) -> bool {
+        let preds = self.predecessors();
 
-        // validate blocks.
+        let mut changed = false;
         for bb in self.block_ids() {
-            let block = &self.blocks[bb];
-            assert_eq!(block.id, bb);
+            let mut succs = vec![];
+            self.block_successors(bb, |succ| if !succs.contains(&succ) { succs.push(succ) });
+            if succs.len() < 2 {
+                continue;
+            }
 
-            let mut in_params = bb.is_entry();
-            let mut in_locals = false;
+            for succ in succs {
+                if preds[succ].len() < 2 {
+                    continue;
+                }
 
-            let mut in_phis = !bb.is_entry();
-            let mut has_terminator = false;
-            let mut instr_count = 0;
+                let new_bb = self.new_block();
+                self.replace_successor(bb, succ, new_bb);
+                self.replace_phi_pred(succ, bb, new_bb);
 
-            let mut at = block.first;
-            while let Some(current) = at.to_option() {
-                instr_count += 1;
+                let jump = self.new_instr_ex(SourceInfo { node: None.into(), values: vec![] }, InstrData::Jump { target: succ });
+                self.append_instr(new_bb, jump);
 
-                // only in one bb.
-                assert!(!visited[current]);
-                visited[current] = true;
+                changed = true;
+            }
+        }
 
-                // instr & bb ids.
-                let instr = &self.instrs[current];
-                assert_eq!(instr.id, current);
-                assert_eq!(instr.bb, bb.some());
+        changed
+    }
+}
 
-                // linked list first.
-                if current.some() == block.first {
-                    assert_eq!(instr.prev, None.into());
-                }
 
-                // linked list last.
-                if current.some() == block.last {
-                    assert_eq!(instr.next, None.into());
-                }
-                // linked list prev/next.
-                else {
-                    let next = instr.next.to_option().unwrap();
-                    let next = &self.instrs[next];
-                    assert_eq!(next.prev, current.some());
-                }
+// inlining
+impl Function {
+    // number of instructions in blocks.
+    pub fn size(&self) -> usize {
+        self.blocks.iter().map(|block| block.len()).sum()
+    }
 
-                // params & locals.
-                if instr.is_param() {
-                    assert!(in_params);
-                }
-                else if in_params {
-                    in_params = false;
-                    in_locals = true;
-                }
-                if instr.is_local() {
-                    assert!(in_locals);
-                }
-                else if !in_params {
-                    in_locals = false;
-                }
+    // moves the instructions after `at` into a new block.
+    pub fn split_block_after(&mut self, at: InstrId) -> BlockId {
+        let bb = self.instrs[at].bb.unwrap();
+        let new_bb = self.new_block();
+
+        let mut current = self.instrs[at].next;
+        while let Some(id) = current.to_option() {
+            current = self.instrs[id].next;
+            self.remove_instr(id);
+            self.append_instr(new_bb, id);
+        }
+
+        // the terminator moved.
+        let mut succs = vec![];
+        self.block_successors(new_bb, |succ| succs.push(succ));
+        for succ in succs {
+            self.replace_phi_pred(succ, bb, new_bb);
+        }
+
+        new_bb
+    }
 
-                // phis.
-                if instr.is_phi() {
-                    assert!(in_phis);
-
-                    // one arg for each pred.
-                    // pred dominated by arg's bb.
-                    let preds = &preds[bb];
-                    let mut visited = vec![false; preds.len()];
-                    let phi_map = self.try_phi(instr.id).unwrap();
-                    for (from_bb, src) in phi_map.iter().copied() {
-                        let pred = preds.iter().position(|p| *p == from_bb).unwrap();
-                        assert!(visited[pred] == false);
-                        visited[pred] = true;
-
-                        let src = src.get(self);
-                        assert!(src.has_value());
-                        let src_bb = src.bb.to_option().unwrap();
-                        assert!(idom.is_dominated_by(from_bb, src_bb));
+    pub fn replace_uses(&mut self, old: InstrId, new: InstrId) {
+        for bb in self.block_ids() {
+            self.block_replace_args(bb, |_, arg| if *arg == old { *arg = new });
+        }
+    }
+
+    // replaces `call` with a copy of `callee`'s body.
+    // - neither function may be in ssa form yet.
+    //   the callee's params & locals become locals of `self`.
+    // - `callee` must not have unreachable blocks.
+    // - returns jump to a new block, where a phi merges the return values.
+    pub fn inline_call(&mut self, call: InstrId, callee: &Function) {
+        let InstrData::Call { func, args_id } = self.instrs[call].data else { unreachable!() };
+        let args = args_id.get(self).to_vec();
+        assert_eq!(args.len(), callee.num_params());
+
+        // the `param` instructions at the start of the entry block, in order.
+        // their locals aren't necessarily the first ones (eg text ir).
+        let mut params = vec![];
+        callee.block_instrs_ex(BlockId::ENTRY, |instr| {
+            let InstrData::Param { id } = instr.data else { return false };
+            params.push((instr.id, id));
+            true
+        });
+        assert_eq!(params.len(), args.len());
+
+        let bb    = self.instrs[call].bb.unwrap();
+        let node  = self.instrs[call].source.node;
+        let after = self.split_block_after(call);
+        self.remove_instr(call);
+
+        let locals: IndexVec<LocalId, LocalId> = callee.locals.iter()
+            .map(|local| self.new_local(&local.name, local.source))
+            .collect();
+
+        let blocks: IndexVec<BlockId, BlockId> = callee.block_ids()
+            .map(|_| self.new_block())
+            .collect();
+
+        // clone, then remap, cause of back edges.
+        let mut instrs = index_vec![OptInstrId::NONE; callee.num_instrs()];
+        for callee_bb in callee.block_ids() {
+            callee.block_instrs(callee_bb, |instr| {
+                match instr.data {
+                    InstrData::Param { id: _ } => {
+                        let index = params.iter().position(|(param, _)| *param == instr.id).unwrap();
+                        instrs[instr.id] = args[index].some();
+                    }
+                    InstrData::Local { id: _ } => (),
+                    _ => {
+                        let id = self.new_instr_ex(instr.source.clone(), instr.data);
+                        self.append_instr(blocks[callee_bb], id);
+                        instrs[instr.id] = id.some();
                     }
                 }
-                else { in_phis = false; }
-
-                // bb dominated by (non-phi) arg bbs.
-                if !instr.is_phi() {
-                    instr.args(self, |arg| {
-                        let arg = arg.get(self);
-                        assert!(arg.has_value());
-                        let arg_bb = arg.bb.to_option().unwrap();
-                        assert!(idom.is_dominated_by(bb, arg_bb));
-                    });
-                }
+            });
+        }
 
-                // terminator: at most one.
-                if instr.is_terminator() {
-                    assert!(!has_terminator);
-                    has_terminator = true;
-                }
+        let mut returns = vec![];
+        for callee_bb in callee.block_ids() {
+            let new_bb = blocks[callee_bb];
+
+            let mut current = new_bb.get(self).first;
+            while let Some(at) = current.to_option() {
+                let instr = |id: InstrId| instrs[id].unwrap();
+                let list  = |this: &mut Function, id: InstrListId| {
+                    let values = callee.instr_lists[id].iter().map(|v| instr(*v)).collect();
+                    this.instr_lists.push(InstrListImpl { values });
+                    InstrListId(this.instr_lists.len() as u32 - 1)
+                };
+                let path = |this: &mut Function, id: PathId| {
+                    let old = &callee.paths[id];
+                    let base = match old.base {
+                        PathBase::Instr(base) => PathBase::Instr(instr(base)),
+                        base => base,
+                    };
+                    let keys = old.keys.iter().map(|key| match key {
+                        PathKey::Field(string) => PathKey::Field(this.add_string(string.get(callee))),
+                        PathKey::Index(index)  => PathKey::Index(instr(*index)),
+                    }).collect();
+                    this.paths.push(PathImpl { base, keys });
+                    PathId(this.paths.len() as u32 - 1)
+                };
 
-                at = instr.next;
-            }
+                use InstrData::*;
+                let data = match self.instrs[at].data {
+                    Copy { src } => Copy { src: instr(src) },
+
+                    Phi { map_id } => {
+                        let map = callee.phi_maps[map_id].iter()
+                            .map(|(from, src)| (blocks[*from], instr(*src)))
+                            .collect();
+                        self.phi_maps.push(PhiMapImpl { map });
+                        Phi { map_id: PhiMapId(self.phi_maps.len() as u32 - 1) }
+                    }
+
+                    ParallelCopy { src: _, copy_id: _ } |
+                    Param { id: _ } |
+                    Local { id: _ } => unreachable!(),
+
+                    GetLocal { src }      => GetLocal { src: locals[src] },
+                    SetLocal { dst, src } => SetLocal { dst: locals[dst], src: instr(src) },
+
+                    LoadString { id } => LoadString { id: self.add_string(id.get(callee)) },
+
+                    data @ (LoadNil | LoadBool { value: _ } | LoadInt { value: _ } |
+                            LoadFloat { value: _ } | LoadEnv | TupleNew0) => data,
+
+                    ListNew  { values } => ListNew  { values: list(self, values) },
+                    TupleNew { values } => TupleNew { values: list(self, values) },
+                    TupleNewNamed { shape, values } => {
+                        let names = shape.get(callee);
+                        TupleNewNamed { shape: self.add_tuple_shape(&names), values: list(self, values) }
+                    }
 
-            assert_eq!(instr_count, block.len());
+                    ReadPath   { path_id } => ReadPath { path_id: path(self, path_id) },
+                    WritePath  { path_id, value, is_def } => WritePath { path_id: path(self, path_id), value: instr(value), is_def },
+                    DeletePath { path_id } => DeletePath { path_id: path(self, path_id) },
 
-            if block.first == None.into() {
-                assert_eq!(block.last, None.into());
+                    Call { func, args_id } => Call { func: instr(func), args_id: list(self, args_id) },
+
+                    Op1 { op, src }        => Op1 { op, src: instr(src) },
+                    Op2 { op, src1, src2 } => Op2 { op, src1: instr(src1), src2: instr(src2) },
+
+                    LoadMethod { traitt, recv, method } => LoadMethod { traitt: instr(traitt), recv: instr(recv), method },
+                    Implements { src, traitt }          => Implements { src: instr(src), traitt: instr(traitt) },
+
+                    Jump       { target }                  => Jump { target: blocks[target] },
+                    SwitchBool { src, on_true, on_false }  => SwitchBool { src: instr(src), on_true: blocks[on_true], on_false: blocks[on_false] },
+                    SwitchNil  { src, on_nil, on_non_nil } => SwitchNil { src: instr(src), on_nil: blocks[on_nil], on_non_nil: blocks[on_non_nil] },
+
+                    Return { src } => {
+                        returns.push((new_bb, instr(src)));
+                        Jump { target: after }
+                    }
+                };
+                self.instrs[at].data = data;
+
+                current = self.instrs[at].next;
             }
         }
 
-        // validate instructions that are not in blocks.
-        for instr_id in self.instr_ids() {
-            if !visited[instr_id] {
-                let instr = &self.instrs[instr_id];
-                assert_eq!(instr.id, instr_id);
-                assert_eq!(instr.bb,   None.into());
-                assert_eq!(instr.prev, None.into());
-                assert_eq!(instr.next, None.into());
+        // params.
+        for ((_, local), arg) in params.iter().copied().zip(args.iter().copied()) {
+            let set = self.new_instr((node, None.into()), InstrData::SetLocal { dst: locals[local], src: arg });
+            self.append_instr(bb, set);
+        }
+        let jump = self.new_instr((node, None.into()), InstrData::Jump { target: blocks[BlockId::ENTRY] });
+        self.append_instr(bb, jump);
+
+        // result.
+        let result =
+            if let [(_, src)] = returns[..] { src }
+            else {
+                // no returns: `after` is unreachable.
+                let result =
+                    if returns.is_empty() { self.new_instr((node, None.into()), InstrData::TupleNew0) }
+                    else                  { self.new_phi((node, None.into()), &returns) };
+                self.prepend_instr(after, result);
+                result
+            };
+        let source_values = core::mem::take(&mut self.instrs[call].source.values);
+        self.instrs[result].source.values.extend(source_values);
+        self.replace_uses(call, result);
+
+        // the item read.
+        if let InstrData::ReadPath { path_id } = self.instrs[func].data {
+            if let PathBase::Items = path_id.get(self).base {
+                let mut used = false;
+                self.all_args(|arg| used |= arg == func);
+                if !used {
+                    self.remove_instr(func);
+                }
             }
         }
     }
+}
 
 
-    pub fn dump(&self) {
-        for bb in self.block_ids() {
-            println!("{}:", bb);
-            self.block_instrs(bb, |instr| println!("  {}", instr.fmt(self)));
+// other
+impl Function {
+    pub fn slow_integrity_check(&self) {
+        if let Err(errors) = super::verify::verify(self) {
+            self.dump();
+            let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            panic!("{} is invalid:\n{}", self.id, errors.join("\n"));
         }
     }
 
+
+    // see `bbir_text`.
+    pub fn dump(&self) {
+        print!("{}", self.text());
+    }
+
     pub fn big_dump(&self) {
         for bb in self.block_ids() {
             println!("{}:", bb);
@@ -1543,6 +1945,12 @@ impl StringId {
     }
 }
 
+impl TupleShapeId {
+    pub fn get(self, fun: &Function) -> Vec<&str> {
+        fun.tuple_shapes[self].iter().map(|name| name.get(fun)).collect()
+    }
+}
+
 
 
 // --- Crate ---
@@ -1552,6 +1960,8 @@ impl Crate {
         Crate {
             functions: index_vec![],
             items:     index_vec![],
+            node_kinds: vec![],
+            reg_alloc:  Default::default(),
         }
     }
 
@@ -1574,6 +1984,26 @@ impl Crate {
         *slot = value;
     }
 
+    pub fn set_node_kind(&mut self, node: NodeId, kind: crate::ValueKind) {
+        if node.usize() >= self.node_kinds.len() {
+            self.node_kinds.resize(node.usize() + 1, None);
+        }
+        self.node_kinds[node.usize()] = Some(kind);
+    }
+
+    pub fn set_reg_alloc(&mut self, kind: super::codegen::RegAllocKind) {
+        self.reg_alloc = kind;
+    }
+
+    #[inline(always)]
+    pub fn num_functions(&self) -> usize { self.functions.len() }
+
+    #[inline(always)]
+    pub fn num_items(&self) -> usize { self.items.len() }
+
+    #[inline(always)]
+    pub fn item(&self, id: ItemId) -> &Item { &self.items[id] }
+
     pub fn read_function(&self, id: FunctionId) -> Ref<'static, Function> {
         self.functions[id].borrow()
     }
@@ -1583,6 +2013,7 @@ impl Crate {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct FunctionDebugInfo {
     pub reg_mapping: Vec<Vec<super::codegen::ValueMapping>>,
     pub pc_to_node:  Vec<OptNodeId>,
@@ -1590,58 +2021,42 @@ pub struct FunctionDebugInfo {
 
 impl Crate {
     pub fn build(self) -> (IndexVec<FunctionId, crate::FuncDesc>, IndexVec<ItemId, Item>, IndexVec<FunctionId, FunctionDebugInfo>) {
+        self.build_ex(&mut PassManager::new(OptLevel::default()))
+    }
+
+    pub fn build_ex(self, passes: &mut PassManager) -> (IndexVec<FunctionId, crate::FuncDesc>, IndexVec<ItemId, Item>, IndexVec<FunctionId, FunctionDebugInfo>) {
         let mut funcs = IndexVec::with_capacity(self.functions.len());
 
         let mut debug_infos = IndexVec::with_capacity(self.functions.len());
 
+        if passes.opt_level() >= OptLevel::O1 {
+            for fun in self.functions.iter() {
+                fun.borrow_mut().remove_unreachable_blocks();
+            }
+
+            opt::inline_functions(&self);
+        }
+
         for fun in self.functions.iter() {
             let mut fun = fun.borrow_mut();
             //fun.dump();
             //fun.big_dump();
 
-            fun.remove_unreachable_blocks();
-            //fun.dump();
-
-            // for local in &fun.locals {
-            //     println!("{:?}", local);
-            // }
-
-            let preds = fun.predecessors();
-
-            let post_order = fun.post_order();
-
-            let post_indices = fun.post_order_indices(&post_order);
-
-            let idoms = fun.immediate_dominators(&preds, &post_order, &post_indices);
-
-            let dom_tree = fun.dominator_tree(&idoms);
-
-            let dom_frontiers = fun.dominance_frontiers(&preds, &idoms);
-
-            opt::local2reg_ex(&mut fun, &preds, &dom_tree, &dom_frontiers);
-            //println!("local2reg done");
-            //fun.dump();
-
-            opt::copy_propagation_ex(&mut fun, &dom_tree);
-            //println!("copy propagation done");
-            //fun.dump();
-
-            opt::dead_copy_elim(&mut fun);
-            //println!("dead copy elim done");
-            //fun.dump();
-
-            transform::convert_to_cssa_naive(&mut fun, &preds);
-            //println!("cssa");
-            //fun.dump();
-            //fun.big_dump();
+            let mut analyses = Analyses::new();
+            passes.run(&mut fun, &mut analyses);
 
-            let result = fun.compile_ex(&post_order, &idoms, &dom_tree);
+            analyses.ensure(&fun, Analysis::DomTree);
+            let peephole = passes.opt_level() >= OptLevel::O1;
+            let result = fun.compile_ex(analyses.post_order(), analyses.idoms(), analyses.dom_tree(), &self.node_kinds, self.reg_alloc, peephole);
             //println!("bytecode:");
             //crate::bytecode::dump(&result.code);
 
             funcs.push(crate::FuncDesc {
                 code: crate::FuncCode::ByteCode(result.code),
                 constants:  result.constants,
+                tuple_shapes: fun.tuple_shapes.iter().map(|names|
+                    names.iter().map(|name| name.get(&fun).to_string()).collect()
+                ).collect(),
                 num_params: fun.num_params,
                 stack_size: result.stack_size,
             });
@@ -1,18 +1,22 @@
 use crate::index_vec::*;
 use crate::ast::*;
+use crate::diagnostics::*;
 use crate::infer;
 use crate::bbir::{*, self};
 use crate::ast::ItemData;
 
 
-pub struct Builder {
+// errors reported by `Infer` are skipped silently.
+pub struct Builder<'d> {
     pub krate: bbir::Crate,
+    diags:     &'d mut Diagnostics,
 }
 
-impl Builder {
-    pub fn new() -> Self {
+impl<'d> Builder<'d> {
+    pub fn new(diags: &'d mut Diagnostics) -> Self {
         Builder {
             krate: bbir::Crate::new(),
+            diags,
         }
     }
 
@@ -42,10 +46,7 @@ impl Builder {
         match &stmt.data {
             StmtData::Item(item) => {
                 match &item.data {
-                    ItemData::Module(module) => {
-                        let _ = module;
-                        unimplemented!()
-                    }
+                    ItemData::Module(_) => {}
 
                     ItemData::Func(func) => {
                         let func_id = self.build_func(ctx, stmt.id, func);
@@ -53,6 +54,32 @@ impl Builder {
                             data: bbir::ItemData::Func(func_id)
                         });
                     }
+
+                    ItemData::Trait(traitt) => {
+                        self.krate.def_item(item.id, bbir::Item {
+                            data: bbir::ItemData::Trait(bbir::Trait {
+                                name:    traitt.name.to_string(),
+                                methods: traitt.methods.iter().map(|m| m.name.to_string()).collect(),
+                            })
+                        });
+                    }
+
+                    ItemData::Impl(imp) => {
+                        for method in &imp.methods {
+                            self.build_stmt(ctx, method);
+                        }
+
+                        if let Some(info) = &imp.info {
+                            self.krate.def_item(item.id, bbir::Item {
+                                data: bbir::ItemData::Impl(bbir::Impl {
+                                    traitt:  info.traitt,
+                                    kind:    info.kind,
+                                    methods: info.methods.clone(),
+                                })
+                            });
+                        }
+
+                    }
                 }
             }
 
@@ -70,6 +97,18 @@ impl Builder {
                 ctx.fun.instr_set_local((stmt.id.some(), stmt.id.some()), lid, v);
             }
 
+            StmtData::Delete (delete) => {
+                let target = &delete.target;
+                if let ExprData::Field(_) | ExprData::Index(_) = target.data {
+                    if let Some((base, lid, keys)) = self.build_path(ctx, target) {
+                        let new_value = ctx.fun.instr_delete_path((target.id.some(), None.into()), base, &keys);
+                        if let Some(lid) = lid.to_option() {
+                            ctx.fun.instr_set_local((target.id.some(), None.into()), lid, new_value);
+                        }
+                    }
+                }
+            }
+
             StmtData::Expr (expr) => {
                 self.build_expr(ctx, expr, false);
             }
@@ -79,6 +118,10 @@ impl Builder {
     }
 
     fn build_expr(&mut self, ctx: &mut Ctx, expr: &Expr, need_value: bool) -> Option<InstrId> {
+        if let Some(kind) = expr.ty.as_ref().and_then(|ty| ty.value_kind()) {
+            self.krate.set_node_kind(expr.id, kind);
+        }
+
         match &expr.data {
             ExprData::Nil => {
                 Some(ctx.fun.instr_load_nil((expr.id.some(), expr.id.some())))
@@ -103,8 +146,7 @@ impl Builder {
 
                 match info.target {
                     expr::IdentTarget::Item(item) => {
-                        let index = ctx.fun.instr_load_int((expr.id.some(), None.into()), item.value() as i64);
-                        Some(ctx.fun.instr_read_path((expr.id.some(), expr.id.some()), PathBase::Items, &[PathKey::Index(index)]))
+                        Some(self.build_read_item(ctx, expr.id, item))
                     }
 
                     expr::IdentTarget::Local { node, local } => {
@@ -124,7 +166,14 @@ impl Builder {
                 for v in &tuple.values {
                     values.push(self.build_expr(ctx, v, true).unwrap());
                 }
-                Some(ctx.fun.instr_tuple_new((expr.id.some(), expr.id.some()), &values))
+
+                if !tuple.names.is_empty() {
+                    let shape = ctx.fun.add_tuple_shape(&tuple.names);
+                    Some(ctx.fun.instr_tuple_new_named((expr.id.some(), expr.id.some()), shape, &values))
+                }
+                else {
+                    Some(ctx.fun.instr_tuple_new((expr.id.some(), expr.id.some()), &values))
+                }
             }
 
             ExprData::List (list) => {
@@ -153,18 +202,12 @@ impl Builder {
                 match op2.kind {
                     expr::Op2Kind::Assign | expr::Op2Kind::Define => {
                         if let ExprData::Tuple(lhs) = &op2.children[0].data {
-                            if op2.kind != expr::Op2Kind::Assign {
-                                // todo: error.
-                                unimplemented!()
-                            }
-
                             let lhs = &lhs.values;
 
                             if let ExprData::Tuple(rhs) = &op2.children[1].data {
                                 let rhs = &rhs.values;
-                                if lhs.len() != rhs.len() {
-                                    // todo: error.
-                                    unimplemented!()
+                                if op2.kind != expr::Op2Kind::Assign || lhs.len() != rhs.len() {
+                                    return need_value.then(|| ctx.fun.instr_load_unit((expr.id.some(), expr.id.some())));
                                 }
 
                                 let mut values = Vec::with_capacity(rhs.len());
@@ -176,9 +219,6 @@ impl Builder {
                                     self.build_assign(ctx, &lhs[i], values[i], false);
                                 }
                             }
-                            else {
-                                unimplemented!()
-                            }
                         }
                         else {
                             let value = self.build_expr(ctx, &op2.children[1], true).unwrap();
@@ -190,7 +230,10 @@ impl Builder {
 
                     expr::Op2Kind::Op2Assign(op) => {
                         if op.is_cancelling() {
-                            unimplemented!()
+                            // @todo: lower like the op2s.
+                            self.diags.error("E0200", expr.source,
+                                format!("compound assignment with {op:?} is not supported"));
+                            return need_value.then(|| ctx.fun.instr_load_unit((expr.id.some(), expr.id.some())));
                         }
 
                         let src1 = self.build_expr(ctx, &op2.children[0], true).unwrap();
@@ -247,11 +290,43 @@ impl Builder {
             }
 
             ExprData::Call (call) => {
-                let func = self.build_expr(ctx, &call.func, true).unwrap();
+                let target = call.info.unwrap().target;
+
+                let func = match target {
+                    expr::CallTarget::Value =>
+                        Some(self.build_expr(ctx, &call.func, true).unwrap()),
+
+                    expr::CallTarget::StaticMethod { func } =>
+                        Some(self.build_read_item(ctx, call.func.id, func)),
+
+                    // needs the receiver.
+                    expr::CallTarget::DynamicMethod { traitt: _, method: _ } => None,
+                };
+
                 let mut args = vec![];
                 for arg in &call.args {
                     args.push(self.build_expr(ctx, arg, true).unwrap());
                 }
+
+                let func = match func {
+                    Some(func) => func,
+
+                    None => {
+                        let expr::CallTarget::DynamicMethod { traitt, method } = target else { unreachable!() };
+
+                        // dispatch is on the first argument.
+                        let Some(receiver) = args.first().copied() else {
+                            self.diags.push(Diagnostic::error("E0203", expr.source,
+                                "trait method call without a receiver".to_string())
+                                .with_note("dynamic dispatch uses the kind of the first argument".to_string()));
+                            return need_value.then(|| ctx.fun.instr_load_unit((expr.id.some(), expr.id.some())));
+                        };
+
+                        let traitt = self.build_read_item(ctx, call.func.id, traitt);
+                        ctx.fun.instr_load_method((call.func.id.some(), call.func.id.some()), traitt, receiver, method)
+                    }
+                };
+
                 Some(ctx.fun.instr_call((expr.id.some(), expr.id.some()), func, &args))
             }
 
@@ -343,7 +418,9 @@ impl Builder {
                         values.push((ctx.fun.get_current_block(), value));
                     }
                     else if value.is_some() {
-                        println!("info: ignoring error BreakTargetTakesNoValue.");
+                        self.diags.push(Diagnostic::error("E0201", expr.source,
+                            "this break target takes no value".to_string())
+                            .with_note("`while` loops don't produce values".to_string()));
                     }
 
                     ctx.fun.instr_jump(expr.id.some(), bb_break);
@@ -391,12 +468,22 @@ impl Builder {
             ExprData::Env => {
                 // @temp-no-env-access.
                 //Some(ctx.fun.instr_load_env(expr.source))
-                println!("ignoring error: no env access");
                 need_value.then(|| ctx.fun.instr_load_unit((expr.id.some(), None.into())))
             }
+
+            ExprData::Implements (implements) => {
+                let src    = self.build_expr(ctx, &implements.value,  true).unwrap();
+                let traitt = self.build_expr(ctx, &implements.traitt, true).unwrap();
+                Some(ctx.fun.instr_implements((expr.id.some(), expr.id.some()), src, traitt))
+            }
         }
     }
 
+    fn build_read_item(&mut self, ctx: &mut Ctx, node: NodeId, item: ItemId) -> InstrId {
+        let index = ctx.fun.instr_load_int((node.some(), None.into()), item.value() as i64);
+        ctx.fun.instr_read_path((node.some(), node.some()), PathBase::Items, &[PathKey::Index(index)])
+    }
+
     fn build_block(&mut self, ctx: &mut Ctx, block: &[Stmt]) {
         for stmt in block {
             self.build_stmt(ctx, stmt);
@@ -448,8 +535,14 @@ impl Builder {
             match &expr.data {
                 ExprData::Field(field) => {
                     let result = rec(this, ctx, &field.base, keys)?;
-                    keys.push(PathKey::Field(
-                        ctx.fun.add_string(field.name)));
+                    if let Some(info) = field.info {
+                        keys.push(PathKey::Index(
+                            ctx.fun.instr_load_int((expr.id.some(), None.into()), info.index as i64)));
+                    }
+                    else {
+                        keys.push(PathKey::Field(
+                            ctx.fun.add_string(field.name)));
+                    }
                     Some(result)
                 }
 
@@ -464,9 +557,10 @@ impl Builder {
                     let info = ident.info.unwrap();
 
                     match info.target {
-                        expr::IdentTarget::Item(item) => {
-                            let _ = item;
-                            unimplemented!()
+                        expr::IdentTarget::Item(_) => {
+                            this.diags.error("E0202", expr.source,
+                                format!("can't access the fields of item {:?}", ident.name));
+                            None
                         }
 
                         expr::IdentTarget::Local { node, local } => {
@@ -485,10 +579,7 @@ impl Builder {
                     Some((PathBase::Env, None.into()))
                 }
 
-                _ => {
-                    println!("ignoring error: invalid path base");
-                    None
-                }
+                _ => None,
             }
         }
 
@@ -526,9 +617,6 @@ impl Builder {
                 }
             }
         }
-        else if let ExprData::Env = lhs.data {
-            println!("ignored error: tried to assign to ENV");
-        }
         else if let ExprData::Field(_) | ExprData::Index(_) = lhs.data {
             if let Some((base, lid, keys)) = self.build_path(ctx, lhs) {
                 let new_value = ctx.fun.instr_write_path((lhs.id.some(), None.into()), base, &keys, rhs, is_def);
@@ -538,9 +626,6 @@ impl Builder {
                 }
             }
         }
-        else {
-            println!("ignoring error: invalid assign target");
-        }
     }
 
     fn build_func(&mut self, ctx: &mut Ctx, node: NodeId, func: &item::Func) -> FunctionId {
@@ -0,0 +1,731 @@
+use super::*;
+
+
+// the text format:
+//  local l0 "name"
+//  string str0 "value"
+//  bb0:
+//    i0 := param l0
+//    i1    return i0
+//
+// - locals & strings come first, in id order.
+// - blocks are in id order, instructions use `InstrFmt`.
+// - instruction ids are kept, they don't need to be dense.
+// - source info isn't included.
+// - `//` starts a comment line.
+
+
+// --- printer ---
+
+pub struct FunctionText<'a>(pub &'a Function);
+
+impl Function {
+    #[inline(always)]
+    pub fn text(&self) -> FunctionText<'_> { FunctionText(self) }
+}
+
+impl<'a> core::fmt::Display for FunctionText<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let FunctionText(fun) = self;
+
+        for i in 0..fun.num_locals() {
+            let id = LocalId::from_usize(i);
+            writeln!(f, "local {} {:?}", id, fun.local_name(id))?;
+        }
+        for i in 0..fun.num_strings() {
+            let id = StringId::from_usize(i);
+            writeln!(f, "string {} {:?}", id, id.get(fun))?;
+        }
+
+        for bb in fun.block_ids() {
+            writeln!(f, "{}:", bb)?;
+
+            let mut result = Ok(());
+            fun.block_instrs(bb, |instr| {
+                if result.is_ok() {
+                    result = writeln!(f, "  {}", instr.fmt(fun));
+                }
+            });
+            result?;
+        }
+        Ok(())
+    }
+}
+
+
+
+// --- parser ---
+
+#[derive(Clone, Debug)]
+pub struct TextError {
+    pub line:    usize,
+    pub message: String,
+}
+
+impl core::fmt::Display for TextError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+type TextResult<T> = Result<T, TextError>;
+
+
+struct Cursor<'a> {
+    text:  &'a str,
+    pos:   usize,
+    line:  usize,
+    // for validating references.
+    num_instrs: usize,
+    defined:    &'a [bool],
+    num_blocks: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn error<T>(&self, message: String) -> TextResult<T> {
+        Err(TextError { line: self.line, message })
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_ws();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: &str) -> TextResult<()> {
+        if !self.eat(token) {
+            return self.error(format!("expected {:?}, found {:?}", token, self.rest()));
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> TextResult<()> {
+        self.skip_ws();
+        if !self.rest().is_empty() {
+            return self.error(format!("unexpected {:?}", self.rest()));
+        }
+        Ok(())
+    }
+
+    fn word(&mut self) -> TextResult<&'a str> {
+        self.skip_ws();
+        let rest = self.rest();
+        let len = rest.find(|c: char|
+            !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' || c == '+'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return self.error(format!("expected a word, found {:?}", rest));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn number<T: core::str::FromStr>(&mut self) -> TextResult<T> {
+        let word = self.word()?;
+        match word.parse() {
+            Ok(value) => Ok(value),
+            Err(_) => self.error(format!("invalid number {:?}", word)),
+        }
+    }
+
+    fn id(&mut self, prefix: &str) -> TextResult<usize> {
+        let word = self.word()?;
+        if let Some(digits) = word.strip_prefix(prefix) {
+            if let Ok(value) = digits.parse::<u32>() {
+                return Ok(value as usize);
+            }
+        }
+        self.error(format!("expected a {}-id, found {:?}", prefix, word))
+    }
+
+    fn instr(&mut self) -> TextResult<InstrId> {
+        let id = self.id("i")?;
+        if id >= self.num_instrs || !self.defined[id] {
+            return self.error(format!("undefined instruction i{}", id));
+        }
+        Ok(InstrId::from_usize(id))
+    }
+
+    fn block(&mut self) -> TextResult<BlockId> {
+        let id = self.id("bb")?;
+        if id >= self.num_blocks {
+            return self.error(format!("undefined block bb{}", id));
+        }
+        Ok(BlockId::from_usize(id))
+    }
+
+    fn local(&mut self, fun: &Function) -> TextResult<LocalId> {
+        let id = self.id("l")?;
+        if id >= fun.num_locals() {
+            return self.error(format!("undefined local l{}", id));
+        }
+        Ok(LocalId::from_usize(id))
+    }
+
+    fn string_id(&mut self, fun: &Function) -> TextResult<StringId> {
+        let id = self.id("str")?;
+        if id >= fun.num_strings() {
+            return self.error(format!("undefined string str{}", id));
+        }
+        Ok(StringId::from_usize(id))
+    }
+
+    // the escapes of `{:?}`.
+    fn string(&mut self) -> TextResult<String> {
+        self.expect("\"")?;
+
+        let mut result = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(result);
+                }
+
+                '\\' => {
+                    let escaped = match chars.next() {
+                        Some((_, 'n'))  => '\n',
+                        Some((_, 'r'))  => '\r',
+                        Some((_, 't'))  => '\t',
+                        Some((_, '0'))  => '\0',
+                        Some((_, '\\')) => '\\',
+                        Some((_, '"'))  => '"',
+                        Some((_, '\'')) => '\'',
+                        Some((_, 'u')) => {
+                            let mut code = String::new();
+                            if !matches!(chars.next(), Some((_, '{'))) {
+                                return self.error("invalid unicode escape".into());
+                            }
+                            for (_, c) in chars.by_ref() {
+                                if c == '}' { break }
+                                code.push(c);
+                            }
+                            match u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                                Some(c) => c,
+                                None => return self.error(format!("invalid unicode escape {:?}", code)),
+                            }
+                        }
+                        _ => return self.error("invalid escape".into()),
+                    };
+                    result.push(escaped);
+                }
+
+                _ => result.push(c),
+            }
+        }
+        self.error("unterminated string".into())
+    }
+
+    fn list<T, F: FnMut(&mut Self) -> TextResult<T>>(&mut self, open: &str, close: &str, mut f: F) -> TextResult<Vec<T>> {
+        self.expect(open)?;
+
+        let mut result = vec![];
+        if self.eat(close) {
+            return Ok(result);
+        }
+        loop {
+            result.push(f(self)?);
+            if self.eat(close) {
+                return Ok(result);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn path(&mut self, fun: &mut Function) -> TextResult<PathId> {
+        let start = self.pos;
+        let base = match self.word()? {
+            "Items" => PathBase::Items,
+            "Env"   => PathBase::Env,
+            _ => {
+                self.pos = start;
+                PathBase::Instr(self.instr()?)
+            }
+        };
+        self.expect(",")?;
+
+        let keys = self.list("[", "]", |this| {
+            this.skip_ws();
+            if this.rest().starts_with("str") {
+                Ok(PathKey::Field(this.string_id(fun)?))
+            }
+            else {
+                Ok(PathKey::Index(this.instr()?))
+            }
+        })?;
+        if keys.is_empty() {
+            return self.error("paths need at least one key".into());
+        }
+
+        Ok(fun.new_path(base, &keys))
+    }
+
+    fn instr_list(&mut self, fun: &mut Function) -> TextResult<InstrListId> {
+        let values = self.list("[", "]", |this| this.instr())?;
+        Ok(fun.new_instr_list(&values))
+    }
+
+    fn instr_data(&mut self, fun: &mut Function) -> TextResult<InstrData> {
+        use InstrData::*;
+
+        let op = self.word()?;
+        let data = match op {
+            "copy" => Copy { src: self.instr()? },
+
+            "phi" => {
+                let map = self.list("{", "}", |this| {
+                    let bb = this.block()?;
+                    this.expect(":")?;
+                    Ok((bb, this.instr()?))
+                })?;
+                Phi { map_id: fun.new_phi_map(&map) }
+            }
+
+            "parallel_copy" => {
+                let src = self.instr()?;
+                self.expect("(")?;
+                let copy_id = self.number()?;
+                self.expect(")")?;
+                ParallelCopy { src, copy_id }
+            }
+
+            "param" => Param { id: self.local(fun)? },
+            "local" => Local { id: self.local(fun)? },
+            "get_local" => GetLocal { src: self.local(fun)? },
+            "set_local" => {
+                let dst = self.local(fun)?;
+                self.expect(",")?;
+                SetLocal { dst, src: self.instr()? }
+            }
+
+            "load_nil" => LoadNil,
+            "load_bool" => {
+                match self.word()? {
+                    "true"  => LoadBool { value: true },
+                    "false" => LoadBool { value: false },
+                    word => return self.error(format!("expected a bool, found {:?}", word)),
+                }
+            }
+            "load_int"    => LoadInt   { value: self.number()? },
+            "load_float"  => LoadFloat { value: self.number()? },
+            "load_string" => LoadString { id: fun.add_string(&self.string()?) },
+            "get_env"     => LoadEnv,
+
+            "new_list" => ListNew { values: self.instr_list(fun)? },
+
+            "tuple_new" => {
+                if self.eat("[]") { TupleNew0 }
+                else { TupleNew { values: self.instr_list(fun)? } }
+            }
+            "tuple_new_named" => {
+                let names = self.list("[", "]", |this| this.string())?;
+                let names = names.iter().map(|name| name.as_str()).collect::<Vec<_>>();
+                let shape = fun.add_tuple_shape(&names);
+                TupleNewNamed { shape, values: self.instr_list(fun)? }
+            }
+
+            "read_path" => ReadPath { path_id: self.path(fun)? },
+            "write_path" => {
+                let is_def = self.eat("(d)");
+                let path_id = self.path(fun)?;
+                WritePath { path_id, value: self.instr()?, is_def }
+            }
+            "delete_path" => DeletePath { path_id: self.path(fun)? },
+
+            "call" => {
+                let func = self.instr()?;
+                self.expect(",")?;
+                Call { func, args_id: self.instr_list(fun)? }
+            }
+
+            "load_method" => {
+                let traitt = self.instr()?;
+                self.expect(",")?;
+                let recv = self.instr()?;
+                self.expect(",")?;
+                LoadMethod { traitt, recv, method: self.number()? }
+            }
+            "implements" => {
+                let src = self.instr()?;
+                self.expect(",")?;
+                Implements { src, traitt: self.instr()? }
+            }
+
+            "jump" => Jump { target: self.block()? },
+            "switch_bool" => {
+                let src = self.instr()?;
+                self.expect(",")?;
+                let on_true = self.block()?;
+                self.expect(",")?;
+                SwitchBool { src, on_true, on_false: self.block()? }
+            }
+            "switch_nil" => {
+                let src = self.instr()?;
+                self.expect(",")?;
+                let on_nil = self.block()?;
+                self.expect(",")?;
+                SwitchNil { src, on_nil, on_non_nil: self.block()? }
+            }
+            "return" => Return { src: self.instr()? },
+
+            _ => {
+                if let Some(op) = super::Op1::parse(op) {
+                    Op1 { op, src: self.instr()? }
+                }
+                else if let Some(op) = super::Op2::parse(op) {
+                    let src1 = self.instr()?;
+                    self.expect(",")?;
+                    Op2 { op, src1, src2: self.instr()? }
+                }
+                else {
+                    return self.error(format!("unknown instruction {:?}", op));
+                }
+            }
+        };
+        self.end()?;
+        Ok(data)
+    }
+}
+
+
+// `fun` must be empty, eg from `Crate::new_function`.
+pub fn parse_function(fun: &mut Function, text: &str) -> TextResult<()> {
+    assert!(fun.num_instrs() == 0 && fun.num_blocks() == 1 && fun.num_locals() == 0);
+
+    // headers, blocks & instruction ids.
+    let mut instrs = vec![];
+    let mut num_blocks = 0;
+    let mut defined = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with("//") {
+            continue;
+        }
+
+        let mut cursor = Cursor { text: trimmed, pos: 0, line: i + 1, num_instrs: 0, defined: &[], num_blocks: 0 };
+
+        if trimmed.starts_with("local ") || trimmed.starts_with("string ") {
+            if num_blocks > 0 {
+                return cursor.error("locals & strings must come before the blocks".into());
+            }
+
+            if cursor.eat("local ") {
+                let id = cursor.id("l")?;
+                if id != fun.num_locals() {
+                    return cursor.error(format!("expected l{}", fun.num_locals()));
+                }
+                let name = cursor.string()?;
+                cursor.end()?;
+                fun.new_local_id(&name, NodeId::ZERO);
+            }
+            else {
+                cursor.expect("string ")?;
+                let id = cursor.id("str")?;
+                if id != fun.num_strings() {
+                    return cursor.error(format!("expected str{}", fun.num_strings()));
+                }
+                let value = cursor.string()?;
+                cursor.end()?;
+                if fun.add_string(&value).usize() != id {
+                    return cursor.error(format!("duplicate string {:?}", value));
+                }
+            }
+        }
+        else if trimmed.starts_with("bb") {
+            let id = cursor.id("bb")?;
+            cursor.expect(":")?;
+            cursor.end()?;
+            if id != num_blocks {
+                return cursor.error(format!("expected bb{}", num_blocks));
+            }
+            if num_blocks > 0 {
+                fun.new_block();
+            }
+            num_blocks += 1;
+        }
+        else {
+            if num_blocks == 0 {
+                return cursor.error("instruction outside of a block".into());
+            }
+
+            let id = cursor.id("i")?;
+            if id >= defined.len() {
+                defined.resize(id + 1, false);
+            }
+            if defined[id] {
+                return cursor.error(format!("i{} is already defined", id));
+            }
+            defined[id] = true;
+
+            let has_value = cursor.eat(":=");
+            let bb = BlockId::from_usize(num_blocks - 1);
+            instrs.push((i + 1, trimmed, cursor.pos, bb, InstrId::from_usize(id), has_value));
+        }
+    }
+    if num_blocks == 0 {
+        return Err(TextError { line: 0, message: "no blocks".into() });
+    }
+
+    // placeholders, so instructions can refer to later ones.
+    for _ in 0..defined.len() {
+        fun.new_instr_ex(SourceInfo { node: None.into(), values: vec![] }, InstrData::LoadNil);
+    }
+    for (_, _, _, bb, id, _) in instrs.iter().copied() {
+        fun.append_instr(bb, id);
+    }
+
+    for (line, text, pos, _, id, has_value) in instrs {
+        let mut cursor = Cursor { text, pos, line, num_instrs: defined.len(), defined: &defined, num_blocks };
+        let data = cursor.instr_data(fun)?;
+        if data.has_value() != has_value {
+            return cursor.error(format!("`:=` doesn't match whether {} has a value", id));
+        }
+        id.get_mut(fun).data = data;
+    }
+
+    fun.reset_builder_state();
+    Ok(())
+}
+
+
+
+// --- file check ---
+
+// runs passes on a function in the text format
+// & matches the output against directives in comments:
+//  `// run: <pass>, <pass>`    the passes, by name.
+//  `// check: <text>`          a later line contains `text`.
+//  `// check-next: <text>`     the line after the last match contains `text`.
+//  `// check-not: <text>`      no line between the last & the next match contains `text`.
+pub fn file_check(input: &str) -> Result<(), String> {
+    enum Check<'a> {
+        Any(&'a str),
+        Next(&'a str),
+        Not(&'a str),
+    }
+
+    let mut passes = vec![];
+    let mut checks = vec![];
+    for line in input.lines() {
+        let Some(comment) = line.trim().strip_prefix("//") else { continue };
+        let comment = comment.trim();
+
+        if let Some(names) = comment.strip_prefix("run:") {
+            for name in names.split(',') {
+                let name = name.trim();
+                let Some(pass) = Pass::by_name(name) else {
+                    return Err(format!("unknown pass {:?}", name));
+                };
+                passes.push(pass);
+            }
+        }
+        else if let Some(text) = comment.strip_prefix("check:") {
+            checks.push(Check::Any(text.trim()));
+        }
+        else if let Some(text) = comment.strip_prefix("check-next:") {
+            checks.push(Check::Next(text.trim()));
+        }
+        else if let Some(text) = comment.strip_prefix("check-not:") {
+            checks.push(Check::Not(text.trim()));
+        }
+    }
+
+    let mut krate = Crate::new();
+    let mut fun = krate.new_function();
+    parse_function(&mut fun, input).map_err(|e| e.to_string())?;
+    fun.slow_integrity_check();
+
+    let mut passes = PassManager::with_passes(OptLevel::default(), passes);
+    passes.run(&mut fun, &mut Analyses::new());
+
+    let output = fun.text().to_string();
+    let lines = output.lines().collect::<Vec<_>>();
+
+    let check_nots = |nots: &mut Vec<&str>, range: core::ops::Range<usize>| {
+        for not in nots.drain(..) {
+            if let Some(line) = lines[range.clone()].iter().find(|line| line.contains(not)) {
+                return Err(format!("check-not {:?} matched {:?}\n{}", not, line, output));
+            }
+        }
+        Ok(())
+    };
+
+    let mut at = 0;
+    let mut nots = vec![];
+    for check in checks {
+        let found = match check {
+            Check::Not(text) => {
+                nots.push(text);
+                continue;
+            }
+
+            Check::Any(text) => {
+                (at..lines.len()).find(|i| lines[*i].contains(text))
+                .ok_or_else(|| format!("check {:?} not found\n{}", text, output))?
+            }
+
+            Check::Next(text) => {
+                if at == 0 || at >= lines.len() || !lines[at].contains(text) {
+                    return Err(format!("check-next {:?} not found\n{}", text, output));
+                }
+                at
+            }
+        };
+
+        check_nots(&mut nots, at..found)?;
+        at = found + 1;
+    }
+    check_nots(&mut nots, at..lines.len())?;
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn build(source: &str) -> Crate {
+        let mut module = parser::parse_module(source.as_bytes()).unwrap();
+
+        let mut diags = Diagnostics::new();
+        let mut infer = infer::Infer::new(&mut diags);
+        infer.assign_ids(&mut module);
+        infer.infer(&mut module);
+
+        let mut builder = bbir_builder::Builder::new(&mut diags);
+        builder.build(&module);
+        let krate = builder.krate;
+        assert!(!diags.has_errors());
+        krate
+    }
+
+    fn round_trip(fun: &Function) {
+        let text = fun.text().to_string();
+
+        let mut krate = Crate::new();
+        let mut parsed = krate.new_function();
+        if let Err(e) = bbir_text::parse_function(&mut parsed, &text) {
+            panic!("{}\n{}", e, text);
+        }
+        parsed.slow_integrity_check();
+        assert_eq!(parsed.text().to_string(), text);
+    }
+
+
+    #[test]
+    fn text_round_trip() {
+        let krate = build(r#"
+            fn f(a, b):
+                var xs = [a, b, "c"]
+                xs[0] = (x: a, y: 2)
+                var i = 0
+                while i < b:
+                    if not xs[i] or a == nil:
+                        break
+                    end
+                    i += 1
+                end
+                return (xs, -i)
+            end
+        "#);
+
+        let passes = PassManager::new(OptLevel::default());
+        for i in 0..krate.num_functions() {
+            let mut fun = krate.write_function(FunctionId::from_usize(i));
+            round_trip(&fun);
+
+            // after each pass, to cover phis & parallel copies.
+            let mut analyses = Analyses::new();
+            for pass in passes.passes().to_vec() {
+                let mut single = PassManager::with_passes(passes.opt_level(), vec![pass]);
+                single.run(&mut fun, &mut analyses);
+                round_trip(&fun);
+            }
+        }
+
+        let mut krate = Crate::new();
+        let mut fun = krate.new_function();
+        bbir_text::parse_function(&mut fun, r#"
+            string str0 "q\"uote\n\u{1f600}"
+            bb0:
+              i1 := load_string "q\"uote\n\u{1f600}"
+              i3 := tuple_new_named ["a b", "\\"] [ i1, i1 ]
+              i4 := load_float -0.5
+              i5    return i3
+        "#).unwrap();
+        assert_eq!(fun.num_strings(), 3);
+        round_trip(&fun);
+    }
+
+    #[test]
+    fn text_errors() {
+        let mut krate = Crate::new();
+
+        let mut fun = krate.new_function();
+        let e = bbir_text::parse_function(&mut fun, "bb0:\n  i0    return i1\n").unwrap_err();
+        assert_eq!(e.line, 2);
+
+        let mut fun = krate.new_function();
+        let e = bbir_text::parse_function(&mut fun, "bb0:\n  i0 := load_nil\n  i0    return i0\n").unwrap_err();
+        assert_eq!(e.line, 3);
+
+        let mut fun = krate.new_function();
+        let e = bbir_text::parse_function(&mut fun, "bb0:\n  i0 := frobnicate\n").unwrap_err();
+        assert_eq!(e.line, 2);
+    }
+
+    #[test]
+    fn check_gvn() {
+        bbir_text::file_check("
+            // run: gvn, dead-code-elim
+            // check: i2 := mul i0, i1
+            // check-not: mul
+            // check: add i2, i2
+            local l0 \"a\"
+            local l1 \"b\"
+            bb0:
+              i0 := param l0
+              i1 := param l1
+              i2 := mul i0, i1
+              i3 := mul i1, i0
+              i4 := add i2, i3
+              i5    return i4
+        ").unwrap();
+    }
+
+    #[test]
+    fn check_sccp() {
+        bbir_text::file_check("
+            // run: sccp
+            // check: i2 := load_int 3
+            // check-next: i3 := load_bool true
+            // check-next: jump bb1
+            // check-not: bb2
+            bb0:
+              i0 := load_int 1
+              i1 := load_int 2
+              i2 := add i0, i1
+              i3 := cmp_lt i0, i1
+              i4    switch_bool i3, bb1, bb2
+            bb1:
+              i5    return i2
+            bb2:
+              i6    return i0
+        ").unwrap();
+    }
+}
+
@@ -1,5 +1,5 @@
-use crate::bytecode::{InstrWord, ByteCodeBuilder};
-use crate::Constant;
+use crate::bytecode::{InstrWord, ByteCodeBuilder, ByteCodeDecoder, OperandKind, Slot};
+use crate::{Constant, ValueKind};
 use crate::index_vec::*;
 use super::*;
 
@@ -21,7 +21,9 @@ pub struct ValueMapping {
 
 
 impl Function {
-    pub fn compile_ex(&self, post_order: &PostOrder, idoms: &ImmediateDominators, dom_tree: &DomTree) -> CompileResult {
+    // `node_kinds` are the value kinds expected by `infer`, see `Crate::set_node_kind`.
+    // `peephole` runs the bytecode level optimizations, see `peephole::peephole`.
+    pub fn compile_ex(&self, post_order: &PostOrder, idoms: &ImmediateDominators, dom_tree: &DomTree, node_kinds: &[Option<ValueKind>], reg_alloc: RegAllocKind, peephole: bool) -> CompileResult {
         let block_order = self.block_order_dominators_first(&idoms, &dom_tree);
 
         let (block_begins, instr_indices) = block_order.block_begins_and_instr_indices(self);
@@ -41,10 +43,16 @@ impl Function {
         //     println!("s{i}: {interval:?}");
         // }
 
-        let regs = alloc_regs_linear_scan(self, &live_intervals, &instr_indices);
+        let regs = alloc_regs(reg_alloc, self, &live_intervals, &instr_indices);
 
-        let GenBytecodeResult { code, constants, stack_size, instr_index_to_pc, pc_to_node }
-            = generate_bytecode(self, &block_order, &regs);
+        let value_kinds = self.value_kinds(post_order);
+        let numbers = number_values(self, &value_kinds, node_kinds);
+
+        let mut result = generate_bytecode(self, &block_order, &regs, &numbers);
+        if peephole {
+            super::peephole::peephole(&mut result);
+        }
+        let GenBytecodeResult { code, constants, stack_size, instr_index_to_pc, pc_to_node } = result;
 
         let reg_mapping = {
             let mut mapping = index_vec![vec![]; stack_size as usize];
@@ -71,13 +79,134 @@ impl Function {
 }
 
 
+// values that are proven to be numbers, or that `infer` expects to be numbers.
+// the `*_NUM` instructions check their operands,
+// so wrong expectations only cost a deoptimization.
+pub fn number_values(fun: &Function, value_kinds: &ValueKinds, node_kinds: &[Option<ValueKind>]) -> IndexVec<InstrId, bool> {
+    let mut result = index_vec![false; fun.num_instrs()];
+    for bb in fun.block_ids() {
+        fun.block_instrs(bb, |instr| {
+            result[instr.id()] = match value_kinds[instr.id()] {
+                Some(kind) => kind == ValueKind::Number,
+                None => instr.source.values.iter().any(|node|
+                    node_kinds.get(node.usize()).copied().flatten() == Some(ValueKind::Number)),
+            };
+        });
+    }
+    result
+}
+
+
 crate::macros::define_id!(Reg, OptReg, "r{}");
 
+// union find over values with joined live ranges.
+fn rep(instr: InstrId, joins: &IndexVec<InstrId, InstrId>) -> InstrId {
+    let mut at = instr;
+    loop {
+        let join = joins[at];
+        if join == at {
+            return at;
+        }
+        else {
+            at = join;
+        }
+    }
+}
+
+fn join(a: InstrId, b: InstrId, joins: &mut IndexVec<InstrId, InstrId>, intervals: &mut IndexVec<InstrId, Vec<(InstrIndex, InstrIndex)>>) -> bool {
+    let rep_a = rep(a, joins);
+    let rep_b = rep(b, joins);
+    if rep_a == rep_b {
+        return true;
+    }
+
+    let int_a = &intervals[rep_a];
+    let int_b = &intervals[rep_b];
+
+    // merge intervals, checking for overlap.
+    let mut new_int: Vec<(InstrIndex, InstrIndex)> = Vec::with_capacity(int_a.len() + int_b.len());
+    let mut at_a = 0;
+    let mut at_b = 0;
+    while at_a < int_a.len() || at_b < int_b.len() {
+        let next =
+            if at_b >= int_b.len() || (at_a < int_a.len() && int_a[at_a].0 < int_b[at_b].0) {
+                at_a += 1;
+                int_a[at_a - 1]
+            }
+            else if at_a >= int_a.len() || int_b[at_b].0 < int_a[at_a].0 {
+                at_b += 1;
+                int_b[at_b - 1]
+            }
+            // overlap.
+            else {
+                return false;
+            };
+
+        if let Some(last) = new_int.last_mut() {
+            // can merge intervals.
+            // the merged interval may reach into the next one.
+            if last.1 == next.0 {
+                last.1 = next.1;
+                continue;
+            }
+            // overlap.
+            if last.1 > next.0 {
+                return false;
+            }
+        }
+        new_int.push(next);
+    }
+
+    intervals[rep_b].clear();
+    intervals[rep_a] = new_int;
+    joins[rep_b] = rep_a;
+
+    return true;
+}
+
+
 pub struct RegisterAllocation {
     pub mapping:  IndexVec<InstrId, OptReg>,
     pub num_regs: usize,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RegAllocKind {
+    #[default]
+    LinearScan,
+    GraphColoring,
+}
+
+pub fn alloc_regs(kind: RegAllocKind, fun: &Function, intervals: &LiveIntervals, instr_indices: &InstrIndices) -> RegisterAllocation {
+    let mut regs = match kind {
+        RegAllocKind::LinearScan    => alloc_regs_linear_scan(fun, intervals, instr_indices),
+        RegAllocKind::GraphColoring => alloc_regs_graph_coloring(fun, intervals),
+    };
+    regs.skip_reserved();
+    regs
+}
+
+impl RegisterAllocation {
+    // registers 254 & 255 encode the `ITEMS` & `ENV` path bases,
+    // so values are moved past them.
+    // params are in the first registers & aren't affected.
+    // @todo: functions with more than 254 params.
+    pub fn skip_reserved(&mut self) {
+        if self.num_regs <= crate::bytecode::PathBase::ITEMS.value() as usize {
+            return;
+        }
+
+        for reg in self.mapping.iter_mut() {
+            if let Some(r) = reg.to_option() {
+                if r.value() >= crate::bytecode::PathBase::ITEMS.value() {
+                    *reg = Reg(r.value() + 2).some();
+                }
+            }
+        }
+        self.num_regs += 2;
+    }
+}
+
 pub fn alloc_regs_linear_scan(fun: &Function, intervals: &LiveIntervals, instr_indices: &InstrIndices) -> RegisterAllocation {
     let mut intervals = intervals.intervals.clone();
     let mut joins     = fun.instr_ids().collect::<IndexVec<InstrId, InstrId>>();
@@ -101,101 +230,6 @@ pub fn alloc_regs_linear_scan(fun: &Function, intervals: &LiveIntervals, instr_i
     //  kinda sus anyway & should be investigated.
     let mut hints     = fun.instr_ids().collect::<IndexVec<InstrId, InstrId>>();
 
-    fn rep(instr: InstrId, joins: &IndexVec<InstrId, InstrId>) -> InstrId {
-        let mut at = instr;
-        loop {
-            let join = joins[at];
-            if join == at {
-                return at;
-            }
-            else {
-                at = join;
-            }
-        }
-    }
-
-    fn join(a: InstrId, b: InstrId, joins: &mut IndexVec<InstrId, InstrId>, intervals: &mut IndexVec<InstrId, Vec<(InstrIndex, InstrIndex)>>) -> bool {
-        let rep_a = rep(a, joins);
-        let rep_b = rep(b, joins);
-        if rep_a == rep_b {
-            return true;
-        }
-
-        let int_a = &intervals[rep_a];
-        let int_b = &intervals[rep_b];
-
-        // merge intervals, checking for overlap.
-        let mut new_int = Vec::with_capacity(int_a.len() + int_b.len());
-        let mut at_a = 0;
-        let mut at_b = 0;
-        while at_a < int_a.len() || at_b < int_b.len() {
-            if at_a >= int_a.len() {
-                new_int.push(int_b[at_b]);
-                at_b += 1;
-            }
-            else if at_b >= int_b.len() {
-                new_int.push(int_a[at_a]);
-                at_a += 1;
-            }
-            else {
-                let (begin_a, end_a) = int_a[at_a];
-                let (begin_b, end_b) = int_b[at_b];
-                // a starts before b?
-                if begin_a < begin_b {
-                    // a ends before b?
-                    if end_a < begin_b {
-                        new_int.push((begin_a, end_a));
-                        at_a += 1;
-                    }
-                    // a ends at b?
-                    else if end_a == begin_b {
-                        // can merge intervals.
-                        new_int.push((begin_a, end_b));
-                        at_a += 1;
-                        at_b += 1;
-                    }
-                    // overlap.
-                    else {
-                        println!("overlap of {},{} with {},{}", begin_a,end_a, begin_b,end_b);
-                        return false;
-                    }
-                }
-                // b starts before a.
-                else if begin_b < begin_a {
-                    // b ends before a?
-                    if end_b < begin_a {
-                        new_int.push((begin_b, end_b));
-                        at_b += 1;
-                    }
-                    // b ends at a?
-                    else if end_b == begin_a {
-                        // can merge intervals.
-                        new_int.push((begin_b, end_a));
-                        at_a += 1;
-                        at_b += 1;
-                    }
-                    // overlap.
-                    else {
-                        println!("overlap of {},{} with {},{}", begin_a,end_a, begin_b,end_b);
-                        return false;
-                    }
-                }
-                // overlap.
-                else {
-                    println!("overlap of {},{} with {},{}", begin_a,end_a, begin_b,end_b);
-                    return false;
-                }
-            }
-        }
-
-        intervals[rep_b].clear();
-        intervals[rep_a] = new_int;
-        joins[rep_b] = rep_a;
-
-        return true;
-    }
-
-
     // phi joins & copy hints.
     for bb in fun.block_ids() {
         fun.block_instrs(bb, |instr| {
@@ -449,7 +483,8 @@ pub fn alloc_regs_linear_scan(fun: &Function, intervals: &LiveIntervals, instr_i
                     InstrData::ParallelCopy { src, copy_id: _ } => src,
                     InstrData::Op1 { op: _, src }            => src,
                     InstrData::Op2 { op: _,  src1, src2: _ } => src1,
-                    InstrData::WritePath { path_id, value: _, is_def: _ } => {
+                    InstrData::WritePath { path_id, value: _, is_def: _ } |
+                    InstrData::DeletePath { path_id } => {
                         match path_id.get(fun).base {
                             PathBase::Instr(base) => base,
                             PathBase::Items | PathBase::Env => break 'first_arg,
@@ -524,6 +559,274 @@ pub fn alloc_regs_linear_scan(fun: &Function, intervals: &LiveIntervals, instr_i
 }
 
 
+fn ranges_overlap(a: &[(InstrIndex, InstrIndex)], b: &[(InstrIndex, InstrIndex)]) -> bool {
+    let mut at_a = 0;
+    let mut at_b = 0;
+    while at_a < a.len() && at_b < b.len() {
+        let (begin_a, end_a) = a[at_a];
+        let (begin_b, end_b) = b[at_b];
+
+        if end_a <= begin_b {
+            at_a += 1;
+        }
+        else if end_b <= begin_a {
+            at_b += 1;
+        }
+        else {
+            return true;
+        }
+    }
+    false
+}
+
+// chaitin/briggs style allocator.
+// - phis & path bases are joined with their values, like in the linear scan.
+// - parallel copies (phi args) are coalesced aggressively.
+// - regular copies are coalesced conservatively (briggs test).
+// - coloring is optimistic. there's no spilling, so `k` is only
+//   the register pressure & nodes that don't fit get a new register.
+pub fn alloc_regs_graph_coloring(fun: &Function, intervals: &LiveIntervals) -> RegisterAllocation {
+    let mut intervals = intervals.intervals.clone();
+    let mut joins     = fun.instr_ids().collect::<IndexVec<InstrId, InstrId>>();
+    let mut fixed     = index_vec![OptReg::NONE; fun.num_instrs()];
+
+    // params are pre-assigned.
+    {
+        let mut i = 0;
+        fun.block_instrs_ex(BlockId::ENTRY, |instr| {
+            if instr.is_param() {
+                fixed[instr.id()] = Reg(i).some();
+                i += 1;
+                return true;
+            }
+            false
+        });
+        assert_eq!(i, fun.num_params() as u32);
+    }
+
+    // max number of values live at the same time.
+    let k = {
+        let mut events = vec![];
+        for ranges in intervals.iter() {
+            for (begin, end) in ranges.iter().copied() {
+                events.push((begin, 1));
+                events.push((end, -1));
+            }
+        }
+        // ends before begins, ranges may touch.
+        events.sort_unstable();
+
+        let mut live = 0i32;
+        let mut max_live = 0;
+        for (_, delta) in events {
+            live += delta;
+            max_live = max_live.max(live);
+        }
+        (max_live as usize).max(fun.num_params()).max(1)
+    };
+
+    fn join_fixed(a: InstrId, b: InstrId,
+        joins: &mut IndexVec<InstrId, InstrId>,
+        intervals: &mut IndexVec<InstrId, Vec<(InstrIndex, InstrIndex)>>,
+        fixed: &mut IndexVec<InstrId, OptReg>,
+    ) -> bool {
+        let rep_a = rep(a, joins);
+        let rep_b = rep(b, joins);
+        if rep_a == rep_b {
+            return true;
+        }
+
+        if fixed[rep_a].to_option().is_some() && fixed[rep_b].to_option().is_some() {
+            return false;
+        }
+
+        if !join(rep_a, rep_b, joins, intervals) {
+            return false;
+        }
+        if fixed[rep_a].to_option().is_none() {
+            fixed[rep_a] = fixed[rep_b];
+        }
+        true
+    }
+
+
+    // required joins & copies.
+    let mut copies = vec![];
+    for bb in fun.block_ids() {
+        fun.block_instrs(bb, |instr| {
+            let base = match instr.data {
+                InstrData::Phi { map_id } => {
+                    for (_, arg) in map_id.get(fun).iter().copied() {
+                        let joined = join_fixed(instr.id(), arg, &mut joins, &mut intervals, &mut fixed);
+                        assert!(joined, "failed to join {} with {}", instr.id(), arg);
+                    }
+                    return;
+                }
+
+                InstrData::Copy { src } |
+                InstrData::ParallelCopy { src, copy_id: _ } => {
+                    copies.push((instr.id(), src));
+                    return;
+                }
+
+                InstrData::WritePath { path_id, value: _, is_def: _ } |
+                InstrData::DeletePath { path_id } => {
+                    match path_id.get(fun).base {
+                        PathBase::Instr(base) => base,
+                        PathBase::Items | PathBase::Env => return,
+                    }
+                }
+
+                _ => return,
+            };
+
+            // codegen updates the base in place.
+            let joined = join_fixed(instr.id(), base, &mut joins, &mut intervals, &mut fixed);
+            assert!(joined, "failed to join {} with {}", instr.id(), base);
+        });
+    }
+
+    // aggressive coalescing.
+    for (dst, src) in copies.iter().copied() {
+        if let InstrData::ParallelCopy { src: _, copy_id: _ } = dst.get(fun).data {
+            join_fixed(dst, src, &mut joins, &mut intervals, &mut fixed);
+        }
+    }
+
+
+    // interference graph.
+    let mut nodes =
+        fun.instr_ids()
+        .filter(|instr| rep(*instr, &joins) == *instr && !intervals[*instr].is_empty())
+        .collect::<Vec<_>>();
+    nodes.sort_unstable_by_key(|node| intervals[*node][0].0);
+
+    let mut adjacent = index_vec![vec![]; fun.num_instrs()];
+    for (i, a) in nodes.iter().copied().enumerate() {
+        let stop_a = intervals[a][intervals[a].len()-1].1;
+        for b in nodes[i+1..].iter().copied() {
+            if intervals[b][0].0 >= stop_a {
+                break;
+            }
+            if ranges_overlap(&intervals[a], &intervals[b]) {
+                adjacent[a].push(b);
+                adjacent[b].push(a);
+            }
+        }
+    }
+
+
+    // conservative coalescing.
+    for (dst, src) in copies.iter().copied() {
+        let InstrData::Copy { src: _ } = dst.get(fun).data else { continue };
+
+        let a = rep(dst, &joins);
+        let b = rep(src, &joins);
+        if a == b || adjacent[a].contains(&b) {
+            continue;
+        }
+        if fixed[a].to_option().is_some() && fixed[b].to_option().is_some() {
+            continue;
+        }
+
+        // briggs: the joined node has fewer than `k` neighbors of significant degree.
+        let mut neighbors = adjacent[a].clone();
+        for n in adjacent[b].iter().copied() {
+            if !neighbors.contains(&n) {
+                neighbors.push(n);
+            }
+        }
+        let significant = neighbors.iter().filter(|n| {
+            let both = adjacent[a].contains(n) && adjacent[b].contains(n);
+            adjacent[**n].len() - both as usize >= k
+        }).count();
+        if significant >= k {
+            continue;
+        }
+
+        let joined = join_fixed(a, b, &mut joins, &mut intervals, &mut fixed);
+        assert!(joined);
+
+        for n in core::mem::take(&mut adjacent[b]) {
+            let adj_n = &mut adjacent[n];
+            adj_n.retain(|m| *m != b);
+            if !adj_n.contains(&a) {
+                adj_n.push(a);
+            }
+        }
+        adjacent[a] = neighbors;
+    }
+    nodes.retain(|node| rep(*node, &joins) == *node);
+
+
+    // simplify.
+    let mut degrees = adjacent.iter().map(|adj| adj.len()).collect::<IndexVec<InstrId, usize>>();
+    let mut remaining = nodes.iter().copied().filter(|node| fixed[*node].to_option().is_none()).collect::<Vec<_>>();
+    let mut stack = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        // optimistically push the node with the highest degree,
+        // if all nodes are significant.
+        let i = remaining.iter().position(|node| degrees[*node] < k)
+            .unwrap_or_else(|| {
+                (0..remaining.len()).max_by_key(|i| degrees[remaining[*i]]).unwrap()
+            });
+
+        let node = remaining.swap_remove(i);
+        for n in adjacent[node].iter().copied() {
+            degrees[n] -= 1;
+        }
+        stack.push(node);
+    }
+
+
+    // select.
+    let mut partners = index_vec![vec![]; fun.num_instrs()];
+    for (dst, src) in copies.iter().copied() {
+        let a = rep(dst, &joins);
+        let b = rep(src, &joins);
+        if a != b {
+            partners[a].push(b);
+            partners[b].push(a);
+        }
+    }
+
+    let mut colors   = fixed;
+    let mut num_regs = fun.num_params();
+    while let Some(node) = stack.pop() {
+        let mut used = vec![false; num_regs + 1];
+        for n in adjacent[node].iter().copied() {
+            if let Some(reg) = colors[n].to_option() {
+                used[reg.usize()] = true;
+            }
+        }
+
+        // prefer the register of a copy partner.
+        let hint = partners[node].iter().find_map(|partner| {
+            colors[*partner].to_option().filter(|reg| !used[reg.usize()])
+        });
+
+        let reg = hint.unwrap_or_else(|| {
+            Reg(used.iter().position(|used| !*used).unwrap() as u32)
+        });
+        colors[node] = reg.some();
+        num_regs = num_regs.max(reg.usize() + 1);
+    }
+
+    let mapping = fun.instr_ids().map(|instr| colors[rep(instr, &joins)]).collect();
+
+    RegisterAllocation { mapping, num_regs }
+}
+
+
+// a call, whose result is returned right away.
+// the caller's frame is no longer needed, so the callee can reuse it.
+pub fn is_tail_call(fun: &Function, instr: &Instr) -> bool {
+    let InstrData::Call { func: _, args_id: _ } = instr.data else { return false };
+    let Some(next) = instr.next().to_option() else { return false };
+    matches!(next.get(fun).data, InstrData::Return { src } if src == instr.id())
+}
+
+
 pub struct GenBytecodeResult {
     pub code:              Vec<InstrWord>,
     pub constants:         Vec<Constant>,
@@ -532,7 +835,13 @@ pub struct GenBytecodeResult {
     pub pc_to_node:        Vec<OptNodeId>,
 }
 
-pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &RegisterAllocation) -> GenBytecodeResult {
+pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &RegisterAllocation, numbers: &IndexVec<InstrId, bool>) -> GenBytecodeResult {
+    // retry with `wide` jumps, if some jump target doesn't fit into 16 bits.
+    generate_bytecode_ex(fun, block_order, regs, numbers, false)
+    .unwrap_or_else(|| generate_bytecode_ex(fun, block_order, regs, numbers, true).unwrap())
+}
+
+fn generate_bytecode_ex(fun: &Function, block_order: &BlockOrder, regs: &RegisterAllocation, numbers: &IndexVec<InstrId, bool>, wide_jumps: bool) -> Option<GenBytecodeResult> {
     assert_eq!(block_order[0], BlockId::ENTRY);
 
     // for instr in fun.instr_ids() {
@@ -540,9 +849,8 @@ pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &Regist
     // }
 
     // @temp
-    const NO_REG: u8 = 127;
+    const NO_REG: u32 = u16::MAX as u32;
     assert!(regs.num_regs < NO_REG as usize);
-    assert!(block_order.len() < u16::MAX as usize / 2);
 
     let num_regs = regs.num_regs as u32;
 
@@ -551,12 +859,50 @@ pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &Regist
     // the validator will detect this.
     let reg = |instr: InstrId| {
         regs.mapping[instr].to_option()
-        .unwrap_or(Reg(NO_REG as u32)).value() as u8
+        .unwrap_or(Reg(NO_REG)).value()
     };
 
-    let mut block_offsets = vec![u16::MAX; fun.num_blocks()];
+    // numeric comparisons that are only used by the `switch_bool` of their block
+    // are fused into a `jump_*_num`.
+    // the parallel copies between them must not overwrite the operands.
+    let mut fused = index_vec![false; fun.num_instrs()];
+    {
+        let mut num_uses = index_vec![0u32; fun.num_instrs()];
+        fun.all_args(|arg| num_uses[arg] += 1);
+
+        for bb in block_order.iter().copied() {
+            let Some(last) = bb.get(fun).last().to_option() else { continue };
+            let InstrData::SwitchBool { src, on_true: _, on_false: _ } = last.get(fun).data else { continue };
+
+            let cmp = src.get(fun);
+            let InstrData::Op2 { op, src1, src2 } = cmp.data else { continue };
+            if !op.is_comparison() || !numbers[src1] || !numbers[src2] {
+                continue;
+            }
+            if cmp.bb() != bb.some() || num_uses[src] != 1 {
+                continue;
+            }
+
+            let mut ok = true;
+            let mut at = cmp.next();
+            while let Some(instr) = at.to_option() {
+                if instr == last { break }
+
+                let InstrData::ParallelCopy { src: _, copy_id: _ } = instr.get(fun).data else { ok = false; break };
+                if reg(instr) == reg(src1) || reg(instr) == reg(src2) {
+                    ok = false;
+                    break;
+                }
+                at = instr.get(fun).next();
+            }
+            fused[src] = ok;
+        }
+    }
+
+    let mut block_offsets = vec![u32::MAX; fun.num_blocks()];
 
     let mut bcb = ByteCodeBuilder::new();
+    bcb.set_wide_jumps(wide_jumps);
 
     // @temp: constants builder (dedup).
     // @temp: @strings-first.
@@ -579,7 +925,7 @@ pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &Regist
     }
 
     for (block_index, bb) in block_order.iter().enumerate() {
-        block_offsets[bb.usize()] = bcb.current_offset() as u16;
+        block_offsets[bb.usize()] = bcb.current_offset() as u32;
 
         let next_bb = block_order.get(block_index + 1).cloned();
 
@@ -653,28 +999,28 @@ pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &Regist
                             // @temp: integers.
                             let c = constants.len();
                             constants.push(Constant::Number { value: value as f64 });
-                            bcb.load_const(dst, c as u16);
+                            bcb.load_const(dst, c as u32);
                         }
                     }
 
                     LoadFloat { value } => {
                         let c = constants.len();
                         constants.push(Constant::Number { value });
-                        bcb.load_const(dst, c as u16);
+                        bcb.load_const(dst, c as u32);
                     }
 
                     LoadString { id } => {
                         // @strings-first.
-                        bcb.load_const(dst, id.usize() as u16);
+                        bcb.load_const(dst, id.usize() as u32);
                     }
 
                     ListNew { values } => {
-                        let values: Vec<u8> = values.get(fun).iter().map(|arg| reg(*arg)).collect();
+                        let values: Vec<u32> = values.get(fun).iter().map(|arg| reg(*arg)).collect();
                         bcb.list_new(dst, &values);
                     }
 
                     TupleNew { values } => {
-                        let values: Vec<u8> = values.get(fun).iter().map(|arg| reg(*arg)).collect();
+                        let values: Vec<u32> = values.get(fun).iter().map(|arg| reg(*arg)).collect();
                         bcb.tuple_new(dst, &values);
                     }
 
@@ -682,6 +1028,11 @@ pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &Regist
                         bcb.load_unit(dst);
                     }
 
+                    TupleNewNamed { shape, values } => {
+                        let values: Vec<u32> = values.get(fun).iter().map(|arg| reg(*arg)).collect();
+                        bcb.tuple_new_named(dst, shape.usize() as u32, &values);
+                    }
+
                     ReadPath { path_id } => {
                         let path = path_id.get(fun);
 
@@ -693,7 +1044,7 @@ pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &Regist
 
                         let keys = path.keys.iter().map(|key| match key {
                             // @strings-first.
-                            PathKey::Field(field) => crate::bytecode::PathKey::Field { string: field.usize() as u16 },
+                            PathKey::Field(field) => crate::bytecode::PathKey::Field { string: field.usize() as u32 },
                             PathKey::Index(index) => crate::bytecode::PathKey::Index { reg: reg(*index) },
                         }).collect::<Vec<_>>();
 
@@ -715,16 +1066,48 @@ pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &Regist
 
                         let keys = path.keys.iter().map(|key| match key {
                             // @strings-first.
-                            PathKey::Field(field) => crate::bytecode::PathKey::Field { string: field.usize() as u16 },
+                            PathKey::Field(field) => crate::bytecode::PathKey::Field { string: field.usize() as u32 },
+                            PathKey::Index(index) => crate::bytecode::PathKey::Index { reg: reg(*index) },
+                        }).collect::<Vec<_>>();
+
+                        if is_def {
+                            bcb.write_path_def(base, reg(value), &keys);
+                        }
+                        else {
+                            bcb.write_path(base, reg(value), &keys);
+                        }
+                    }
+
+                    DeletePath { path_id } => {
+                        let path = path_id.get(fun);
+
+                        let base = match path.base {
+                            PathBase::Items => crate::bytecode::PathBase::ITEMS,
+                            PathBase::Env   => crate::bytecode::PathBase::ENV,
+                            PathBase::Instr(instr) => {
+                                let base = reg(instr);
+                                assert_eq!(dst, base);
+                                crate::bytecode::PathBase::reg(base)
+                            }
+                        };
+
+                        let keys = path.keys.iter().map(|key| match key {
+                            // @strings-first.
+                            PathKey::Field(field) => crate::bytecode::PathKey::Field { string: field.usize() as u32 },
                             PathKey::Index(index) => crate::bytecode::PathKey::Index { reg: reg(*index) },
                         }).collect::<Vec<_>>();
 
-                        bcb.write_path(base, &keys, reg(value), is_def);
+                        bcb.delete_path(base, &keys);
                     }
 
                     Call { func, args_id } => {
-                        let args: Vec<u8> = args_id.get(fun).iter().map(|arg| reg(*arg)).collect();
-                        bcb.call(dst, reg(func), &args);
+                        let args: Vec<u32> = args_id.get(fun).iter().map(|arg| reg(*arg)).collect();
+                        if is_tail_call(fun, instr) {
+                            bcb.tail_call(reg(func), &args);
+                        }
+                        else {
+                            bcb.call(dst, reg(func), &args);
+                        }
                     }
 
                     Op1 { op, src } => {
@@ -737,10 +1120,25 @@ pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &Regist
                     }
 
                     Op2 { op, src1, src2 } => {
+                        if fused[instr.id()] {
+                            return;
+                        }
+
+                        let is_num = numbers[src1] && numbers[src2];
                         let src1 = reg(src1);
                         let src2 = reg(src2);
                         use self::Op2::*;
                         match op {
+                            Add   if is_num => bcb.add_num(dst, src1, src2),
+                            Sub   if is_num => bcb.sub_num(dst, src1, src2),
+                            Mul   if is_num => bcb.mul_num(dst, src1, src2),
+                            CmpEq if is_num => bcb.cmp_eq_num(dst, src1, src2),
+                            CmpNe if is_num => bcb.cmp_ne_num(dst, src1, src2),
+                            CmpLe if is_num => bcb.cmp_le_num(dst, src1, src2),
+                            CmpLt if is_num => bcb.cmp_lt_num(dst, src1, src2),
+                            CmpGe if is_num => bcb.cmp_ge_num(dst, src1, src2),
+                            CmpGt if is_num => bcb.cmp_gt_num(dst, src1, src2),
+
                             Add         => bcb.add(dst, src1, src2),
                             Sub         => bcb.sub(dst, src1, src2),
                             Mul         => bcb.mul(dst, src1, src2),
@@ -759,33 +1157,73 @@ pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &Regist
                         }
                     }
 
+                    LoadMethod { traitt, recv, method } => {
+                        bcb.load_method(dst, reg(traitt), reg(recv), method);
+                    }
+
+                    Implements { src, traitt } => {
+                        bcb.implements(dst, reg(src), reg(traitt));
+                    }
+
                     Jump { target } => {
                         if Some(target) != next_bb {
-                            bcb.jump(target.usize() as u16);
+                            bcb.jump(target.usize() as u32);
                         }
                     }
 
                     // @todo-opt: special case if neither branch is next_bb.
                     SwitchBool { src, on_true, on_false } => {
+                        if fused[src] {
+                            let InstrData::Op2 { op, src1, src2 } = src.get(fun).data else { unreachable!() };
+                            let (src1, src2) = (reg(src1), reg(src2));
+
+                            let jump = |bcb: &mut ByteCodeBuilder, value: bool, target: BlockId| {
+                                let target = target.usize() as u32;
+                                use self::Op2::*;
+                                match op {
+                                    CmpEq => bcb.jump_eq_num(src1, src2, value, target),
+                                    CmpNe => bcb.jump_ne_num(src1, src2, value, target),
+                                    CmpLe => bcb.jump_le_num(src1, src2, value, target),
+                                    CmpLt => bcb.jump_lt_num(src1, src2, value, target),
+                                    CmpGe => bcb.jump_ge_num(src1, src2, value, target),
+                                    CmpGt => bcb.jump_gt_num(src1, src2, value, target),
+                                    _ => unreachable!()
+                                }
+                            };
+
+                            if Some(on_true) != next_bb {
+                                jump(bcb, true, on_true);
+                            }
+                            if Some(on_false) != next_bb {
+                                jump(bcb, false, on_false);
+                            }
+                            return;
+                        }
+
                         if Some(on_true) != next_bb {
-                            bcb.jump_true(reg(src), on_true.usize() as u16);
+                            bcb.jump_true(reg(src), on_true.usize() as u32);
                         }
                         if Some(on_false) != next_bb {
-                            bcb.jump_false(reg(src), on_false.usize() as u16);
+                            bcb.jump_false(reg(src), on_false.usize() as u32);
                         }
                     }
 
                     // @todo-opt: special case if neither branch is next_bb.
                     SwitchNil { src, on_nil, on_non_nil } => {
                         if Some(on_nil) != next_bb {
-                            bcb.jump_nil(reg(src), on_nil.usize() as u16);
+                            bcb.jump_nil(reg(src), on_nil.usize() as u32);
                         }
                         if Some(on_non_nil) != next_bb {
-                            bcb.jump_non_nil(reg(src), on_non_nil.usize() as u16);
+                            bcb.jump_not_nil(reg(src), on_non_nil.usize() as u32);
                         }
                     }
 
                     Return { src } => {
+                        // the `tail_call` returns.
+                        let prev = instr.prev().to_option().map(|prev| prev.get(fun));
+                        if prev.map(|prev| is_tail_call(fun, prev)).unwrap_or(false) {
+                            return;
+                        }
                         bcb.ret(reg(src));
                     }
                 }
@@ -797,55 +1235,53 @@ pub fn generate_bytecode(fun: &Function, block_order: &BlockOrder, regs: &Regist
 
     assert_eq!(pc_to_node.len(), code.len());
 
-    let mut i = 0;
-    while i < code.len() {
-        let instr = &mut code[i];
-        i += 1;
-
-        use crate::bytecode::opcode::*;
-        match instr.opcode() as u8 {
-            JUMP | JUMP_TRUE | JUMP_FALSE | JUMP_NIL | JUMP_NOT_NIL => {
-                let block = instr.u16() as usize;
-                instr.patch_u16(block_offsets[block]);
-            }
-
-            NOP | UNREACHABLE |
-            COPY | SWAP |
-            LOAD_NIL | LOAD_BOOL | LOAD_INT | LOAD_CONST | LOAD_ENV |
-            LIST_NEW |
-            TUPLE_NEW | LOAD_UNIT |
-            MAP_NEW |
-            READ_PATH | WRITE_PATH | WRITE_PATH_DEF |
-            ADD | SUB | MUL | DIV | FLOOR_DIV | REM |
-            ADD_INT | NEGATE |
-            NOT |
-            CMP_EQ | CMP_NE | CMP_LE | CMP_LT | CMP_GE | CMP_GT |
-            CALL | RET |
-            EXTRA
-            => (),
-
-            0 | END ..= 254 => unreachable!(),
+    // targets are block indices until now.
+    for instr in ByteCodeDecoder::decode_raw(&code).unwrap() {
+        for operand in &instr.operands {
+            if operand.kind != OperandKind::Target {
+                continue;
+            }
+
+            let target = block_offsets[operand.value as usize];
+            match operand.slot {
+                Slot::U16 => {
+                    code[operand.at as usize].patch_u16(target as u16);
+
+                    // the high bits go into the prefix, see `WideInstrWord`.
+                    if instr.is_wide() {
+                        let prefix = code[instr.pc as usize].wide();
+                        code[instr.pc as usize] = InstrWord::encode_wide(prefix & 0xff | (target >> 16) << 8);
+                    }
+                    else if target > u16::MAX as u32 {
+                        return None;
+                    }
+                }
+
+                Slot::Extra => code[operand.at as usize].patch_extra(target),
+
+                Slot::C1 | Slot::C2 | Slot::C3 | Slot::List => unreachable!()
+            }
         }
     }
 
-    GenBytecodeResult { code, constants, stack_size: num_regs, instr_index_to_pc, pc_to_node }
+    Some(GenBytecodeResult { code, constants, stack_size: num_regs, instr_index_to_pc, pc_to_node })
 }
 
-fn impl_parallel_copy(num_regs: usize, copied_to: &[Vec<u8>], bcb: &mut ByteCodeBuilder) {
+fn impl_parallel_copy(num_regs: usize, copied_to: &[Vec<u32>], bcb: &mut ByteCodeBuilder) {
     #[derive(Clone, Copy, Debug, PartialEq)]
     enum Status {
         None,
         Visited, // but not written to.
         Path,
-        Cycle      { prev: u8 },
-        CycleStart { last: u8 },
+        Cycle      { prev: u32 },
+        CycleStart { last: u32 },
     }
 
     let mut status = vec![Status::None; num_regs];
 
 
     // returns whether `src` is in a cycle starting at `src0`.
-    fn copy_paths(src0: usize, src: usize, copied_to: &[Vec<u8>], status: &mut [Status], bcb: &mut ByteCodeBuilder) -> bool {
+    fn copy_paths(src0: usize, src: usize, copied_to: &[Vec<u32>], status: &mut [Status], bcb: &mut ByteCodeBuilder) -> bool {
         debug_assert_eq!(status[src], Status::None);
         status[src] = Status::Visited;
 
@@ -858,11 +1294,11 @@ fn impl_parallel_copy(num_regs: usize, copied_to: &[Vec<u8>], bcb: &mut ByteCode
                 cycle = true;
 
                 assert_eq!(status[src0], Status::Visited);
-                status[src0] = Status::CycleStart { last: src as u8 };
+                status[src0] = Status::CycleStart { last: src as u32 };
             }
             else if status[dst] == Status::Visited {
                 status[dst] = Status::Path;
-                bcb.copy(dst as u8, src as u8);
+                bcb.copy(dst as u32, src as u32);
             }
             else {
                 assert_eq!(status[dst], Status::None);
@@ -872,11 +1308,11 @@ fn impl_parallel_copy(num_regs: usize, copied_to: &[Vec<u8>], bcb: &mut ByteCode
                     assert!(cycle == false);
                     cycle = true;
 
-                    status[dst] = Status::Cycle { prev: src as u8 };
+                    status[dst] = Status::Cycle { prev: src as u32 };
                 }
                 else {
                     status[dst] = Status::Path;
-                    bcb.copy(dst as u8, src as u8);
+                    bcb.copy(dst as u32, src as u32);
                 }
             }
             debug_assert!(status[dst] != Status::None && status[dst] != Status::Visited);
@@ -898,7 +1334,7 @@ fn impl_parallel_copy(num_regs: usize, copied_to: &[Vec<u8>], bcb: &mut ByteCode
                 continue;
             }
 
-            bcb.swap(src0 as u8, last);
+            bcb.swap(src0 as u32, last);
 
             let mut at = last as usize;
             loop {
@@ -906,7 +1342,7 @@ fn impl_parallel_copy(num_regs: usize, copied_to: &[Vec<u8>], bcb: &mut ByteCode
                 if prev as usize == src0 {
                     break
                 }
-                bcb.swap(at as u8, prev);
+                bcb.swap(at as u32, prev);
 
                 at = prev as usize;
             }
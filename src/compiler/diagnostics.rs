@@ -0,0 +1,276 @@
+use super::ast::{SourcePos, SourceRange};
+use super::parser::{ParseError, ParseErrorData};
+
+
+// error codes:
+//  E00xx: names, items & statements (infer).
+//  E01xx: types (infer).
+//  E02xx: lowering (bbir_builder).
+//  E03xx: syntax (parser).
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Note    => "note",
+            Severity::Warning => "warning",
+            Severity::Error   => "error",
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub source:  SourceRange,
+    pub message: String, // may be empty.
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity:  Severity,
+    pub code:      &'static str,
+    pub message:   String,
+    pub primary:   Label,
+    pub secondary: Vec<Label>,
+    pub notes:     Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, source: SourceRange, message: String) -> Self {
+        Diagnostic {
+            severity, code, message,
+            primary:   Label { source, message: String::new() },
+            secondary: vec![],
+            notes:     vec![],
+        }
+    }
+
+    #[inline(always)]
+    pub fn error(code: &'static str, source: SourceRange, message: String) -> Self {
+        Self::new(Severity::Error, code, source, message)
+    }
+
+    #[inline(always)]
+    pub fn warning(code: &'static str, source: SourceRange, message: String) -> Self {
+        Self::new(Severity::Warning, code, source, message)
+    }
+
+    pub fn with_label(mut self, message: String) -> Self {
+        self.primary.message = message;
+        self
+    }
+
+    pub fn with_secondary(mut self, source: SourceRange, message: String) -> Self {
+        self.secondary.push(Label { source, message });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+
+    // rustc style:
+    //  error[E0100]: expected Number, found String
+    //   --> main.kb:6:11
+    //    |
+    //  6 | let bad = s + 1
+    //    |           ^^^^^ label
+    //    |
+    //    = note: ...
+    pub fn render(&self, path: &str, source: &str, out: &mut String) {
+        use core::fmt::Write;
+
+        let lines: Vec<&str> = source.lines().collect();
+
+        let mut labels: Vec<(&Label, bool)> = vec![(&self.primary, true)];
+        labels.extend(self.secondary.iter().map(|label| (label, false)));
+        labels.sort_by_key(|(label, _)| (label.source.begin.line, label.source.begin.column));
+
+        let max_line = labels.iter().map(|(label, _)| label.source.begin.line).max().unwrap();
+        let width = max_line.to_string().len();
+        let pad = "";
+
+        _ = writeln!(out, "{}[{}]: {}", self.severity.name(), self.code, self.message);
+        _ = writeln!(out, "{pad:width$}--> {path}:{}", self.primary.source.begin);
+        _ = writeln!(out, "{pad:width$} |");
+
+        let mut prev_line = None;
+        for (label, is_primary) in labels {
+            let SourceRange { begin, end } = label.source;
+            let Some(line) = begin.line.checked_sub(1).and_then(|i| lines.get(i as usize)) else {
+                continue;
+            };
+
+            if prev_line != Some(begin.line) {
+                if prev_line.is_some_and(|prev| begin.line > prev + 1) {
+                    _ = writeln!(out, "{pad:width$} ...");
+                }
+                _ = writeln!(out, "{:width$} | {line}", begin.line);
+                prev_line = Some(begin.line);
+            }
+
+            // multi-line ranges are underlined to the end of the first line.
+            let (col_begin, col_end) = Self::columns(line, begin, end);
+            let marker = if is_primary { "^" } else { "-" };
+            _ = write!(out, "{pad:width$} | {pad:col_begin$}{}", marker.repeat(col_end - col_begin));
+            if !label.message.is_empty() {
+                _ = write!(out, " {}", label.message);
+            }
+            _ = writeln!(out);
+        }
+
+        if !self.notes.is_empty() {
+            _ = writeln!(out, "{pad:width$} |");
+        }
+        for note in &self.notes {
+            _ = writeln!(out, "{pad:width$} = note: {note}");
+        }
+    }
+
+    // source columns are byte offsets, carets are chars.
+    fn columns(line: &str, begin: SourcePos, end: SourcePos) -> (usize, usize) {
+        let to_chars = |column: usize| {
+            let mut column = column.min(line.len());
+            while !line.is_char_boundary(column) { column -= 1 }
+            line[..column].chars().count()
+        };
+
+        let begin_col = to_chars(begin.column.saturating_sub(1) as usize);
+        let end_col =
+            if end.line == begin.line { to_chars(end.column.saturating_sub(1) as usize) }
+            else { line.chars().count() };
+
+        (begin_col, end_col.max(begin_col + 1))
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(e: &ParseError) -> Self {
+        let message = match &e.data {
+            ParseErrorData::Expected(token)     => format!("expected {token:?}"),
+            ParseErrorData::ExpectedExpression  => "expected expression".to_string(),
+            ParseErrorData::UnexpectedEof       => "unexpected end of input".to_string(),
+            ParseErrorData::UnexpectedChar      => "unexpected character".to_string(),
+            ParseErrorData::TrailingInput       => "trailing input".to_string(),
+            ParseErrorData::TempWonkyString     => "invalid string".to_string(),
+        };
+        Diagnostic::error("E0300", e.source, message)
+    }
+}
+
+
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics { diagnostics: vec![] }
+    }
+
+    #[inline(always)]
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    #[inline(always)]
+    pub fn error(&mut self, code: &'static str, source: SourceRange, message: String) {
+        self.push(Diagnostic::error(code, source, message));
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool { self.diagnostics.is_empty() }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    #[inline(always)]
+    pub fn iter(&self) -> core::slice::Iter<'_, Diagnostic> { self.diagnostics.iter() }
+
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let mut out = String::new();
+        for diagnostic in &self.diagnostics {
+            diagnostic.render(path, source, &mut out);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(line: u32, begin: u32, end: u32) -> SourceRange {
+        SourceRange {
+            begin: SourcePos { line, column: begin },
+            end:   SourcePos { line, column: end },
+        }
+    }
+
+    #[test]
+    fn render() {
+        let source = "let s = \"hi\"\nlet n = 1\nlet bad = s + 1\n";
+
+        let mut diags = Diagnostics::new();
+        diags.push(Diagnostic::error("E0102", range(3, 11, 16), "invalid operands for Add: String and Number".into())
+            .with_label("String + Number".into())
+            .with_secondary(range(1, 5, 6), "defined here".into())
+            .with_note("only numbers can be added".into()));
+        diags.push(Diagnostic::warning("unused_local", range(2, 5, 6), "unused local \"n\"".into()));
+        assert!(diags.has_errors());
+
+        let expected = concat!(
+            "error[E0102]: invalid operands for Add: String and Number\n",
+            " --> main.kb:3:11\n",
+            "  |\n",
+            "1 | let s = \"hi\"\n",
+            "  |     - defined here\n",
+            "  ...\n",
+            "3 | let bad = s + 1\n",
+            "  |           ^^^^^ String + Number\n",
+            "  |\n",
+            "  = note: only numbers can be added\n",
+            "\n",
+            "warning[unused_local]: unused local \"n\"\n",
+            " --> main.kb:2:5\n",
+            "  |\n",
+            "2 | let n = 1\n",
+            "  |     ^\n",
+            "\n",
+        );
+        assert_eq!(diags.render("main.kb", source), expected);
+    }
+
+    #[test]
+    fn render_columns() {
+        // carets count chars, not bytes.
+        let source = "let ä = 1 + \"ö\"";
+        let diag = Diagnostic::error("E0102", range(1, 10, 18), "bad".into());
+        let mut out = String::new();
+        diag.render("main.kb", source, &mut out);
+        assert!(out.ends_with("1 | let ä = 1 + \"ö\"\n  |         ^^^^^^^\n"), "{out}");
+
+        // multi-line ranges end at the end of the first line.
+        let source = "let x = (1,\n  2)";
+        let diag = Diagnostic::error("E0001", SourceRange {
+            begin: SourcePos { line: 1, column: 9 },
+            end:   SourcePos { line: 2, column: 5 },
+        }, "bad".into());
+        let mut out = String::new();
+        diag.render("main.kb", source, &mut out);
+        assert!(out.ends_with("  |         ^^^\n"), "{out}");
+    }
+}
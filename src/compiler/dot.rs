@@ -0,0 +1,230 @@
+use core::fmt::Write;
+
+use super::*;
+
+
+// graphviz exporters, for debugging the analyses.
+// render with `dot -Tsvg fn0.dot -o fn0.svg`.
+
+
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"'  => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            _    => result.push(c),
+        }
+    }
+    result
+}
+
+
+impl Function {
+    pub fn cfg_dot(&self) -> String {
+        let mut result = String::new();
+        writeln!(result, "digraph {} {{", self.id()).unwrap();
+        self.write_cfg_dot(&mut result, "");
+        writeln!(result, "}}").unwrap();
+        result
+    }
+
+    pub fn dom_tree_dot(&self, idoms: &ImmediateDominators, dom_tree: &DomTree, frontiers: &DominanceFrontiers) -> String {
+        let mut result = String::new();
+        writeln!(result, "digraph {} {{", self.id()).unwrap();
+        self.write_dom_tree_dot(&mut result, "", idoms, dom_tree, frontiers);
+        writeln!(result, "}}").unwrap();
+        result
+    }
+
+    // requires ssa.
+    pub fn interference_dot(&self, idoms: &ImmediateDominators, interference: &Interference) -> String {
+        let mut result = String::new();
+        writeln!(result, "graph {} {{", self.id()).unwrap();
+        self.write_interference_dot(&mut result, "", false, idoms, interference);
+        writeln!(result, "}}").unwrap();
+        result
+    }
+
+
+    // blocks list their instructions.
+    // switch edges are labelled with the case they're taken in.
+    fn write_cfg_dot(&self, out: &mut String, prefix: &str) {
+        writeln!(out, "  node [shape=box, fontname=monospace];").unwrap();
+
+        for bb in self.block_ids() {
+            let mut label = format!("{}:\\l", bb);
+            self.block_instrs(bb, |instr| {
+                label.push_str(&escape(&instr.fmt(self).to_string()));
+                label.push_str("\\l");
+            });
+            writeln!(out, "  {}{} [label=\"{}\"];", prefix, bb, label).unwrap();
+        }
+
+        for bb in self.block_ids() {
+            let Some(last) = self.block_terminator(bb).to_option() else { continue };
+
+            let mut edge = |to: BlockId, label: &str| {
+                write!(out, "  {}{} -> {}{}", prefix, bb, prefix, to).unwrap();
+                if !label.is_empty() {
+                    write!(out, " [label=\"{}\"]", label).unwrap();
+                }
+                writeln!(out, ";").unwrap();
+            };
+
+            use InstrData::*;
+            match last.get(self).data {
+                Jump { target } => { edge(target, "") }
+                SwitchBool { src: _, on_true, on_false } => {
+                    edge(on_true,  "true");
+                    edge(on_false, "false");
+                }
+                SwitchNil { src: _, on_nil, on_non_nil } => {
+                    edge(on_nil,     "nil");
+                    edge(on_non_nil, "non-nil");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // tree edges are solid, dominance frontier edges are dashed.
+    fn write_dom_tree_dot(&self, out: &mut String, prefix: &str, idoms: &ImmediateDominators, dom_tree: &DomTree, frontiers: &DominanceFrontiers) {
+        writeln!(out, "  node [shape=ellipse];").unwrap();
+
+        for bb in self.block_ids() {
+            if idoms.is_unreachable(bb) {
+                continue;
+            }
+            writeln!(out, "  {}{} [label=\"{}\"];", prefix, bb, bb).unwrap();
+        }
+
+        for bb in self.block_ids() {
+            for child in dom_tree[bb].iter() {
+                writeln!(out, "  {}{} -> {}{};", prefix, bb, prefix, child).unwrap();
+            }
+            for frontier in frontiers[bb].iter() {
+                writeln!(out, "  {}{} -> {}{} [style=dashed, constraint=false];",
+                    prefix, bb, prefix, frontier).unwrap();
+            }
+        }
+    }
+
+    // an edge per interfering pair of values.
+    // copies are dotted edges to their source, they're coalescing candidates.
+    fn write_interference_dot(&self, out: &mut String, prefix: &str, directed: bool, idoms: &ImmediateDominators, interference: &Interference) {
+        writeln!(out, "  node [shape=circle];").unwrap();
+
+        let (op, dir) = if directed { ("->", ", dir=none") } else { ("--", "") };
+
+        let mut values = vec![];
+        for bb in self.block_ids() {
+            if idoms.is_unreachable(bb) {
+                continue;
+            }
+            self.block_instrs(bb, |instr| {
+                if instr.has_value() {
+                    values.push(instr.id());
+                }
+            });
+        }
+
+        for value in &values {
+            writeln!(out, "  {}{} [label=\"{}\"];", prefix, value, value).unwrap();
+        }
+
+        for (i, a) in values.iter().copied().enumerate() {
+            for b in values[i+1..].iter().copied() {
+                if interference.interfere(a, b) {
+                    writeln!(out, "  {}{} {} {}{} [style=solid{}];", prefix, a, op, prefix, b, dir).unwrap();
+                }
+            }
+
+            if let Some(src) = a.get(self).try_any_copy() {
+                writeln!(out, "  {}{} {} {}{} [style=dotted{}];", prefix, a, op, prefix, src, dir).unwrap();
+            }
+        }
+    }
+}
+
+
+impl Crate {
+    // writes `<dir>/fn<id>.dot` for each function,
+    // with the cfg, the dominator tree & the interference graph.
+    pub fn write_dot_files(&self, dir: &std::path::Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+
+        for i in 0..self.num_functions() {
+            let fun = self.read_function(FunctionId::from_usize(i));
+
+            let mut analyses = Analyses::new();
+            analyses.ensure(&fun, Analysis::DomTree);
+            analyses.ensure(&fun, Analysis::DominanceFrontiers);
+            let interference = fun.interference(analyses.post_order(), analyses.idoms());
+
+            let mut result = String::new();
+            writeln!(result, "digraph {} {{", fun.id()).unwrap();
+            writeln!(result, "  subgraph cluster_cfg {{\n  label=\"cfg\";").unwrap();
+            fun.write_cfg_dot(&mut result, "cfg_");
+            writeln!(result, "  }}").unwrap();
+            writeln!(result, "  subgraph cluster_dom {{\n  label=\"dominator tree\";").unwrap();
+            fun.write_dom_tree_dot(&mut result, "dom_", analyses.idoms(), analyses.dom_tree(), analyses.dom_frontiers());
+            writeln!(result, "  }}").unwrap();
+            writeln!(result, "  subgraph cluster_interference {{\n  label=\"interference\";").unwrap();
+            fun.write_interference_dot(&mut result, "live_", true, analyses.idoms(), &interference);
+            writeln!(result, "  }}").unwrap();
+            writeln!(result, "}}").unwrap();
+
+            std::fs::write(dir.join(format!("{}.dot", fun.id())), result)?;
+        }
+        Ok(())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn dot_diamond() {
+        let mut krate = Crate::new();
+        let mut fun = krate.new_function();
+        bbir_text::parse_function(&mut fun, "
+            local l0 \"a\"
+            bb0:
+              i0 := param l0
+              i1    switch_bool i0, bb1, bb2
+            bb1:
+              i2 := load_int 1
+              i3    jump bb3
+            bb2:
+              i4 := load_int 2
+              i5    jump bb3
+            bb3:
+              i6 := phi { bb1: i2, bb2: i4 }
+              i7 := add i6, i0
+              i8    return i7
+        ").unwrap();
+
+        let cfg = fun.cfg_dot();
+        assert!(cfg.contains("bb0 -> bb1 [label=\"true\"];"));
+        assert!(cfg.contains("bb0 -> bb2 [label=\"false\"];"));
+        assert!(cfg.contains("i6 := phi { bb1: i2, bb2: i4 }\\l"));
+
+        let mut analyses = Analyses::new();
+        analyses.ensure(&fun, Analysis::DomTree);
+        analyses.ensure(&fun, Analysis::DominanceFrontiers);
+
+        let dom = fun.dom_tree_dot(analyses.idoms(), analyses.dom_tree(), analyses.dom_frontiers());
+        assert!(dom.contains("bb0 -> bb3;"));
+        assert!(dom.contains("bb1 -> bb3 [style=dashed, constraint=false];"));
+
+        let interference = fun.interference(analyses.post_order(), analyses.idoms());
+        let live = fun.interference_dot(analyses.idoms(), &interference);
+        assert!(live.contains("i0 -- i2"));
+        assert!(!live.contains("i2 -- i4"));
+    }
+}
@@ -1,8 +1,10 @@
 use crate::macros::define_id;
+use crate::ValueKind;
 use super::ast::*;
+use super::diagnostics::*;
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Type {
     None,
     Error,
@@ -14,23 +16,220 @@ pub enum Type {
     String,
     Unit,
     Tuple       (Box<[Type]>), // this is actually 16 bytes, oops.
+    NamedTuple  (Box<NamedTuple>),
     List        (Box<Type>),
     Map         (Box<(Type, Type)>),
     Func        (Box<(Box<[Type]>, Type)>),
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedTuple {
+    pub names: Box<[String]>,
+    pub types: Box<[Type]>,
+}
+
+impl Type {
+    // the runtime kind of values of this type, if there is exactly one.
+    pub fn value_kind(&self) -> Option<ValueKind> {
+        match self {
+            Type::None | Type::Error | Type::Any => None,
+
+            Type::Nil       => Some(ValueKind::Nil),
+            Type::Bool      => Some(ValueKind::Bool),
+            Type::Number    => Some(ValueKind::Number),
+            Type::String    => Some(ValueKind::String),
+            Type::Unit      => Some(ValueKind::Unit),
+            Type::Tuple(values) => {
+                if values.is_empty() { Some(ValueKind::Unit) }
+                else                 { Some(ValueKind::Tuple) }
+            }
+            Type::NamedTuple(_) => Some(ValueKind::Tuple),
+            Type::List(_)   => Some(ValueKind::List),
+            Type::Map(_)    => Some(ValueKind::Map),
+            Type::Func(_)   => Some(ValueKind::Func),
+        }
+    }
+
+    // the type of a phi.
+    // `None` is the type of diverging expressions, so it's the identity.
+    // types without a common structure join to `Any`.
+    pub fn join(&self, other: &Type) -> Type {
+        match (self, other) {
+            (Type::None, _) => other.clone(),
+            (_, Type::None) => self.clone(),
+
+            (Type::Error, _) | (_, Type::Error) => Type::Error,
+
+            (Type::List(a), Type::List(b)) => Type::List(Box::new(a.join(b))),
+
+            (Type::Tuple(a), Type::Tuple(b)) if a.len() == b.len() =>
+                Type::Tuple(a.iter().zip(b.iter()).map(|(a, b)| a.join(b)).collect()),
+
+            (Type::NamedTuple(a), Type::NamedTuple(b)) if a.names == b.names =>
+                Type::NamedTuple(Box::new(NamedTuple {
+                    names: a.names.clone(),
+                    types: a.types.iter().zip(b.types.iter()).map(|(a, b)| a.join(b)).collect(),
+                })),
+
+            _ => if self == other { self.clone() } else { Type::Any }
+        }
+    }
+
+    // whether a value of this type can be used, where `expected` is expected.
+    // `Any` is compatible with everything, in both directions.
+    // `None` & `Error` are too, so errors don't cascade.
+    pub fn is_compatible(&self, expected: &Type) -> bool {
+        match (self, expected) {
+            (Type::None | Type::Error | Type::Any, _) |
+            (_, Type::None | Type::Error | Type::Any) => true,
+
+            (Type::List(a), Type::List(b)) => a.is_compatible(b),
+
+            (Type::Tuple(a), Type::Tuple(b)) =>
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.is_compatible(b)),
+
+            (Type::NamedTuple(a), Type::NamedTuple(b)) =>
+                a.names == b.names && a.types.iter().zip(b.types.iter()).all(|(a, b)| a.is_compatible(b)),
+
+            (Type::Map(a), Type::Map(b)) => a.0.is_compatible(&b.0) && a.1.is_compatible(&b.1),
+
+            (Type::Func(a), Type::Func(b)) => a.0.len() == b.0.len(),
+
+            _ => self == expected,
+        }
+    }
+}
+
+impl core::fmt::Display for Type {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fn list(f: &mut core::fmt::Formatter<'_>, types: &[Type]) -> core::fmt::Result {
+            for (i, ty) in types.iter().enumerate() {
+                if i > 0 { write!(f, ", ")? }
+                write!(f, "{ty}")?;
+            }
+            Ok(())
+        }
 
+        match self {
+            Type::None      => write!(f, "None"),
+            Type::Error     => write!(f, "{{error}}"),
+            Type::Any       => write!(f, "Any"),
+            Type::Nil       => write!(f, "Nil"),
+            Type::Bool      => write!(f, "Bool"),
+            Type::Number    => write!(f, "Number"),
+            Type::String    => write!(f, "String"),
+            Type::Unit      => write!(f, "()"),
+            Type::Tuple(types) => {
+                write!(f, "(")?;
+                list(f, types)?;
+                if types.len() == 1 { write!(f, ",")? }
+                write!(f, ")")
+            }
+            Type::NamedTuple(tuple) => {
+                write!(f, "(")?;
+                for (i, (name, ty)) in tuple.names.iter().zip(tuple.types.iter()).enumerate() {
+                    if i > 0 { write!(f, ", ")? }
+                    write!(f, "{name}: {ty}")?;
+                }
+                write!(f, ")")
+            }
+            Type::List(ty)  => write!(f, "[{ty}]"),
+            Type::Map(kv)   => write!(f, "{{{}: {}}}", kv.0, kv.1),
+            Type::Func(sig) => {
+                write!(f, "fn(")?;
+                list(f, &sig.0)?;
+                write!(f, ") -> {}", sig.1)
+            }
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub enum TypeErrorKind {
+    Mismatch        { expected: Type, found: Type },
+    InvalidOp1      { op: Op1, ty: Type },
+    InvalidOp2      { op: Op2, lhs: Type, rhs: Type },
+    NotCallable     (Type),
+    NotIndexable    (Type),
+    NoField         { ty: Type, name: String },
+    TupleIndex      { ty: Type, index: usize },
+    ArgCount        { expected: usize, found: usize },
+}
 
-pub struct Infer {
+impl TypeErrorKind {
+    pub fn code(&self) -> &'static str {
+        use TypeErrorKind::*;
+        match self {
+            Mismatch     { .. } => "E0100",
+            InvalidOp1   { .. } => "E0101",
+            InvalidOp2   { .. } => "E0102",
+            NotCallable  (_)    => "E0103",
+            NotIndexable (_)    => "E0104",
+            NoField      { .. } => "E0105",
+            TupleIndex   { .. } => "E0106",
+            ArgCount     { .. } => "E0107",
+        }
+    }
+}
+
+impl core::fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use TypeErrorKind::*;
+        match self {
+            Mismatch { expected, found } => write!(f, "expected {expected}, found {found}"),
+            InvalidOp1 { op, ty } => write!(f, "invalid operand for {op:?}: {ty}"),
+            InvalidOp2 { op, lhs, rhs } => write!(f, "invalid operands for {op:?}: {lhs} and {rhs}"),
+            NotCallable (ty) => write!(f, "{ty} is not callable"),
+            NotIndexable (ty) => write!(f, "{ty} can't be indexed"),
+            NoField { ty, name } => write!(f, "{ty} has no field {name:?}"),
+            TupleIndex { ty, index } => write!(f, "index {index} is out of bounds for {ty}"),
+            ArgCount { expected, found } => write!(f, "expected {expected} arguments, found {found}"),
+        }
+    }
+}
+
+
+#[derive(Clone)]
+struct TraitInfo {
+    item:    ItemId,
+    name:    String,
+    methods: Vec<(String, usize)>, // name, num_params.
+    impls:   Vec<(ValueKind, Vec<ItemId>)>,
+}
+
+
+
+pub struct Infer<'d> {
     prev_item_id: ItemId,
     prev_node_id: NodeId,
+    traits:       Vec<TraitInfo>,
+    item_types:   Vec<Type>,
+    diags:        &'d mut Diagnostics,
 }
 
-impl Infer {
-    pub fn new() -> Self {
+impl<'d> Infer<'d> {
+    pub fn new(diags: &'d mut Diagnostics) -> Self {
         Infer {
             prev_item_id: ItemId::ZERO,
             prev_node_id: NodeId::ZERO,
+            traits:       vec![],
+            item_types:   vec![],
+            diags,
+        }
+    }
+
+    fn type_error(&mut self, source: SourceRange, kind: TypeErrorKind) {
+        let mut diag = Diagnostic::error(kind.code(), source, kind.to_string());
+        if let TypeErrorKind::Mismatch { expected, found: _ } = &kind {
+            diag = diag.with_label(format!("expected {expected}"));
+        }
+        self.diags.push(diag);
+    }
+
+    fn expect_type(&mut self, source: SourceRange, found: &Type, expected: &Type) {
+        if !found.is_compatible(expected) {
+            self.type_error(source, TypeErrorKind::Mismatch { expected: expected.clone(), found: found.clone() });
         }
     }
 
@@ -54,13 +253,18 @@ impl Infer {
                 let id0 = self.prev_node_id;
                 match &mut item.data {
                     ItemData::Module(module) => {
-                        let _ = module;
-                        unimplemented!()
+                        self.assign_ids_block(&mut module.block.stmts);
                     }
 
                     ItemData::Func(func) => {
                         self.assign_ids_block(&mut func.body);
                     }
+
+                    ItemData::Trait(_) => {}
+
+                    ItemData::Impl(imp) => {
+                        self.assign_ids_block(&mut imp.methods);
+                    }
                 }
                 let id1 = self.prev_node_id;
                 item.num_nodes = id1.value() - id0.value();
@@ -72,6 +276,8 @@ impl Infer {
                 }
             }
 
+            StmtData::Delete (delete) => { self.assign_ids_expr(&mut delete.target); }
+
             StmtData::Expr (expr) => { self.assign_ids_expr(expr); }
 
             StmtData::Empty => (),
@@ -162,6 +368,11 @@ impl Infer {
             }
 
             ExprData::Env => {}
+
+            ExprData::Implements (implements) => {
+                self.assign_ids_expr(&mut implements.value);
+                self.assign_ids_expr(&mut implements.traitt);
+            }
         }
     }
 
@@ -173,43 +384,137 @@ impl Infer {
 
 
     pub fn infer(&mut self, module: &mut item::Module) {
+        self.item_types.resize(self.prev_item_id.usize() + 1, Type::Any);
         self.infer_module(module);
     }
 
     fn infer_module(&mut self, module: &mut item::Module) {
-        let mut ctx = InferCtx::new(None);
-        self.infer_block(&mut ctx, &mut module.block.stmts);
+        self.infer_fixpoint(None, |this, ctx| {
+            this.infer_block(ctx, &mut module.block.stmts);
+        });
+    }
+
+    // a `var` has the join of its initializer & all of its assignments.
+    // those can come after a use, or later in a loop, so the body is
+    // inferred again until the `var` types don't change anymore.
+    fn infer_fixpoint<R>(&mut self, parent: Option<&InferCtx>, mut f: impl FnMut(&mut Self, &mut InferCtx) -> R) -> (InferCtx, R) {
+        const MAX_PASSES: u32 = 8;
+
+        let num_diags = self.diags.diagnostics.len();
+        let traits    = self.traits.clone();
+
+        let mut assumed = vec![];
+        let mut pass = 0;
+        loop {
+            let mut ctx = InferCtx::new(parent);
+            ctx.assumed = assumed;
+
+            let result = f(self, &mut ctx);
+
+            let Some(next) = ctx.next_assumed() else {
+                return (ctx, result);
+            };
+
+            // undo the pass.
+            self.diags.diagnostics.truncate(num_diags);
+            self.traits = traits.clone();
+
+            assumed = next;
+
+            // give up, `Any` is always a fixed point.
+            pass += 1;
+            if pass == MAX_PASSES {
+                for (ty, var) in assumed.iter_mut().zip(&ctx.vars) {
+                    if var.is_some() {
+                        *ty = Type::Any;
+                    }
+                }
+            }
+        }
     }
 
     fn infer_stmt(&mut self, ctx: &mut InferCtx, stmt: &mut Stmt) {
         match &mut stmt.data {
             StmtData::Item (item) => {
                 match &mut item.data {
-                    ItemData::Module(module) => {
-                        let _ = module;
-                        unimplemented!()
+                    ItemData::Module(_) => {
+                        self.diags.error("E0019", item.source, "modules are not supported".to_string());
                     }
 
                     ItemData::Func(func) => {
-                        let mut fctx = InferCtx::new(Some(ctx));
+                        let (fctx, body) = self.infer_fixpoint(Some(ctx), |this, fctx| {
+                            for param in &func.params {
+                                fctx.add_local_decl(stmt.id, param.name, Type::Any);
+                            }
 
-                        for param in &func.params {
-                            fctx.add_local_decl(stmt.id, param.name);
-                        }
+                            this.infer_value_block(fctx, &mut func.body, None)
+                        });
+                        let ret = body.join(&fctx.ret_ty);
+
+                        let params = vec![Type::Any; func.params.len()].into_boxed_slice();
+                        self.item_types[item.id.usize()] = Type::Func(Box::new((params, ret)));
+                    }
 
-                        self.infer_value_block(&mut fctx, &mut func.body, None);
+                    ItemData::Trait(_) => {}
+
+                    ItemData::Impl(imp) => {
+                        for method in &mut imp.methods {
+                            self.infer_stmt(ctx, method);
+                        }
                     }
                 }
             }
 
             StmtData::Local (local) => {
-                if let Some(value) = &mut local.value {
-                    self.infer_expr(ctx, value, None);
-                }
-                let lid = ctx.add_local_decl(stmt.id, local.name);
+                let lid = match local.kind {
+                    expr::LocalKind::Let => {
+                        let mut ty = Type::Any;
+                        if let Some(value) = &mut local.value {
+                            ty = self.infer_expr(ctx, value, None);
+                        }
+                        ctx.add_local_decl(stmt.id, local.name, ty)
+                    }
+
+                    expr::LocalKind::Var => {
+                        // a `var` without a value starts out as unit.
+                        let mut ty = Type::Unit;
+                        if let Some(value) = &mut local.value {
+                            ty = self.infer_expr(ctx, value, None);
+                        }
+                        ctx.add_var_decl(stmt.id, local.name, ty)
+                    }
+                };
                 local.info = Some(expr::LocalInfo { id: lid });
             }
 
+            StmtData::Delete (delete) => {
+                let target = &mut delete.target;
+                if let ExprData::Field(_) | ExprData::Index(_) = target.data {
+                    target.ty = Some(self.infer_path(ctx, target, None));
+
+                    if let ExprData::Field(field) = &target.data {
+                        if field.info.is_some() {
+                            self.diags.push(Diagnostic::error("E0002", target.source,
+                                format!("can't delete tuple field {:?}", field.name))
+                                .with_note("tuples have a fixed shape".to_string()));
+                        }
+                    }
+                    if let ExprData::Index(index) = &target.data {
+                        if let Some(Type::Tuple(_) | Type::NamedTuple(_)) = &index.base.ty {
+                            self.diags.push(Diagnostic::error("E0002", target.source,
+                                "can't delete tuple element".to_string())
+                                .with_note("tuples have a fixed shape".to_string()));
+                        }
+                    }
+                }
+                else {
+                    self.diags.push(Diagnostic::error("E0001", target.source,
+                        "invalid delete target".to_string())
+                        .with_note("only fields and indices can be deleted".to_string()));
+                    target.ty = Some(Type::Error);
+                }
+            }
+
             StmtData::Expr (expr) => { self.infer_expr(ctx, expr, None); }
 
             StmtData::Empty => (),
@@ -219,7 +524,7 @@ impl Infer {
     fn infer_expr(&mut self, ctx: &mut InferCtx, expr: &mut Expr, expected_ty: Option<&Type>) -> Type {
         let ty = match &mut expr.data {
             ExprData::Nil => {
-                Type::Any
+                Type::Nil
             }
 
             ExprData::Bool (_) => {
@@ -241,7 +546,7 @@ impl Infer {
                 else {
                     ident.info = Some(expr::IdentInfo { target: expr::IdentTarget::Dynamic });
                 }
-                Type::Any
+                self.ident_type(ctx, ident.info.unwrap().target)
             }
 
 
@@ -250,14 +555,34 @@ impl Infer {
                 for value in &mut tuple.values {
                     types.push(self.infer_expr(ctx, value, None));
                 }
-                Type::Tuple(types.into_boxed_slice())
+
+                if !tuple.names.is_empty() {
+                    for (i, name) in tuple.names.iter().enumerate() {
+                        if tuple.names[..i].contains(name) {
+                            self.diags.error("E0003", expr.source, format!("duplicate tuple field {name:?}"));
+                        }
+                    }
+
+                    let names = tuple.names.iter().map(|name| name.to_string()).collect();
+                    Type::NamedTuple(Box::new(NamedTuple { names, types: types.into_boxed_slice() }))
+                }
+                else if types.is_empty() {
+                    Type::Unit
+                }
+                else {
+                    Type::Tuple(types.into_boxed_slice())
+                }
             }
 
             ExprData::List (list) => {
+                let mut ty = Type::None;
                 for value in &mut list.values {
-                    self.infer_expr(ctx, value, None);
+                    ty = ty.join(&self.infer_expr(ctx, value, None));
                 }
-                Type::List(Box::new(Type::Any))
+                if ty == Type::None {
+                    ty = Type::Any;
+                }
+                Type::List(Box::new(ty))
             }
 
             ExprData::Do (doo) => {
@@ -269,8 +594,8 @@ impl Infer {
             }
 
             ExprData::Op1 (op1) => {
-                self.infer_expr(ctx, &mut op1.child, None);
-                Type::Any
+                let ty = self.infer_expr(ctx, &mut op1.child, None);
+                self.infer_op1(expr.source, op1.kind.0, ty)
             }
 
             ExprData::Op2 (op2) => {
@@ -280,7 +605,7 @@ impl Infer {
 
                         if let ExprData::Tuple(lhs) = &mut op_lhs.data {
                             if op2.kind != expr::Op2Kind::Assign {
-                                println!("error: tried to define tuple");
+                                self.diags.error("E0004", expr.source, "can't define a tuple".to_string());
                             }
 
                             let lhs = &mut lhs.values;
@@ -288,7 +613,8 @@ impl Infer {
                             if let ExprData::Tuple(rhs) = &mut op_rhs.data {
                                 let rhs = &mut rhs.values;
                                 if lhs.len() != rhs.len() {
-                                    println!("error: tuple assign length mismatch");
+                                    self.diags.error("E0005", expr.source,
+                                        format!("can't assign {} values to {} targets", rhs.len(), lhs.len()));
                                 }
 
                                 for i in 0..lhs.len() {
@@ -301,7 +627,13 @@ impl Infer {
                                 op_rhs.ty = Some(Type::Any);
                             }
                             else {
-                                unimplemented!()
+                                self.infer_expr(ctx, op_rhs, None);
+                                for lhs in lhs.iter_mut() {
+                                    self.infer_assign(ctx, lhs, &Type::Any, false);
+                                }
+                                self.diags.push(Diagnostic::error("E0005", op_rhs.source,
+                                    "can't destructure a non-tuple expression".to_string())
+                                    .with_note("the right hand side must be a tuple literal".to_string()));
                             }
 
                             op_lhs.ty = Some(Type::Any);
@@ -316,15 +648,23 @@ impl Infer {
 
                     expr::Op2Kind::Op2Assign(op) => {
                         let [src1, src2] = &mut op2.children;
-                        let src1 = self.infer_expr(ctx, src1, None);
-                        let src2 = self.infer_expr(ctx, src2, None);
+                        let src1_ty = self.infer_expr(ctx, src1, None);
+                        let src2_ty = self.infer_expr(ctx, src2, None);
 
-                        // todo: do op analysis w/ src1,2.
-                        let _ = (op, src1, src2);
-                        let value = Type::Any;
+                        let value = self.infer_op2(expr.source, op, &src1_ty, &src2_ty);
 
-                        let is_define = false;
-                        self.infer_assign(ctx, &mut op2.children[0], &value, is_define);
+                        let is_var = Self::join_path(src1, value.clone())
+                            .is_some_and(|(target, root)| ctx.join_var(target, &root));
+
+                        // the target was already inferred as `src1`.
+                        if is_var {}
+                        else if let ExprData::Ident(_) | ExprData::Field(_) | ExprData::Index(_) = src1.data {
+                            self.expect_type(expr.source, &value, &src1_ty);
+                        }
+                        else {
+                            let is_define = false;
+                            self.infer_assign(ctx, &mut op2.children[0], &value, is_define);
+                        }
 
                         Type::Unit
                     }
@@ -333,12 +673,7 @@ impl Infer {
                         let [src1, src2] = &mut op2.children;
                         let src1 = self.infer_expr(ctx, src1, None);
                         let src2 = self.infer_expr(ctx, src2, None);
-
-                        // todo: do op analysis w/ src1,2.
-                        let _ = (op, src1, src2);
-                        let result = Type::Any;
-
-                        result
+                        self.infer_op2(expr.source, op, &src1, &src2)
                     }
                 }
             }
@@ -349,24 +684,78 @@ impl Infer {
             }
 
             ExprData::Call (call) => {
-                self.infer_expr(ctx, &mut call.func, None);
+                let func = self.infer_expr(ctx, &mut call.func, None);
+
+                let mut args = Vec::with_capacity(call.args.len());
                 for arg in &mut call.args {
-                    self.infer_expr(ctx, arg, None);
+                    args.push(self.infer_expr(ctx, arg, None));
+                }
+
+                let mut target = expr::CallTarget::Value;
+                if let Some((traitt, method)) = self.try_trait_method(&call.func) {
+                    let info = self.traits.iter().find(|t| t.item == traitt).unwrap();
+                    let (name, num_params) = &info.methods[method];
+
+                    if args.len() != *num_params {
+                        let kind = TypeErrorKind::ArgCount { expected: *num_params, found: args.len() };
+                        self.diags.push(Diagnostic::error(kind.code(), expr.source, kind.to_string())
+                            .with_note(format!("{}.{} takes {} arguments", info.name, name, num_params)));
+                    }
+
+                    let imp = args.first().and_then(Type::value_kind).and_then(|kind|
+                        info.impls.iter().find(|(k, _)| *k == kind));
+
+                    target =
+                        if let Some((_, methods)) = imp {
+                            expr::CallTarget::StaticMethod { func: methods[method] }
+                        }
+                        else {
+                            expr::CallTarget::DynamicMethod { traitt, method: method as u32 }
+                        };
+                }
+                let is_method = !matches!(target, expr::CallTarget::Value);
+                call.info = Some(expr::CallInfo { target });
+
+                match func {
+                    Type::Func(sig) => {
+                        let (params, ret) = *sig;
+                        if args.len() != params.len() {
+                            self.type_error(expr.source,
+                                TypeErrorKind::ArgCount { expected: params.len(), found: args.len() });
+                        }
+                        for (i, (arg, param)) in args.iter().zip(params.iter()).enumerate() {
+                            self.expect_type(call.args[i].source, arg, param);
+                        }
+                        ret
+                    }
+
+                    Type::Any | Type::None | Type::Error => Type::Any,
+
+                    _ if is_method => Type::Any,
+
+                    _ => {
+                        self.type_error(call.func.source, TypeErrorKind::NotCallable(func));
+                        Type::Error
+                    }
                 }
-                Type::Any
             }
 
             ExprData::If (iff) => {
-                self.infer_expr(ctx, &mut iff.condition, Some(&Type::Bool));
-                self.infer_if_block(ctx, expr.id, &mut iff.on_true, None);
-                if let Some(on_false) = &mut iff.on_false {
-                    self.infer_if_block(ctx, expr.id, on_false, None);
-                }
-                Type::Any
+                let cond = self.infer_expr(ctx, &mut iff.condition, Some(&Type::Bool));
+                self.expect_type(iff.condition.source, &cond, &Type::Bool);
+
+                let on_true = self.infer_if_block(ctx, expr.id, &mut iff.on_true, None);
+                let on_false =
+                    if let Some(on_false) = &mut iff.on_false {
+                        self.infer_if_block(ctx, expr.id, on_false, None)
+                    }
+                    else { Type::Unit };
+                on_true.join(&on_false)
             }
 
             ExprData::While (whilee) => {
-                self.infer_expr(ctx, &mut whilee.condition, Some(&Type::Bool));
+                let cond = self.infer_expr(ctx, &mut whilee.condition, Some(&Type::Bool));
+                self.expect_type(whilee.condition.source, &cond, &Type::Bool);
 
                 let bs = ctx.begin_break_scope(expr.id, whilee.label, true, Type::None);
                 self.infer_block(ctx, &mut whilee.body);
@@ -376,38 +765,56 @@ impl Infer {
             }
 
             ExprData::Break (brk) => {
-                let target = ctx.current_break_target(expr.source, brk.label);
+                let target = ctx.current_break_target(self.diags, expr.source, brk.label);
                 brk.info = Some(target.map(|bs| expr::BreakInfo {
                     node: bs.node, scope_index: bs.index }));
 
-                if let Some(value) = &mut brk.value {
-                    let ty = target.map(|bs| bs.ty.clone()).unwrap_or(Type::Any);
-                    self.infer_expr(ctx, value, Some(&ty));
+                let scope = target.map(|bs| bs.index as usize);
+
+                let ty =
+                    if let Some(value) = &mut brk.value {
+                        let expected = scope.map(|i| ctx.break_scopes[i].ty.clone()).unwrap_or(Type::Any);
+                        self.infer_expr(ctx, value, Some(&expected))
+                    }
+                    else { Type::Unit };
+
+                if let Some(i) = scope {
+                    let bs = &mut ctx.break_scopes[i];
+                    bs.ty = bs.ty.join(&ty);
                 }
 
-                Type::Unit
+                Type::None
             }
 
             ExprData::Continue (cont) => {
-                let target = ctx.current_continue_target(expr.source, cont.label);
+                let target = ctx.current_continue_target(self.diags, expr.source, cont.label);
                 cont.info = Some(target.map(|bs| expr::BreakInfo {
                     node: bs.node, scope_index: bs.index }));
 
-                Type::Unit
+                Type::None
             }
 
             ExprData::Return (ret) => {
-                if let Some(value) = &mut ret.value {
-                    self.infer_expr(ctx, value, None);
-                }
-                Type::Unit
+                let ty =
+                    if let Some(value) = &mut ret.value {
+                        self.infer_expr(ctx, value, None)
+                    }
+                    else { Type::Unit };
+                ctx.ret_ty = ctx.ret_ty.join(&ty);
+                Type::None
             }
 
             ExprData::Env => {
                 // @temp-no-env-access.
-                println!("error {}: can't read ENV.", expr.source);
+                self.diags.error("E0006", expr.source, "can't read ENV".to_string());
                 Type::Any
             }
+
+            ExprData::Implements (implements) => {
+                self.infer_expr(ctx, &mut implements.value, None);
+                self.infer_expr(ctx, &mut implements.traitt, None);
+                Type::Bool
+            }
         };
         expr.ty = Some(ty.clone());
         ty
@@ -419,14 +826,89 @@ impl Infer {
 
         match &mut expr.data {
             ExprData::Field (field) => {
-                let _ = self.infer_path(ctx, &mut field.base, None);
-                Type::Any
+                let base = self.infer_path(ctx, &mut field.base, None);
+                field.base.ty = Some(base.clone());
+
+                match base {
+                    Type::NamedTuple(tuple) => {
+                        if let Some(index) = tuple.names.iter().position(|name| name == field.name) {
+                            field.info = Some(expr::FieldInfo { index: index as u32 });
+                            tuple.types[index].clone()
+                        }
+                        else {
+                            self.type_error(expr.source, TypeErrorKind::NoField {
+                                ty: Type::NamedTuple(tuple), name: field.name.to_string() });
+                            Type::Error
+                        }
+                    }
+
+                    Type::Map(_) | Type::Any | Type::None => Type::Any,
+
+                    Type::Error => Type::Error,
+
+                    _ => {
+                        self.type_error(expr.source, TypeErrorKind::NoField {
+                            ty: base, name: field.name.to_string() });
+                        Type::Error
+                    }
+                }
             }
 
             ExprData::Index (index) => {
-                let _ = self.infer_path(ctx, &mut index.base,  None);
-                self.infer_expr(ctx, &mut index.index, None);
-                Type::Any
+                let base = self.infer_path(ctx, &mut index.base,  None);
+                index.base.ty = Some(base.clone());
+
+                let key = self.infer_expr(ctx, &mut index.index, None);
+
+                // constant tuple indices.
+                let literal = match index.index.data {
+                    ExprData::Number(value) => value.parse::<usize>().ok(),
+                    _ => None,
+                };
+
+                match base {
+                    Type::List(ty) => {
+                        self.expect_type(index.index.source, &key, &Type::Number);
+                        *ty
+                    }
+
+                    Type::Map(kv) => {
+                        self.expect_type(index.index.source, &key, &kv.0);
+                        kv.1
+                    }
+
+                    Type::Tuple(_) | Type::NamedTuple(_) => {
+                        self.expect_type(index.index.source, &key, &Type::Number);
+
+                        let types = match &base {
+                            Type::Tuple(types)      => &types[..],
+                            Type::NamedTuple(tuple) => &tuple.types[..],
+                            _ => unreachable!()
+                        };
+
+                        if let Some(i) = literal {
+                            if let Some(ty) = types.get(i) {
+                                ty.clone()
+                            }
+                            else {
+                                self.type_error(expr.source, TypeErrorKind::TupleIndex { ty: base, index: i });
+                                Type::Error
+                            }
+                        }
+                        else {
+                            types.iter().fold(Type::None, |ty, t| ty.join(t))
+                        }
+                    }
+
+                    Type::Any | Type::None => Type::Any,
+
+                    Type::Error => Type::Error,
+
+                    _ => {
+                        self.type_error(expr.source, TypeErrorKind::NotIndexable(base));
+                        Type::Error
+                    }
+                }
             }
 
             ExprData::Ident(ident) => {
@@ -436,7 +918,7 @@ impl Infer {
                 else {
                     ident.info = Some(expr::IdentInfo { target: expr::IdentTarget::Dynamic });
                 }
-                Type::Any
+                self.ident_type(ctx, ident.info.unwrap().target)
             }
 
             ExprData::Env => {
@@ -444,12 +926,55 @@ impl Infer {
             }
 
             _ => {
-                println!("error {}: invalid path base", expr.source);
+                self.diags.error("E0007", expr.source, "invalid path base".to_string());
                 Type::Error
             }
         }
     }
 
+    // the type of the root of `path` after `value` is written at `path`.
+    // only for paths that start at a local, with `infer_path` already done.
+    fn join_path(path: &Expr, value: Type) -> Option<(expr::IdentTarget, Type)> {
+        match &path.data {
+            ExprData::Field(field) => {
+                let mut base = field.base.ty.clone()?;
+                if let (Type::NamedTuple(tuple), Some(info)) = (&mut base, field.info) {
+                    let ty = &mut tuple.types[info.index as usize];
+                    *ty = ty.join(&value);
+                }
+                Self::join_path(&field.base, base)
+            }
+
+            ExprData::Index(index) => {
+                let mut base = index.base.ty.clone()?;
+                let literal = match index.index.data {
+                    ExprData::Number(value) => value.parse::<usize>().ok(),
+                    _ => None,
+                };
+
+                let types = match &mut base {
+                    Type::List(ty)          => core::slice::from_mut(&mut **ty),
+                    Type::Map(kv)           => core::slice::from_mut(&mut kv.1),
+                    Type::Tuple(types)      => &mut types[..],
+                    Type::NamedTuple(tuple) => &mut tuple.types[..],
+                    _ => &mut [],
+                };
+                let types = match (&index.base.ty, literal) {
+                    (Some(Type::Tuple(_) | Type::NamedTuple(_)), Some(i)) => types.get_mut(i..i+1).unwrap_or_default(),
+                    _ => types,
+                };
+                for ty in types {
+                    *ty = ty.join(&value);
+                }
+                Self::join_path(&index.base, base)
+            }
+
+            ExprData::Ident(ident) => Some((ident.info?.target, value)),
+
+            _ => None,
+        }
+    }
+
     fn infer_assign(&mut self, ctx: &mut InferCtx, lhs: &mut Expr, rhs: &Type, is_def: bool) {
         if let ExprData::Ident(ident) = &mut lhs.data {
             if let Some(decl) = ctx.find_decl(ident.name) {
@@ -457,21 +982,35 @@ impl Infer {
             }
             else {
                 if is_def != false {
-                    println!("error: tried to define global");
+                    self.diags.error("E0008", lhs.source, format!("can't define global {:?}", ident.name));
                 }
                 ident.info = Some(expr::IdentInfo { target: expr::IdentTarget::Dynamic });
             }
-            lhs.ty = Some(Type::Any);
+
+            let target = ident.info.unwrap().target;
+            let ty = self.ident_type(ctx, target);
+            if !ctx.join_var(target, rhs) {
+                self.expect_type(lhs.source, rhs, &ty);
+            }
+            lhs.ty = Some(ty);
         }
         else if let ExprData::Env = lhs.data {
-            println!("error: tried to assign to ENV");
+            self.diags.error("E0006", lhs.source, "can't assign to ENV".to_string());
             lhs.ty = Some(Type::Error);
         }
         else if let ExprData::Field(_) | ExprData::Index(_) = lhs.data {
-            lhs.ty = Some(self.infer_path(ctx, lhs, Some(rhs)));
+            let ty = self.infer_path(ctx, lhs, Some(rhs));
+
+            // writing into a `var` widens its type.
+            let is_var = Self::join_path(lhs, rhs.clone())
+                .is_some_and(|(target, root)| ctx.join_var(target, &root));
+            if !is_var {
+                self.expect_type(lhs.source, rhs, &ty);
+            }
+            lhs.ty = Some(ty);
         }
         else {
-            println!("error {}: invalid assign target", lhs.source);
+            self.diags.error("E0009", lhs.source, "invalid assign target".to_string());
             lhs.ty = Some(Type::Error);
         }
     }
@@ -482,39 +1021,161 @@ impl Infer {
         for stmt in block.iter() {
             if let StmtData::Item(item) = &stmt.data {
                 match &item.data {
-                    ItemData::Module(module) => {
-                        let _ = module;
-                        unimplemented!()
-                    }
+                    ItemData::Module(_) => {}
 
                     ItemData::Func(func) => {
                         if let Some(name) = func.name {
-                            ctx.add_item_decl(name, item.id);
+                            ctx.add_item_decl(self.diags, item.source, name, item.id);
                         }
+
+                        // the return type isn't known until the body is inferred.
+                        let params = vec![Type::Any; func.params.len()].into_boxed_slice();
+                        self.item_types[item.id.usize()] = Type::Func(Box::new((params, Type::Any)));
                     }
+
+                    ItemData::Trait(traitt) => {
+                        ctx.add_item_decl(self.diags, item.source, traitt.name, item.id);
+                        self.traits.push(TraitInfo {
+                            item:    item.id,
+                            name:    traitt.name.to_string(),
+                            methods: traitt.methods.iter().map(|m| (m.name.to_string(), m.params.len())).collect(),
+                            impls:   vec![],
+                        });
+                    }
+
+                    ItemData::Impl(_) => {}
+                }
+            }
+        }
+
+        // collect impls.
+        // done after the traits, so impls can come first.
+        for stmt in block.iter_mut() {
+            if let StmtData::Item(item) = &mut stmt.data {
+                if let ItemData::Impl(imp) = &mut item.data {
+                    imp.info = self.collect_impl(ctx, item.source, imp);
                 }
             }
         }
 
         // infer stmts.
+        // items first, so their signatures are known at the call sites.
+        for stmt in block.iter_mut() {
+            if let StmtData::Item(_) = stmt.data {
+                self.infer_stmt(ctx, stmt);
+            }
+        }
+
         let block_scope = ctx.begin_scope();
         for stmt in block.iter_mut() {
-            self.infer_stmt(ctx, stmt);
+            if !matches!(stmt.data, StmtData::Item(_)) {
+                self.infer_stmt(ctx, stmt);
+            }
         }
 
         ctx.end_scope(block_scope);
         ctx.end_scope(item_scope);
     }
 
+    fn collect_impl(&mut self, ctx: &InferCtx, source: SourceRange, imp: &item::Impl) -> Option<item::ImplInfo> {
+        let traitt = match ctx.find_decl(imp.traitt).map(|decl| decl.target) {
+            Some(expr::IdentTarget::Item(id)) if self.trait_info(id).is_some() => id,
+            _ => {
+                self.diags.error("E0010", source, format!("{:?} is not a trait", imp.traitt));
+                return None;
+            }
+        };
+
+        let Some(kind) = ValueKind::from_name(imp.ty) else {
+            self.diags.error("E0011", source, format!("{:?} is not a type", imp.ty));
+            return None;
+        };
+
+        // not `trait_info`, so `diags` stays accessible.
+        let info = self.traits.iter().find(|t| t.item == traitt).unwrap();
+
+        if info.impls.iter().any(|(k, _)| *k == kind) {
+            self.diags.error("E0012", source, format!("duplicate impl of {} for {}", info.name, kind.name()));
+            return None;
+        }
+
+        let mut methods: Vec<Option<ItemId>> = vec![None; info.methods.len()];
+        let mut ok = true;
+        for stmt in &imp.methods {
+            let StmtData::Item(item) = &stmt.data else { unreachable!() };
+            let ItemData::Func(func) = &item.data else { unreachable!() };
+            let name = func.name.unwrap();
+
+            let Some(index) = info.methods.iter().position(|(n, _)| n == name) else {
+                self.diags.error("E0013", stmt.source, format!("{name:?} is not a method of {}", info.name));
+                ok = false;
+                continue;
+            };
+
+            let num_params = info.methods[index].1;
+            if func.params.len() != num_params {
+                self.diags.push(Diagnostic::error("E0014", stmt.source,
+                    format!("method {name:?} has {} parameters, expected {num_params}", func.params.len()))
+                    .with_note(format!("{}.{name} takes {num_params} parameters", info.name)));
+                ok = false;
+            }
+
+            if methods[index].is_some() {
+                self.diags.error("E0015", stmt.source, format!("duplicate definition of {name:?}"));
+                ok = false;
+            }
+            methods[index] = Some(item.id);
+        }
+
+        for (i, method) in methods.iter().enumerate() {
+            if method.is_none() {
+                self.diags.error("E0016", source, format!("missing method {:?} of {}", info.methods[i].0, info.name));
+                ok = false;
+            }
+        }
+
+        if !ok {
+            return None;
+        }
+
+        let methods: Vec<ItemId> = methods.into_iter().map(Option::unwrap).collect();
+
+        let info = self.traits.iter_mut().find(|t| t.item == traitt).unwrap();
+        info.impls.push((kind, methods.clone()));
+
+        Some(item::ImplInfo { traitt, kind, methods })
+    }
+
+    fn trait_info(&self, item: ItemId) -> Option<&TraitInfo> {
+        self.traits.iter().find(|t| t.item == item)
+    }
+
+    // `Trait.method`
+    fn try_trait_method(&mut self, func: &Expr) -> Option<(ItemId, usize)> {
+        let ExprData::Field(field) = &func.data else { return None };
+        let ExprData::Ident(ident) = &field.base.data else { return None };
+        let expr::IdentTarget::Item(item) = ident.info?.target else { return None };
+
+        let info = self.trait_info(item)?;
+        let method = info.methods.iter().position(|(name, _)| *name == field.name);
+        if method.is_none() {
+            let message = format!("{:?} is not a method of {}", field.name, info.name);
+            self.diags.error("E0013", func.source, message);
+        }
+        Some((item, method?))
+    }
+
     fn infer_do_block(&mut self, ctx: &mut InferCtx, node: NodeId, label: Option<&str>, block: &mut [Stmt], expected_ty: Option<&Type>) -> Type {
         // @todo: use.
         let _ = expected_ty;
 
-        let bs = ctx.begin_break_scope(node, label, false, Type::Any);
+        let bs = ctx.begin_break_scope(node, label, false, Type::None);
         self.infer_block(ctx, block);
+        let ty = ctx.break_scopes[bs as usize].ty.clone();
         ctx.end_break_scope(bs);
 
-        Type::Any
+        // falling off the end produces unit.
+        if Self::block_diverges(block) { ty } else { ty.join(&Type::Unit) }
     }
 
     fn infer_value_block(&mut self, ctx: &mut InferCtx, block: &mut [Stmt], expected_ty: Option<&Type>) -> Type {
@@ -522,7 +1183,63 @@ impl Infer {
         let _ = expected_ty;
 
         self.infer_block(ctx, block);
-        Type::Any
+
+        // same rules as `Builder::build_value_block`.
+        if let [Stmt { data: StmtData::Expr(expr), .. }] = block {
+            return expr.ty.clone().unwrap();
+        }
+        if Self::block_diverges(block) { Type::None } else { Type::Unit }
+    }
+
+    fn block_diverges(block: &[Stmt]) -> bool {
+        match block.last() {
+            Some(Stmt { data: StmtData::Expr(expr), .. }) => expr.ty == Some(Type::None),
+            _ => false,
+        }
+    }
+
+    fn ident_type(&self, ctx: &InferCtx, target: expr::IdentTarget) -> Type {
+        match target {
+            expr::IdentTarget::Local { node: _, local } => ctx.locals[local.usize()].clone(),
+            expr::IdentTarget::Item(item)               => self.item_types[item.usize()].clone(),
+            expr::IdentTarget::Dynamic                  => Type::Any,
+        }
+    }
+
+    fn infer_op1(&mut self, source: SourceRange, op: Op1, ty: Type) -> Type {
+        let expected = match op {
+            Op1::Not    => Type::Bool,
+            Op1::Negate => Type::Number,
+        };
+        if !ty.is_compatible(&expected) {
+            self.type_error(source, TypeErrorKind::InvalidOp1 { op, ty });
+            return Type::Error;
+        }
+        expected
+    }
+
+    fn infer_op2(&mut self, source: SourceRange, op: Op2, lhs: &Type, rhs: &Type) -> Type {
+        use Op2::*;
+        let (ok, result) = match op {
+            Add | Sub | Mul | Div | FloorDiv | Rem =>
+                (lhs.is_compatible(&Type::Number) && rhs.is_compatible(&Type::Number), Type::Number),
+
+            CmpLe | CmpLt | CmpGe | CmpGt =>
+                (lhs.is_compatible(&Type::Number) && rhs.is_compatible(&Type::Number), Type::Bool),
+
+            CmpEq | CmpNe => (true, Type::Bool),
+
+            // `lhs` is the result, if it short circuits.
+            And | Or => (lhs.is_compatible(&Type::Bool), Type::Bool.join(rhs)),
+
+            OrElse => (true, if *lhs == Type::Nil { rhs.clone() } else { lhs.join(rhs) }),
+        };
+
+        if !ok {
+            self.type_error(source, TypeErrorKind::InvalidOp2 { op, lhs: lhs.clone(), rhs: rhs.clone() });
+            return Type::Error;
+        }
+        result
     }
 
     fn infer_if_block(&mut self, ctx: &mut InferCtx, node: NodeId, block: &mut expr::IfBlock, expected_ty: Option<&Type>) -> Type {
@@ -556,8 +1273,13 @@ struct BreakScope {
 struct InferCtx {
     scope:          u32,
     decls:          Vec<Decl>,
-    locals:         Vec<()>,
+    locals:         Vec<Type>,
+    // the join of each `var`'s initializer & assignments so far.
+    vars:           Vec<Option<Type>>,
+    // the `var` types from the previous pass, see `infer_fixpoint`.
+    assumed:        Vec<Type>,
     break_scopes:   Vec<BreakScope>,
+    ret_ty:         Type,
 }
 
 impl InferCtx {
@@ -577,13 +1299,17 @@ impl InferCtx {
             scope,
             decls,
             locals: vec![],
+            vars: vec![],
+            assumed: vec![],
             break_scopes: vec![],
+            ret_ty: Type::None,
         }
     }
 
-    fn add_local_decl(&mut self, node: NodeId, name: &str) -> LocalId {
+    fn add_local_decl(&mut self, node: NodeId, name: &str, ty: Type) -> LocalId {
         let id = LocalId(self.locals.len() as u32);
-        self.locals.push(());
+        self.locals.push(ty);
+        self.vars.push(None);
         self.decls.push(Decl {
             name:   name.to_string(),
             scope:  self.scope,
@@ -592,10 +1318,40 @@ impl InferCtx {
         id
     }
 
-    fn add_item_decl(&mut self, name: &str, id: ItemId) {
+    fn add_var_decl(&mut self, node: NodeId, name: &str, init: Type) -> LocalId {
+        let ty = self.assumed.get(self.locals.len()).cloned().unwrap_or_else(|| init.clone());
+        let id = self.add_local_decl(node, name, ty);
+        self.vars[id.usize()] = Some(init);
+        id
+    }
+
+    // returns whether `target` is a `var`.
+    fn join_var(&mut self, target: expr::IdentTarget, ty: &Type) -> bool {
+        if let expr::IdentTarget::Local { node: _, local } = target {
+            if let Some(var) = &mut self.vars[local.usize()] {
+                *var = var.join(ty);
+                return true;
+            }
+        }
+        false
+    }
+
+    // the `var` types for the next pass, if they changed.
+    fn next_assumed(&self) -> Option<Vec<Type>> {
+        let mut changed = false;
+        let next = self.locals.iter().zip(&self.vars).map(|(ty, var)| {
+            let Some(var) = var else { return ty.clone() };
+            let next = ty.join(var);
+            changed |= next != *ty;
+            next
+        }).collect();
+        changed.then_some(next)
+    }
+
+    fn add_item_decl(&mut self, diags: &mut Diagnostics, source: SourceRange, name: &str, id: ItemId) {
         if let Some(decl) = self.find_decl(name) {
             if decl.scope == self.scope {
-                println!("duplicate definition of {name:?}");
+                diags.error("E0015", source, format!("duplicate definition of {name:?}"));
             }
         }
         self.decls.push(Decl {
@@ -636,31 +1392,32 @@ impl InferCtx {
         self.break_scopes.pop();
     }
 
-    fn current_break_target(&self, source: SourceRange, label: Option<&str>) -> Option<&BreakScope> {
+    fn current_break_target(&self, diags: &mut Diagnostics, source: SourceRange, label: Option<&str>) -> Option<&BreakScope> {
         if let Some(label) = label {
             for scope in self.break_scopes.iter().rev() {
                 if scope.label.as_deref() == Some(label) {
                     return Some(scope);
                 }
             }
-            println!("error {source}: no break target with label {label}");
+            diags.error("E0017", source, format!("no break target with label {label}"));
             return None;
         }
         else {
             if let Some(scope) = self.break_scopes.last() {
                 return Some(scope);
             }
-            println!("error {source}: no break target");
+            diags.error("E0017", source, "no break target".to_string());
             return None;
         }
     }
 
-    fn current_continue_target(&self, source: SourceRange, label: Option<&str>) -> Option<&BreakScope> {
+    fn current_continue_target(&self, diags: &mut Diagnostics, source: SourceRange, label: Option<&str>) -> Option<&BreakScope> {
         if let Some(label) = label {
             for scope in self.break_scopes.iter().rev() {
                 if scope.label.as_deref() == Some(label) {
                     if !scope.can_continue {
-                        println!("error {source}: {label} is not a continue target");
+                        diags.push(Diagnostic::error("E0018", source, format!("{label} is not a continue target"))
+                            .with_note("only loops can be continued".to_string()));
                         return None;
                     }
                     else {
@@ -668,7 +1425,7 @@ impl InferCtx {
                     }
                 }
             }
-            println!("error {source}: no continue target with label {label}");
+            diags.error("E0018", source, format!("no continue target with label {label}"));
             return None;
         }
         else {
@@ -677,9 +1434,110 @@ impl InferCtx {
                     return Some(scope);
                 }
             }
-            println!("error {source}: no continue target");
+            diags.error("E0018", source, "no continue target".to_string());
             return None;
         }
     }
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn type_errors(source: &str) -> Vec<(&'static str, u32, String)> {
+        let mut module = parser::parse_module(source.as_bytes()).unwrap();
+
+        let mut diags = Diagnostics::new();
+        let mut infer = Infer::new(&mut diags);
+        infer.assign_ids(&mut module);
+        infer.infer(&mut module);
+
+        diags.iter().map(|d| (d.code, d.primary.source.begin.line, d.message.clone())).collect()
+    }
+
+    #[test]
+    fn type_checking() {
+        let source = "
+            fn add(a, b): return a + b end
+            let n = add(1, 2)
+            let s = \"hi\"
+            let bad = s + 1
+            let xs = [1, 2, 3]
+            xs[0] = \"a\"
+            let t = (1, \"x\")
+            let u = t[5]
+            let p = (x: 1, y: 2)
+            let q = p.z
+            add(1)
+            n(2)
+            if 1: 2 end
+            let c = not 3
+        ";
+        let errors = type_errors(source);
+        let expected = [
+            ("E0102",  5, "invalid operands for Add: String and Number"),
+            ("E0100",  7, "expected Number, found String"),
+            ("E0106",  9, "index 5 is out of bounds for (Number, String)"),
+            ("E0105", 11, "(x: Number, y: Number) has no field \"z\""),
+            ("E0107", 12, "expected 2 arguments, found 1"),
+            ("E0103", 13, "Number is not callable"),
+            ("E0100", 14, "expected Bool, found Number"),
+            ("E0101", 15, "invalid operand for Not: Number"),
+        ];
+        assert_eq!(errors.len(), expected.len(), "{errors:?}");
+        for ((code, line, message), (e_code, e_line, e_message)) in errors.iter().zip(expected) {
+            assert_eq!((*code, *line, message.as_str()), (e_code, e_line, e_message));
+        }
+
+        // unknown types don't produce errors.
+        let source = "
+            fn f(x, g):
+                let y = x + 1
+                let z = x.foo[2]
+                return g(y, z)
+            end
+        ";
+        assert_eq!(type_errors(source), []);
+    }
+
+    #[test]
+    fn var_types() {
+        // a `var` has the join of its initializer & assignments,
+        // including ones after a use, in loops & into its elements.
+        let source = "
+            fn f(n):
+                var i = 0
+                while i < n:
+                    if i: 1 end
+                    i += 1
+                end
+                var s = 1
+                let a = not s
+                s = \"a\"
+                var t = \"x\"
+                t = \"y\"
+                var k = 0
+                while n:
+                    let b = k + 1
+                    k = \"a\"
+                end
+                var xs = [1, 2]
+                let c = not xs[1]
+                xs[0] = \"a\"
+                return t + 1
+            end
+        ";
+        let errors = type_errors(source);
+        let expected = [
+            ("E0100",  5, "expected Bool, found Number"),
+            ("E0102", 21, "invalid operands for Add: String and Number"),
+        ];
+        assert_eq!(errors.len(), expected.len(), "{errors:?}");
+        for ((code, line, message), (e_code, e_line, e_message)) in errors.iter().zip(expected) {
+            assert_eq!((*code, *line, message.as_str()), (e_code, e_line, e_message));
+        }
+    }
+}
@@ -0,0 +1,473 @@
+use super::ast::*;
+use super::bbir::{self, FunctionId};
+use super::diagnostics::*;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lint {
+    UnknownGlobal,
+    UnusedLocal,
+    UnusedParam,
+    UnreachableCode,
+    Shadowing,
+}
+
+impl Lint {
+    pub const COUNT: usize = 5;
+
+    pub const ALL: [Lint; Lint::COUNT] = [
+        Lint::UnknownGlobal,
+        Lint::UnusedLocal,
+        Lint::UnusedParam,
+        Lint::UnreachableCode,
+        Lint::Shadowing,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnknownGlobal   => "unknown_global",
+            Lint::UnusedLocal     => "unused_local",
+            Lint::UnusedParam     => "unused_param",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::Shadowing       => "shadowing",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Self::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Clone, Debug)]
+pub struct LintConfig {
+    levels: [LintLevel; Lint::COUNT],
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        LintConfig { levels: [LintLevel::Warn; Lint::COUNT] }
+    }
+
+    #[inline(always)]
+    pub fn level(&self, lint: Lint) -> LintLevel { self.levels[lint as usize] }
+
+    #[inline(always)]
+    pub fn set(&mut self, lint: Lint, level: LintLevel) { self.levels[lint as usize] = level }
+
+    // returns false, if there's no lint called `name`.
+    pub fn set_by_name(&mut self, name: &str, level: LintLevel) -> bool {
+        let Some(lint) = Lint::from_name(name) else { return false };
+        self.set(lint, level);
+        true
+    }
+}
+
+impl Default for LintConfig {
+    fn default() -> Self { Self::new() }
+}
+
+
+// runs on the resolved ast & the unoptimized bbir,
+// so `krate` must not have been built yet.
+// `globals` are the names defined in the env, eg by `Vm::add_func`.
+pub fn lint(config: &LintConfig, globals: &[String], module: &item::Module, krate: &bbir::Crate, diags: &mut Diagnostics) {
+    let mut linter = Linter {
+        config, globals, diags,
+        reachability: Linter::reachability(krate),
+        last_node:    NodeId::ZERO,
+        locals:       vec![],
+    };
+
+    let scope = linter.begin_scope();
+    linter.lint_block(&module.block.stmts);
+    linter.end_scope(scope);
+}
+
+
+// per node: whether it has reachable/unreachable instructions.
+const HAS_REACHABLE:   u8 = 1;
+const HAS_UNREACHABLE: u8 = 2;
+
+struct LocalDecl<'a> {
+    name:     &'a str,
+    source:   SourceRange,
+    node:     NodeId,
+    local:    crate::infer::LocalId,
+    is_param: bool,
+    used:     bool,
+}
+
+struct Linter<'a, 'c> {
+    config:  &'c LintConfig,
+    globals: &'c [String],
+    diags:   &'c mut Diagnostics,
+
+    reachability: Vec<u8>,
+    last_node:    NodeId,
+
+    locals: Vec<LocalDecl<'a>>,
+}
+
+impl<'a, 'c> Linter<'a, 'c> {
+    fn reachability(krate: &bbir::Crate) -> Vec<u8> {
+        let mut result = vec![];
+
+        for i in 0..krate.num_functions() {
+            let fun = krate.read_function(FunctionId::from_usize(i));
+
+            let preds        = fun.predecessors();
+            let post_order   = fun.post_order();
+            let post_indices = fun.post_order_indices(&post_order);
+            let idoms        = fun.immediate_dominators(&preds, &post_order, &post_indices);
+
+            for bb in fun.block_ids() {
+                let flag = if idoms.is_unreachable(bb) { HAS_UNREACHABLE } else { HAS_REACHABLE };
+                fun.block_instrs(bb, |instr| {
+                    if let Some(node) = instr.source.node.to_option() {
+                        if node.usize() >= result.len() {
+                            result.resize(node.usize() + 1, 0);
+                        }
+                        result[node.usize()] |= flag;
+                    }
+                });
+            }
+        }
+
+        result
+    }
+
+    fn emit(&mut self, lint: Lint, diag: Diagnostic) {
+        let severity = match self.config.level(lint) {
+            LintLevel::Allow => return,
+            LintLevel::Warn  => Severity::Warning,
+            LintLevel::Deny  => Severity::Error,
+        };
+        self.diags.push(Diagnostic { severity, ..diag });
+    }
+
+
+    fn begin_scope(&mut self) -> usize {
+        self.locals.len()
+    }
+
+    fn end_scope(&mut self, scope: usize) {
+        for decl in self.locals.drain(scope..).collect::<Vec<_>>() {
+            if decl.used || decl.name.starts_with('_') {
+                continue;
+            }
+
+            let (lint, what) =
+                if decl.is_param { (Lint::UnusedParam, "parameter") }
+                else             { (Lint::UnusedLocal, "local") };
+            self.emit(lint, Diagnostic::warning(lint.name(), decl.source,
+                format!("unused {what} {:?}", decl.name))
+                .with_note("prefix the name with `_` to silence this".to_string()));
+        }
+    }
+
+    fn add_local(&mut self, name: &'a str, source: SourceRange, node: NodeId, local: crate::infer::LocalId, is_param: bool) {
+        if let Some(prev) = self.locals.iter().rev().find(|decl| decl.name == name) {
+            let prev = prev.source;
+            self.emit(Lint::Shadowing, Diagnostic::warning(Lint::Shadowing.name(), source,
+                format!("{name:?} shadows an earlier local"))
+                .with_secondary(prev, "previously declared here".to_string()));
+        }
+        self.locals.push(LocalDecl { name, source, node, local, is_param, used: false });
+    }
+
+
+    fn lint_block(&mut self, block: &'a [Stmt<'a>]) {
+        let scope = self.begin_scope();
+
+        let mut prev_reachable = true;
+        let mut reported = false;
+        for stmt in block {
+            let first = stmt.id;
+            self.lint_stmt(stmt);
+            let last = self.last_node;
+
+            // items are separate functions.
+            if let StmtData::Item(_) = stmt.data {
+                continue;
+            }
+
+            let mut flags = 0;
+            for node in first.value()..=last.value() {
+                flags |= self.reachability.get(node as usize).copied().unwrap_or(0);
+            }
+
+            let unreachable = flags == HAS_UNREACHABLE;
+            if unreachable && prev_reachable && !reported {
+                self.emit(Lint::UnreachableCode, Diagnostic::warning(Lint::UnreachableCode.name(), stmt.source,
+                    "unreachable code".to_string())
+                    .with_note("the preceding code always returns, breaks, or continues".to_string()));
+                reported = true;
+            }
+            if flags != 0 {
+                prev_reachable = !unreachable;
+            }
+        }
+
+        self.end_scope(scope);
+    }
+
+    fn lint_stmt(&mut self, stmt: &'a Stmt<'a>) {
+        self.last_node = stmt.id;
+
+        match &stmt.data {
+            StmtData::Item(item) => {
+                match &item.data {
+                    ItemData::Module(module) => self.lint_block(&module.block.stmts),
+
+                    ItemData::Func(func) => {
+                        // outer locals aren't visible.
+                        let outer = core::mem::take(&mut self.locals);
+
+                        let scope = self.begin_scope();
+                        for (i, param) in func.params.iter().enumerate() {
+                            self.add_local(param.name, param.source, stmt.id,
+                                crate::infer::LocalId::from_usize(i), true);
+                        }
+                        self.lint_block(&func.body);
+                        self.end_scope(scope);
+
+                        self.locals = outer;
+                    }
+
+                    ItemData::Trait(_) => {}
+
+                    ItemData::Impl(imp) => {
+                        for method in &imp.methods {
+                            self.lint_stmt(method);
+                        }
+                    }
+                }
+
+                self.last_node = NodeId::new_unck(stmt.id.value() + item.num_nodes);
+            }
+
+            StmtData::Local(local) => {
+                if let Some(value) = &local.value {
+                    self.lint_expr(value);
+                }
+                if let Some(info) = local.info {
+                    self.add_local(local.name, stmt.source, stmt.id, info.id, false);
+                }
+            }
+
+            StmtData::Delete(delete) => self.lint_expr(&delete.target),
+
+            StmtData::Expr(expr) => self.lint_expr(expr),
+
+            StmtData::Empty => (),
+        }
+    }
+
+    fn lint_expr(&mut self, expr: &'a Expr<'a>) {
+        self.last_node = self.last_node.max(expr.id);
+
+        match &expr.data {
+            ExprData::Nil |
+            ExprData::Bool(_) |
+            ExprData::Number(_) |
+            ExprData::QuotedString(_) |
+            ExprData::Env => (),
+
+            ExprData::Ident(ident) => self.lint_ident(expr, ident, true),
+
+            ExprData::Tuple(tuple) => {
+                for value in &tuple.values {
+                    self.lint_expr(value);
+                }
+            }
+
+            ExprData::List(list) => {
+                for value in &list.values {
+                    self.lint_expr(value);
+                }
+            }
+
+            ExprData::Do(doo) => self.lint_block(&doo.stmts),
+
+            ExprData::SubExpr(sub_expr) => self.lint_expr(sub_expr),
+
+            ExprData::Op1(op1) => self.lint_expr(&op1.child),
+
+            ExprData::Op2(op2) => {
+                let [lhs, rhs] = &op2.children;
+                match op2.kind {
+                    // plain assignments don't use the target.
+                    expr::Op2Kind::Assign | expr::Op2Kind::Define => {
+                        self.lint_assign_target(lhs);
+                        self.lint_expr(rhs);
+                    }
+
+                    expr::Op2Kind::Op2(_) |
+                    expr::Op2Kind::Op2Assign(_) => {
+                        self.lint_expr(lhs);
+                        self.lint_expr(rhs);
+                    }
+                }
+            }
+
+            ExprData::Field(field) => self.lint_expr(&field.base),
+
+            ExprData::Index(index) => {
+                self.lint_expr(&index.base);
+                self.lint_expr(&index.index);
+            }
+
+            ExprData::Call(call) => {
+                self.lint_expr(&call.func);
+                for arg in &call.args {
+                    self.lint_expr(arg);
+                }
+            }
+
+            ExprData::If(iff) => {
+                self.lint_expr(&iff.condition);
+                self.lint_block(&iff.on_true.stmts);
+                if let Some(on_false) = &iff.on_false {
+                    self.lint_block(&on_false.stmts);
+                }
+            }
+
+            ExprData::While(whilee) => {
+                self.lint_expr(&whilee.condition);
+                self.lint_block(&whilee.body);
+            }
+
+            ExprData::Break(brk) => {
+                if let Some(value) = &brk.value {
+                    self.lint_expr(value);
+                }
+            }
+
+            ExprData::Continue(_) => (),
+
+            ExprData::Return(returnn) => {
+                if let Some(value) = &returnn.value {
+                    self.lint_expr(value);
+                }
+            }
+
+            ExprData::Implements(implements) => {
+                self.lint_expr(&implements.value);
+                self.lint_expr(&implements.traitt);
+            }
+        }
+    }
+
+    fn lint_assign_target(&mut self, target: &'a Expr<'a>) {
+        self.last_node = self.last_node.max(target.id);
+
+        match &target.data {
+            ExprData::Ident(ident) => self.lint_ident(target, ident, false),
+
+            ExprData::Tuple(tuple) => {
+                for value in &tuple.values {
+                    self.lint_assign_target(value);
+                }
+            }
+
+            _ => self.lint_expr(target),
+        }
+    }
+
+    fn lint_ident(&mut self, expr: &Expr, ident: &expr::Ident, is_use: bool) {
+        let Some(info) = ident.info else { return };
+
+        match info.target {
+            expr::IdentTarget::Local { node, local } => {
+                if is_use {
+                    if let Some(decl) = self.locals.iter_mut().rev().find(|decl| decl.node == node && decl.local == local) {
+                        decl.used = true;
+                    }
+                }
+            }
+
+            expr::IdentTarget::Dynamic => {
+                if !self.globals.iter().any(|name| name == ident.name) {
+                    self.emit(Lint::UnknownGlobal, Diagnostic::warning(Lint::UnknownGlobal.name(), expr.source,
+                        format!("unknown global {:?}", ident.name))
+                        .with_note("globals must be defined in the env when the crate is loaded".to_string()));
+                }
+            }
+
+            expr::IdentTarget::Item(_) => (),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser, infer, bbir_builder};
+
+    fn run_lints(config: &LintConfig, source: &str) -> Vec<(&'static str, Severity, SourceRange)> {
+        let mut module = parser::parse_module(source.as_bytes()).unwrap();
+
+        let mut diags = Diagnostics::new();
+        let mut infer = infer::Infer::new(&mut diags);
+        infer.assign_ids(&mut module);
+        infer.infer(&mut module);
+
+        let mut builder = bbir_builder::Builder::new(&mut diags);
+        builder.build(&module);
+        let krate = builder.krate;
+        assert!(diags.is_empty());
+
+        lint(config, &["print".to_string()], &module, &krate, &mut diags);
+        diags.iter().map(|d| (d.code, d.severity, d.primary.source)).collect()
+    }
+
+    fn range(line: u32, begin: u32, end: u32) -> SourceRange {
+        SourceRange {
+            begin: SourcePos { line, column: begin },
+            end:   SourcePos { line, column: end },
+        }
+    }
+
+    #[test]
+    fn lints() {
+        let source = [
+            "fn f(x, y, _z):",
+            "    let x = 2",
+            "    var unused = 3",
+            "    return x",
+            "    print(x)",
+            "end",
+            "pirnt(f(1, 2, 3))",
+        ].join("\n");
+
+        // `x` is shadowed before it's used.
+        let w = Severity::Warning;
+        let lints = run_lints(&LintConfig::new(), &source);
+        assert_eq!(lints, [
+            ("shadowing",        w, range(2, 5, 14)),
+            ("unreachable_code", w, range(5, 5, 13)),
+            ("unused_local",     w, range(3, 5, 19)),
+            ("unused_param",     w, range(1, 6, 7)),
+            ("unused_param",     w, range(1, 9, 10)),
+            ("unknown_global",   w, range(7, 1, 6)),
+        ]);
+
+        let mut config = LintConfig::new();
+        config.set(Lint::UnusedParam, LintLevel::Allow);
+        assert!(config.set_by_name("unknown_global", LintLevel::Deny));
+        assert!(!config.set_by_name("unknown", LintLevel::Deny));
+
+        let lints = run_lints(&config, &source);
+        assert!(!lints.iter().any(|(code, _, _)| *code == "unused_param"));
+        assert!(lints.contains(&("unknown_global", Severity::Error, range(7, 1, 6))));
+    }
+}
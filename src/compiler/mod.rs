@@ -1,16 +1,25 @@
 pub mod ast;
 pub mod parser;
+pub mod diagnostics;
 pub mod infer;
 pub mod bbir;
 pub mod bbir_builder;
+pub mod bbir_text;
+pub mod dot;
 pub mod analysis;
 pub mod opt;
 pub mod transform;
+pub mod verify;
+pub mod lint;
 pub mod codegen;
+pub mod peephole;
+pub mod pass;
 
 pub use ast::*;
 pub use parser::*;
+pub use diagnostics::*;
 pub use bbir::*;
 pub use analysis::*;
+pub use pass::*;
 
 
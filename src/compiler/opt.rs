@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use crate::index_vec::*;
 use super::*;
 
@@ -117,7 +118,178 @@ pub fn local2reg_ex(fun: &mut Function, preds: &Predecessors, dom_tree: &DomTree
             }
         }
     }
-    fun.slow_integrity_check();
+}
+
+
+// callees with more instructions aren't inlined.
+const INLINE_MAX_SIZE: usize = 40;
+
+// callers aren't grown beyond this.
+const INLINE_MAX_CALLER_SIZE: usize = 1000;
+
+// inlines calls of items that are functions.
+// - runs before ssa construction & requires that there are no unreachable blocks.
+// - callees are processed before their callers, so their own calls are already inlined.
+// - (mutually) recursive functions aren't inlined.
+// - items that are ever assigned to aren't inlined.
+pub fn inline_functions(krate: &Crate) {
+    let num_functions = krate.num_functions();
+
+    // statically known item functions.
+    let mut item_funcs: Vec<Option<FunctionId>> = (0..krate.num_items())
+        .map(|item| match krate.item(ItemId::from_usize(item)).data {
+            bbir::ItemData::Func(func) => Some(func),
+            _ => None,
+        })
+        .collect();
+
+    for i in 0..num_functions {
+        let fun = krate.read_function(FunctionId::from_usize(i));
+        for bb in fun.block_ids() {
+            fun.block_instrs(bb, |instr| {
+                let InstrData::WritePath { path_id, value: _, is_def: _ } = instr.data else { return };
+                let path = path_id.get(&fun);
+                let PathBase::Items = path.base else { return };
+
+                match inline_item_index(&fun, path.keys) {
+                    Some(item) => { if let Some(func) = item_funcs.get_mut(item) { *func = None } }
+                    None       => item_funcs.iter_mut().for_each(|func| *func = None),
+                }
+            });
+        }
+    }
+
+    let callee = |fun: &Function, instr: &Instr| -> Option<FunctionId> {
+        let InstrData::Call { func, args_id: _ } = instr.data else { return None };
+        let InstrData::ReadPath { path_id } = func.get(fun).data else { return None };
+        let path = path_id.get(fun);
+        let PathBase::Items = path.base else { return None };
+        *item_funcs.get(inline_item_index(fun, path.keys)?)?
+    };
+
+    // call graph.
+    let mut calls: IndexVec<FunctionId, Vec<FunctionId>> = index_vec![vec![]; num_functions];
+    for (i, calls) in calls.iter_mut().enumerate() {
+        let fun = krate.read_function(FunctionId::from_usize(i));
+        for bb in fun.block_ids() {
+            fun.block_instrs(bb, |instr| {
+                if let Some(func) = callee(&fun, instr) {
+                    calls.push(func);
+                }
+            });
+        }
+    }
+
+    let sccs = inline_sccs(&calls);
+
+    let mut recursive = index_vec![false; num_functions];
+    for scc in &sccs {
+        for func in scc.iter().copied() {
+            recursive[func] = scc.len() > 1 || calls[func].contains(&func);
+        }
+    }
+
+    for func in sccs.into_iter().flatten() {
+        let mut fun = krate.write_function(func);
+
+        let mut sites = vec![];
+        for bb in fun.block_ids() {
+            fun.block_instrs(bb, |instr| {
+                if let Some(callee) = callee(&fun, instr) {
+                    sites.push((instr.id(), callee));
+                }
+            });
+        }
+
+        for (call, callee) in sites {
+            if callee == func || recursive[callee] {
+                continue;
+            }
+
+            let callee = krate.read_function(callee);
+            let InstrData::Call { func: _, args_id } = call.get(&fun).data else { unreachable!() };
+            if args_id.get(&fun).len() != callee.num_params() {
+                continue;
+            }
+
+            if callee.size() > INLINE_MAX_SIZE || fun.size() + callee.size() > INLINE_MAX_CALLER_SIZE {
+                continue;
+            }
+
+            fun.inline_call(call, &callee);
+        }
+    }
+}
+
+// `[Index(load_int item)]`.
+fn inline_item_index(fun: &Function, keys: &[PathKey]) -> Option<usize> {
+    let [PathKey::Index(index)] = keys else { return None };
+    let InstrData::LoadInt { value } = index.get(fun).data else { return None };
+    value.try_into().ok()
+}
+
+// strongly connected components (tarjan), callees before callers.
+fn inline_sccs(calls: &IndexVec<FunctionId, Vec<FunctionId>>) -> Vec<Vec<FunctionId>> {
+    struct Tarjan<'a> {
+        calls:    &'a IndexVec<FunctionId, Vec<FunctionId>>,
+        index:    IndexVec<FunctionId, Option<u32>>,
+        low_link: IndexVec<FunctionId, u32>,
+        on_stack: IndexVec<FunctionId, bool>,
+        stack:    Vec<FunctionId>,
+        next:     u32,
+        sccs:     Vec<Vec<FunctionId>>,
+    }
+
+    impl<'a> Tarjan<'a> {
+        fn visit(&mut self, func: FunctionId) {
+            self.index[func]    = Some(self.next);
+            self.low_link[func] = self.next;
+            self.next += 1;
+            self.stack.push(func);
+            self.on_stack[func] = true;
+
+            for callee in self.calls[func].iter().copied() {
+                if let Some(index) = self.index[callee] {
+                    if self.on_stack[callee] {
+                        self.low_link[func] = self.low_link[func].min(index);
+                    }
+                }
+                else {
+                    self.visit(callee);
+                    self.low_link[func] = self.low_link[func].min(self.low_link[callee]);
+                }
+            }
+
+            if Some(self.low_link[func]) == self.index[func] {
+                let mut scc = vec![];
+                loop {
+                    let top = self.stack.pop().unwrap();
+                    self.on_stack[top] = false;
+                    scc.push(top);
+                    if top == func { break }
+                }
+                self.sccs.push(scc);
+            }
+        }
+    }
+
+    let n = calls.len();
+    let mut tarjan = Tarjan {
+        calls,
+        index:    index_vec![None; n],
+        low_link: index_vec![0; n],
+        on_stack: index_vec![false; n],
+        stack:    vec![],
+        next:     0,
+        sccs:     vec![],
+    };
+    for func in 0..n {
+        let func = FunctionId::from_usize(func);
+        if tarjan.index[func].is_none() {
+            tarjan.visit(func);
+        }
+    }
+    tarjan.sccs
 }
 
 
@@ -141,7 +313,6 @@ pub fn copy_propagation_ex(fun: &mut Function, dom_tree: &DomTree) {
     }
 
     visit(BlockId::ENTRY, fun, &dom_tree);
-    fun.slow_integrity_check();
 }
 
 
@@ -171,7 +342,1236 @@ pub fn dead_copy_elim(fun: &mut Function) {
             current = next;
         }
     }
+}
+
+
+
+// dominator based global value numbering.
+// - requires ssa.
+// - instructions with the same `GvnKey` compute the same value.
+//   the dominated ones are removed & their uses replaced.
+// - path bases must be instructions. ssa values are immutable
+//   (cssa inserts copies before in-place writes), but the env & items aren't.
+pub fn gvn(fun: &mut Function, dom_tree: &DomTree) {
+    fn visit(bb: BlockId, fun: &mut Function, dom_tree: &DomTree,
+        table: &mut HashMap<GvnKey, InstrId>, replace: &mut IndexVec<InstrId, OptInstrId>,
+    ) {
+        let mut added = vec![];
+
+        let mut current = bb.get(fun).first();
+        while let Some(at) = current.to_option() {
+            let instr = at.get(fun);
+            current = instr.next();
+
+            // non-phi args are defined in dominating blocks, which have been numbered.
+            let mut data = instr.data;
+            data.replace_args(fun, |_, arg| {
+                if let Some(new_arg) = replace[*arg].to_option() {
+                    *arg = new_arg;
+                }
+            });
+            at.get_mut(fun).data = data;
+
+            let Some(key) = GvnKey::new(fun, at.get(fun)) else { continue };
+
+            if let Some(prev) = table.get(&key).copied() {
+                // put value info on the remaining instruction.
+                let source_values = core::mem::take(&mut at.get_mut(fun).source.values);
+                prev.get_mut(fun).source.values.extend(source_values);
+
+                fun.remove_instr(at);
+                replace[at] = prev.some();
+            }
+            else {
+                table.insert(key.clone(), at);
+                added.push(key);
+            }
+        }
+
+        for d in dom_tree[bb].iter() {
+            visit(*d, fun, dom_tree, table, replace);
+        }
+
+        for key in added {
+            table.remove(&key);
+        }
+    }
+
+    let mut table   = HashMap::new();
+    let mut replace = index_vec![OptInstrId::NONE; fun.num_instrs()];
+    visit(BlockId::ENTRY, fun, dom_tree, &mut table, &mut replace);
+
+    // phi args on back edges.
+    for bb in fun.block_ids() {
+        fun.block_replace_args(bb, |_, arg| {
+            if let Some(new_arg) = replace[*arg].to_option() {
+                *arg = new_arg;
+            }
+        });
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum GvnKey {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(u64),
+    String(StringId),
+    Op1(Op1, InstrId),
+    Op2(Op2, InstrId, InstrId),
+    ReadPath(InstrId, Vec<PathKey>),
+}
+
+impl GvnKey {
+    fn new(fun: &Function, instr: &Instr) -> Option<GvnKey> {
+        Some(match instr.data {
+            InstrData::LoadNil             => GvnKey::Nil,
+            InstrData::LoadBool  { value } => GvnKey::Bool(value),
+            InstrData::LoadInt   { value } => GvnKey::Int(value),
+            InstrData::LoadFloat { value } => GvnKey::Float(value.to_bits()),
+            InstrData::LoadString { id }   => GvnKey::String(id),
+
+            InstrData::Op1 { op, src } => GvnKey::Op1(op, src),
+
+            InstrData::Op2 { op, src1, src2 } => {
+                match op {
+                    Op2::Add | Op2::Mul | Op2::CmpEq | Op2::CmpNe =>
+                        GvnKey::Op2(op, src1.min(src2), src1.max(src2)),
+
+                    Op2::Sub | Op2::Div | Op2::FloorDiv | Op2::Rem |
+                    Op2::CmpLe | Op2::CmpLt | Op2::CmpGe | Op2::CmpGt =>
+                        GvnKey::Op2(op, src1, src2),
+
+                    Op2::And | Op2::Or | Op2::OrElse => return None,
+                }
+            }
+
+            InstrData::ReadPath { path_id } => {
+                let path = path_id.get(fun);
+                let PathBase::Instr(base) = path.base else { return None };
+                GvnKey::ReadPath(base, path.keys.to_vec())
+            }
+
+            _ => return None,
+        })
+    }
+}
+
+
+// mark & sweep.
+// roots are instructions with side effects (see `InstrData::is_pure`),
+// and ops that may raise errors.
+pub fn dead_code_elim(fun: &mut Function) {
+    let mut live = index_vec![false; fun.num_instrs()];
+
+    let mut stack = vec![];
+    for bb in fun.block_ids() {
+        fun.block_instrs(bb, |instr| {
+            if !is_pure_ex(fun, instr) {
+                live[instr.id()] = true;
+                stack.push(instr.id());
+            }
+        });
+    }
+
+    while let Some(at) = stack.pop() {
+        at.get(fun).args(fun, |arg| {
+            if !live[arg] {
+                live[arg] = true;
+                stack.push(arg);
+            }
+        });
+    }
+
+    fun.retain_instrs(|instr| live[instr.id()]);
+}
+
+// `InstrData::is_pure`, and ops whose operands are known, so they can't fail.
+// sccp folds most of these, but this doesn't depend on it.
+fn is_pure_ex(fun: &Function, instr: &Instr) -> bool {
+    if instr.is_pure() {
+        return true;
+    }
+
+    let is_number = |src: InstrId| {
+        matches!(src.get(fun).data, InstrData::LoadInt { value: _ } | InstrData::LoadFloat { value: _ })
+    };
+    let is_non_zero = |src: InstrId| {
+        match src.get(fun).data {
+            InstrData::LoadInt   { value } => value != 0,
+            InstrData::LoadFloat { value } => value != 0.0,
+            _ => false,
+        }
+    };
+
+    match instr.data {
+        InstrData::Op1 { op, src } => {
+            match op {
+                Op1::Not    => matches!(src.get(fun).data, InstrData::LoadBool { value: _ }),
+                Op1::Negate => is_number(src),
+            }
+        }
+
+        InstrData::Op2 { op, src1, src2 } => {
+            use Op2::*;
+            match op {
+                Add | Sub | Mul |
+                CmpLe | CmpLt | CmpGe | CmpGt => is_number(src1) && is_number(src2),
+
+                Div | FloorDiv | Rem => is_number(src1) && is_non_zero(src2),
+
+                CmpEq | CmpNe => true,
+
+                And | Or | OrElse => false,
+            }
+        }
+
+        _ => false,
+    }
+}
+
+
+// gives each loop header a single predecessor outside the loop (the preheader),
+// which unconditionally jumps to the header.
+// outer phi args are merged in the preheader.
+pub fn insert_preheaders(fun: &mut Function, preds: &Predecessors, loops: &LoopForest) {
+    for lp in loops.loops.iter() {
+        let header = lp.header;
+        let outside: Vec<BlockId> = preds[header].iter().copied()
+            .filter(|pred| !lp.latches.contains(pred))
+            .collect();
+
+        // already has one.
+        if let [pred] = outside[..] {
+            let terminator = fun.block_terminator(pred).unwrap();
+            if let InstrData::Jump { target: _ } = terminator.get(fun).data {
+                continue;
+            }
+        }
+
+        let preheader = fun.new_block();
+
+        // retarget the outside edges.
+        for pred in outside.iter().copied() {
+            fun.replace_successor(pred, header, preheader);
+        }
+
+        // move the outside phi args.
+        let mut phi = fun.block_first_phi(header);
+        let mut cursor = OptInstrId::NONE;
+        while let Some(at) = phi.to_option() {
+            let Some(map) = fun.try_phi(at) else { break };
+
+            let (outer, mut inner): (Vec<PhiEntry>, Vec<PhiEntry>) =
+                map.iter().copied().partition(|(from, _)| outside.contains(from));
+
+            let src =
+                if let [(_, src)] = outer[..] { src }
+                else {
+                    let source = at.get(fun).source.clone();
+                    let outer_phi = fun.new_phi((None.into(), None.into()), &outer);
+                    outer_phi.get_mut(fun).source = source;
+                    fun.insert_after(preheader, cursor, outer_phi);
+                    cursor = outer_phi.some();
+                    outer_phi
+                };
+            inner.push((preheader, src));
+            fun.set_phi(at, &inner);
+
+            phi = at.get(fun).next();
+        }
+
+        let jump = fun.new_instr_ex(SourceInfo { node: None.into(), values: vec![] }, InstrData::Jump { target: header });
+        fun.append_instr(preheader, jump);
+    }
+}
+
+
+// loop invariant code motion.
+// - requires preheaders (see `insert_preheaders`) & ssa.
+// - instructions that can't fail are hoisted from anywhere in the loop.
+// - ops that may fail (see `has_no_side_effects`) are only hoisted,
+//   if the loop is known to execute. so the header's test is copied
+//   in front of the preheader (see `guard_loop`), & they're hoisted
+//   from the code at the start of the loop, that runs before any side effects.
+pub fn licm(fun: &mut Function, preds: &Predecessors, post_order: &PostOrder, loops: &LoopForest) {
+    // outer loops first, so invariants move as far out as possible.
+    for lp in loops.pre_order() {
+        let lp = &loops.loops[lp];
+
+        let preheader = preds[lp.header].iter().copied()
+            .find(|pred| !lp.latches.contains(pred))
+            .unwrap();
+
+        // hoisted instructions are no longer in the loop.
+        // blocks created by `guard_loop` aren't either.
+        let mut in_loop = index_vec![false; fun.num_blocks()];
+        for bb in lp.blocks.iter().copied() {
+            in_loop[bb] = true;
+        }
+
+        let is_invariant = |fun: &Function, instr: &Instr| {
+            let mut invariant = true;
+            instr.args(fun, |arg| {
+                let arg_bb = arg.get(fun).bb().unwrap();
+                invariant &= !in_loop.get(arg_bb).copied().unwrap_or(false);
+            });
+            invariant
+        };
+
+        // definitions before uses.
+        for bb in post_order.iter().rev().copied() {
+            if !in_loop[bb] {
+                continue;
+            }
+
+            let mut current = bb.get(fun).first();
+            while let Some(at) = current.to_option() {
+                let instr = at.get(fun);
+                current = instr.next();
+
+                if instr.is_phi() || instr.is_terminator() || !instr.has_value() || !is_pure_ex(fun, instr) {
+                    continue;
+                }
+                if !is_invariant(fun, instr) {
+                    continue;
+                }
+
+                fun.remove_instr(at);
+                fun.insert_before_terminator(preheader, at);
+            }
+        }
+
+        let Some((guarded, header_copies)) = guard_loop(fun, preds, lp, preheader) else { continue };
+
+        for at in loop_prefix(fun, preds, lp) {
+            let instr = at.get(fun);
+            if instr.is_phi() || !instr.has_value() || !is_invariant(fun, instr) {
+                continue;
+            }
+
+            // the guard already computed it.
+            if let Some(copy) = header_copies.get(&at).copied() {
+                fun.replace_uses(at, copy);
+                fun.remove_instr(at);
+                continue;
+            }
+
+            fun.remove_instr(at);
+            fun.insert_before_terminator(guarded, at);
+        }
+    }
+}
 
-    fun.slow_integrity_check();
+// ops whose result only depends on their args.
+// they may raise errors, so they can't be removed.
+fn has_no_side_effects(fun: &Function, instr: &Instr) -> bool {
+    if is_pure_ex(fun, instr) {
+        return true;
+    }
+
+    match instr.data {
+        InstrData::Op1 { op: _, src: _ } => true,
+        InstrData::Op2 { op, src1: _, src2: _ } => !matches!(op, Op2::And | Op2::Or | Op2::OrElse),
+        // env & item reads depend on writes in the loop.
+        InstrData::ReadPath { path_id } => matches!(path_id.get(fun).base, PathBase::Instr(_)),
+        _ => false,
+    }
 }
 
+// for loops, that are only left through the header's `switch_bool`:
+//  preheader:  jump header
+// becomes
+//  preheader:  <copy of the header>; switch_bool, guarded, exit
+//  guarded:    jump header
+// so the loop runs at least once, when `guarded` is reached.
+// header values used after the loop are merged with their copies in `exit`.
+// returns `guarded` & the copies of the header's instructions.
+fn guard_loop(fun: &mut Function, preds: &Predecessors, lp: &Loop, preheader: BlockId) -> Option<(BlockId, HashMap<InstrId, InstrId>)> {
+    let header = lp.header;
+
+    let [(from, exit)] = lp.exits[..] else { return None };
+    if from != header || preds[exit].len() != 1 || fun.block_first_phi(exit).is_some() {
+        return None;
+    }
+
+    let terminator = fun.block_terminator(header).unwrap();
+    let InstrData::SwitchBool { src: _, on_true: _, on_false: _ } = terminator.get(fun).data else { return None };
+
+    // the header runs twice for the first iteration,
+    // so it must not have side effects.
+    let mut header_phis   = vec![];
+    let mut header_instrs = vec![];
+    let mut copyable = true;
+    fun.block_instrs(header, |instr| {
+        if instr.is_phi() {
+            header_phis.push(instr.id());
+        }
+        else if !instr.is_terminator() {
+            copyable &= has_no_side_effects(fun, instr);
+            header_instrs.push(instr.id());
+        }
+    });
+    if !copyable {
+        return None;
+    }
+
+    // phis take their value from the preheader.
+    let mut copies = HashMap::new();
+    for phi in header_phis.iter().copied() {
+        let src = fun.try_phi(phi).unwrap().get(preheader).unwrap();
+        copies.insert(phi, src);
+    }
+
+    let copy = |fun: &mut Function, at: InstrId, copies: &HashMap<InstrId, InstrId>| {
+        let instr = at.get(fun);
+        let source = instr.source.clone();
+        let mut data = fun.clone_instr_data(instr.data);
+        data.replace_args(fun, |_, arg| {
+            if let Some(copy) = copies.get(arg) { *arg = *copy }
+        });
+        fun.new_instr_ex(source, data)
+    };
+
+    let jump = fun.block_terminator(preheader).unwrap();
+    fun.remove_instr(jump);
+
+    for at in header_instrs.iter().copied() {
+        let id = copy(fun, at, &copies);
+        fun.append_instr(preheader, id);
+        copies.insert(at, id);
+    }
+
+    let guarded = fun.new_block();
+    let jump = fun.new_instr_ex(SourceInfo { node: None.into(), values: vec![] }, InstrData::Jump { target: header });
+    fun.append_instr(guarded, jump);
+    fun.replace_phi_pred(header, preheader, guarded);
+
+    let switch = copy(fun, terminator, &copies);
+    fun.append_instr(preheader, switch);
+    let InstrData::SwitchBool { src: _, on_true, on_false } = &mut switch.get_mut(fun).data else { unreachable!() };
+    for target in [on_true, on_false] {
+        if *target != exit { *target = guarded }
+    }
+
+    // values used after the loop.
+    let mut used_outside = vec![];
+    for bb in fun.block_ids() {
+        if bb == preheader || lp.blocks.contains(&bb) {
+            continue;
+        }
+        fun.block_args(bb, |arg| {
+            if copies.contains_key(&arg) && arg.get(fun).bb().to_option() == Some(header) && !used_outside.contains(&arg) {
+                used_outside.push(arg);
+            }
+        });
+    }
+    for value in used_outside {
+        let map = [(header, value), (preheader, copies[&value])];
+        let phi = fun.new_phi((None.into(), None.into()), &map);
+        fun.prepend_instr(exit, phi);
+
+        for bb in fun.block_ids() {
+            if bb == preheader || lp.blocks.contains(&bb) {
+                continue;
+            }
+            fun.block_replace_args(bb, |_, arg| if *arg == value { *arg = phi });
+        }
+        // undo the replacement in the phi itself.
+        fun.set_phi(phi, &map);
+    }
+
+    let header_copies = header_instrs.iter().map(|at| (*at, copies[at])).collect();
+    Some((guarded, header_copies))
+}
+
+// the instructions that run on every iteration before any side effects:
+// the header & the straight line code at the start of the body.
+fn loop_prefix(fun: &Function, preds: &Predecessors, lp: &Loop) -> Vec<InstrId> {
+    let mut result = vec![];
+
+    let header = lp.header;
+    fun.block_instrs(header, |instr| {
+        if !instr.is_phi() && !instr.is_terminator() {
+            result.push(instr.id());
+        }
+    });
+
+    let InstrData::SwitchBool { src: _, on_true, on_false } = fun.block_terminator(header).unwrap().get(fun).data else { unreachable!() };
+    let mut bb = if lp.blocks.contains(&on_true) { on_true } else { on_false };
+
+    loop {
+        if bb == header || preds[bb].len() != 1 {
+            break;
+        }
+
+        let mut side_effects = false;
+        fun.block_instrs_ex(bb, |instr| {
+            if instr.is_terminator() {
+                return false;
+            }
+            if !has_no_side_effects(fun, instr) {
+                side_effects = true;
+                return false;
+            }
+            result.push(instr.id());
+            true
+        });
+        if side_effects {
+            break;
+        }
+
+        let InstrData::Jump { target } = fun.block_terminator(bb).unwrap().get(fun).data else { break };
+        if target == header || !lp.blocks.contains(&target) {
+            break;
+        }
+        bb = target;
+    }
+
+    result
+}
+
+
+// sparse conditional constant propagation (wegman & zadeck).
+// - requires ssa (run after local2reg & copy propagation).
+// - folds op1/op2 & phis with constant operands into loads.
+// - turns switches on constants into jumps & removes the unreachable blocks.
+// - folding must match `VmImpl::generic_*` exactly.
+//   ops that would raise a runtime error are not folded.
+pub fn sccp(fun: &mut Function) {
+    let mut values = index_vec![Lattice::Undef; fun.num_instrs()];
+
+    let mut users = index_vec![vec![]; fun.num_instrs()];
+    for bb in fun.block_ids() {
+        fun.block_instrs(bb, |instr| {
+            instr.args(fun, |arg| users[arg].push(instr.id()));
+        });
+    }
+
+    // executable predecessors, for each block.
+    let mut executable: IndexVec<BlockId, Vec<BlockId>> = index_vec![vec![]; fun.num_blocks()];
+    let mut visited = index_vec![false; fun.num_blocks()];
+
+    let mut flow_list: Vec<(OptBlockId, BlockId)> = vec![(None.into(), BlockId::ENTRY)];
+    let mut ssa_list: Vec<InstrId> = vec![];
+
+    while !flow_list.is_empty() || !ssa_list.is_empty() {
+        while let Some((from, bb)) = flow_list.pop() {
+            if let Some(from) = from.to_option() {
+                if executable[bb].contains(&from) {
+                    continue;
+                }
+                executable[bb].push(from);
+            }
+
+            let first_visit = !visited[bb];
+            visited[bb] = true;
+
+            fun.block_instrs(bb, |instr| {
+                if first_visit || instr.is_phi() {
+                    sccp_eval(fun, instr, &executable, &mut values, &users, &mut ssa_list, &mut flow_list);
+                }
+            });
+        }
+
+        while let Some(id) = ssa_list.pop() {
+            let instr = id.get(fun);
+            let Some(bb) = instr.bb().to_option() else { continue };
+            if visited[bb] {
+                sccp_eval(fun, instr, &executable, &mut values, &users, &mut ssa_list, &mut flow_list);
+            }
+        }
+    }
+
+    // rewrite.
+    let mut replace = index_vec![OptInstrId::NONE; fun.num_instrs()];
+    for bb in fun.block_ids() {
+        if !visited[bb] {
+            continue;
+        }
+
+        let mut current = bb.get(fun).first();
+        while let Some(at) = current.to_option() {
+            let instr = at.get(fun);
+            current = instr.next();
+
+            // skips the loads inserted for phis.
+            let Some(Lattice::Const(value)) = values.get(at).copied() else { continue };
+
+            match instr.data {
+                InstrData::Op1 { op: _, src: _ } |
+                InstrData::Op2 { op: _, src1: _, src2: _ } => {
+                    at.get_mut(fun).data = value.load();
+                }
+
+                // loads can't be mixed with phis.
+                InstrData::Phi { map_id: _ } => {
+                    let source = instr.source.clone();
+                    let load = fun.new_instr_ex(source, value.load());
+                    fun.insert_after(bb, fun.block_last_phi(bb), load);
+                    fun.remove_instr(at);
+                    replace[at] = load.some();
+                }
+
+                _ => (),
+            }
+        }
+
+        let terminator = fun.block_terminator(bb).unwrap();
+        let (target, dropped) = match terminator.get(fun).data {
+            InstrData::SwitchBool { src, on_true, on_false } => {
+                match values[src] {
+                    Lattice::Const(ConstValue::Bool(true))  => (on_true,  on_false),
+                    Lattice::Const(ConstValue::Bool(false)) => (on_false, on_true),
+                    _ => continue,
+                }
+            }
+
+            InstrData::SwitchNil { src, on_nil, on_non_nil } => {
+                match values[src] {
+                    Lattice::Const(ConstValue::Nil) => (on_nil, on_non_nil),
+                    Lattice::Const(_)               => (on_non_nil, on_nil),
+                    _ => continue,
+                }
+            }
+
+            _ => continue,
+        };
+        terminator.get_mut(fun).data = InstrData::Jump { target };
+
+        // `bb` is no longer a predecessor of `dropped`.
+        if dropped != target {
+            let mut phi = fun.block_first_phi(dropped);
+            while let Some(at) = phi.to_option() {
+                let Some(map) = fun.try_phi(at) else { break };
+                let map: Vec<PhiEntry> = map.iter().copied().filter(|(from, _)| *from != bb).collect();
+                fun.set_phi(at, &map);
+                phi = at.get(fun).next();
+            }
+        }
+    }
+
+    for bb in fun.block_ids() {
+        fun.block_replace_args(bb, |_, arg| {
+            if let Some(new_arg) = replace[*arg].to_option() {
+                *arg = new_arg;
+            }
+        });
+    }
+
+    fun.remove_unreachable_blocks();
+}
+
+
+#[derive(Clone, Copy, Debug)]
+enum ConstValue {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(StringId),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Lattice {
+    Undef,
+    Const(ConstValue),
+    Varying,
+}
+
+impl ConstValue {
+    // strings are interned per function.
+    fn same(self, other: ConstValue) -> bool {
+        use ConstValue::*;
+        match (self, other) {
+            (Nil, Nil) => true,
+            (Bool(a), Bool(b)) => a == b,
+            (Number(a), Number(b)) => a.to_bits() == b.to_bits(),
+            (String(a), String(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    // `VmImpl::raw_eq`.
+    fn raw_eq(self, other: ConstValue) -> bool {
+        use ConstValue::*;
+        match (self, other) {
+            (Number(a), Number(b)) => a == b,
+            _ => self.same(other),
+        }
+    }
+
+    fn load(self) -> InstrData {
+        match self {
+            ConstValue::Nil           => InstrData::LoadNil,
+            ConstValue::Bool(value)   => InstrData::LoadBool { value },
+            ConstValue::String(id)    => InstrData::LoadString { id },
+            ConstValue::Number(value) => {
+                // -0.0 isn't an int.
+                let int = value as i64;
+                if int as f64 == value && (value != 0.0 || value.is_sign_positive()) {
+                    InstrData::LoadInt { value: int }
+                }
+                else {
+                    InstrData::LoadFloat { value }
+                }
+            }
+        }
+    }
+}
+
+impl Lattice {
+    fn meet(self, other: Lattice) -> Lattice {
+        use Lattice::*;
+        match (self, other) {
+            (Undef, x) | (x, Undef) => x,
+            (Const(a), Const(b)) if a.same(b) => self,
+            _ => Varying,
+        }
+    }
+
+    fn same(self, other: Lattice) -> bool {
+        use Lattice::*;
+        match (self, other) {
+            (Undef, Undef) | (Varying, Varying) => true,
+            (Const(a), Const(b)) => a.same(b),
+            _ => false,
+        }
+    }
+}
+
+fn sccp_fold_op1(op: Op1, src: ConstValue) -> Option<ConstValue> {
+    use ConstValue::*;
+    match (op, src) {
+        (Op1::Not,    Bool(value))   => Some(Bool(!value)),
+        (Op1::Negate, Number(value)) => Some(Number(-value)),
+        _ => None,
+    }
+}
+
+fn sccp_fold_op2(op: Op2, src1: ConstValue, src2: ConstValue) -> Option<ConstValue> {
+    use ConstValue::*;
+
+    if let Op2::CmpEq | Op2::CmpNe = op {
+        let eq = src1.raw_eq(src2);
+        return Some(Bool(if op == Op2::CmpEq { eq } else { !eq }));
+    }
+
+    let (Number(a), Number(b)) = (src1, src2) else { return None };
+    Some(match op {
+        Op2::Add => Number(a + b),
+        Op2::Sub => Number(a - b),
+        Op2::Mul => Number(a * b),
+        Op2::Div      => { if b == 0.0 { return None } Number(a / b) }
+        Op2::FloorDiv => { if b == 0.0 { return None } Number((a / b).floor()) }
+        Op2::Rem      => { if b == 0.0 { return None } Number(a % b) }
+        Op2::CmpLe => Bool(a <= b),
+        Op2::CmpLt => Bool(a <  b),
+        Op2::CmpGe => Bool(a >= b),
+        Op2::CmpGt => Bool(a >  b),
+
+        // lowered to control flow.
+        Op2::And | Op2::Or | Op2::OrElse => return None,
+
+        Op2::CmpEq | Op2::CmpNe => unreachable!(),
+    })
+}
+
+fn sccp_eval(fun: &Function, instr: &Instr,
+    executable: &IndexVec<BlockId, Vec<BlockId>>,
+    values: &mut IndexVec<InstrId, Lattice>,
+    users: &IndexVec<InstrId, Vec<InstrId>>,
+    ssa_list: &mut Vec<InstrId>,
+    flow_list: &mut Vec<(OptBlockId, BlockId)>,
+) {
+    let bb = instr.bb().unwrap();
+
+    let constant = |value: &Lattice| -> Result<ConstValue, Lattice> {
+        match value {
+            Lattice::Const(value) => Ok(*value),
+            _ => Err(*value),
+        }
+    };
+
+    use InstrData::*;
+    let new_value = match instr.data {
+        LoadNil             => Lattice::Const(ConstValue::Nil),
+        LoadBool  { value } => Lattice::Const(ConstValue::Bool(value)),
+        LoadInt   { value } => Lattice::Const(ConstValue::Number(value as f64)),
+        LoadFloat { value } => Lattice::Const(ConstValue::Number(value)),
+        LoadString { id }   => Lattice::Const(ConstValue::String(id)),
+
+        Copy { src } => values[src],
+
+        Phi { map_id } => {
+            let mut result = Lattice::Undef;
+            for (from, src) in map_id.get(fun).iter() {
+                if executable[bb].contains(from) {
+                    result = result.meet(values[*src]);
+                }
+            }
+            result
+        }
+
+        Op1 { op, src } => {
+            match constant(&values[src]) {
+                Ok(src) => sccp_fold_op1(op, src).map_or(Lattice::Varying, Lattice::Const),
+                Err(value) => value,
+            }
+        }
+
+        Op2 { op, src1, src2 } => {
+            match (constant(&values[src1]), constant(&values[src2])) {
+                (Ok(src1), Ok(src2)) => sccp_fold_op2(op, src1, src2).map_or(Lattice::Varying, Lattice::Const),
+                (Err(Lattice::Varying), _) | (_, Err(Lattice::Varying)) => Lattice::Varying,
+                _ => Lattice::Undef,
+            }
+        }
+
+        Jump { target } => {
+            flow_list.push((bb.some(), target));
+            return;
+        }
+
+        SwitchBool { src, on_true, on_false } => {
+            match values[src] {
+                Lattice::Undef => (),
+                Lattice::Const(ConstValue::Bool(true))  => flow_list.push((bb.some(), on_true)),
+                Lattice::Const(ConstValue::Bool(false)) => flow_list.push((bb.some(), on_false)),
+                // non-bools are a runtime error, keep both edges.
+                _ => {
+                    flow_list.push((bb.some(), on_true));
+                    flow_list.push((bb.some(), on_false));
+                }
+            }
+            return;
+        }
+
+        SwitchNil { src, on_nil, on_non_nil } => {
+            match values[src] {
+                Lattice::Undef => (),
+                Lattice::Const(ConstValue::Nil) => flow_list.push((bb.some(), on_nil)),
+                Lattice::Const(_)               => flow_list.push((bb.some(), on_non_nil)),
+                Lattice::Varying => {
+                    flow_list.push((bb.some(), on_nil));
+                    flow_list.push((bb.some(), on_non_nil));
+                }
+            }
+            return;
+        }
+
+        Return { src: _ } |
+        SetLocal { dst: _, src: _ } => return,
+
+        ParallelCopy { src: _, copy_id: _ } |
+        Param { id: _ } |
+        Local { id: _ } |
+        GetLocal { src: _ } |
+        LoadEnv |
+        ListNew { values: _ } |
+        TupleNew { values: _ } |
+        TupleNew0 |
+        TupleNewNamed { shape: _, values: _ } |
+        ReadPath { path_id: _ } |
+        WritePath { path_id: _, value: _, is_def: _ } |
+        DeletePath { path_id: _ } |
+        Call { func: _, args_id: _ } |
+        LoadMethod { traitt: _, recv: _, method: _ } |
+        Implements { src: _, traitt: _ } => Lattice::Varying,
+    };
+
+    let id = instr.id();
+    if !new_value.same(values[id]) {
+        values[id] = new_value;
+        ssa_list.extend(users[id].iter().copied());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn compile(source: &str) -> Vec<FuncDesc> {
+        let mut module = parser::parse_module(source.as_bytes()).unwrap();
+
+        let mut diags = Diagnostics::new();
+        let mut infer = infer::Infer::new(&mut diags);
+        infer.assign_ids(&mut module);
+        infer.infer(&mut module);
+
+        let mut builder = bbir_builder::Builder::new(&mut diags);
+        builder.build(&module);
+        let krate = builder.krate;
+        assert!(!diags.has_errors());
+
+        let (funcs, _, _) = krate.build();
+        funcs.into_inner()
+    }
+
+    fn count(funcs: &[FuncDesc], op: u8) -> usize {
+        let mut result = 0;
+        for func in funcs {
+            let FuncCode::ByteCode(code) = &func.code else { continue };
+            let mut decoder = ByteCodeDecoder::new(code);
+            while let Some(instr) = decoder.next() {
+                result += (instr.opcode == op) as usize;
+            }
+        }
+        result
+    }
+
+
+    fn count_calls(funcs: &[FuncDesc]) -> usize {
+        [opcode::CALL, opcode::TAIL_CALL, opcode::CALL0, opcode::CALL1, opcode::CALL2, opcode::CALL3]
+            .into_iter().map(|op| count(funcs, op)).sum()
+    }
+
+
+    #[test]
+    fn inline_simple() {
+        let funcs = compile("
+            fn add1(x):
+                return x + 1
+            end
+
+            fn f(y):
+                return add1(y) * add1(2)
+            end
+        ");
+        assert_eq!(count_calls(&funcs), 0);
+    }
+
+    #[test]
+    fn inline_params() {
+        // the param's local isn't `l0`.
+        let mut krate = Crate::new();
+        let mut callee = krate.new_function();
+        bbir_text::parse_function(&mut callee, "
+            local l0 \"t\"
+            local l1 \"x\"
+            bb0:
+              i0 := param l1
+              i1 := local l0
+              i2 := load_int 1
+              i3 := add i0, i2
+              i4    return i3
+        ").unwrap();
+
+        let mut fun = krate.new_function();
+        bbir_text::parse_function(&mut fun, "
+            local l0 \"f\"
+            bb0:
+              i0 := param l0
+              i1 := load_int 5
+              i2 := call i0, [ i1 ]
+              i3    return i2
+        ").unwrap();
+
+        fun.inline_call(InstrId::from_usize(2), &callee);
+        fun.slow_integrity_check();
+
+        let text = fun.text().to_string();
+        assert!(text.contains("local l2 \"x\""), "{text}");
+        assert!(text.contains("set_local l2, i1"), "{text}");
+        assert!(text.contains("add i1, "), "{text}");
+        assert!(!text.contains("call"), "{text}");
+    }
+
+    #[test]
+    fn inline_limits() {
+        // too large.
+        let funcs = compile("
+            fn big(x):
+                let a = x + 1
+                let b = a * a - x
+                let c = b * b - a
+                let d = c * c - b
+                let e = d * d - c
+                let f = e * e - d
+                let g = f * f - e
+                let h = g * g - f
+                let i = h * h - g
+                let j = i * i - h
+                let k = j * j - i
+                return k * k - j
+            end
+
+            fn f(y):
+                return big(y)
+            end
+        ");
+        assert_eq!(count_calls(&funcs), 1);
+    }
+
+    #[test]
+    fn inline_recursive() {
+        // neither the recursive calls nor calls into recursive sccs are inlined.
+        let funcs = compile("
+            fn fact(n):
+                if n < 2: return 1 end
+                return n * fact(n - 1)
+            end
+
+            fn even(n):
+                if n == 0: return true end
+                return odd(n - 1)
+            end
+
+            fn odd(n):
+                if n == 0: return false end
+                return even(n - 1)
+            end
+
+            fn f(n):
+                return (fact(n), even(n))
+            end
+        ");
+        assert_eq!(count_calls(&funcs), 5);
+    }
+
+    #[test]
+    fn gvn_same_block() {
+        let funcs = compile("
+            fn f(a, b):
+                return a*b + a*b
+            end
+        ");
+        assert_eq!(count(&funcs, opcode::MUL), 1);
+        assert_eq!(count(&funcs, opcode::ADD_NUM), 1);
+    }
+
+    #[test]
+    fn gvn_commutative() {
+        let funcs = compile("
+            fn f(a, b):
+                return (a*b + b*a) + (a - b) + (b - a)
+            end
+        ");
+        assert_eq!(count(&funcs, opcode::MUL), 1);
+        assert_eq!(count(&funcs, opcode::SUB), 2);
+    }
+
+    #[test]
+    fn gvn_dominating() {
+        let funcs = compile("
+            fn f(a, b, c):
+                let x = a*b
+                if c:
+                    return a*b
+                end
+                return x
+            end
+        ");
+        assert_eq!(count(&funcs, opcode::MUL), 1);
+    }
+
+    #[test]
+    fn gvn_not_dominating() {
+        let funcs = compile("
+            fn f(a, b, c):
+                if c:
+                    return a*b
+                end
+                return a*b
+            end
+        ");
+        assert_eq!(count(&funcs, opcode::MUL), 2);
+    }
+
+    #[test]
+    fn gvn_loop() {
+        // `life.kb`.
+        let funcs = compile("
+            fn f(board, w, h):
+                let y = 0
+                while y < h:
+                    let x = 0
+                    while x < w:
+                        if board[y*w + x] > 0:
+                            board[y*w + x] = board[y*w + x] - 1
+                        end
+                        x += 1
+                    end
+                    y += 1
+                end
+                return board
+            end
+        ");
+        assert_eq!(count(&funcs, opcode::MUL), 1);
+    }
+
+    #[test]
+    fn gvn_read_path() {
+        let funcs = compile("
+            fn f(l, i):
+                return l[i] + l[i]
+            end
+
+            fn g():
+                return global + global
+            end
+        ");
+        // env reads aren't merged.
+        assert_eq!(count(&funcs, opcode::READ_PATH), 3);
+    }
+
+    #[test]
+    fn licm_guarded() {
+        // `y*w` may fail, so it's hoisted behind a copy of the loop's test.
+        // `s` is merged with its initial value on the loop's exit.
+        bbir_text::file_check("
+            // run: insert-preheaders, licm
+            // check: bb0:
+            // check: cmp_lt i3, i0
+            // check-next: switch_bool
+            // check: bb1:
+            // check-not: mul
+            // check: bb3:
+            // check-next: phi { bb1: i7, bb0: i3 }
+            // check-next: return
+            // check: bb4:
+            // check-next: mul i1, i2
+            // check-next: jump bb1
+            local l0 \"n\"
+            local l1 \"y\"
+            local l2 \"w\"
+            bb0:
+              i0 := param l0
+              i1 := param l1
+              i2 := param l2
+              i3 := load_int 0
+              i4 := load_int 1
+              i5    jump bb1
+            bb1:
+              i6 := phi { bb0: i3, bb2: i11 }
+              i7 := phi { bb0: i3, bb2: i10 }
+              i8 := cmp_lt i6, i0
+              i12    switch_bool i8, bb2, bb3
+            bb2:
+              i9 := mul i1, i2
+              i10 := add i7, i9
+              i11 := add i6, i4
+              i13    jump bb1
+            bb3:
+              i14    return i7
+        ").unwrap();
+
+        // after a call, the mul may not run at all.
+        bbir_text::file_check("
+            // run: insert-preheaders, licm
+            // check: i15 := call
+            // check-next: mul i1, i2
+            local l0 \"n\"
+            local l1 \"y\"
+            local l2 \"w\"
+            bb0:
+              i0 := param l0
+              i1 := param l1
+              i2 := param l2
+              i3 := load_int 0
+              i4 := load_int 1
+              i5    jump bb1
+            bb1:
+              i6 := phi { bb0: i3, bb2: i11 }
+              i8 := cmp_lt i6, i0
+              i12    switch_bool i8, bb2, bb3
+            bb2:
+              i15 := call i0, [ i6 ]
+              i9 := mul i1, i2
+              i10 := add i6, i9
+              i11 := add i10, i4
+              i13    jump bb1
+            bb3:
+              i14    return i6
+        ").unwrap();
+    }
+
+    #[test]
+    fn sccp_loop_phi() {
+        // `x` stays 1, so the loop's phi, the compare & the else branch fold away.
+        bbir_text::file_check("
+            // run: sccp
+            // check: i4 := phi
+            // check-next: := load_int 1
+            // check-not: phi
+            // check: return
+            // check-not: load_int 2
+            local l0 \"n\"
+            bb0:
+              i0 := param l0
+              i1 := load_int 1
+              i2 := load_int 0
+              i3    jump bb1
+            bb1:
+              i4 := phi { bb0: i2, bb4: i12 }
+              i5 := phi { bb0: i1, bb4: i11 }
+              i6 := cmp_lt i4, i0
+              i7    switch_bool i6, bb2, bb3
+            bb2:
+              i8 := cmp_eq i5, i1
+              i9    switch_bool i8, bb4, bb5
+            bb3:
+              i10    return i5
+            bb4:
+              i11 := phi { bb2: i1, bb5: i13 }
+              i12 := add i4, i1
+              i15    jump bb1
+            bb5:
+              i13 := load_int 2
+              i14    jump bb4
+        ").unwrap();
+    }
+
+    #[test]
+    fn sccp_run() {
+        let funcs = compile("
+            fn f(n):
+                var x = 1
+                var i = 0
+                while i < n:
+                    if x == 1: x = 1 else: x = 2 end
+                    i += 1
+                end
+                return x + 2*3
+            end
+        ");
+        for op in [opcode::MUL, opcode::MUL_NUM, opcode::CMP_EQ, opcode::CMP_EQ_NUM, opcode::JUMP_EQ_NUM, opcode::JUMP_NE_NUM] {
+            assert_eq!(count(&funcs, op), 0, "{}", opcode::name(op));
+        }
+        assert_eq!(count(&funcs, opcode::LOAD_INT), 3);
+    }
+
+    #[test]
+    fn dead_code_elim() {
+        // `i8` & `i9` only use each other.
+        bbir_text::file_check("
+            // run: dead-code-elim
+            // check: i2 := load_int 2
+            // check-not: mul
+            // check-next: i4 := call i0, [ i2 ]
+            // check-next: i5 := read_path i1, [i2]
+            // check-next: i6 := add i1, i2
+            // check: bb1:
+            // check-next: switch_bool i5, bb1, bb2
+            // check: return i4
+            local l0 \"g\"
+            local l1 \"xs\"
+            bb0:
+              i0 := param l0
+              i1 := param l1
+              i2 := load_int 2
+              i3 := mul i2, i2
+              i4 := call i0, [ i2 ]
+              i5 := read_path i1, [i2]
+              i6 := add i1, i2
+              i7    jump bb1
+            bb1:
+              i8 := phi { bb0: i2, bb1: i9 }
+              i9 := cmp_eq i8, i2
+              i10    switch_bool i5, bb1, bb2
+            bb2:
+              i11    return i4
+        ").unwrap();
+    }
+}
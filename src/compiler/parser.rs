@@ -39,6 +39,7 @@ pub enum TokenData<'a> {
     FatArrow,
     ColonEq,
     KwLet, KwVar,
+    KwDel,
     KwDo,
     KwIf,
     KwElif,
@@ -51,6 +52,9 @@ pub enum TokenData<'a> {
     KwReturn,
     KwEnd,
     KwFn,
+    KwTrait,
+    KwImpl,
+    KwImplements,
     KwAnd,
     KwOr,
     KwNot,
@@ -100,11 +104,11 @@ impl<'a> TokenData<'a> {
 
             LParen | LBracket | LCurly |
             Dot | Comma | Colon | Semicolon |
-            KwLet | KwVar |
+            KwLet | KwVar | KwDel |
             KwDo |
             KwIf | KwElif | KwElse |
             KwWhile | KwFor | KwIn |
-            KwFn |
+            KwFn | KwTrait | KwImpl | KwImplements |
             KwAnd | KwOr |
             OpAdd | OpAddAssign |
             OpMinus | OpMinusAssign |
@@ -126,11 +130,11 @@ impl<'a> TokenData<'a> {
             Ident (_) | Number (_) | Bool(_) | Nil | QuotedString(_) |
             Label(_) |
             LParen | LBracket | LCurly |
-            KwLet | KwVar |
+            KwLet | KwVar | KwDel |
             KwDo | KwIf | KwElif | KwElse | KwWhile | KwFor |
             KwBreak | KwContinue | KwReturn |
             KwEnd |
-            KwFn |
+            KwFn | KwTrait | KwImpl | KwImplements |
             KwEnv |
             KwNot
             => true,
@@ -187,6 +191,7 @@ impl<'a> TokenData<'a> {
             KwDo | KwIf | KwWhile | KwFor |
             KwBreak | KwContinue | KwReturn |
             KwFn |
+            KwImplements |
             KwNot |
             KwEnv |
             OpMinus | OpMul
@@ -194,6 +199,7 @@ impl<'a> TokenData<'a> {
 
             RParen | RBracket | RCurly |
             Dot | Comma | Colon | Semicolon |
+            KwDel | KwTrait | KwImpl |
             KwEnd |
             KwElif | KwElse |
             KwIn |
@@ -616,6 +622,7 @@ impl<'i> Tokenizer<'i> {
                 "end"       => TokenData::KwEnd,
                 "let"       => TokenData::KwLet,
                 "var"       => TokenData::KwVar,
+                "del"       => TokenData::KwDel,
                 "do"        => TokenData::KwDo,
                 "if"        => TokenData::KwIf,
                 "elif"      => TokenData::KwElif,
@@ -627,6 +634,9 @@ impl<'i> Tokenizer<'i> {
                 "continue"  => TokenData::KwContinue,
                 "return"    => TokenData::KwReturn,
                 "fn"        => TokenData::KwFn,
+                "trait"     => TokenData::KwTrait,
+                "impl"      => TokenData::KwImpl,
+                "implements" => TokenData::KwImplements,
                 "and"       => TokenData::KwAnd,
                 "or"        => TokenData::KwOr,
                 "not"       => TokenData::KwNot,
@@ -791,13 +801,22 @@ impl<'p, 'i> Parser<'p, 'i> {
 
         // tuples & sub-expr.
         if let TokenData::LParen = current.data {
+            // named tuple.
+            if self.peek(0).filter(|at| at.data.is_ident()).is_some() && self.peek_if(1, TokenData::Colon) {
+                let (names, values) = self.parse_named_exprs(TokenData::RParen)?;
+                let data = ExprData::Tuple(Box::new(expr::Tuple { values, names }));
+
+                let end = self.expect(TokenData::RParen)?.end;
+                return Ok(Expr::new(SourceRange { begin, end }, data));
+            }
+
             let (values, had_comma) = self.parse_comma_exprs(TokenData::RParen)?;
             let data =
                 if values.len() == 1 && !had_comma {
                     ExprData::SubExpr(Box::new(values.into_iter().next().unwrap()))
                 }
                 else {
-                    ExprData::Tuple(Box::new(expr::Tuple { values }))
+                    ExprData::Tuple(Box::new(expr::Tuple { values, names: vec![] }))
                 };
 
             let end = self.expect(TokenData::RParen)?.end;
@@ -910,6 +929,18 @@ impl<'p, 'i> Parser<'p, 'i> {
             return Ok(Expr::new(current.source, ExprData::Env));
         }
 
+        // implements.
+        if current.data == TokenData::KwImplements {
+            self.expect(TokenData::LParen)?;
+            let value = self.parse_expr(0)?;
+            self.expect(TokenData::Comma)?;
+            let traitt = self.parse_expr(0)?;
+            let end = self.expect(TokenData::RParen)?.end;
+
+            let data = ExprData::Implements(Box::new(expr::Implements { value, traitt }));
+            return Ok(Expr::new(SourceRange { begin, end }, data));
+        }
+
 
         Err(ParseError::at(&current, ParseErrorData::ExpectedExpression))
     }
@@ -956,6 +987,7 @@ impl<'p, 'i> Parser<'p, 'i> {
                     ExprData::Call(Box::new(expr::Call {
                         func: result,
                         args,
+                        info: None,
                     })));
                 continue;
             }
@@ -973,6 +1005,7 @@ impl<'p, 'i> Parser<'p, 'i> {
                     ExprData::Field(Box::new(expr::Field {
                         base: result,
                         name: name.value,
+                        info: None,
                     })));
                 continue;
             }
@@ -1035,6 +1068,25 @@ impl<'p, 'i> Parser<'p, 'i> {
         Ok((result, had_comma))
     }
 
+    // `name: expr, ...`
+    pub fn parse_named_exprs(&mut self, until: TokenData<'static>) -> ParseResult<(Vec<&'i str>, Vec<Expr<'i>>)> {
+        let mut names  = vec![];
+        let mut values = vec![];
+
+        let mut had_comma = true;
+        while had_comma && !self.peek_if(0, until) {
+            names.push(self.expect_ident()?.value);
+            self.expect(TokenData::Colon)?;
+            values.push(self.parse_expr(0)?);
+
+            if !self.next_if(TokenData::Comma) {
+                had_comma = false;
+            }
+        }
+
+        Ok((names, values))
+    }
+
     /*
     // bool: ends with comma.
     pub fn parse_kv_exprs(&mut self, until: TokenData<'static>) -> ParseResult<(Vec<(Ident<'i>, Expr<'i>)>, bool)> {
@@ -1090,6 +1142,18 @@ impl<'p, 'i> Parser<'p, 'i> {
                 let (source, func) = self.parse_func(at.source.begin)?;
                 stmts.push(Stmt::new(source, StmtData::Item(Item::new(source, ItemData::Func(func)))));
             }
+            // trait
+            else if at.data == TokenData::KwTrait {
+                self.next().unwrap();
+                let (source, traitt) = self.parse_trait(at.source.begin)?;
+                stmts.push(Stmt::new(source, StmtData::Item(Item::new(source, ItemData::Trait(traitt)))));
+            }
+            // impl
+            else if at.data == TokenData::KwImpl {
+                self.next().unwrap();
+                let (source, imp) = self.parse_impl(at.source.begin)?;
+                stmts.push(Stmt::new(source, StmtData::Item(Item::new(source, ItemData::Impl(imp)))));
+            }
             // local ::= (let | var) ident (= expr)? (;)?
             else if at.data == TokenData::KwLet
             ||      at.data == TokenData::KwVar {
@@ -1120,6 +1184,14 @@ impl<'p, 'i> Parser<'p, 'i> {
                     }),
                 ));
             }
+            // del ::= del expr
+            else if at.data == TokenData::KwDel {
+                self.next().unwrap();
+
+                let target = self.parse_expr(0)?;
+                let source = SourceRange { begin: at.source.begin, end: target.source.end };
+                stmts.push(Stmt::new(source, StmtData::Delete(expr::Delete { target })));
+            }
             // expr stmt
             else {
                 let expr = self.parse_expr(0)?;
@@ -1184,8 +1256,9 @@ impl<'p, 'i> Parser<'p, 'i> {
 
         let mut had_comma = true;
         while had_comma {
+            let Some(at) = self.peek(0).copied() else { break };
             let Some(name) = self.next_if_ident() else { break };
-            result.push(item::FuncParam { name });
+            result.push(item::FuncParam { source: at.source, name });
 
             if !self.next_if(TokenData::Comma) {
                 had_comma = false;
@@ -1233,6 +1306,55 @@ impl<'p, 'i> Parser<'p, 'i> {
     }
 
 
+    pub fn parse_trait(&mut self, begin: SourcePos) -> ParseResult<(SourceRange, item::Trait<'i>)> {
+        // trait name ':' (fn name ( params ))* end
+        let name = self.expect_ident()?.value;
+        self.expect(TokenData::Colon)?;
+
+        let mut methods = vec![];
+        loop {
+            if self.next_if(TokenData::Semicolon) { continue }
+            if self.peek_if(0, TokenData::KwEnd)  { break }
+
+            self.expect(TokenData::KwFn)?;
+            let name = self.expect_ident()?.value;
+            self.expect(TokenData::LParen)?;
+            let params = self.parse_func_params()?.0;
+            self.expect(TokenData::RParen)?;
+
+            methods.push(item::TraitMethod { name, params });
+        }
+        let end = self.expect(TokenData::KwEnd)?.end;
+
+        Ok((SourceRange { begin, end }, item::Trait { name, methods }))
+    }
+
+    pub fn parse_impl(&mut self, begin: SourcePos) -> ParseResult<(SourceRange, item::Impl<'i>)> {
+        // impl trait for type ':' func* end
+        let traitt = self.expect_ident()?.value;
+        self.expect(TokenData::KwFor)?;
+        let ty = self.expect_ident()?.value;
+        self.expect(TokenData::Colon)?;
+
+        let mut methods = vec![];
+        loop {
+            if self.next_if(TokenData::Semicolon) { continue }
+            if self.peek_if(0, TokenData::KwEnd)  { break }
+
+            let at = *self.peek_or_eof(0)?;
+            self.expect(TokenData::KwFn)?;
+            let (source, func) = self.parse_func(at.source.begin)?;
+            if func.name.is_none() {
+                return Err(ParseError::at(&at, ParseErrorData::Expected(TokenData::Ident(""))));
+            }
+            methods.push(Stmt::new(source, StmtData::Item(Item::new(source, ItemData::Func(func)))));
+        }
+        let end = self.expect(TokenData::KwEnd)?.end;
+
+        Ok((SourceRange { begin, end }, item::Impl { traitt, ty, methods, info: None }))
+    }
+
+
     pub fn parse_module(&mut self, begin: SourcePos) -> ParseResult<item::Module<'i>> {
         let (source, block) = self.parse_block(begin)?;
         return Ok(item::Module { source, block });
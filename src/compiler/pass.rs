@@ -0,0 +1,412 @@
+use std::time::Duration;
+
+use super::*;
+
+
+// the analyses a pass can require.
+// they only depend on the cfg.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Analysis {
+    Predecessors,
+    PostOrder,
+    PostOrderIndices,
+    ImmediateDominators,
+    DomTree,
+    DominanceFrontiers,
+    LoopForest,
+}
+
+#[derive(Default)]
+pub struct Analyses {
+    preds:         Option<Predecessors>,
+    post_order:    Option<PostOrder>,
+    post_indices:  Option<PostOrderIndices>,
+    idoms:         Option<ImmediateDominators>,
+    dom_tree:      Option<DomTree>,
+    dom_frontiers: Option<DominanceFrontiers>,
+    loops:         Option<LoopForest>,
+}
+
+impl Analyses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn invalidate(&mut self) {
+        *self = Self::default();
+    }
+
+    // computes `analysis` & its dependencies, if they aren't cached.
+    pub fn ensure(&mut self, fun: &Function, analysis: Analysis) {
+        use Analysis::*;
+        match analysis {
+            Predecessors => {
+                if self.preds.is_none() {
+                    self.preds = Some(fun.predecessors());
+                }
+            }
+
+            PostOrder => {
+                if self.post_order.is_none() {
+                    self.post_order = Some(fun.post_order());
+                }
+            }
+
+            PostOrderIndices => {
+                if self.post_indices.is_none() {
+                    self.ensure(fun, PostOrder);
+                    self.post_indices = Some(fun.post_order_indices(self.post_order()));
+                }
+            }
+
+            ImmediateDominators => {
+                if self.idoms.is_none() {
+                    self.ensure(fun, Predecessors);
+                    self.ensure(fun, PostOrderIndices);
+                    self.idoms = Some(fun.immediate_dominators(
+                        self.preds(), self.post_order(), self.post_indices()));
+                }
+            }
+
+            DomTree => {
+                if self.dom_tree.is_none() {
+                    self.ensure(fun, ImmediateDominators);
+                    self.dom_tree = Some(fun.dominator_tree(self.idoms()));
+                }
+            }
+
+            DominanceFrontiers => {
+                if self.dom_frontiers.is_none() {
+                    self.ensure(fun, ImmediateDominators);
+                    self.dom_frontiers = Some(fun.dominance_frontiers(self.preds(), self.idoms()));
+                }
+            }
+
+            LoopForest => {
+                if self.loops.is_none() {
+                    self.ensure(fun, ImmediateDominators);
+                    self.loops = Some(fun.loop_forest(self.preds(), self.post_order(), self.idoms()));
+                }
+            }
+        }
+    }
+
+    // the accessors panic, if the analysis wasn't `ensure`d.
+    #[inline(always)] pub fn preds(&self)         -> &Predecessors       { self.preds.as_ref().unwrap() }
+    #[inline(always)] pub fn post_order(&self)    -> &PostOrder          { self.post_order.as_ref().unwrap() }
+    #[inline(always)] pub fn post_indices(&self)  -> &PostOrderIndices   { self.post_indices.as_ref().unwrap() }
+    #[inline(always)] pub fn idoms(&self)         -> &ImmediateDominators { self.idoms.as_ref().unwrap() }
+    #[inline(always)] pub fn dom_tree(&self)      -> &DomTree            { self.dom_tree.as_ref().unwrap() }
+    #[inline(always)] pub fn dom_frontiers(&self) -> &DominanceFrontiers { self.dom_frontiers.as_ref().unwrap() }
+    #[inline(always)] pub fn loops(&self)         -> &LoopForest         { self.loops.as_ref().unwrap() }
+}
+
+
+#[derive(Clone, Copy)]
+pub struct Pass {
+    pub name:     &'static str,
+    pub requires: &'static [Analysis],
+    // passes that don't change the cfg keep the cached analyses.
+    pub preserves_cfg: bool,
+    pub run: fn(&mut Function, &Analyses),
+}
+
+impl Pass {
+    pub const REMOVE_UNREACHABLE_BLOCKS: Pass = Pass {
+        name: "remove-unreachable-blocks",
+        requires: &[],
+        preserves_cfg: false,
+        run: |fun, _| fun.remove_unreachable_blocks(),
+    };
+
+    pub const LOCAL2REG: Pass = Pass {
+        name: "local2reg",
+        requires: &[Analysis::Predecessors, Analysis::DomTree, Analysis::DominanceFrontiers],
+        preserves_cfg: true,
+        run: |fun, an| opt::local2reg_ex(fun, an.preds(), an.dom_tree(), an.dom_frontiers()),
+    };
+
+    pub const COPY_PROPAGATION: Pass = Pass {
+        name: "copy-propagation",
+        requires: &[Analysis::DomTree],
+        preserves_cfg: true,
+        run: |fun, an| opt::copy_propagation_ex(fun, an.dom_tree()),
+    };
+
+    pub const SCCP: Pass = Pass {
+        name: "sccp",
+        requires: &[],
+        preserves_cfg: false,
+        run: |fun, _| opt::sccp(fun),
+    };
+
+    pub const INSERT_PREHEADERS: Pass = Pass {
+        name: "insert-preheaders",
+        requires: &[Analysis::Predecessors, Analysis::LoopForest],
+        preserves_cfg: false,
+        run: |fun, an| opt::insert_preheaders(fun, an.preds(), an.loops()),
+    };
+
+    pub const LICM: Pass = Pass {
+        name: "licm",
+        requires: &[Analysis::Predecessors, Analysis::PostOrder, Analysis::LoopForest],
+        preserves_cfg: false,
+        run: |fun, an| opt::licm(fun, an.preds(), an.post_order(), an.loops()),
+    };
+
+    pub const GVN: Pass = Pass {
+        name: "gvn",
+        requires: &[Analysis::DomTree],
+        preserves_cfg: true,
+        run: |fun, an| opt::gvn(fun, an.dom_tree()),
+    };
+
+    pub const DEAD_COPY_ELIM: Pass = Pass {
+        name: "dead-copy-elim",
+        requires: &[],
+        preserves_cfg: true,
+        run: |fun, _| opt::dead_copy_elim(fun),
+    };
+
+    pub const DEAD_CODE_ELIM: Pass = Pass {
+        name: "dead-code-elim",
+        requires: &[],
+        preserves_cfg: true,
+        run: |fun, _| opt::dead_code_elim(fun),
+    };
+
+    pub const CSSA: Pass = Pass {
+        name: "cssa",
+        requires: &[],
+        preserves_cfg: false,
+        run: |fun, _| { transform::convert_to_cssa(fun); },
+    };
+
+    pub const ALL: &'static [Pass] = &[
+        Pass::REMOVE_UNREACHABLE_BLOCKS,
+        Pass::LOCAL2REG,
+        Pass::COPY_PROPAGATION,
+        Pass::SCCP,
+        Pass::INSERT_PREHEADERS,
+        Pass::LICM,
+        Pass::GVN,
+        Pass::DEAD_COPY_ELIM,
+        Pass::DEAD_CODE_ELIM,
+        Pass::CSSA,
+    ];
+
+    pub fn by_name(name: &str) -> Option<Pass> {
+        Pass::ALL.iter().find(|pass| pass.name == name).copied()
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    // no inlining, only the passes codegen relies on.
+    O0,
+    #[default]
+    O1,
+}
+
+pub struct PassManager {
+    opt_level:  OptLevel,
+    passes:     Vec<Pass>,
+    dump_after: Vec<&'static str>,
+    dumps:      String,
+    timings:    Option<Vec<Duration>>,
+}
+
+impl PassManager {
+    pub fn new(opt_level: OptLevel) -> Self {
+        let passes = match opt_level {
+            OptLevel::O0 => vec![
+                Pass::REMOVE_UNREACHABLE_BLOCKS,
+                Pass::LOCAL2REG,
+                // the linear scan allocator trips over
+                // the copies local2reg leaves behind.
+                Pass::COPY_PROPAGATION,
+                Pass::DEAD_COPY_ELIM,
+                Pass::CSSA,
+            ],
+
+            OptLevel::O1 => vec![
+                Pass::REMOVE_UNREACHABLE_BLOCKS,
+                Pass::LOCAL2REG,
+                Pass::COPY_PROPAGATION,
+                Pass::SCCP,
+                Pass::INSERT_PREHEADERS,
+                Pass::LICM,
+                // after licm, hoisted instructions may be redundant.
+                Pass::GVN,
+                Pass::DEAD_COPY_ELIM,
+                Pass::DEAD_CODE_ELIM,
+                Pass::CSSA,
+            ],
+        };
+        Self::with_passes(opt_level, passes)
+    }
+
+    // codegen expects the function to be in cssa,
+    // so the last pass should be `Pass::CSSA`.
+    pub fn with_passes(opt_level: OptLevel, passes: Vec<Pass>) -> Self {
+        PassManager { opt_level, passes, dump_after: vec![], dumps: String::new(), timings: None }
+    }
+
+    #[inline(always)]
+    pub fn opt_level(&self) -> OptLevel { self.opt_level }
+
+    #[inline(always)]
+    pub fn passes(&self) -> &[Pass] { &self.passes }
+
+    // returns false, if there's no pass with that name.
+    // the function's text after the pass is collected, see `take_dumps`.
+    pub fn dump_after(&mut self, name: &str) -> bool {
+        let Some(pass) = self.passes.iter().find(|pass| pass.name == name) else { return false };
+        self.dump_after.push(pass.name);
+        true
+    }
+
+    // the dumps since the last call, in the order the passes ran.
+    pub fn take_dumps(&mut self) -> String {
+        core::mem::take(&mut self.dumps)
+    }
+
+    pub fn enable_timing(&mut self) {
+        self.timings = Some(vec![Duration::ZERO; self.passes.len()]);
+    }
+
+    pub fn run(&mut self, fun: &mut Function, analyses: &mut Analyses) {
+        for (i, pass) in self.passes.iter().enumerate() {
+            let t0 = std::time::Instant::now();
+
+            for analysis in pass.requires.iter().copied() {
+                analyses.ensure(fun, analysis);
+            }
+            (pass.run)(fun, analyses);
+            if !pass.preserves_cfg {
+                analyses.invalidate();
+            }
+
+            if let Some(timings) = &mut self.timings {
+                timings[i] += t0.elapsed();
+            }
+
+            if cfg!(debug_assertions) {
+                if let Err(errors) = verify::verify(fun) {
+                    fun.dump();
+                    let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                    panic!("{} is invalid after {}:\n{}", fun.id(), pass.name, errors.join("\n"));
+                }
+            }
+
+            if self.dump_after.contains(&pass.name) {
+                use core::fmt::Write;
+                _ = write!(self.dumps, "{} after {}:\n{}", fun.id(), pass.name, fun.text());
+            }
+        }
+    }
+
+    // totals over all functions, in pipeline order.
+    pub fn timing_report(&self) -> Option<String> {
+        use core::fmt::Write;
+
+        let timings = self.timings.as_ref()?;
+        let width = self.passes.iter().map(|pass| pass.name.len()).max().unwrap_or(0);
+
+        let mut result = String::new();
+        for (pass, time) in self.passes.iter().zip(timings) {
+            writeln!(result, "{:width$}  {:?}", pass.name, time).unwrap();
+        }
+        Some(result)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn parse(text: &str) -> std::cell::RefMut<'static, Function> {
+        let mut krate = Crate::new();
+        let mut fun = krate.new_function();
+        bbir_text::parse_function(&mut fun, text).unwrap();
+        fun
+    }
+
+    // the loop's back edge is critical.
+    const LOOP: &str = "
+        local l0 \"c\"
+        bb0:
+          i0 := param l0
+          i1    jump bb1
+        bb1:
+          i2    switch_bool i0, bb1, bb2
+        bb2:
+          i3    return i0
+    ";
+
+    const KEEP: Pass = Pass {
+        name: "keep",
+        requires: &[Analysis::LoopForest],
+        preserves_cfg: true,
+        run: |_, _| (),
+    };
+
+    const SPLIT: Pass = Pass {
+        name: "split",
+        requires: &[Analysis::Predecessors],
+        preserves_cfg: false,
+        run: |fun, _| { fun.split_critical_edges(); },
+    };
+
+    // fails, if the analyses are stale.
+    const CHECK: Pass = Pass {
+        name: "check",
+        requires: &[Analysis::Predecessors, Analysis::PostOrder],
+        preserves_cfg: true,
+        run: |fun, an| {
+            assert_eq!(an.preds().len(), fun.num_blocks());
+            assert_eq!(an.post_order().len(), fun.num_blocks());
+        },
+    };
+
+    #[test]
+    fn analysis_invalidation() {
+        let mut fun = parse(LOOP);
+
+        // requirements & their dependencies are computed & kept.
+        let mut analyses = Analyses::new();
+        PassManager::with_passes(OptLevel::O1, vec![KEEP]).run(&mut fun, &mut analyses);
+        assert!(analyses.loops.is_some() && analyses.idoms.is_some() && analyses.preds.is_some());
+        assert!(analyses.dom_tree.is_none());
+
+        // cfg changes drop everything.
+        PassManager::with_passes(OptLevel::O1, vec![SPLIT]).run(&mut fun, &mut analyses);
+        assert!(analyses.loops.is_none() && analyses.idoms.is_none() && analyses.preds.is_none());
+        assert_eq!(fun.num_blocks(), 4);
+
+        let mut fun = parse(LOOP);
+        let mut analyses = Analyses::new();
+        PassManager::with_passes(OptLevel::O1, vec![CHECK, SPLIT, CHECK]).run(&mut fun, &mut analyses);
+    }
+
+    #[test]
+    fn dump_after() {
+        let mut passes = PassManager::new(OptLevel::O0);
+        assert!(passes.dump_after("cssa"));
+        // not part of the O0 pipeline.
+        assert!(!passes.dump_after("licm"));
+        assert!(!passes.dump_after("nope"));
+
+        let mut fun = parse(LOOP);
+        passes.run(&mut fun, &mut Analyses::new());
+
+        let dumps = passes.take_dumps();
+        assert!(dumps.starts_with("fn0 after cssa:\nlocal l0 \"c\"\nbb0:\n"), "{dumps}");
+        assert_eq!(dumps.matches(" after ").count(), 1);
+        assert_eq!(dumps.lines().count(), 1 + fun.text().to_string().lines().count());
+        assert!(passes.take_dumps().is_empty());
+    }
+}
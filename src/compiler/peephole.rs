@@ -0,0 +1,442 @@
+use crate::bytecode::{opcode, ByteCodeBuilder, ByteCodeDecoder, Instr, InstrData, InstrWord, PathBase, PathKey};
+use super::codegen::GenBytecodeResult;
+
+
+// bytecode level cleanups, after `generate_bytecode`:
+// - `cmp_* t, a, b; jump_true/false t` becomes `jump_*_num a, b`.
+// - `load_int t, k; add d, a, t` becomes `add_int d, k` (with a copy, if `d != a`).
+// - `copy t, s; copy d, t` becomes `copy d, s`.
+// - calls with up to 3 args become `call0`..`call3`.
+// - jump chains are threaded, jumps to the next instruction are removed.
+// the temporaries must be dead after the pair.
+
+
+pub fn peephole(gen: &mut GenBytecodeResult) {
+    let Some(mut instrs) = ByteCodeDecoder::decode(&gen.code) else { unreachable!() };
+    let n = instrs.len();
+
+    // jump targets are instruction indices, until `encode`.
+    let mut pc_to_index = vec![u32::MAX; gen.code.len() + 1];
+    for (i, instr) in instrs.iter().enumerate() {
+        pc_to_index[instr.pc as usize] = i as u32;
+    }
+    pc_to_index[gen.code.len()] = n as u32;
+
+    for instr in instrs.iter_mut() {
+        if let Some(target) = target_mut(&mut instr.data) {
+            *target = pc_to_index[*target as usize];
+            debug_assert!(*target != u32::MAX);
+        }
+    }
+
+    let live = Liveness::compute(&instrs);
+
+    thread_jumps(&mut instrs);
+
+    let mut is_target = vec![false; n + 1];
+    for instr in instrs.iter() {
+        if let Some(target) = target(&instr.data) {
+            is_target[target as usize] = true;
+        }
+    }
+
+    let mut instrs: Vec<Option<Instr>> = instrs.into_iter().map(Some).collect();
+
+    for i in 1..n {
+        if is_target[i] {
+            continue;
+        }
+        let (Some(first), Some(second)) = (&instrs[i-1], &instrs[i]) else { continue };
+        if let Some((first, second)) = fuse(first, second, |reg| live.is_live_out(i, reg)) {
+            instrs[i-1] = first;
+            instrs[i]   = second;
+        }
+    }
+
+    for instr in instrs.iter_mut().flatten() {
+        if let InstrData::Call { dst: _, func: _, args } = &instr.data {
+            if instr.opcode == opcode::CALL && args.len() <= 3 {
+                instr.opcode = opcode::CALL0 + args.len() as u8;
+            }
+        }
+    }
+
+    // backwards, so jumps over removed jumps are removed too.
+    // `jump_true/false` check their operand, so they stay.
+    for i in (0..n).rev() {
+        let Some(instr) = &instrs[i] else { continue };
+
+        use opcode::*;
+        let removable = matches!(instr.opcode, JUMP | JUMP_NIL | JUMP_NOT_NIL);
+        let Some(target) = target(&instr.data) else { continue };
+        let target = target as usize;
+
+        if removable && target > i && instrs[i+1 .. target].iter().all(|instr| instr.is_none()) {
+            instrs[i] = None;
+        }
+    }
+
+
+    // the layout depends on whether the jumps need `wide` prefixes.
+    let (_, mut pcs) = encode(&instrs, false, &[]);
+    let wide_jumps = pcs[n] > u16::MAX as u32;
+    if wide_jumps {
+        (_, pcs) = encode(&instrs, true, &[]);
+    }
+    let (code, new_pcs) = encode(&instrs, wide_jumps, &pcs);
+    debug_assert_eq!(pcs, new_pcs);
+
+    let mut pc_to_node = Vec::with_capacity(code.len());
+    for (i, instr) in instrs.iter().enumerate() {
+        let Some(instr) = instr else { continue };
+        let node = gen.pc_to_node[instr.pc as usize];
+        for _ in pcs[i] .. pcs[i+1] {
+            pc_to_node.push(node);
+        }
+    }
+    assert_eq!(pc_to_node.len(), code.len());
+
+    // old pcs map to the start of their instruction's replacement.
+    let mut old_to_new = Vec::with_capacity(gen.code.len() + 1);
+    let mut index = 0;
+    for i in &pc_to_index[..gen.code.len()] {
+        if *i != u32::MAX {
+            index = *i as usize;
+        }
+        old_to_new.push(pcs[index]);
+    }
+    old_to_new.push(pcs[n]);
+    for pc in gen.instr_index_to_pc.iter_mut() {
+        *pc = old_to_new[*pc as usize];
+    }
+
+    gen.code       = code;
+    gen.pc_to_node = pc_to_node;
+}
+
+
+fn fuse<Live: Fn(u32) -> bool>(first: &Instr, second: &Instr, live_after: Live) -> Option<(Option<Instr>, Option<Instr>)> {
+    use opcode::*;
+    use InstrData::*;
+
+    match (&first.data, &second.data) {
+        (Op2 { dst, src1, src2 }, JumpC1 { target, src }) => {
+            let op = match first.opcode {
+                CMP_EQ | CMP_EQ_NUM => JUMP_EQ_NUM,
+                CMP_NE | CMP_NE_NUM => JUMP_NE_NUM,
+                CMP_LE | CMP_LE_NUM => JUMP_LE_NUM,
+                CMP_LT | CMP_LT_NUM => JUMP_LT_NUM,
+                CMP_GE | CMP_GE_NUM => JUMP_GE_NUM,
+                CMP_GT | CMP_GT_NUM => JUMP_GT_NUM,
+                _ => return None,
+            };
+            let value = match second.opcode {
+                JUMP_TRUE  => true,
+                JUMP_FALSE => false,
+                _ => return None,
+            };
+            if src != dst || live_after(*dst) {
+                return None;
+            }
+
+            let data = JumpC2 { target: *target, src1: *src1, src2: *src2, value };
+            Some((None, Some(Instr { pc: first.pc, opcode: op, data })))
+        }
+
+        (LoadInt { dst: temp, value }, Op2 { dst, src1, src2 }) => {
+            let temp = *temp;
+            let (src, value) = match second.opcode {
+                ADD | ADD_NUM if *src2 == temp && *src1 != temp => (*src1, *value),
+                ADD | ADD_NUM if *src1 == temp && *src2 != temp => (*src2, *value),
+                SUB | SUB_NUM if *src2 == temp && *src1 != temp => (*src1, value.checked_neg()?),
+                _ => return None,
+            };
+            if live_after(temp) {
+                return None;
+            }
+
+            let copy = (*dst != src).then_some(
+                Instr { pc: second.pc, opcode: COPY, data: Copy { dst: *dst, src } });
+            let add = Instr { pc: second.pc, opcode: ADD_INT, data: AddInt { dst: *dst, value } };
+            Some((copy, Some(add)))
+        }
+
+        (Copy { dst: temp, src }, Copy { dst, src: temp2 }) => {
+            if temp != temp2 || temp == src || live_after(*temp) {
+                return None;
+            }
+
+            let copy = (dst != src).then_some(
+                Instr { pc: second.pc, opcode: COPY, data: Copy { dst: *dst, src: *src } });
+            Some((None, copy))
+        }
+
+        _ => None,
+    }
+}
+
+
+// unconditional jumps are followed.
+fn thread_jumps(instrs: &mut [Instr]) {
+    // limits the work for cycles of jumps.
+    const MAX_HOPS: usize = 8;
+
+    for i in 0..instrs.len() {
+        let Some(mut target) = target(&instrs[i].data) else { continue };
+
+        for _ in 0..MAX_HOPS {
+            let Some(next) = instrs.get(target as usize) else { break };
+            let InstrData::Jump { target: next_target } = next.data else { break };
+            if next_target == target {
+                break;
+            }
+            target = next_target;
+        }
+
+        *target_mut(&mut instrs[i].data).unwrap() = target;
+    }
+}
+
+
+// `pcs[i]` is the offset of `instrs[i]`, or of the next instruction, if it was removed.
+// `pcs` has an extra entry for the end of the code.
+fn encode(instrs: &[Option<Instr>], wide_jumps: bool, targets: &[u32]) -> (Vec<InstrWord>, Vec<u32>) {
+    let mut b = ByteCodeBuilder::new();
+    b.set_wide_jumps(wide_jumps);
+
+    let mut pcs = Vec::with_capacity(instrs.len() + 1);
+    for instr in instrs {
+        pcs.push(b.current_offset() as u32);
+        let Some(instr) = instr else { continue };
+
+        if let Some(target) = target(&instr.data) {
+            let mut instr = instr.clone();
+            *target_mut(&mut instr.data).unwrap() = targets.get(target as usize).copied().unwrap_or(0);
+            instr.encode(&mut b);
+        }
+        else {
+            instr.encode(&mut b);
+        }
+    }
+    pcs.push(b.current_offset() as u32);
+
+    (b.build(), pcs)
+}
+
+
+fn target(data: &InstrData) -> Option<u32> {
+    use InstrData::*;
+    match data {
+        Jump   { target } |
+        JumpC1 { target, src: _ } |
+        JumpC2 { target, src1: _, src2: _, value: _ } => Some(*target),
+        _ => None,
+    }
+}
+
+fn target_mut(data: &mut InstrData) -> Option<&mut u32> {
+    use InstrData::*;
+    match data {
+        Jump   { target } |
+        JumpC1 { target, src: _ } |
+        JumpC2 { target, src1: _, src2: _, value: _ } => Some(target),
+        _ => None,
+    }
+}
+
+fn successors<F: FnMut(usize)>(instrs: &[Instr], i: usize, mut f: F) {
+    use InstrData::*;
+    match &instrs[i].data {
+        Jump { target } => f(*target as usize),
+
+        JumpC1 { target, src: _ } |
+        JumpC2 { target, src1: _, src2: _, value: _ } => {
+            f(*target as usize);
+            f(i + 1);
+        }
+
+        Unreachable |
+        TailCall { func: _, args: _ } |
+        Ret { src: _ } => (),
+
+        _ => f(i + 1),
+    }
+}
+
+// calls `f(reg, is_def)` for the registers `data` reads & writes.
+fn visit_regs<F: FnMut(u32, bool)>(data: &InstrData, mut f: F) {
+    use InstrData::*;
+
+    let path = |base: &PathBase, keys: &[PathKey], f: &mut F| {
+        if !PathBase::is_reserved(base.value()) {
+            f(base.value(), false);
+        }
+        for key in keys {
+            if let PathKey::Index { reg } = key {
+                f(*reg, false);
+            }
+        }
+    };
+
+    match data {
+        Nop | Unreachable => (),
+
+        Copy { dst, src } => { f(*src, false); f(*dst, true) }
+        Swap { dst, src } => {
+            f(*dst, false); f(*src, false);
+            f(*dst, true);  f(*src, true);
+        }
+
+        LoadNil    { dst }           |
+        LoadBool   { dst, value: _ } |
+        LoadInt    { dst, value: _ } |
+        LoadConst  { dst, index: _ } |
+        LoadEnv    { dst }           |
+        LoadGlobal { dst, slot: _ }  |
+        LoadUnit   { dst }           |
+        MapNew     { dst }           => f(*dst, true),
+
+        ListNew       { dst, values } |
+        TupleNew      { dst, values } |
+        TupleNewNamed { dst, shape: _, values } => {
+            for v in values { f(*v, false) }
+            f(*dst, true);
+        }
+
+        ReadPath { dst, base, keys } => {
+            path(base, keys, &mut f);
+            f(*dst, true);
+        }
+        WritePath { base, keys, value } => {
+            path(base, keys, &mut f);
+            f(*value, false);
+        }
+        DeletePath { base, keys } => path(base, keys, &mut f),
+
+        Op1 { dst, src } => { f(*src, false); f(*dst, true) }
+        Op2 { dst, src1, src2 } => { f(*src1, false); f(*src2, false); f(*dst, true) }
+
+        AddInt { dst, value: _ } => { f(*dst, false); f(*dst, true) }
+
+        Jump   { target: _ } => (),
+        JumpC1 { target: _, src } => f(*src, false),
+        JumpC2 { target: _, src1, src2, value: _ } => { f(*src1, false); f(*src2, false) }
+
+        Call { dst, func, args } => {
+            f(*func, false);
+            for arg in args { f(*arg, false) }
+            f(*dst, true);
+        }
+        TailCall { func, args } => {
+            f(*func, false);
+            for arg in args { f(*arg, false) }
+        }
+        Ret { src } => f(*src, false),
+
+        LoadMethod { dst, traitt, recv, method: _ } => { f(*traitt, false); f(*recv, false); f(*dst, true) }
+        Implements { dst, src, traitt } => { f(*src, false); f(*traitt, false); f(*dst, true) }
+    }
+}
+
+
+// registers that are read after each instruction.
+struct Liveness {
+    words:    usize,
+    live_out: Vec<u64>,
+}
+
+impl Liveness {
+    fn compute(instrs: &[Instr]) -> Liveness {
+        let n = instrs.len();
+
+        let mut num_regs = 0;
+        for instr in instrs {
+            visit_regs(&instr.data, |reg, _| num_regs = num_regs.max(reg as usize + 1));
+        }
+        let words = num_regs.div_ceil(64);
+
+        let mut live_in  = vec![0u64; n * words];
+        let mut live_out = vec![0u64; n * words];
+        let mut scratch  = vec![0u64; words];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for i in (0..n).rev() {
+                scratch.fill(0);
+                successors(instrs, i, |succ| {
+                    if succ < n {
+                        for w in 0..words {
+                            scratch[w] |= live_in[succ*words + w];
+                        }
+                    }
+                });
+                live_out[i*words .. (i+1)*words].copy_from_slice(&scratch);
+
+                visit_regs(&instrs[i].data, |reg, is_def| {
+                    if is_def { scratch[reg as usize / 64] &= !(1 << (reg % 64)) }
+                });
+                visit_regs(&instrs[i].data, |reg, is_def| {
+                    if !is_def { scratch[reg as usize / 64] |= 1 << (reg % 64) }
+                });
+
+                if live_in[i*words .. (i+1)*words] != scratch[..] {
+                    live_in[i*words .. (i+1)*words].copy_from_slice(&scratch);
+                    changed = true;
+                }
+            }
+        }
+
+        Liveness { words, live_out }
+    }
+
+    fn is_live_out(&self, i: usize, reg: u32) -> bool {
+        self.live_out[i*self.words + reg as usize / 64] & (1 << (reg % 64)) != 0
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::bytecode::{opcode, ByteCodeBuilder, ByteCodeDecoder};
+    use crate::compiler::codegen::GenBytecodeResult;
+
+    #[test]
+    fn peephole_rewrites() {
+        let mut b = ByteCodeBuilder::new();
+        b.load_int(1, 5);       // 0
+        b.add(0, 0, 1);         // 1
+        b.copy(2, 0);           // 2
+        b.copy(3, 2);           // 3
+        b.cmp_lt(4, 3, 0);      // 4
+        b.jump_false(4, 7);     // 5
+        b.jump(7);              // 6
+        b.jump(8);              // 7
+        b.ret(3);               // 8
+        let code = b.build();
+
+        let mut gen = GenBytecodeResult {
+            pc_to_node: vec![None.into(); code.len()],
+            code,
+            constants:  vec![],
+            stack_size: 5,
+            instr_index_to_pc: vec![0, 5, 8, 9].into(),
+        };
+        peephole::peephole(&mut gen);
+
+        let instrs = ByteCodeDecoder::decode(&gen.code).unwrap();
+        let ops = instrs.iter().map(|instr| instr.opcode).collect::<Vec<_>>();
+        assert_eq!(ops, [opcode::ADD_INT, opcode::COPY, opcode::JUMP_LT_NUM, opcode::RET]);
+
+        let bytecode::InstrData::JumpC2 { target, src1, src2, value } = instrs[2].data else { unreachable!() };
+        assert_eq!((target, src1, src2, value), (4, 3, 0, false));
+        let bytecode::InstrData::Copy { dst, src } = instrs[1].data else { unreachable!() };
+        assert_eq!((dst, src), (3, 0));
+
+        assert_eq!(gen.pc_to_node.len(), gen.code.len());
+        assert_eq!(gen.instr_index_to_pc.inner(), &[0, 2, 4, 5]);
+    }
+}
@@ -2,13 +2,85 @@ use crate::index_vec::*;
 use super::*;
 
 
-// - conventional ssa: phis & all their arguments don't interfere (live intervals don't overlap & can be joined).
+// conventional ssa: phis & all their arguments don't interfere (live intervals don't overlap & can be joined).
+// - splits critical edges.
+// - inserts the copies of `convert_to_cssa_naive`,
+//   then removes the ones that aren't needed (boissinot et al.):
+//   a copy is coalesced, if the phi congruence classes of its source & destination
+//   don't interfere.
+// - interference is liveness based, not value based: codegen joins the live
+//   intervals of a class & that requires them to be disjoint.
+//   so copies of values, that stay live, are kept, even if they're equal.
+// - returns the new predecessors.
+pub fn convert_to_cssa(fun: &mut Function) -> Predecessors {
+    fun.split_critical_edges();
+
+    let preds = fun.predecessors();
+    convert_to_cssa_naive(fun, &preds);
+
+    let post_order = fun.post_order();
+    let post_indices = fun.post_order_indices(&post_order);
+    let idoms = fun.immediate_dominators(&preds, &post_order, &post_indices);
+
+    let mut removed = index_vec![false; fun.num_instrs()];
+    {
+        let interference = fun.interference(&post_order, &idoms);
+        let mut classes = PhiClasses::new(fun);
+
+        // the members of a class never interfere.
+        // if the members of two classes don't either,
+        // the copy between them can be removed & the classes merged.
+        for bb in fun.block_ids() {
+            fun.block_instrs(bb, |instr| {
+                let InstrData::ParallelCopy { src, copy_id: _ } = instr.data else { return };
+
+                let dst_class = classes.find(instr.id());
+                let src_class = classes.find(src);
+                if dst_class != src_class {
+                    let interfere = classes.members[dst_class].iter().any(|a|
+                        classes.members[src_class].iter().any(|b| interference.interfere(*a, *b)));
+                    if interfere {
+                        return;
+                    }
+                    classes.union(dst_class, src_class);
+                }
+
+                removed[instr.id()] = true;
+            });
+        }
+    }
+
+    // replace uses of removed copies with their sources.
+    let resolve = |fun: &Function, mut instr: InstrId| {
+        while removed[instr] {
+            let InstrData::ParallelCopy { src, copy_id: _ } = instr.get(fun).data else { unreachable!() };
+            instr = src;
+        }
+        instr
+    };
+    for bb in fun.block_ids() {
+        fun.block_replace_args(bb, |fun, arg| *arg = resolve(fun, *arg));
+    }
+    for instr in fun.instr_ids() {
+        if removed[instr] {
+            fun.remove_instr(instr);
+        }
+    }
+
+    fun.slow_cssa_check(&preds);
+
+    preds
+}
+
+
 // - assumes there are no critical edges.
 // - inserts parallel copies for phi arguments in predecessor blocks.
 // - inserts parallel copies for phi outputs after phis.
 // - inserts copies before in-place mutating instructions (like set_index).
 // - obviously not idempotent.
 pub fn convert_to_cssa_naive(fun: &mut Function, preds: &Predecessors) {
+    assert!(!fun.has_critical_edges(preds));
+
     // reused across iterations. cleared at end of iter.
     let mut pred_copy_ids = index_vec![None; fun.num_blocks()];
 
@@ -66,7 +138,8 @@ pub fn convert_to_cssa_naive(fun: &mut Function, preds: &Predecessors) {
             let instr = at.get(fun);
             let next  = instr.next();
 
-            if let InstrData::WritePath { path_id, value: _, is_def: _ } = instr.data {
+            if let InstrData::WritePath { path_id, value: _, is_def: _ }
+                 | InstrData::DeletePath { path_id } = instr.data {
                 if let PathBase::Instr(base) = path_id.get(fun).base {
                     let copy = fun.new_instr_ex(instr.source.clone(), InstrData::Copy { src: base });
                     fun.insert_before(bb, at.some(), copy);
@@ -78,6 +151,204 @@ pub fn convert_to_cssa_naive(fun: &mut Function, preds: &Predecessors) {
             cursor = next;
         }
     }
+}
+
+
+// phi congruence classes: phis & their args.
+// union find, `members` is only valid for representatives.
+struct PhiClasses {
+    parents: IndexVec<InstrId, InstrId>,
+    members: IndexVec<InstrId, Vec<InstrId>>,
+}
+
+impl PhiClasses {
+    fn new(fun: &Function) -> Self {
+        let mut classes = PhiClasses {
+            parents: fun.instr_ids().collect(),
+            members: fun.instr_ids().map(|instr| vec![instr]).collect(),
+        };
+
+        for bb in fun.block_ids() {
+            fun.block_instrs(bb, |instr| {
+                if let Some(map) = fun.try_phi(instr.id()) {
+                    for (_, arg) in map.iter() {
+                        classes.union(instr.id(), *arg);
+                    }
+                }
+            });
+        }
+
+        classes
+    }
+
+    fn find(&self, instr: InstrId) -> InstrId {
+        let mut at = instr;
+        while self.parents[at] != at {
+            at = self.parents[at];
+        }
+        at
+    }
+
+    fn union(&mut self, a: InstrId, b: InstrId) {
+        let a = self.find(a);
+        let b = self.find(b);
+        if a != b {
+            self.parents[b] = a;
+            let moved = core::mem::take(&mut self.members[b]);
+            self.members[a].extend(moved);
+        }
+    }
+}
 
-    fun.slow_integrity_check();
+
+impl Function {
+    // conventional ssa & no critical edges.
+    pub fn slow_cssa_check(&self, preds: &Predecessors) {
+        assert!(!self.has_critical_edges(preds));
+
+        let post_order = self.post_order();
+        let post_indices = self.post_order_indices(&post_order);
+        let idoms = self.immediate_dominators(preds, &post_order, &post_indices);
+        let interference = self.interference(&post_order, &idoms);
+
+        let classes = PhiClasses::new(self);
+        for members in classes.members.iter() {
+            for (i, a) in members.iter().enumerate() {
+                for b in &members[i+1..] {
+                    assert!(!interference.interfere(*a, *b), "{a} & {b} interfere");
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn parse(text: &str) -> std::cell::RefMut<'static, Function> {
+        let mut krate = Crate::new();
+        let mut fun = krate.new_function();
+        bbir_text::parse_function(&mut fun, text).unwrap();
+        fun
+    }
+
+    // `i3` is live after the loop, while `i4` is its next value.
+    // the back edge is critical & gets its own block for the copy.
+    const LOST_COPY: &str = "
+        local l0 \"c\"
+        bb0:
+          i0 := param l0
+          i1 := load_int 1
+          i2    jump bb1
+        bb1:
+          i3 := phi { bb0: i1, bb1: i4 }
+          i4 := add i3, i1
+          i5    switch_bool i0, bb1, bb2
+        bb2:
+          i6    return i3
+    ";
+
+    // the phis' args are each other.
+    const SWAP: &str = "
+        local l0 \"c\"
+        bb0:
+          i0 := param l0
+          i1 := load_int 1
+          i2 := load_int 2
+          i3    jump bb1
+        bb1:
+          i4 := phi { bb0: i1, bb2: i5 }
+          i5 := phi { bb0: i2, bb2: i4 }
+          i6    switch_bool i0, bb2, bb3
+        bb2:
+          i7    jump bb1
+        bb3:
+          i8 := add i4, i5
+          i9    return i8
+    ";
+
+    #[test]
+    fn cssa_lost_copy() {
+        bbir_text::file_check(&["
+            // run: cssa
+            // check: bb1:
+            // check-next: := phi { bb0: i8, bb3: i9 }
+            // check: switch_bool i0, bb3, bb2
+            // check: bb2:
+            // check-next: return i10
+            // check: bb3:
+            // check-next: i9 := parallel_copy i4
+            // check-next: jump bb1
+        ", LOST_COPY].concat()).unwrap();
+    }
+
+    #[test]
+    fn cssa_swap() {
+        // one parallel copy on the back edge swaps the values.
+        bbir_text::file_check(&["
+            // run: cssa
+            // check: bb1:
+            // check-next: i12 := phi { bb0: i1, bb2: i11 }
+            // check-next: i15 := phi { bb0: i2, bb2: i14 }
+            // check: bb2:
+            // check-next: i11 := parallel_copy i15 (3)
+            // check-next: i14 := parallel_copy i4 (3)
+            // check-next: jump bb1
+        ", SWAP].concat()).unwrap();
+    }
+
+    #[test]
+    fn cssa_coalesce() {
+        // the args only live until the phi, so all copies are removed.
+        bbir_text::file_check("
+            // run: cssa
+            // check-not: parallel_copy
+            // check: phi { bb1: i2, bb2: i4 }
+            // check-not: parallel_copy
+            local l0 \"c\"
+            bb0:
+              i0 := param l0
+              i1    switch_bool i0, bb1, bb2
+            bb1:
+              i2 := load_int 1
+              i3    jump bb3
+            bb2:
+              i4 := load_int 2
+              i5    jump bb3
+            bb3:
+              i6 := phi { bb1: i2, bb2: i4 }
+              i7    return i6
+        ").unwrap();
+    }
+
+    #[test]
+    fn split_critical_edges() {
+        let mut fun = parse(LOST_COPY);
+        assert!(fun.has_critical_edges(&fun.predecessors()));
+
+        fun.split_critical_edges();
+        assert!(!fun.has_critical_edges(&fun.predecessors()));
+        fun.slow_integrity_check();
+
+        let text = fun.text().to_string();
+        assert!(text.contains("switch_bool i0, bb3, bb2"), "{text}");
+        assert!(text.contains("phi { bb0: i1, bb3: i4 }"), "{text}");
+        assert!(text.contains("bb3:\n  i7    jump bb1"), "{text}");
+    }
+
+    #[test]
+    #[should_panic(expected = "interfere")]
+    fn cssa_check_interference() {
+        let fun = parse(SWAP);
+        fun.slow_cssa_check(&fun.predecessors());
+    }
+
+    #[test]
+    #[should_panic(expected = "has_critical_edges")]
+    fn cssa_check_critical_edges() {
+        let fun = parse(LOST_COPY);
+        fun.slow_cssa_check(&fun.predecessors());
+    }
 }
@@ -0,0 +1,424 @@
+use crate::index_vec::*;
+use super::*;
+
+
+// checks the invariants the passes rely on.
+// unlike a panic, the result lists every violation.
+
+
+#[derive(Clone, Debug)]
+pub struct VerifyError {
+    pub bb:      OptBlockId,
+    pub instr:   OptInstrId,
+    pub message: String,
+}
+
+impl core::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(bb) = self.bb.to_option() {
+            write!(f, "{}: ", bb)?;
+        }
+        if let Some(instr) = self.instr.to_option() {
+            write!(f, "{}: ", instr)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+
+pub fn verify(fun: &Function) -> Result<(), Vec<VerifyError>> {
+    let mut v = Verifier { fun, errors: vec![] };
+
+    v.check_linkage();
+    v.check_ids();
+    // the analyses below assume the structure is sound.
+    if v.errors.is_empty() {
+        v.check_layout();
+    }
+    if v.errors.is_empty() {
+        v.check_dominance();
+    }
+
+    if v.errors.is_empty() { Ok(()) }
+    else { Err(v.errors) }
+}
+
+
+struct Verifier<'a> {
+    fun:    &'a Function,
+    errors: Vec<VerifyError>,
+}
+
+impl<'a> Verifier<'a> {
+    fn error(&mut self, bb: OptBlockId, instr: OptInstrId, message: String) {
+        self.errors.push(VerifyError { bb, instr, message });
+    }
+
+    fn in_range(&self, id: InstrId) -> bool {
+        id.usize() < self.fun.num_instrs()
+    }
+
+
+    // block & instr ids, the linked lists & block lengths.
+    fn check_linkage(&mut self) {
+        let fun = self.fun;
+
+        let mut visited = index_vec![false; fun.num_instrs()];
+
+        for bb in fun.block_ids() {
+            let block = bb.get(fun);
+            if block.id() != bb {
+                self.error(bb.some(), None.into(), format!("block has id {}", block.id()));
+            }
+
+            if block.first().is_none() != block.last().is_none() {
+                self.error(bb.some(), None.into(), "only one of first & last is set".into());
+            }
+
+            let mut prev  = OptInstrId::from(None);
+            let mut count = 0;
+            let mut at = block.first();
+            while let Some(current) = at.to_option() {
+                if !self.in_range(current) {
+                    self.error(bb.some(), None.into(), format!("{} is out of range", current));
+                    break;
+                }
+                if visited[current] {
+                    self.error(bb.some(), current.some(), "instruction is linked more than once".into());
+                    break;
+                }
+                visited[current] = true;
+                count += 1;
+
+                let instr = current.get(fun);
+                if instr.id() != current {
+                    self.error(bb.some(), current.some(), format!("instruction has id {}", instr.id()));
+                }
+                if instr.bb() != bb.some() {
+                    self.error(bb.some(), current.some(), format!("instruction thinks it's in {:?}", instr.bb().to_option()));
+                }
+                if instr.prev() != prev {
+                    self.error(bb.some(), current.some(), format!("prev is {:?}, expected {:?}", instr.prev().to_option(), prev.to_option()));
+                }
+                if instr.next().is_none() && block.last() != current.some() {
+                    self.error(bb.some(), current.some(), "list ends before the block's last instruction".into());
+                }
+
+                prev = current.some();
+                at = instr.next();
+            }
+
+            if count != block.len() {
+                self.error(bb.some(), None.into(), format!("block has {} instructions, but len is {}", count, block.len()));
+            }
+        }
+
+        // instructions that are not in blocks.
+        for id in fun.instr_ids() {
+            if visited[id] {
+                continue;
+            }
+
+            let instr = id.get(fun);
+            if instr.id() != id {
+                self.error(None.into(), id.some(), format!("instruction has id {}", instr.id()));
+            }
+            if instr.bb().is_some() || instr.prev().is_some() || instr.next().is_some() {
+                self.error(instr.bb(), id.some(), "unlinked instruction has bb, prev or next".into());
+            }
+        }
+    }
+
+    // referenced ids are in range & each phi map, instr list
+    // & path belongs to exactly one instruction.
+    fn check_ids(&mut self) {
+        let fun = self.fun;
+
+        let mut phi_maps    = index_vec![OptInstrId::from(None); fun.num_phi_maps()];
+        let mut instr_lists = index_vec![OptInstrId::from(None); fun.num_instr_lists()];
+        let mut paths       = index_vec![OptInstrId::from(None); fun.num_paths()];
+
+        // returns whether `key` is in range.
+        fn claim<K: Key + core::fmt::Debug>(v: &mut Verifier, owners: &mut IndexVec<K, OptInstrId>, key: K, bb: BlockId, instr: InstrId) -> bool {
+            if key.usize() >= owners.len() {
+                v.error(bb.some(), instr.some(), format!("{:?} is out of range", key));
+                return false;
+            }
+            if let Some(owner) = owners[key].to_option() {
+                v.error(bb.some(), instr.some(), format!("{:?} is also used by {}", key, owner));
+                return true;
+            }
+            owners[key] = instr.some();
+            true
+        }
+
+        for bb in fun.block_ids() {
+            let mut at = bb.get(fun).first();
+            while let Some(id) = at.to_option() {
+                let instr = id.get(fun);
+                at = instr.next();
+
+                use InstrData::*;
+                let in_range = match instr.data {
+                    Phi { map_id } => claim(self, &mut phi_maps, map_id, bb, id),
+
+                    ListNew { values } |
+                    TupleNew { values } |
+                    TupleNewNamed { shape: _, values } => claim(self, &mut instr_lists, values, bb, id),
+
+                    Call { func: _, args_id } => claim(self, &mut instr_lists, args_id, bb, id),
+
+                    ReadPath { path_id } |
+                    WritePath { path_id, value: _, is_def: _ } |
+                    DeletePath { path_id } => claim(self, &mut paths, path_id, bb, id),
+
+                    _ => true,
+                };
+                // `args` would index out of range.
+                if !in_range {
+                    continue;
+                }
+
+                let mut args_ok = true;
+                instr.args(fun, |arg| args_ok &= arg.usize() < fun.num_instrs());
+                if !args_ok {
+                    self.error(bb.some(), id.some(), "argument is out of range".into());
+                }
+
+                let mut targets_ok = true;
+                match instr.data {
+                    Jump { target } => {
+                        targets_ok = target.usize() < fun.num_blocks();
+                    }
+                    SwitchBool { src: _, on_true: a, on_false: b } |
+                    SwitchNil  { src: _, on_nil: a, on_non_nil: b } => {
+                        targets_ok = a.usize() < fun.num_blocks() && b.usize() < fun.num_blocks();
+                    }
+                    _ => {}
+                }
+                if !targets_ok {
+                    self.error(bb.some(), id.some(), "jump target is out of range".into());
+                }
+
+                if let Phi { map_id } = instr.data {
+                    for (from_bb, _) in map_id.get(fun).iter() {
+                        if from_bb.usize() >= fun.num_blocks() {
+                            self.error(bb.some(), id.some(), format!("{} is out of range", from_bb));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // params, then locals in the entry block; phis at the start of the other blocks.
+    // every block ends with its only terminator.
+    fn check_layout(&mut self) {
+        let fun = self.fun;
+
+        for bb in fun.block_ids() {
+            let mut in_params = bb.is_entry();
+            let mut in_locals = bb.is_entry();
+            let mut in_phis   = !bb.is_entry();
+
+            let last = bb.get(fun).last();
+            if last.is_none() {
+                self.error(bb.some(), None.into(), "block is empty".into());
+            }
+
+            let mut at = bb.get(fun).first();
+            while let Some(id) = at.to_option() {
+                let instr = id.get(fun);
+                at = instr.next();
+
+                if instr.is_param() {
+                    if !in_params {
+                        self.error(bb.some(), id.some(), "param is not in the entry block's param prefix".into());
+                    }
+                    continue;
+                }
+                in_params = false;
+
+                if instr.is_local() {
+                    if !in_locals {
+                        self.error(bb.some(), id.some(), "local is not in the entry block's local prefix".into());
+                    }
+                    continue;
+                }
+                in_locals = false;
+
+                if instr.is_phi() {
+                    if !in_phis {
+                        self.error(bb.some(), id.some(), "phi is not at the start of a block".into());
+                    }
+                    continue;
+                }
+                in_phis = false;
+
+                if instr.is_terminator() != (id.some() == last) {
+                    if instr.is_terminator() {
+                        self.error(bb.some(), id.some(), "terminator is not the last instruction".into());
+                    }
+                    else {
+                        self.error(bb.some(), id.some(), "block doesn't end with a terminator".into());
+                    }
+                }
+            }
+        }
+    }
+
+    // phis have an entry per predecessor.
+    // every use is dominated by its definition.
+    fn check_dominance(&mut self) {
+        let fun = self.fun;
+
+        let preds        = fun.predecessors();
+        let post_order   = fun.post_order();
+        let post_indices = fun.post_order_indices(&post_order);
+        let idoms        = fun.immediate_dominators(&preds, &post_order, &post_indices);
+
+        let mut positions = index_vec![0; fun.num_instrs()];
+        for bb in fun.block_ids() {
+            let mut position = 0;
+            fun.block_instrs(bb, |instr| {
+                positions[instr.id()] = position;
+                position += 1;
+            });
+        }
+
+        for bb in fun.block_ids() {
+            if idoms.is_unreachable(bb) {
+                continue;
+            }
+
+            let mut at = bb.get(fun).first();
+            while let Some(id) = at.to_option() {
+                let instr = id.get(fun);
+                at = instr.next();
+
+                let check_def = |v: &mut Self, arg: InstrId, use_bb: BlockId, after: Option<InstrId>| {
+                    let def = arg.get(fun);
+                    if !def.has_value() {
+                        v.error(bb.some(), id.some(), format!("{} doesn't have a value", arg));
+                        return;
+                    }
+                    let Some(def_bb) = def.bb().to_option() else {
+                        v.error(bb.some(), id.some(), format!("{} isn't in a block", arg));
+                        return;
+                    };
+
+                    let dominated =
+                        if def_bb == use_bb {
+                            after.map(|after| positions[arg] < positions[after]).unwrap_or(true)
+                        }
+                        else {
+                            idoms.is_dominated_by(use_bb, def_bb)
+                        };
+                    if !dominated {
+                        v.error(bb.some(), id.some(), format!("use of {} isn't dominated by its definition", arg));
+                    }
+                };
+
+                if let InstrData::Phi { map_id } = instr.data {
+                    let preds = &preds[bb];
+                    let mut seen = vec![false; preds.len()];
+
+                    for (from_bb, src) in map_id.get(fun).iter().copied() {
+                        let Some(pred) = preds.iter().position(|p| *p == from_bb) else {
+                            self.error(bb.some(), id.some(), format!("{} is not a predecessor", from_bb));
+                            continue;
+                        };
+                        if seen[pred] {
+                            self.error(bb.some(), id.some(), format!("{} has multiple entries", from_bb));
+                            continue;
+                        }
+                        seen[pred] = true;
+
+                        // the value flows along the edge, so it's used at the end of the pred.
+                        if !idoms.is_unreachable(from_bb) {
+                            check_def(self, src, from_bb, None);
+                        }
+                    }
+
+                    for (pred, seen) in preds.iter().zip(seen) {
+                        if !seen {
+                            self.error(bb.some(), id.some(), format!("no entry for predecessor {}", pred));
+                        }
+                    }
+                }
+                else {
+                    instr.args(fun, |arg| check_def(self, arg, bb, Some(id)));
+                }
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    // `crate::*` also exports the bytecode's `InstrData`.
+    use crate::compiler::bbir::InstrData;
+
+    fn parse(text: &str) -> std::cell::RefMut<'static, Function> {
+        let mut krate = Crate::new();
+        let mut fun = krate.new_function();
+        bbir_text::parse_function(&mut fun, text).unwrap();
+        fun
+    }
+
+    fn messages(fun: &Function) -> Vec<String> {
+        match verify::verify(fun) {
+            Ok(()) => vec![],
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    const DIAMOND: &str = "
+        local l0 \"a\"
+        bb0:
+          i0 := param l0
+          i1    switch_bool i0, bb1, bb2
+        bb1:
+          i2 := load_int 1
+          i3    jump bb3
+        bb2:
+          i4 := load_int 2
+          i5    jump bb3
+        bb3:
+          i6 := phi { bb1: i2, bb2: i4 }
+          i7 := new_list [ i6 ]
+          i8    return i7
+    ";
+
+    #[test]
+    fn verify_errors() {
+        let fun = parse(DIAMOND);
+        assert_eq!(messages(&fun), Vec::<String>::new());
+
+        // use in a sibling block.
+        let mut fun = parse(DIAMOND);
+        InstrId::new_unck(2).get_mut(&mut fun).data = InstrData::Copy { src: InstrId::new_unck(4) };
+        assert_eq!(messages(&fun), ["bb1: i2: use of i4 isn't dominated by its definition"]);
+
+        // shared instr list.
+        let mut fun = parse(DIAMOND);
+        let InstrData::ListNew { values } = InstrId::new_unck(7).get(&fun).data else { unreachable!() };
+        InstrId::new_unck(2).get_mut(&mut fun).data = InstrData::ListNew { values };
+        assert_eq!(messages(&fun).len(), 1);
+        assert!(messages(&fun)[0].starts_with("bb3: i7: InstrListId(0) is also used by i2"));
+
+        // edge without a phi entry.
+        let mut fun = parse(DIAMOND);
+        InstrId::new_unck(1).get_mut(&mut fun).data = InstrData::SwitchBool {
+            src: InstrId::new_unck(0), on_true: BlockId::new_unck(1), on_false: BlockId::new_unck(3) };
+        assert_eq!(messages(&fun), ["bb3: i6: no entry for predecessor bb0"]);
+
+        // terminator in the middle of a block.
+        let mut fun = parse(DIAMOND);
+        InstrId::new_unck(2).get_mut(&mut fun).data = InstrData::Jump { target: BlockId::new_unck(3) };
+        assert_eq!(messages(&fun), ["bb1: i2: terminator is not the last instruction"]);
+    }
+}
@@ -0,0 +1,630 @@
+use crate::index_vec::*;
+use crate::bytecode::*;
+use crate::value::*;
+use crate::compiler::{NodeId, OptNodeId, ItemId};
+use crate::compiler::bbir::{FunctionId, Item, ItemData, Trait, Impl, FunctionDebugInfo};
+use crate::compiler::codegen::ValueMapping;
+
+
+// kbtf, the kibi transfer format.
+// a compiled crate, so scripts can be loaded without parsing & compiling.
+//
+// layout, little endian. lengths are u32, strings are a length & utf-8.
+//  magic      "kbtf"
+//  version    u32
+//  flags      u32, see `FLAG_DEBUG_INFO`.
+//  checksum   u32, crc32 of everything after it.
+//  functions  len, then per function:
+//   num_params & stack_size, u32 each.
+//   constants     len, then a tag byte & the value.
+//   tuple shapes  len, then per shape, len & field names.
+//   code          len, then the words.
+//  items      len, then a tag byte & the data.
+//  debug info, if flagged. per function:
+//   reg mapping   len, then per reg, len & (pc_begin, pc_end, len & nodes).
+//   pc_to_node    len, then nodes. u32::MAX is none.
+//
+// the bytecode is verified by `Vm::load_crate`.
+// item indices are in registers, so the vm checks them when a path runs.
+// the items are checked here, `load_crate` relies on them.
+
+
+#[derive(Clone, Debug)]
+pub enum Constant {
+    Nil,
+    Bool   { value: bool    },
+    Number { value: f64     },
+    String { value: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct FuncDesc {
+    pub code:       FuncCode,
+    pub constants:  Vec<Constant>,
+    pub tuple_shapes: Vec<Vec<String>>, // field names.
+    pub num_params: u32,
+    pub stack_size: u32,
+}
+
+impl FuncDesc {
+    pub fn verify(&self) -> Result<(), ByteCodeError> {
+        let FuncCode::ByteCode(code) = &self.code else { return Ok(()) };
+
+        if self.num_params > self.stack_size {
+            return Err(ByteCodeError { pc: 0, message: "more params than registers".into() });
+        }
+        verify_bytecode(code, &self.constants, &self.tuple_shapes, self.stack_size)
+    }
+}
+
+
+pub const KBTF_MAGIC:   [u8; 4] = *b"kbtf";
+pub const KBTF_VERSION: u32     = 1;
+
+pub const FLAG_DEBUG_INFO: u32 = 1;
+
+const CONSTANT_NIL:    u8 = 0;
+const CONSTANT_BOOL:   u8 = 1;
+const CONSTANT_NUMBER: u8 = 2;
+const CONSTANT_STRING: u8 = 3;
+
+const ITEM_NONE:  u8 = 0;
+const ITEM_FUNC:  u8 = 1;
+const ITEM_TRAIT: u8 = 2;
+const ITEM_IMPL:  u8 = 3;
+
+const HEADER_SIZE: usize = 16;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum KbtfError {
+    NotKbtf,
+    Version(u32),
+    Checksum,
+    Truncated,
+    Malformed(&'static str),
+    // only bytecode functions can be written.
+    NativeFunc { func: usize },
+}
+
+impl core::fmt::Display for KbtfError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            KbtfError::NotKbtf           => write!(f, "not a kbtf file"),
+            KbtfError::Version(version)  => write!(f, "unsupported kbtf version {}", version),
+            KbtfError::Checksum          => write!(f, "checksum mismatch"),
+            KbtfError::Truncated         => write!(f, "unexpected end of file"),
+            KbtfError::Malformed(what)   => write!(f, "malformed kbtf: {}", what),
+            KbtfError::NativeFunc { func } => write!(f, "function {} is native", func),
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct KbtfCrate {
+    pub funcs:      IndexVec<FunctionId, FuncDesc>,
+    pub items:      IndexVec<ItemId, Item>,
+    pub debug_info: Option<IndexVec<FunctionId, FunctionDebugInfo>>,
+}
+
+
+pub fn write_kbtf(funcs: &[FuncDesc], items: &[Item], debug_info: Option<&[FunctionDebugInfo]>) -> Result<Vec<u8>, KbtfError> {
+    let mut w = Writer { buffer: Vec::with_capacity(1024) };
+
+    w.buffer.extend_from_slice(&KBTF_MAGIC);
+    w.u32(KBTF_VERSION);
+    w.u32(if debug_info.is_some() { FLAG_DEBUG_INFO } else { 0 });
+    w.u32(0); // checksum, patched below.
+
+    w.len(funcs.len());
+    for (index, func) in funcs.iter().enumerate() {
+        let FuncCode::ByteCode(code) = &func.code else {
+            return Err(KbtfError::NativeFunc { func: index });
+        };
+
+        w.u32(func.num_params);
+        w.u32(func.stack_size);
+
+        w.len(func.constants.len());
+        for constant in &func.constants {
+            match constant {
+                Constant::Nil              => w.u8(CONSTANT_NIL),
+                Constant::Bool   { value } => { w.u8(CONSTANT_BOOL);   w.u8(*value as u8) }
+                Constant::Number { value } => { w.u8(CONSTANT_NUMBER); w.u64(value.to_bits()) }
+                Constant::String { value } => { w.u8(CONSTANT_STRING); w.str(value) }
+            }
+        }
+
+        w.len(func.tuple_shapes.len());
+        for names in &func.tuple_shapes {
+            w.len(names.len());
+            for name in names {
+                w.str(name);
+            }
+        }
+
+        w.len(code.len());
+        for word in code {
+            w.u32(word.value());
+        }
+    }
+
+    w.len(items.len());
+    for item in items {
+        match &item.data {
+            ItemData::None => w.u8(ITEM_NONE),
+
+            ItemData::Func(id) => {
+                w.u8(ITEM_FUNC);
+                w.u32(id.value());
+            }
+
+            ItemData::Trait(traitt) => {
+                w.u8(ITEM_TRAIT);
+                w.str(&traitt.name);
+                w.len(traitt.methods.len());
+                for method in &traitt.methods {
+                    w.str(method);
+                }
+            }
+
+            ItemData::Impl(imp) => {
+                w.u8(ITEM_IMPL);
+                w.u32(imp.traitt.value());
+                w.u8(imp.kind.usize() as u8);
+                w.len(imp.methods.len());
+                for method in &imp.methods {
+                    w.u32(method.value());
+                }
+            }
+        }
+    }
+
+    if let Some(debug_info) = debug_info {
+        assert_eq!(debug_info.len(), funcs.len());
+        for info in debug_info {
+            w.len(info.reg_mapping.len());
+            for mappings in &info.reg_mapping {
+                w.len(mappings.len());
+                for mapping in mappings {
+                    w.u32(mapping.pc_begin);
+                    w.u32(mapping.pc_end);
+                    w.len(mapping.values.len());
+                    for value in &mapping.values {
+                        w.u32(value.value());
+                    }
+                }
+            }
+
+            w.len(info.pc_to_node.len());
+            for node in &info.pc_to_node {
+                w.u32(node.to_option().map_or(u32::MAX, |node| node.value()));
+            }
+        }
+    }
+
+    let checksum = crc32(&w.buffer[HEADER_SIZE..]);
+    w.buffer[12..HEADER_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    Ok(w.buffer)
+}
+
+
+pub fn read_kbtf(bytes: &[u8]) -> Result<KbtfCrate, KbtfError> {
+    if bytes.len() < HEADER_SIZE || bytes[..4] != KBTF_MAGIC {
+        return Err(KbtfError::NotKbtf);
+    }
+
+    let mut r = Reader { bytes, pos: 4 };
+    let version = r.u32()?;
+    if version != KBTF_VERSION {
+        return Err(KbtfError::Version(version));
+    }
+    let flags    = r.u32()?;
+    let checksum = r.u32()?;
+    if crc32(&bytes[HEADER_SIZE..]) != checksum {
+        return Err(KbtfError::Checksum);
+    }
+    if flags & !FLAG_DEBUG_INFO != 0 {
+        return Err(KbtfError::Malformed("unknown flags"));
+    }
+
+    let num_funcs = r.len()?;
+    let mut funcs = IndexVec::with_capacity(num_funcs);
+    for _ in 0..num_funcs {
+        let num_params = r.u32()?;
+        let stack_size = r.u32()?;
+
+        let num_constants = r.len()?;
+        let mut constants = Vec::with_capacity(num_constants);
+        for _ in 0..num_constants {
+            constants.push(match r.u8()? {
+                CONSTANT_NIL    => Constant::Nil,
+                CONSTANT_BOOL   => Constant::Bool   { value: r.u8()? != 0 },
+                CONSTANT_NUMBER => Constant::Number { value: f64::from_bits(r.u64()?) },
+                CONSTANT_STRING => Constant::String { value: r.str()? },
+                _ => return Err(KbtfError::Malformed("unknown constant")),
+            });
+        }
+
+        let num_shapes = r.len()?;
+        let mut tuple_shapes = Vec::with_capacity(num_shapes);
+        for _ in 0..num_shapes {
+            let num_names = r.len()?;
+            let mut names = Vec::with_capacity(num_names);
+            for _ in 0..num_names {
+                names.push(r.str()?);
+            }
+            tuple_shapes.push(names);
+        }
+
+        let code_len = r.len()?;
+        let mut code = Vec::with_capacity(code_len);
+        for _ in 0..code_len {
+            code.push(InstrWord::from_value(r.u32()?));
+        }
+
+        funcs.push(FuncDesc {
+            code: FuncCode::ByteCode(code),
+            constants,
+            tuple_shapes,
+            num_params,
+            stack_size,
+        });
+    }
+    if funcs.len() == 0 {
+        return Err(KbtfError::Malformed("no entry function"));
+    }
+
+    let num_items = r.len()?;
+    let mut items = IndexVec::with_capacity(num_items);
+    for _ in 0..num_items {
+        let data = match r.u8()? {
+            ITEM_NONE => ItemData::None,
+
+            ITEM_FUNC => ItemData::Func(FunctionId::new_unck(r.u32()?)),
+
+            ITEM_TRAIT => {
+                let name = r.str()?;
+                let num_methods = r.len()?;
+                let mut methods = Vec::with_capacity(num_methods);
+                for _ in 0..num_methods {
+                    methods.push(r.str()?);
+                }
+                ItemData::Trait(Trait { name, methods })
+            }
+
+            ITEM_IMPL => {
+                let traitt = ItemId::new_unck(r.u32()?);
+                let kind = ValueKind::from_usize(r.u8()? as usize)
+                    .ok_or(KbtfError::Malformed("unknown value kind"))?;
+                let num_methods = r.len()?;
+                let mut methods = Vec::with_capacity(num_methods);
+                for _ in 0..num_methods {
+                    methods.push(ItemId::new_unck(r.u32()?));
+                }
+                ItemData::Impl(Impl { traitt, kind, methods })
+            }
+
+            _ => return Err(KbtfError::Malformed("unknown item")),
+        };
+        items.push(Item { data });
+    }
+    check_items(funcs.len(), &items)?;
+
+    let mut debug_info = None;
+    if flags & FLAG_DEBUG_INFO != 0 {
+        let mut infos = IndexVec::with_capacity(funcs.len());
+        for func in funcs.iter() {
+            let num_regs = r.len()?;
+            let mut reg_mapping = Vec::with_capacity(num_regs);
+            for _ in 0..num_regs {
+                let num_mappings = r.len()?;
+                let mut mappings = Vec::with_capacity(num_mappings);
+                for _ in 0..num_mappings {
+                    let pc_begin = r.u32()?;
+                    let pc_end   = r.u32()?;
+                    let num_values = r.len()?;
+                    let mut values = Vec::with_capacity(num_values);
+                    for _ in 0..num_values {
+                        values.push(NodeId::new_unck(r.u32()?));
+                    }
+                    mappings.push(ValueMapping { pc_begin, pc_end, values });
+                }
+                reg_mapping.push(mappings);
+            }
+
+            let num_pcs = r.len()?;
+            let FuncCode::ByteCode(code) = &func.code else { unreachable!() };
+            if num_pcs != code.len() {
+                return Err(KbtfError::Malformed("debug info doesn't match the code"));
+            }
+            let mut pc_to_node = Vec::with_capacity(num_pcs);
+            for _ in 0..num_pcs {
+                let node = r.u32()?;
+                pc_to_node.push(if node == u32::MAX { OptNodeId::from(None) } else { NodeId::new_unck(node).some() });
+            }
+
+            infos.push(FunctionDebugInfo { reg_mapping, pc_to_node });
+        }
+        debug_info = Some(infos);
+    }
+
+    if r.pos != bytes.len() {
+        return Err(KbtfError::Malformed("trailing bytes"));
+    }
+
+    Ok(KbtfCrate { funcs, items, debug_info })
+}
+
+
+// the references between items, which `load_crate` doesn't check.
+fn check_items(num_funcs: usize, items: &IndexVec<ItemId, Item>) -> Result<(), KbtfError> {
+    let is_func = |id: ItemId| matches!(items.inner().get(id.usize()), Some(Item { data: ItemData::Func(_) }));
+
+    for item in items.iter() {
+        match &item.data {
+            ItemData::None | ItemData::Trait(_) => (),
+
+            ItemData::Func(id) => {
+                if id.usize() >= num_funcs {
+                    return Err(KbtfError::Malformed("item refers to a missing function"));
+                }
+            }
+
+            ItemData::Impl(imp) => {
+                let Some(Item { data: ItemData::Trait(traitt) }) = items.inner().get(imp.traitt.usize()) else {
+                    return Err(KbtfError::Malformed("impl of a non-trait"));
+                };
+                if imp.methods.len() != traitt.methods.len() {
+                    return Err(KbtfError::Malformed("impl doesn't match its trait"));
+                }
+                if !imp.methods.iter().all(|method| is_func(*method)) {
+                    return Err(KbtfError::Malformed("impl method isn't a function"));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+
+// crc-32 (ieee).
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+
+struct Writer {
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(len.try_into().unwrap());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.buffer.extend_from_slice(value.as_bytes());
+    }
+}
+
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos:   usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], KbtfError> {
+        let bytes = self.bytes.get(self.pos..self.pos + len).ok_or(KbtfError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, KbtfError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, KbtfError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, KbtfError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    // every element takes at least a byte,
+    // so lengths beyond the rest of the file are truncated files.
+    // this also bounds the allocations.
+    fn len(&mut self) -> Result<usize, KbtfError> {
+        let len = self.u32()? as usize;
+        if len > self.bytes.len() - self.pos {
+            return Err(KbtfError::Truncated);
+        }
+        Ok(len)
+    }
+
+    fn str(&mut self) -> Result<String, KbtfError> {
+        let len = self.len()?;
+        let bytes = self.bytes(len)?;
+        let value = core::str::from_utf8(bytes).map_err(|_| KbtfError::Malformed("invalid utf-8"))?;
+        Ok(value.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::compiler::bbir;
+    use crate::index_vec::IndexVec;
+    use crate::compiler::ItemId;
+    use crate::compiler::bbir::{FunctionId, FunctionDebugInfo};
+    use super::crc32;
+
+    fn build(source: &str) -> (IndexVec<FunctionId, FuncDesc>, IndexVec<ItemId, bbir::Item>, IndexVec<FunctionId, FunctionDebugInfo>) {
+        let mut module = parser::parse_module(source.as_bytes()).unwrap();
+
+        let mut diags = Diagnostics::new();
+        let mut infer = infer::Infer::new(&mut diags);
+        infer.assign_ids(&mut module);
+        infer.infer(&mut module);
+
+        let mut builder = bbir_builder::Builder::new(&mut diags);
+        builder.build(&module);
+        let krate = builder.krate;
+        assert!(!diags.has_errors());
+        krate.build()
+    }
+
+    #[test]
+    fn kbtf_roundtrip() {
+        // the out of bounds write fails the test.
+        let source = "
+            trait Double:
+                fn double(self)
+            end
+            impl Double for Number:
+                fn double(self):
+                    return self * 2
+                end
+            end
+            let t = (a: 6, b: \"x\")
+            if Double.double(t.a) + 9 != 21:
+                let fail = [0]
+                fail[5] = 1
+            end
+        ";
+
+        let (funcs, items, debug_info) = build(source);
+
+        let bytes = write_kbtf(funcs.inner(), items.inner(), Some(debug_info.inner())).unwrap();
+        let krate = read_kbtf(&bytes).unwrap();
+        assert_eq!(krate.funcs.len(), funcs.len());
+        assert_eq!(krate.items.len(), items.len());
+
+        let debug = krate.debug_info.as_ref().unwrap();
+        let again = write_kbtf(krate.funcs.inner(), krate.items.inner(), Some(debug.inner())).unwrap();
+        assert_eq!(again, bytes);
+
+        let mut vm = Vm::new();
+        vm.load_crate(0, krate.funcs.inner(), krate.items.inner()).unwrap();
+        vm.call(0, 0, &[]).unwrap();
+
+        // without debug info.
+        let small = write_kbtf(funcs.inner(), items.inner(), None).unwrap();
+        assert!(small.len() < bytes.len());
+        assert!(read_kbtf(&small).unwrap().debug_info.is_none());
+
+        // broken files.
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(read_kbtf(&corrupt).unwrap_err(), KbtfError::Checksum);
+
+        assert_eq!(read_kbtf(b"#!kibi").unwrap_err(), KbtfError::NotKbtf);
+
+        let mut version = bytes.clone();
+        version[4] = 2;
+        assert_eq!(read_kbtf(&version).unwrap_err(), KbtfError::Version(2));
+
+        let with_checksum = |mut bytes: Vec<u8>| {
+            let checksum = crc32(&bytes[16..]);
+            bytes[12..16].copy_from_slice(&checksum.to_le_bytes());
+            bytes
+        };
+        let truncated = with_checksum(bytes[..bytes.len() - 3].to_vec());
+        assert_eq!(read_kbtf(&truncated).unwrap_err(), KbtfError::Truncated);
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(read_kbtf(&with_checksum(trailing)).unwrap_err(), KbtfError::Malformed("trailing bytes"));
+
+        // items `load_crate` would panic on.
+        let mut bad_items = items.clone();
+        for item in bad_items.iter_mut() {
+            if let bbir::ItemData::Impl(imp) = &mut item.data {
+                imp.methods.clear();
+            }
+        }
+        let bad = write_kbtf(funcs.inner(), bad_items.inner(), None).unwrap();
+        assert_eq!(read_kbtf(&bad).unwrap_err(), KbtfError::Malformed("impl doesn't match its trait"));
+
+        let native = FuncDesc {
+            code: FuncCode::Native(NativeFuncPtrEx(|_| Ok(NativeFuncReturn::Unit))),
+            constants: vec![],
+            tuple_shapes: vec![],
+            num_params: 0,
+            stack_size: 0,
+        };
+        assert_eq!(write_kbtf(&[native], &[], None).unwrap_err(), KbtfError::NativeFunc { func: 0 });
+    }
+
+    #[test]
+    fn kbtf_malformed_bytecode() {
+        let source = "
+            fn f(x): if x == nil: f(x) end end
+            f(\"a\")
+        ";
+        let (funcs, items, _) = build(source);
+
+        // patches the first operand of `kind` in the module & round trips the crate.
+        let load = |kind: OperandKind, value: u32| {
+            let mut funcs = funcs.clone();
+            let FuncCode::ByteCode(code) = &mut funcs.inner_mut()[0].code else { unreachable!() };
+            let instrs = ByteCodeDecoder::decode_raw(code).unwrap();
+            let operand = instrs.iter().flat_map(|i| &i.operands).find(|o| o.kind == kind).unwrap();
+            match operand.slot {
+                Slot::U16 => code[operand.at as usize].patch_u16(value as u16),
+                _         => code[operand.at as usize] = InstrWord::encode_extra(value),
+            }
+
+            let bytes = write_kbtf(funcs.inner(), items.inner(), None).unwrap();
+            let krate = read_kbtf(&bytes).unwrap();
+            let mut vm = Vm::new();
+            vm.load_crate(0, krate.funcs.inner(), krate.items.inner())?;
+            Ok(vm)
+        };
+
+        let message = |error: LinkError| {
+            let LinkError::InvalidByteCode { func: 0, error } = error else { panic!("{error}") };
+            error.message
+        };
+
+        // items are indexed by number.
+        let constants = &funcs.inner()[0].constants;
+        let string = constants.iter().position(|c| matches!(c, Constant::String { .. })).unwrap() as u32;
+        let error = load(OperandKind::Key, bytecode::PathKey::Field { string }.encode()).err().unwrap();
+        assert_eq!(message(error), "read_path: items need an index key");
+
+        let key = bytecode::PathKey::Index { reg: 200 }.encode();
+        let error = load(OperandKind::Key, key).err().unwrap();
+        assert_eq!(message(error), format!("read_path: invalid Key operand {}", key));
+
+        // item indices are checked when the path runs.
+        let mut vm = load(OperandKind::Int, 1).unwrap();
+        assert_eq!(vm.call(0, 0, &[]), Ok(()));
+        let mut vm = load(OperandKind::Int, 1000).unwrap();
+        assert_eq!(vm.call(0, 0, &[]), Err(VmError::InvalidOperation));
+    }
+}
@@ -4,11 +4,13 @@ pub mod compiler;
 pub mod bytecode;
 pub mod value;
 pub mod vm;
+pub mod kbtf;
 
 pub use compiler::*;
 pub use bytecode::*;
 pub use value::*;
 pub use vm::*;
+pub use kbtf::*;
 
 
 
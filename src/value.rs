@@ -10,10 +10,11 @@ pub(crate) enum Value {
     Number { value: f64    },
     String { value: Rc<String>, },
     Unit,
-    Tuple  { values: Rc<Vec<Value>>, },
+    Tuple  { shape: u32, values: Rc<Vec<Value>>, }, // shape 0: positional.
     List   { values: Rc<Vec<Value>>, },
     Map    { values: Rc<Vec<(Value, Value)>> },
     Func   { proto: usize  },
+    Trait  { id:    usize  },
 }
 
 impl From<bool> for Value { #[inline(always)] fn from(value: bool) -> Self { Value::Bool   { value } } }
@@ -24,6 +25,94 @@ impl Value {
     pub fn is_nil(&self) -> bool {
         if let Value::Nil = self { true } else { false }
     }
+
+    #[inline]
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            Value::Nil                  => ValueKind::Nil,
+            Value::Bool   { value: _ }  => ValueKind::Bool,
+            Value::Number { value: _ }  => ValueKind::Number,
+            Value::String { value: _ }  => ValueKind::String,
+            Value::Unit                 => ValueKind::Unit,
+            Value::Tuple  { shape: _, values: _ } => ValueKind::Tuple,
+            Value::List   { values: _ } => ValueKind::List,
+            Value::Map    { values: _ } => ValueKind::Map,
+            Value::Func   { proto: _ }  => ValueKind::Func,
+            Value::Trait  { id: _ }     => ValueKind::Trait,
+        }
+    }
+}
+
+
+// the runtime "type" of a value.
+// used as the `Type` in `impl Trait for Type`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueKind {
+    Nil,
+    Bool,
+    Number,
+    String,
+    Unit,
+    Tuple,
+    List,
+    Map,
+    Func,
+    Trait,
+}
+
+impl ValueKind {
+    pub const COUNT: usize = 10;
+
+    pub fn from_name(name: &str) -> Option<ValueKind> {
+        Some(match name {
+            "Nil"    => ValueKind::Nil,
+            "Bool"   => ValueKind::Bool,
+            "Number" => ValueKind::Number,
+            "String" => ValueKind::String,
+            "Unit"   => ValueKind::Unit,
+            "Tuple"  => ValueKind::Tuple,
+            "List"   => ValueKind::List,
+            "Map"    => ValueKind::Map,
+            "Func"   => ValueKind::Func,
+            "Trait"  => ValueKind::Trait,
+            _ => return None,
+        })
+    }
+
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueKind::Nil    => "Nil",
+            ValueKind::Bool   => "Bool",
+            ValueKind::Number => "Number",
+            ValueKind::String => "String",
+            ValueKind::Unit   => "Unit",
+            ValueKind::Tuple  => "Tuple",
+            ValueKind::List   => "List",
+            ValueKind::Map    => "Map",
+            ValueKind::Func   => "Func",
+            ValueKind::Trait  => "Trait",
+        }
+    }
+
+    #[inline(always)]
+    pub fn usize(self) -> usize { self as usize }
+
+    pub fn from_usize(index: usize) -> Option<ValueKind> {
+        Some(match index {
+            0 => ValueKind::Nil,
+            1 => ValueKind::Bool,
+            2 => ValueKind::Number,
+            3 => ValueKind::String,
+            4 => ValueKind::Unit,
+            5 => ValueKind::Tuple,
+            6 => ValueKind::List,
+            7 => ValueKind::Map,
+            8 => ValueKind::Func,
+            9 => ValueKind::Trait,
+            _ => return None,
+        })
+    }
 }
 
 
@@ -63,6 +152,7 @@ pub(crate) struct FuncProto {
     pub func_idx:   u32,
     pub code:       FuncCode,
     pub constants:  Vec<Value>,
+    pub tuple_shapes: Vec<u32>,
     pub num_params: u32,
     pub stack_size: u32,
 }
@@ -83,23 +173,3 @@ impl FuncCode {
     }
 }
 
-
-
-// @temp: move to kbtf.
-
-#[derive(Clone, Debug)]
-pub enum Constant {
-    Nil,
-    Bool   { value: bool    },
-    Number { value: f64     },
-    String { value: String },
-}
-
-#[derive(Clone, Debug)]
-pub struct FuncDesc {
-    pub code:       FuncCode,
-    pub constants:  Vec<Constant>,
-    pub num_params: u32,
-    pub stack_size: u32,
-}
-
@@ -3,6 +3,7 @@ use core::sync::atomic::{AtomicBool, Ordering as MemOrder};
 
 use crate::bytecode::*;
 use crate::value::*;
+use crate::kbtf::*;
 
 
 pub trait DebugHook: 'static {
@@ -32,7 +33,9 @@ impl Vm {
     }
 
 
-    pub fn add_func(&mut self, name: &str, desc: FuncDesc) {
+    pub fn add_func(&mut self, name: &str, desc: FuncDesc) -> Result<(), ByteCodeError> {
+        desc.verify()?;
+
         let constants = desc.constants.into_iter().map(|c| { match c {
             Constant::Nil              => Value::Nil,
             Constant::Bool   { value } => Value::Bool { value },
@@ -40,21 +43,62 @@ impl Vm {
             Constant::String { value } => VmImpl::string_new(&value),
         }}).collect();
 
+        let tuple_shapes = desc.tuple_shapes.iter().map(|names| self.inner.tuple_shape(names)).collect();
+
         self.inner.add_func(name, FuncProto {
             krate: None.into(),
             func_idx: 0,
             code: desc.code,
             constants,
+            tuple_shapes,
             num_params: desc.num_params,
             stack_size: desc.stack_size,
         });
+        Ok(())
     }
 
-    pub fn load_crate(&mut self, dst: u32, funcs: &[FuncDesc], items: &[crate::bbir::Item]) {
+    // the names currently defined in the global env.
+    pub fn global_names(&self) -> Vec<String> {
+        let Value::Map { values: env } = &self.inner.env else { return vec![] };
+        env.iter().filter_map(|(key, _)| match key {
+            Value::String { value } => Some(value.to_string()),
+            _ => None,
+        }).collect()
+    }
+
+    // fails, if the crate uses globals, that aren't defined in the env,
+    // or if a function's bytecode doesn't verify.
+    // nothing is loaded in that case.
+    pub fn load_crate(&mut self, dst: u32, funcs: &[FuncDesc], items: &[crate::bbir::Item]) -> Result<(), LinkError> {
+        for (func, desc) in funcs.iter().enumerate() {
+            desc.verify().map_err(|error| LinkError::InvalidByteCode { func, error })?;
+        }
+
+        // globals the crate defines itself are resolved at runtime.
+        let mut defined = vec![];
+        for desc in funcs {
+            if let FuncCode::ByteCode(code) = &desc.code {
+                VmImpl::defined_globals(code, &desc.constants, &mut defined);
+            }
+        }
+
+        let mut codes   = Vec::with_capacity(funcs.len());
+        let mut missing = vec![];
+        for desc in funcs {
+            let mut code = desc.code.clone();
+            if let FuncCode::ByteCode(code) = &mut code {
+                self.inner.link(code, &desc.constants, &defined, &mut missing);
+            }
+            codes.push(code);
+        }
+        if !missing.is_empty() {
+            return Err(LinkError::Missing(missing));
+        }
+
         let krate = CrateId::from_usize(self.inner.krates.len());
 
         let func_base = self.inner.func_protos.len();
-        for (index, desc) in funcs.iter().enumerate() {
+        for (index, (desc, code)) in funcs.iter().zip(codes).enumerate() {
             let constants = desc.constants.iter().map(|c| { match c {
                 Constant::Nil              => Value::Nil,
                 Constant::Bool   { value } => (*value).into(),
@@ -62,34 +106,69 @@ impl Vm {
                 Constant::String { value } => VmImpl::string_new(value),
             }}).collect();
 
+            let tuple_shapes = desc.tuple_shapes.iter().map(|names| self.inner.tuple_shape(names)).collect();
+
             self.inner.func_protos.push(FuncProto {
                 krate:    krate.some(),
                 func_idx: index as u32,
-                code: desc.code.clone(),
+                code,
                 constants,
+                tuple_shapes,
                 num_params: desc.num_params,
                 stack_size: desc.stack_size,
             });
         }
 
-        let crate_items = items.iter().map(|item| {
+        let crate_items: Vec<Item> = items.iter().map(|item| {
             let mut value = Value::Nil;
             let read_only = true;
             let mut uninitialized = true;
-            match item.data {
+            match &item.data {
                 crate::bbir::ItemData::None => (),
 
                 crate::bbir::ItemData::Func(id) => {
                     value = Value::Func { proto: func_base + id.usize() };
                     uninitialized = false;
                 }
+
+                crate::bbir::ItemData::Trait(traitt) => {
+                    let id = self.inner.traits.len();
+                    self.inner.traits.push(Trait {
+                        name:    traitt.name.clone(),
+                        methods: traitt.methods.clone(),
+                        vtables: vec![None; ValueKind::COUNT],
+                    });
+                    value = Value::Trait { id };
+                    uninitialized = false;
+                }
+
+                // registered below, once all traits exist.
+                crate::bbir::ItemData::Impl(_) => (),
             }
 
             Item { value, read_only, uninitialized }
         }).collect();
+
+        // fill in the vtables.
+        for item in items {
+            let crate::bbir::ItemData::Impl(imp) = &item.data else { continue };
+
+            let Value::Trait { id } = crate_items[imp.traitt.usize()].value else { unreachable!() };
+
+            let vtable = imp.methods.iter().map(|method| {
+                let Value::Func { proto } = crate_items[method.usize()].value else { unreachable!() };
+                proto
+            }).collect::<Vec<_>>();
+
+            let traitt = &mut self.inner.traits[id];
+            assert_eq!(vtable.len(), traitt.methods.len());
+            traitt.vtables[imp.kind.usize()] = Some(vtable);
+        }
+
         self.inner.krates.push(Crate { items: crate_items });
 
         *self.inner.reg_mut(dst) = Value::Func { proto: func_base };
+        Ok(())
     }
 
     pub fn call(&mut self, dst: u32, func: u32, args: &[u32]) -> VmResult<()> {
@@ -115,6 +194,11 @@ impl Vm {
     }
 
 
+    pub fn implements(&self, value: u32, traitt: u32) -> VmResult<bool> {
+        let this = &self.inner;
+        this.implements(this.reg(value), this.reg(traitt))
+    }
+
     #[inline]
     pub fn generic_print(&mut self, reg: u32) {
         let value = self.inner.reg(reg);
@@ -167,13 +251,17 @@ pub struct DebugFrame {
     pub krate:    OptCrateId,
     pub func_idx: u32,
 
-    pub pc:   u16,
+    pub pc:   u32,
     pub base: u32,
     pub top:  u32,
+
+    // the number of frames tail calls replaced,
+    // between this frame & the one below.
+    pub elided: u32,
 }
 
 impl DebugFrame {
-    fn from_stack_frame(vm: &VmImpl, frame: &StackFrame, pc: u16) -> DebugFrame {
+    fn from_stack_frame(vm: &VmImpl, frame: &StackFrame, pc: u32) -> DebugFrame {
         let proto = &vm.func_protos[frame.func_proto];
         DebugFrame {
             krate: proto.krate,
@@ -181,6 +269,7 @@ impl DebugFrame {
             pc,
             base: frame.base,
             top:  frame.top,
+            elided: frame.tail_calls,
         }
     }
 }
@@ -190,11 +279,11 @@ impl Vm {
         let this = &self.inner;
         for i in 1 .. this.frames.len() - 1 {
             let frame = &this.frames[i];
-            f(DebugFrame::from_stack_frame(this, frame, frame.pc as u16));
+            f(DebugFrame::from_stack_frame(this, frame, frame.pc));
         }
         if this.frames.len() > 1 {
             let frame = this.frames.last().unwrap();
-            f(DebugFrame::from_stack_frame(this, frame, this.pc as u16));
+            f(DebugFrame::from_stack_frame(this, frame, this.pc as u32));
         }
     }
 
@@ -210,6 +299,26 @@ pub enum VmError {
 pub type VmResult<T> = Result<T, VmError>;
 
 
+#[derive(Clone, Debug)]
+pub enum LinkError {
+    Missing(Vec<String>),
+    // `func` is the index into the crate's functions.
+    InvalidByteCode { func: usize, error: ByteCodeError },
+}
+
+impl core::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LinkError::Missing(missing) =>
+                write!(f, "undefined globals: {}", missing.join(", ")),
+
+            LinkError::InvalidByteCode { func, error } =>
+                write!(f, "invalid bytecode in function {}: {}", func, error),
+        }
+    }
+}
+
+
 #[derive(Debug)]
 struct StackFrame {
     func_proto: usize,
@@ -220,6 +329,9 @@ struct StackFrame {
     pc:   u32,
     base: u32,
     top:  u32,
+
+    // frames replaced by tail calls.
+    tail_calls: u32,
 }
 
 impl StackFrame {
@@ -228,6 +340,7 @@ impl StackFrame {
         is_native: true,
         dst_abs: 0,
         pc: 0, base: 0, top: 0,
+        tail_calls: 0,
     };
 }
 
@@ -241,10 +354,24 @@ struct Crate {
     items: Vec<Item>,
 }
 
+struct Trait {
+    name:    String,
+    methods: Vec<String>,
+    // indexed by `ValueKind`.
+    // entries are func protos, in the order of `methods`.
+    vtables: Vec<Option<Vec<usize>>>,
+}
+
+struct TupleShape {
+    names: Vec<String>,
+}
+
 
 pub(crate) struct VmImpl {
     func_protos: Vec<FuncProto>,
     krates:      Vec<Crate>,
+    traits:      Vec<Trait>,
+    tuple_shapes: Vec<TupleShape>,
 
     pc:     usize,
     frames: Vec<StackFrame>,
@@ -297,6 +424,8 @@ impl VmImpl {
         let mut vm = VmImpl {
             func_protos: vec![],
             krates:      vec![],
+            traits:      vec![],
+            tuple_shapes: vec![TupleShape { names: vec![] }],
 
             pc:     usize::MAX,
             frames: vec![StackFrame::ROOT],
@@ -341,6 +470,84 @@ impl VmImpl {
     }
 
 
+    // resolves the env names used by `code` against the env.
+    // reads are rewritten into `LOAD_GLOBAL`s, which is safe, cause env slots are stable:
+    // deleted entries are left as tombstones, see `delete_global`.
+    // writes still look up the name, they're rare.
+    fn defined_globals(code: &[InstrWord], constants: &[Constant], defined: &mut Vec<String>) {
+        let mut decoder = ByteCodeDecoder::new(code);
+        while let Some(instr) = decoder.next() {
+            let InstrData::WritePath { base, keys, value: _ } = &instr.data else { continue };
+            if *base != PathBase::ENV || instr.opcode != opcode::WRITE_PATH_DEF {
+                continue;
+            }
+
+            let PathKey::Field { string } = keys[0] else { continue };
+            let Constant::String { value: name } = &constants[string as usize] else { unreachable!() };
+            if !defined.contains(name) {
+                defined.push(name.clone());
+            }
+        }
+    }
+
+    fn link(&self, code: &mut [InstrWord], constants: &[Constant], defined: &[String], missing: &mut Vec<String>) {
+        let Value::Map { values: env } = &self.env else { unreachable!() };
+
+        let mut patches = vec![];
+
+        let mut decoder = ByteCodeDecoder::new(code);
+        while let Some(instr) = decoder.next() {
+            let (dst, base, keys) = match &instr.data {
+                InstrData::ReadPath   { dst, base, keys }      => (Some(*dst), *base, keys),
+                InstrData::WritePath  { base, keys, value: _ } => (None, *base, keys),
+                InstrData::DeletePath { base, keys }           => (None, *base, keys),
+                _ => continue,
+            };
+            // defining globals is how the env gets new entries.
+            if base != PathBase::ENV || instr.opcode == opcode::WRITE_PATH_DEF {
+                continue;
+            }
+
+            let PathKey::Field { string } = keys[0] else { continue };
+            let Constant::String { value: name } = &constants[string as usize] else { unreachable!() };
+
+            let slot = env.iter().position(|(key, _)|
+                matches!(key, Value::String { value } if **value == *name));
+            let Some(slot) = slot else {
+                if !defined.contains(name) && !missing.contains(name) {
+                    missing.push(name.clone());
+                }
+                continue;
+            };
+
+            if let (Some(dst), Ok(slot)) = (dst, u32::try_from(slot)) {
+                if !PathBase::is_reserved(dst) {
+                    patches.push((instr.pc as usize, decoder.offset(), dst, slot, keys[1..].to_vec()));
+                }
+            }
+        }
+
+        // `read_path dst, env, [name, keys..]` becomes
+        // `load_global dst, slot; read_path dst, dst, [keys..]`, or
+        // `load_global dst, slot; nop`. padded with `nop`s, so the code size doesn't change.
+        // with `wide` operands, the rewrite may not fit, then the read is left alone.
+        for (begin, end, dst, slot, keys) in patches {
+            let mut b = ByteCodeBuilder::new();
+            b.load_global(dst, slot);
+            if !keys.is_empty() {
+                b.read_path(dst, PathBase::reg(dst), &keys);
+            }
+            while b.current_offset() < end - begin {
+                b.nop();
+            }
+
+            let words = b.build();
+            if words.len() == end - begin {
+                code[begin..end].copy_from_slice(&words);
+            }
+        }
+    }
+
     fn add_func(&mut self, name: &str, proto: FuncProto) {
         let proto_index = self.func_protos.len();
         self.func_protos.push(proto);
@@ -392,7 +599,7 @@ impl VmImpl {
 
         fn mark_value(heap: &mut Vec<GcObject>, value: &Value) {
             match value {
-                Value::Tuple { values } |
+                Value::Tuple { shape: _, values } |
                 Value::List { values } => {
                     for v in values.iter() {
                         mark_value(heap, v);
@@ -464,8 +671,8 @@ impl VmImpl {
 
             (Unit, Unit) => true,
 
-            (Tuple { values: v1 }, Tuple { values: v2 }) =>
-                Self::raw_eq_list(v1, v2),
+            (Tuple { shape: s1, values: v1 }, Tuple { shape: s2, values: v2 }) =>
+                s1 == s2 && Self::raw_eq_list(v1, v2),
 
             (List { values: v1 }, List { values: v2 }) =>
                 Self::raw_eq_list(v1, v2),
@@ -645,6 +852,20 @@ impl VmImpl {
         }
     }
 
+    fn implements(&self, value: &Value, traitt: &Value) -> VmResult<bool> {
+        let Value::Trait { id } = traitt else { return Err(VmError::InvalidOperation) };
+        Ok(self.traits[*id].vtables[value.kind().usize()].is_some())
+    }
+
+    fn load_method(&self, traitt: &Value, recv: &Value, method: usize) -> VmResult<Value> {
+        let Value::Trait { id } = traitt else { return Err(VmError::InvalidOperation) };
+        let traitt = &self.traits[*id];
+
+        let vtable = traitt.vtables[recv.kind().usize()].as_ref().ok_or(VmError::InvalidOperation)?;
+        let proto  = *vtable.get(method).ok_or(VmError::InvalidOperation)?;
+        Ok(Value::Func { proto })
+    }
+
     fn generic_print(&self, value: &Value) {
         match value {
             Value::Nil              => print!("nil"),
@@ -652,9 +873,18 @@ impl VmImpl {
             Value::Number { value } => print!("{}", value),
             Value::String { value } => print!("{}", value),
             Value::Unit => print!("()"),
-            Value::Tuple  { values } => {
+            Value::Tuple  { shape, values } => {
                 print!("(");
-                if values.len() == 1 {
+                if *shape != 0 {
+                    let names = &self.tuple_shapes[*shape as usize].names;
+                    for (i, v) in values.iter().enumerate() {
+                        print!("{}: ", names[i]);
+                        self.generic_print(v);
+                        if i < values.len() - 1 { print!(", ") }
+                    }
+                    print!(")");
+                }
+                else if values.len() == 1 {
                     self.generic_print(&values[0]);
                     print!(",)");
                 }
@@ -685,6 +915,7 @@ impl VmImpl {
                 print!("}}");
             }
             Value::Func { proto } => print!("<Func {}>", proto),
+            Value::Trait { id } => print!("<Trait {}>", self.traits[*id].name),
         }
     }
 
@@ -701,44 +932,90 @@ impl VmImpl {
 
     fn tuple_new(values: Vec<Value>) -> Value {
         if values.len() != 0 {
-            Value::Tuple { values: Rc::new(values) }
+            Value::Tuple { shape: 0, values: Rc::new(values) }
         }
         else {
             Value::Unit
         }
     }
 
+    fn tuple_new_named(shape: u32, values: Vec<Value>) -> Value {
+        debug_assert!(!values.is_empty());
+        Value::Tuple { shape, values: Rc::new(values) }
+    }
+
+    // shapes are interned, so field names are stored once per shape.
+    fn tuple_shape(&mut self, names: &[String]) -> u32 {
+        if let Some(index) = self.tuple_shapes.iter().position(|shape| shape.names == names) {
+            return index as u32;
+        }
+
+        let index = self.tuple_shapes.len();
+        self.tuple_shapes.push(TupleShape { names: names.to_vec() });
+        index as u32
+    }
+
+    fn tuple_index(&self, shape: u32, key: PathKey) -> VmResult<usize> {
+        match key {
+            PathKey::Field { string } => {
+                let Value::String { value: name } = self.load_const(string as usize) else { return Err(VmError::InvalidOperation) };
+                let names = &self.tuple_shapes[shape as usize].names;
+                names.iter().position(|n| n == name.as_str()).ok_or(VmError::InvalidOperation)
+            }
+
+            PathKey::Index { reg } => {
+                let Value::Number { value: index } = self.reg(reg) else { return Err(VmError::InvalidOperation) };
+                Ok(*index as usize)
+            }
+        }
+    }
+
 
     fn map_new() -> Value {
         Value::Map { values: Rc::new(vec![]) }
     }
 
-    fn map_index<'m>(map: &'m Vec<(Value, Value)>, key: &Value) -> Option<&'m Value> {
-        for (k, v) in map.iter() {
-            if Self::raw_eq(k, key) {
-                return Some(v);
-            }
+    // nil keys are tombstones, so they're never found.
+    fn map_position(map: &[(Value, Value)], key: &Value) -> Option<usize> {
+        if key.is_nil() {
+            return None;
         }
-        None
+        map.iter().position(|(k, _)| Self::raw_eq(k, key))
+    }
+
+    fn map_index<'m>(map: &'m Vec<(Value, Value)>, key: &Value) -> Option<&'m Value> {
+        Self::map_position(map, key).map(|index| &map[index].1)
     }
 
     fn map_index_mut<'m>(map: &'m mut Vec<(Value, Value)>, key: &Value) -> Option<&'m mut Value> {
-        for (k, v) in map.iter_mut() {
-            if Self::raw_eq(k, key) {
-                return Some(v);
-            }
+        Self::map_position(map, key).map(|index| &mut map[index].1)
+    }
+
+    // adds a new entry, reusing the key's tombstone, if there is one.
+    // only the env has tombstones, see `delete_global`.
+    fn map_insert(map: &mut Vec<(Value, Value)>, key: &Value, value: Value) -> VmResult<()> {
+        if key.is_nil() {
+            return Err(VmError::InvalidOperation);
         }
-        None
+
+        let tombstone = map.iter().position(|(k, v)| k.is_nil() && Self::raw_eq(v, key));
+        if let Some(index) = tombstone {
+            map[index] = (key.clone(), value);
+        }
+        else {
+            map.push((key.clone(), value));
+        }
+        Ok(())
     }
 
     fn map_def(map: &mut Vec<(Value, Value)>, key: &Value, value: Value) -> VmResult<()> {
         if let Some(slot) = Self::map_index_mut(map, key) {
             *slot = value;
+            Ok(())
         }
         else {
-            map.push((key.clone(), value));
+            Self::map_insert(map, key, value)
         }
-        Ok(())
     }
 
     fn read_path(&self, base: &Value, keys: &[InstrWord]) -> VmResult<Value> {
@@ -751,10 +1028,21 @@ impl VmImpl {
                 Err(VmError::InvalidOperation)
             }
 
-            Value::Tuple { values } |
+            Value::Tuple { shape, values } => {
+                let index = self.tuple_index(*shape, key)?;
+
+                let value = values.get(index).ok_or(VmError::InvalidOperation)?;
+                if rem_keys.is_empty() {
+                    Ok(value.clone())
+                }
+                else {
+                    self.read_path(value, rem_keys)
+                }
+            }
+
             Value::List { values } => {
                 let PathKey::Index { reg } = key else { return Err(VmError::InvalidOperation) };
-                let key = self.reg(reg as u32);
+                let key = self.reg(reg);
 
                 let Value::Number { value: index } = key else { return Err(VmError::InvalidOperation) };
                 let index = *index as usize;
@@ -773,7 +1061,7 @@ impl VmImpl {
                     // @temp: maps are still like "tables/objects".
                     //  cause env is still a map, which we should change.
                     PathKey::Field { string } => self.load_const(string as usize),
-                    PathKey::Index { reg }    => self.reg(reg as u32),
+                    PathKey::Index { reg }    => self.reg(reg),
                 };
 
                 let value = Self::map_index(values, key).ok_or(VmError::InvalidOperation)?;
@@ -798,12 +1086,8 @@ impl VmImpl {
                 Err(VmError::InvalidOperation)
             }
 
-            Value::Tuple { values } => {
-                let PathKey::Index { reg } = key else { return Err(VmError::InvalidOperation) };
-                let key = self.reg(reg as u32);
-
-                let Value::Number { value: index } = key else { return Err(VmError::InvalidOperation) };
-                let index = *index as usize;
+            Value::Tuple { shape, values } => {
+                let index = self.tuple_index(*shape, key)?;
 
                 let values = Rc::make_mut(values);
 
@@ -819,7 +1103,7 @@ impl VmImpl {
 
             Value::List { values } => {
                 let PathKey::Index { reg } = key else { return Err(VmError::InvalidOperation) };
-                let key = self.reg(reg as u32);
+                let key = self.reg(reg);
 
                 let Value::Number { value: index } = key else { return Err(VmError::InvalidOperation) };
                 let index = *index as usize;
@@ -851,7 +1135,7 @@ impl VmImpl {
                     // @temp: maps are still like "tables/objects".
                     //  cause env is still a map, which we should change.
                     PathKey::Field { string } => self.load_const(string as usize),
-                    PathKey::Index { reg }    => self.reg(reg as u32),
+                    PathKey::Index { reg }    => self.reg(reg),
                 };
 
                 let values = Rc::make_mut(values);
@@ -864,8 +1148,7 @@ impl VmImpl {
                     }
                     else {
                         if is_def {
-                            values.push((key.clone(), value));
-                            Ok(())
+                            Self::map_insert(values, key, value)
                         }
                         else { Err(VmError::InvalidOperation) }
                     }
@@ -882,6 +1165,90 @@ impl VmImpl {
         }
     }
 
+    // removes the entry at the last key.
+    // like reads, deleting a missing key or an index out of bounds is an error.
+    // tuples have a fixed shape, so only their elements can be deleted from.
+    fn delete_path(&self, base: &mut Value, keys: &[InstrWord]) -> VmResult<()> {
+        let key = PathKey::decode(keys[0]);
+        let rem_keys = &keys[1..];
+
+        match base {
+            Value::Tuple { shape, values } => {
+                if rem_keys.is_empty() {
+                    return Err(VmError::InvalidOperation);
+                }
+
+                let index = self.tuple_index(*shape, key)?;
+
+                let values = Rc::make_mut(values);
+                let slot = values.get_mut(index).ok_or(VmError::InvalidOperation)?;
+                self.delete_path(slot, rem_keys)
+            }
+
+            Value::List { values } => {
+                let PathKey::Index { reg } = key else { return Err(VmError::InvalidOperation) };
+                let key = self.reg(reg);
+
+                let Value::Number { value: index } = key else { return Err(VmError::InvalidOperation) };
+                let index = *index as usize;
+
+                let values = Rc::make_mut(values);
+                if index >= values.len() {
+                    return Err(VmError::InvalidOperation);
+                }
+
+                if rem_keys.is_empty() {
+                    values.remove(index);
+                    Ok(())
+                }
+                else {
+                    self.delete_path(&mut values[index], rem_keys)
+                }
+            }
+
+            Value::Map { values } => {
+                let key = match key {
+                    // @temp: maps are still like "tables/objects".
+                    //  cause env is still a map, which we should change.
+                    PathKey::Field { string } => self.load_const(string as usize),
+                    PathKey::Index { reg }    => self.reg(reg),
+                };
+
+                let values = Rc::make_mut(values);
+                let index = Self::map_position(values, key).ok_or(VmError::InvalidOperation)?;
+
+                if rem_keys.is_empty() {
+                    values.remove(index);
+                    Ok(())
+                }
+                else {
+                    self.delete_path(&mut values[index].1, rem_keys)
+                }
+            }
+
+            _ => Err(VmError::InvalidOperation)
+        }
+    }
+
+    // the env is linked by slot, so its entries are replaced by a
+    // `(nil, key)` tombstone, instead of being removed.
+    fn delete_global(&self, env: &mut Value, keys: &[InstrWord]) -> VmResult<()> {
+        if keys.len() > 1 {
+            return self.delete_path(env, keys);
+        }
+
+        let Value::Map { values } = env else { unreachable!() };
+        let key = match PathKey::decode(keys[0]) {
+            PathKey::Field { string } => self.load_const(string as usize),
+            PathKey::Index { reg }    => self.reg(reg),
+        };
+
+        let values = Rc::make_mut(values);
+        let index = Self::map_position(values, key).ok_or(VmError::InvalidOperation)?;
+        values[index] = (Value::Nil, key.clone());
+        Ok(())
+    }
+
 
     #[inline(always)]
     fn check_interrupt(&mut self) -> VmResult<()> {
@@ -956,6 +1323,17 @@ impl VmImpl {
         result
     }
 
+    // patches the current instruction to its generic version.
+    // `*_NUM` instructions call this, when their operands aren't numbers.
+    #[inline(never)]
+    fn deoptimize(&mut self, generic_op: u8) {
+        let frame = self.frames.last().unwrap();
+        let proto = &mut self.func_protos[frame.func_proto];
+
+        let FuncCode::ByteCode(code) = &mut proto.code else { unreachable!() };
+        code[self.pc - 1].patch_opcode(generic_op);
+    }
+
     #[inline(always)]
     fn next_instr_extra(&mut self) -> InstrWord {
         let result = self.next_instr();
@@ -971,6 +1349,29 @@ impl VmImpl {
         &proto.constants[index]
     }
 
+    #[inline(always)]
+    fn load_tuple_shape(&self, index: usize) -> u32 {
+        let frame = self.frames.last().unwrap();
+        let proto = &self.func_protos[frame.func_proto];
+        proto.tuple_shapes[index]
+    }
+
+    // the crate & index of the item an `ITEMS` path starts with.
+    // functions added with `add_func` have no crate.
+    fn item_path(&self, key: InstrWord) -> Result<(usize, usize), VmError> {
+        let PathKey::Index { reg } = PathKey::decode(key) else { return Err(VmError::InvalidOperation) };
+        let Value::Number { value: index } = self.reg(reg) else { return Err(VmError::InvalidOperation) };
+        let index = *index as usize;
+
+        let frame = self.frames.last().unwrap();
+        let proto = &self.func_protos[frame.func_proto];
+        let krate = proto.krate.to_option().ok_or(VmError::InvalidOperation)?.usize();
+        if index >= self.krates[krate].items.len() {
+            return Err(VmError::InvalidOperation);
+        }
+        Ok((krate, index))
+    }
+
     #[inline(never)]
     fn run(&mut self) -> (VmResult<bool>,) { // wrap in tuple to prevent accidental usage of the `?` operator. (that would mess up the counter)
         if self.frames.len() == 1 {
@@ -1010,10 +1411,51 @@ impl VmImpl {
                 };
             }
 
+            // the codegen only emits `*_NUM` instructions, if the operands
+            // are known (or expected) to be numbers.
+            // if that guess was wrong, we fall back to the generic version.
+            macro_rules! vm_op2_num {
+                ($instr: expr, $generic_op: expr, $generic: ident, |$v1: ident, $v2: ident| $e: expr) => {{
+                    let (dst, src1, src2) = self.reg3_dst($instr.c3());
+                    let value: Value = match (src1, src2) {
+                        (Value::Number { value: $v1 }, Value::Number { value: $v2 }) => $e.into(),
+
+                        _ => {
+                            let value = vm_try!(self.$generic(src1, src2)).into();
+                            self.deoptimize($generic_op);
+                            value
+                        }
+                    };
+                    *self.reg_mut(dst) = value;
+                }};
+            }
+
+            // no generic version to deoptimize to.
+            macro_rules! vm_jump_num {
+                ($instr: expr, $generic: ident, |$v1: ident, $v2: ident| $e: expr) => {{
+                    let (src1, src2, value) = $instr.c3();
+                    let target = self.next_instr_extra().extra();
+
+                    let src1 = self.reg(src1);
+                    let src2 = self.reg(src2);
+                    let condition = match (src1, src2) {
+                        (Value::Number { value: $v1 }, Value::Number { value: $v2 }) => $e,
+                        _ => vm_try!(self.$generic(src1, src2)),
+                    };
+
+                    if condition == (value != 0) {
+                        vm_jump!(target);
+                    }
+                }};
+            }
+
             debug_assert!(self.counter <= self.counter_target);
 
+            // the `wide` prefix of the next instruction.
+            let mut wide = 0;
+
             if self.counter > 0 { loop {
-                let instr  = self.next_instr();
+                let instr  = WideInstrWord { word: self.next_instr(), wide: core::mem::take(&mut wide) };
                 let opcode = instr.opcode() as u8;
 
                 use opcode::*;
@@ -1068,6 +1510,16 @@ impl VmImpl {
                     }
 
 
+                    LOAD_GLOBAL => {
+                        let (dst, slot) = instr.c1u16();
+                        let Value::Map { values: env } = &self.env else { unreachable!() };
+                        // @todo-speed: remove checks.
+                        let Some((key, value)) = env.get(slot as usize) else { vm_err!(VmError::InvalidOperation) };
+                        // the global was deleted.
+                        if key.is_nil() { vm_err!(VmError::InvalidOperation) }
+                        *self.reg_mut(dst) = value.clone();
+                    }
+
                     LOAD_ENV => {
                         //let dst = instr.c1();
                         //*self.reg_mut(dst) = self.env.clone();
@@ -1082,7 +1534,7 @@ impl VmImpl {
                         let mut values = Vec::with_capacity(len as usize);
                         for _ in 0..len {
                             let v = self.next_instr_extra();
-                            values.push(self.reg(v.u16()).clone());
+                            values.push(self.reg(v.extra()).clone());
                         }
 
                         *self.reg_mut(dst) = Self::list_new(values);
@@ -1095,12 +1547,26 @@ impl VmImpl {
                         let mut values = Vec::with_capacity(len as usize);
                         for _ in 0..len {
                             let v = self.next_instr_extra();
-                            values.push(self.reg(v.u16()).clone());
+                            values.push(self.reg(v.extra()).clone());
                         }
 
                         *self.reg_mut(dst) = Self::tuple_new(values);
                     }
 
+                    TUPLE_NEW_NAMED => {
+                        let (dst, len) = instr.c1u16();
+                        let shape = self.next_instr_extra().extra();
+                        let shape = self.load_tuple_shape(shape as usize);
+
+                        let mut values = Vec::with_capacity(len as usize);
+                        for _ in 0..len {
+                            let v = self.next_instr_extra();
+                            values.push(self.reg(v.extra()).clone());
+                        }
+
+                        *self.reg_mut(dst) = Self::tuple_new_named(shape, values);
+                    }
+
                     LOAD_UNIT => {
                         let dst = instr.c1();
                         *self.reg_mut(dst) = Value::Unit;
@@ -1120,23 +1586,13 @@ impl VmImpl {
                         let keys = &code[self.pc .. self.pc + num_keys as usize];
                         self.pc += num_keys as usize;
 
-                        let value = match base as u8 {
+                        let value = match base {
                             // Items
                             254 => {
-                                let key = PathKey::decode(keys[0]);
+                                let (krate, index) = vm_try!(self.item_path(keys[0]));
                                 let rem_keys = &keys[1..];
 
-                                // this is waaay too nasty!!!
-                                let PathKey::Index { reg: index } = key else { unreachable!()};
-                                let index = self.reg(index as u32);
-                                let Value::Number { value: index } = index else { unreachable!()};
-                                let index = *index as usize;
-
-                                let frame = self.frames.last().unwrap();
-                                let proto = &self.func_protos[frame.func_proto];
-                                let krate = &self.krates[proto.krate.unwrap().usize()];
-
-                                let item = &krate.items[index];
+                                let item = &self.krates[krate].items[index];
                                 if item.uninitialized { vm_err!(VmError::InvalidOperation) }
 
                                 if rem_keys.len() > 0 {
@@ -1151,7 +1607,7 @@ impl VmImpl {
                             255 => vm_try!(self.read_path(&self.env, keys)),
 
                             // Reg
-                            0..=253 => vm_try!(self.read_path(self.reg(base), keys)),
+                            _ => vm_try!(self.read_path(self.reg(base), keys)),
                         };
 
                         *self.reg_mut(dst) = value;
@@ -1170,23 +1626,13 @@ impl VmImpl {
                         // @todo-safety: this is UB, if base is in the keys.
                         let this = unsafe { &mut *(self as *mut VmImpl)  };
 
-                        match base as u8 {
+                        match base {
                             // Items
                             254 => {
-                                let key = PathKey::decode(keys[0]);
+                                let (krate, index) = vm_try!(self.item_path(keys[0]));
                                 let rem_keys = &keys[1..];
 
-                                // this is waaay too nasty!!!
-                                let PathKey::Index { reg: index } = key else { unreachable!()};
-                                let index = self.reg(index as u32);
-                                let Value::Number { value: index } = index else { unreachable!()};
-                                let index = *index as usize;
-
-                                let frame = self.frames.last().unwrap();
-                                let proto = &self.func_protos[frame.func_proto];
-                                let krate = &mut this.krates[proto.krate.unwrap().usize()];
-
-                                let item = &mut krate.items[index];
+                                let item = &mut this.krates[krate].items[index];
                                 if item.uninitialized { vm_err!(VmError::InvalidOperation) }
                                 if item.read_only     { vm_err!(VmError::InvalidOperation) }
 
@@ -1205,7 +1651,44 @@ impl VmImpl {
 
                             // Reg
                             // unsafe
-                            0..=253 => vm_try!(self.write_path(this.reg_mut(base), keys, value, is_def)),
+                            _ => vm_try!(self.write_path(this.reg_mut(base), keys, value, is_def)),
+                        }
+                    }
+
+                    DELETE_PATH => {
+                        let (base, num_keys) = instr.c2();
+
+                        let code = unsafe { self.get_current_function_bytecode() };
+                        let keys = &code[self.pc .. self.pc + num_keys as usize];
+                        self.pc += num_keys as usize;
+
+                        // @todo-safety: this is UB, if base is in the keys.
+                        let this = unsafe { &mut *(self as *mut VmImpl)  };
+
+                        match base {
+                            // Items
+                            254 => {
+                                let (krate, index) = vm_try!(self.item_path(keys[0]));
+                                let rem_keys = &keys[1..];
+
+                                // items themselves can't be deleted.
+                                if rem_keys.is_empty() { vm_err!(VmError::InvalidOperation) }
+
+                                let item = &mut this.krates[krate].items[index];
+                                if item.uninitialized { vm_err!(VmError::InvalidOperation) }
+                                if item.read_only     { vm_err!(VmError::InvalidOperation) }
+
+                                // unsafe
+                                vm_try!(self.delete_path(&mut item.value, rem_keys));
+                            }
+
+                            // Env
+                            // unsafe
+                            255 => vm_try!(self.delete_global(&mut this.env, keys)),
+
+                            // Reg
+                            // unsafe
+                            _ => vm_try!(self.delete_path(this.reg_mut(base), keys)),
                         }
                     }
 
@@ -1308,6 +1791,18 @@ impl VmImpl {
                     }
 
 
+                    ADD_NUM    => vm_op2_num!(instr, ADD,    generic_add, |v1, v2| v1 + v2),
+                    SUB_NUM    => vm_op2_num!(instr, SUB,    generic_sub, |v1, v2| v1 - v2),
+                    MUL_NUM    => vm_op2_num!(instr, MUL,    generic_mul, |v1, v2| v1 * v2),
+
+                    CMP_EQ_NUM => vm_op2_num!(instr, CMP_EQ, generic_eq,  |v1, v2| v1 == v2),
+                    CMP_NE_NUM => vm_op2_num!(instr, CMP_NE, generic_ne,  |v1, v2| v1 != v2),
+                    CMP_LE_NUM => vm_op2_num!(instr, CMP_LE, generic_le,  |v1, v2| v1 <= v2),
+                    CMP_LT_NUM => vm_op2_num!(instr, CMP_LT, generic_lt,  |v1, v2| v1 <  v2),
+                    CMP_GE_NUM => vm_op2_num!(instr, CMP_GE, generic_ge,  |v1, v2| v1 >= v2),
+                    CMP_GT_NUM => vm_op2_num!(instr, CMP_GT, generic_gt,  |v1, v2| v1 >  v2),
+
+
                     JUMP => {
                         let target = instr.u16();
                         vm_jump!(target);
@@ -1361,10 +1856,17 @@ impl VmImpl {
                         }
                     }
 
+                    JUMP_EQ_NUM => vm_jump_num!(instr, generic_eq, |v1, v2| v1 == v2),
+                    JUMP_NE_NUM => vm_jump_num!(instr, generic_ne, |v1, v2| v1 != v2),
+                    JUMP_LE_NUM => vm_jump_num!(instr, generic_le, |v1, v2| v1 <= v2),
+                    JUMP_LT_NUM => vm_jump_num!(instr, generic_lt, |v1, v2| v1 <  v2),
+                    JUMP_GE_NUM => vm_jump_num!(instr, generic_ge, |v1, v2| v1 >= v2),
+                    JUMP_GT_NUM => vm_jump_num!(instr, generic_gt, |v1, v2| v1 >  v2),
+
 
                     CALL => {
                         let (dst, func) = instr.c2();
-                        let num_args = self.next_instr_extra().u16();
+                        let num_args = self.next_instr_extra().extra();
 
                         let args = {
                             let code = unsafe { self.get_current_function_bytecode() };
@@ -1381,12 +1883,60 @@ impl VmImpl {
                             for (i, arg) in args.iter().enumerate() {
                                 debug_assert_eq!(arg.opcode() as u8, EXTRA);
 
-                                let arg = arg.u16() as usize;
+                                let arg = arg.extra() as usize;
                                 vm.stack[dst_base + i] = vm.stack[src_base + arg].clone();
                             }
                         }));
                     }
 
+                    CALL0 | CALL1 | CALL2 | CALL3 => {
+                        let (dst, func, arg0) = instr.c3();
+                        let num_args = (opcode - CALL0) as u32;
+
+                        let rest = {
+                            let code = unsafe { self.get_current_function_bytecode() };
+
+                            let num_rest = num_args.saturating_sub(1) as usize;
+                            let result = &code[self.pc .. self.pc + num_rest];
+                            self.pc += num_rest;
+                            result
+                        };
+
+                        let frame = self.frames.last().unwrap();
+                        let src_base = frame.base as usize;
+
+                        vm_try!(self.pre_call(dst, func, num_args, |vm, dst_base| {
+                            if num_args > 0 {
+                                vm.stack[dst_base] = vm.stack[src_base + arg0 as usize].clone();
+                            }
+                            for (i, arg) in rest.iter().enumerate() {
+                                debug_assert_eq!(arg.opcode() as u8, EXTRA);
+
+                                let arg = arg.extra() as usize;
+                                vm.stack[dst_base + 1 + i] = vm.stack[src_base + arg].clone();
+                            }
+                        }));
+                    }
+
+                    TAIL_CALL => {
+                        let func = instr.c1();
+                        let num_args = self.next_instr_extra().extra();
+
+                        let args = {
+                            let code = unsafe { self.get_current_function_bytecode() };
+
+                            let result = &code[self.pc .. self.pc + num_args as usize];
+                            self.pc += num_args as usize;
+                            result
+                        };
+
+                        if vm_try!(self.tail_call(func, args)) {
+                            self.counter = self.counter.wrapping_sub(1);
+                            result = Ok(());
+                            break;
+                        }
+                    }
+
                     RET => {
                         let src = instr.c1();
 
@@ -1402,6 +1952,26 @@ impl VmImpl {
                         }
                     }
 
+                    LOAD_METHOD => {
+                        let (dst, traitt, recv) = instr.c3();
+                        let method = self.next_instr_extra().extra();
+
+                        let func = vm_try!(self.load_method(self.reg(traitt), self.reg(recv), method as usize));
+                        *self.reg_mut(dst) = func;
+                    }
+
+                    IMPLEMENTS => {
+                        let (dst, src, traitt) = self.reg3_dst(instr.c3());
+                        *self.reg_mut(dst) = vm_try!(self.implements(src, traitt)).into();
+                    }
+
+                    // doesn't count as an instruction,
+                    // so the prefixed instruction runs before any pause.
+                    WIDE => {
+                        wide = instr.word.wide();
+                        continue;
+                    }
+
                     // @todo-speed: this inserts a check to reduce dispatch table size.
                     //  may want an unreachable_unchecked() in release.
                     0 | END ..= 255 => unreachable!()
@@ -1465,8 +2035,6 @@ impl VmImpl {
     fn pre_call<CopyArgs: FnOnce(&mut VmImpl, usize)>(&mut self,
         dst: u32, func: u32, num_args: u32, copy_args: CopyArgs
     ) -> VmResult<bool> {
-        assert!(num_args < 128);
-
         let frame = self.frames.last_mut().unwrap();
         let caller_base = frame.base;
 
@@ -1495,6 +2063,7 @@ impl VmImpl {
             dst_abs: caller_base + dst,
             pc: u32::MAX,
             base, top,
+            tail_calls: 0,
         });
         self.pc = 0;
         self.stack.resize(top as usize, Value::Nil);
@@ -1536,6 +2105,62 @@ impl VmImpl {
         }
     }
 
+    // returns whether the current frame was popped & its caller is native (like `post_call`).
+    fn tail_call(&mut self, func: u32, args: &[InstrWord]) -> VmResult<bool> {
+        let frame = self.frames.last().unwrap();
+        let base = frame.base;
+
+        let Value::Func { proto: func_proto } = self.stack[(base + func) as usize] else {
+            return Err(VmError::InvalidOperation);
+        };
+        let proto = &self.func_protos[func_proto];
+
+        // native functions don't run in the vm's frames.
+        // call them normally & return the result.
+        if proto.code.is_native() {
+            let run = self.pre_call(func, func, args.len() as u32, |vm, dst_base| {
+                for (i, arg) in args.iter().enumerate() {
+                    let arg = arg.extra() as usize;
+                    vm.stack[dst_base + i] = vm.stack[base as usize + arg].clone();
+                }
+            })?;
+            debug_assert!(!run);
+
+            let value = self.reg(func).clone();
+            let dst_abs = self.frames.last().unwrap().dst_abs;
+            self.stack[dst_abs as usize] = value;
+            return self.post_call();
+        }
+
+        // check args.
+        if args.len() as u32 != proto.num_params {
+            return Err(VmError::InvalidOperation);
+        }
+        let top = base + proto.stack_size;
+
+        // the args may overlap the callee's registers,
+        // so they're copied to the end of the stack first.
+        let old_top = self.stack.len();
+        for arg in args {
+            debug_assert_eq!(arg.opcode() as u8, opcode::EXTRA);
+
+            let arg = arg.extra() as usize;
+            let value = self.stack[base as usize + arg].clone();
+            self.stack.push(value);
+        }
+        self.stack.drain(base as usize .. old_top);
+        self.stack.resize(top as usize, Value::Nil);
+
+        // replace frame.
+        let frame = self.frames.last_mut().unwrap();
+        frame.func_proto = func_proto;
+        frame.top        = top;
+        frame.tail_calls += 1;
+        self.pc = 0;
+
+        Ok(false)
+    }
+
     // caller is responsible for returning the value.
     fn post_call(&mut self) -> VmResult<bool> {
         // pop frame.
@@ -1913,6 +2538,670 @@ mod tests {
         assert!(vm.inner.raw_eq(j, 150.0.into()));
     }
     */
+
+    use std::cell::RefCell;
+    use crate::*;
+
+
+    fn compile(source: &str) -> Result<(Vec<FuncDesc>, Vec<bbir::Item>), Diagnostics> {
+        compile_with(source, codegen::RegAllocKind::default())
+    }
+
+    fn compile_with(source: &str, reg_alloc: codegen::RegAllocKind) -> Result<(Vec<FuncDesc>, Vec<bbir::Item>), Diagnostics> {
+        let mut module = parser::parse_module(source.as_bytes()).unwrap();
+
+        let mut diags = Diagnostics::new();
+        let mut infer = infer::Infer::new(&mut diags);
+        infer.assign_ids(&mut module);
+        infer.infer(&mut module);
+
+        let mut builder = bbir_builder::Builder::new(&mut diags);
+        builder.build(&module);
+        let mut krate = builder.krate;
+        if diags.has_errors() {
+            return Err(diags);
+        }
+        krate.set_reg_alloc(reg_alloc);
+
+        let (funcs, items, _) = krate.build();
+        Ok((funcs.inner().clone(), items.inner().clone()))
+    }
+
+    thread_local! {
+        static CHECKED: RefCell<Vec<String>> = RefCell::new(vec![]);
+    }
+
+    fn show(vm: &VmImpl, value: &Value) -> String {
+        let join = |values: &[Value]| values.iter().map(|v| show(vm, v)).collect::<Vec<_>>().join(", ");
+        match value {
+            Value::Nil              => "nil".into(),
+            Value::Bool   { value } => value.to_string(),
+            Value::Number { value } => value.to_string(),
+            Value::String { value } => format!("{:?}", value),
+            Value::Unit             => "()".into(),
+            Value::Tuple  { shape: 0, values } => format!("({})", join(values)),
+            Value::Tuple  { shape, values } => {
+                let names = &vm.tuple_shapes[*shape as usize].names;
+                let fields = names.iter().zip(values.iter()).map(|(n, v)| format!("{n}: {}", show(vm, v)));
+                format!("({})", fields.collect::<Vec<_>>().join(", "))
+            }
+            Value::List   { values } => format!("[{}]", join(values)),
+            Value::Map    { values } => {
+                let entries = values.iter().map(|(k, v)| format!("{}: {}", show(vm, k), show(vm, v)));
+                format!("{{{}}}", entries.collect::<Vec<_>>().join(", "))
+            }
+            Value::Func   { proto } => format!("<Func {proto}>"),
+            Value::Trait  { id }    => format!("<Trait {}>", vm.traits[*id].name),
+        }
+    }
+
+    fn check(vm: &mut Vm) -> VmResult<NativeFuncReturn> {
+        let value = show(&vm.inner, vm.inner.reg(0));
+        CHECKED.with(|c| c.borrow_mut().push(value));
+        Ok(NativeFuncReturn::Unit)
+    }
+
+    // runs `source` with a `check(x)` native.
+    // returns the checked values, even if the script fails.
+    fn run(source: &str) -> (VmResult<()>, Vec<String>) {
+        run_with(source, codegen::RegAllocKind::default())
+    }
+
+    fn run_with(source: &str, reg_alloc: codegen::RegAllocKind) -> (VmResult<()>, Vec<String>) {
+        let (funcs, items) = compile_with(source, reg_alloc).unwrap_or_else(|diags|
+            panic!("{}", diags.render("test.kb", source)));
+        run_in(Vm::new(), &funcs, &items)
+    }
+
+    // runs a compiled crate in `vm`, adding the `check(x)` native.
+    fn run_in(mut vm: Vm, funcs: &[FuncDesc], items: &[bbir::Item]) -> (VmResult<()>, Vec<String>) {
+        vm.add_func("check", FuncDesc {
+            code: FuncCode::Native(NativeFuncPtrEx(check)),
+            constants: vec![],
+            tuple_shapes: vec![],
+            num_params: 1,
+            stack_size: 1,
+        }).unwrap();
+        vm.load_crate(0, funcs, items).unwrap();
+
+        CHECKED.with(|c| c.borrow_mut().clear());
+        let result = vm.call(0, 0, &[]);
+        (result, CHECKED.with(|c| c.take()))
+    }
+
+
+    #[test]
+    fn traits() {
+        let source = "
+            trait Show:
+                fn show(self)
+                fn twice(self, x)
+            end
+            impl Show for Number:
+                fn show(self): return self + 1 end
+                fn twice(self, x): return 2*x end
+            end
+            impl Show for String:
+                fn show(self): return \"s\" end
+                fn twice(self, x): return 3*x end
+            end
+
+            -- static dispatch.
+            check(Show.show(41))
+            check(Show.twice(\"a\", 5))
+
+            -- dynamic dispatch.
+            fn show_all(xs):
+                var i = 0
+                while i < 2:
+                    check(Show.show(xs[i]))
+                    i += 1
+                end
+            end
+            show_all([1, \"b\"])
+            check(implements(1, Show))
+            check(implements(nil, Show))
+            check(Show.show(nil))
+        ";
+        let (result, checked) = run(source);
+        assert_eq!(checked, ["42", "15", "2", "\"s\"", "true", "false"]);
+        assert_eq!(result, Err(VmError::InvalidOperation));
+
+        // no receiver to dispatch on.
+        let source = "
+            trait Make:
+                fn make()
+            end
+            Make.make()
+        ";
+        let diags = compile(source).unwrap_err();
+        assert_eq!(diags.iter().map(|d| d.code).collect::<Vec<_>>(), ["E0203"]);
+    }
+
+    #[test]
+    fn named_tuples() {
+        let source = "
+            let p = (x: 1, y: 2)
+            check(p)
+            check(p.x + p.y)
+            check(p[1])
+
+            -- field reads on unknown shapes look up the name.
+            fn get_y(t): return t.y end
+            check(get_y(p))
+            check(get_y((y: 7, z: 3)))
+
+            -- shapes are shared, so equality compares names & values.
+            check(p == (x: 1, y: 2))
+            check(p == (y: 2, x: 1))
+            check(p == (1, 2))
+
+            var v = (name: \"a\", n: 5)
+            v.n = 6
+            check(v)
+            check((a: 1))
+        ";
+        let (result, checked) = run(source);
+        assert_eq!(result, Ok(()));
+        assert_eq!(checked, [
+            "(x: 1, y: 2)", "3", "2", "2", "7",
+            "true", "false", "false",
+            "(name: \"a\", n: 6)", "(a: 1)",
+        ]);
+
+        let diags = compile("let p = (x: 1, x: 2)\nlet r = (x: 1)\nlet q = r.z").unwrap_err();
+        assert_eq!(diags.iter().map(|d| d.code).collect::<Vec<_>>(), ["E0003", "E0105"]);
+    }
+
+    #[test]
+    fn delete_paths() {
+        let source = "
+            var xs = [1, 2, 3, 4]
+            del xs[1]
+            check(xs)
+
+            var n = [[1, 2], [3]]
+            del n[0][0]
+            check(n)
+
+            var t = (a: [1, 2], b: 3)
+            del t.a[0]
+            check(t)
+
+            -- values are copied on write.
+            fn f(ys):
+                del ys[0]
+                return ys
+            end
+            let zs = [7, 8]
+            check(f(zs))
+            check(zs)
+
+            ENV.foo := 5
+            del ENV.foo
+            ENV.foo := 6
+            check(ENV.foo)
+
+            del xs[10]
+            check(xs)
+        ";
+        let (result, checked) = run(source);
+        assert_eq!(checked, ["[1, 3, 4]", "[[2], [3]]", "(a: [2], b: 3)", "[8]", "[7, 8]", "6"]);
+        assert_eq!(result, Err(VmError::InvalidOperation));
+
+        // tuples have a fixed shape.
+        // `infer` rejects this for known tuples, so the type is hidden in a parameter.
+        let (result, _) = run("fn f(t): del t.a end\nf((a: 1, b: 2))");
+        assert_eq!(result, Err(VmError::InvalidOperation));
+    }
+
+    #[test]
+    fn delete_map_keys() {
+        // maps have no literals yet, so they're built in bytecode.
+        // `deleted` returns `{"a": 1, "b": 2}` with "a" deleted, `fresh` returns `{"b": 2}`.
+        let map = |delete: bool| {
+            let mut b = ByteCodeBuilder::new();
+            b.map_new(0);
+            b.load_int(1, 1);
+            b.load_const(2, 0);
+            b.write_path_def(bytecode::PathBase::reg(0), 1, &[bytecode::PathKey::Index { reg: 2 }]);
+            b.load_int(1, 2);
+            b.load_const(3, 1);
+            b.write_path_def(bytecode::PathBase::reg(0), 1, &[bytecode::PathKey::Index { reg: 3 }]);
+            if delete {
+                b.delete_path(bytecode::PathBase::reg(0), &[bytecode::PathKey::Index { reg: 2 }]);
+            }
+            else {
+                b.map_new(0);
+                b.write_path_def(bytecode::PathBase::reg(0), 1, &[bytecode::PathKey::Index { reg: 3 }]);
+            }
+            b.ret(0);
+
+            FuncDesc {
+                code: FuncCode::ByteCode(b.build()),
+                constants: vec![Constant::String { value: "a".into() }, Constant::String { value: "b".into() }],
+                tuple_shapes: vec![],
+                num_params: 0,
+                stack_size: 4,
+            }
+        };
+
+        let mut vm = Vm::new();
+        vm.add_func("deleted", map(true)).unwrap();
+        vm.add_func("fresh",   map(false)).unwrap();
+
+        let (funcs, items) = compile("check(deleted() == fresh())\ncheck(deleted())").unwrap();
+        let (result, checked) = run_in(vm, &funcs, &items);
+        assert_eq!(result, Ok(()));
+        assert_eq!(checked, ["true", "{\"b\": 2}"]);
+    }
+
+    #[test]
+    fn hoisted_invariants() {
+        // `y*w` is hoisted out of the loop,
+        // but must only fail, if the loop runs.
+        let (result, checked) = run("
+            fn f(n, y, w):
+                var s = 0
+                var i = 0
+                while i < n:
+                    s += y*w
+                    i += 1
+                end
+                return s
+            end
+            check(f(0, \"a\", 2))
+            check(f(3, 2, 5))
+            check(f(1, \"a\", 2))
+        ");
+        assert_eq!(checked, ["0", "30"]);
+        assert!(result.is_err());
+    }
+
+    fn opcodes(code: &[InstrWord]) -> Vec<u8> {
+        let mut result = vec![];
+        let mut decoder = ByteCodeDecoder::new(code);
+        while let Some(instr) = decoder.next() {
+            result.push(instr.opcode);
+        }
+        result
+    }
+
+    #[test]
+    fn number_specialization() {
+        // `s` & `i` are proven numbers.
+        let (funcs, _) = compile("
+            fn f(n):
+                var s = 0
+                var i = 0
+                while i < n:
+                    s = s + i*2
+                    i += 1
+                end
+                return s
+            end
+        ").unwrap();
+        let ops: Vec<u8> = funcs.iter().flat_map(|func| match &func.code {
+            FuncCode::ByteCode(code) => opcodes(code),
+            _ => vec![],
+        }).collect();
+        assert!(ops.contains(&opcode::ADD_NUM));
+        assert!(ops.contains(&opcode::MUL_NUM));
+        assert!(!ops.contains(&opcode::ADD) && !ops.contains(&opcode::MUL));
+        // the peephole fuses the loop's test into a `jump_lt_num`.
+        assert!(!ops.contains(&opcode::CMP_LT) && !ops.contains(&opcode::JUMP_FALSE));
+        assert!(ops.contains(&opcode::CMP_LT_NUM) || ops.contains(&opcode::JUMP_LT_NUM));
+    }
+
+    #[test]
+    fn number_deoptimization() {
+        fn desc(f: impl FnOnce(&mut ByteCodeBuilder)) -> FuncDesc {
+            let mut b = ByteCodeBuilder::new();
+            f(&mut b);
+            FuncDesc {
+                code: FuncCode::ByteCode(b.build()),
+                constants: vec![],
+                tuple_shapes: vec![],
+                num_params: 2,
+                stack_size: 3,
+            }
+        }
+
+        let mut vm = Vm::new();
+        vm.add_func("check", FuncDesc {
+            code: FuncCode::Native(NativeFuncPtrEx(check)),
+            constants: vec![],
+            tuple_shapes: vec![],
+            num_params: 1,
+            stack_size: 1,
+        }).unwrap();
+        let eq = vm.inner.func_protos.len();
+        vm.add_func("eq", desc(|b| { b.cmp_eq_num(2, 0, 1); b.ret(2) })).unwrap();
+        let add = vm.inner.func_protos.len();
+        vm.add_func("add", desc(|b| { b.add_num(2, 0, 1); b.ret(2) })).unwrap();
+
+        let source = "
+            check(eq(1, 1))
+            check(eq(\"a\", \"a\"))
+            check(eq(1, 2))
+            check(add(1, 2))
+            check(add(\"a\", 1))
+        ";
+        let (funcs, items) = compile(source).unwrap();
+        vm.load_crate(0, &funcs, &items).unwrap();
+
+        CHECKED.with(|c| c.borrow_mut().clear());
+        let result = vm.call(0, 0, &[]);
+        assert_eq!(CHECKED.with(|c| c.take()), ["true", "true", "false", "3"]);
+        assert_eq!(result, Err(VmError::InvalidOperation));
+
+        let code = |proto: usize| {
+            let FuncCode::ByteCode(code) = &vm.inner.func_protos[proto].code else { unreachable!() };
+            opcodes(code)
+        };
+        // patched on the first non-number operand.
+        assert_eq!(code(eq), [opcode::CMP_EQ, opcode::RET]);
+        // failing ops aren't patched.
+        assert_eq!(code(add), [opcode::ADD_NUM, opcode::RET]);
+    }
+
+    #[test]
+    fn reg_alloc_kinds() {
+        // phis that swap, values live across calls & loops, in place updates of copies.
+        let source = "
+            fn fib(n):
+                var a = 0
+                var b = 1
+                var i = 0
+                while i < n:
+                    let t = a
+                    a = b
+                    b = t + b
+                    i += 1
+                end
+                return a
+            end
+
+            fn rec(n):
+                if n < 2: return n end
+                return rec(n - 1) + rec(n - 2)
+            end
+
+            fn lists(n):
+                var xs = [0, 0, 0]
+                let ys = xs
+                var i = 0
+                while i < n:
+                    xs[i % 3] = xs[i % 3] + i
+                    i += 1
+                end
+                return (xs, ys)
+            end
+
+            check(fib(10))
+            check(rec(10))
+            check(lists(5))
+            let x = fib(5)
+            check((x, rec(x), fib(x) + x))
+        ";
+        let expected = ["55", "55", "([3, 5, 2], [0, 0, 0])", "(5, 5, 10)"];
+
+        for reg_alloc in [codegen::RegAllocKind::LinearScan, codegen::RegAllocKind::GraphColoring] {
+            let (result, checked) = run_with(source, reg_alloc);
+            assert_eq!(result, Ok(()), "{reg_alloc:?}");
+            assert_eq!(checked, expected, "{reg_alloc:?}");
+        }
+    }
+
+    #[test]
+    fn delete_linked_global() {
+        fn first(_vm: &mut Vm) -> VmResult<NativeFuncReturn> {
+            CHECKED.with(|c| c.borrow_mut().push("first".into()));
+            Ok(NativeFuncReturn::Unit)
+        }
+
+        fn run_linked(source: &str) -> (VmResult<()>, Vec<String>) {
+            let (funcs, items) = compile(source).unwrap();
+
+            let mut vm = Vm::new();
+            for (name, f, num_params) in [("first", first as _, 0), ("check", check as _, 1)] {
+                vm.add_func(name, FuncDesc {
+                    code: FuncCode::Native(NativeFuncPtrEx(f)),
+                    constants: vec![],
+                    tuple_shapes: vec![],
+                    num_params,
+                    stack_size: num_params,
+                }).unwrap();
+            }
+            vm.load_crate(0, &funcs, &items).unwrap();
+
+            CHECKED.with(|c| c.borrow_mut().clear());
+            let result = vm.call(0, 0, &[]);
+            (result, CHECKED.with(|c| c.take()))
+        }
+
+        // `check` is linked to the slot after `first`.
+        let (result, checked) = run_linked("del ENV.first\ncheck(1)\nfirst()");
+        assert_eq!(checked, ["1"]);
+        assert_eq!(result, Err(VmError::InvalidOperation));
+
+        // defining it again reuses the slot.
+        let (result, checked) = run_linked("del ENV.first\nENV.first := check\nfirst(2)\ncheck(3)");
+        assert_eq!(checked, ["2", "3"]);
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn tail_calls() {
+        // returns `(frames, elided frames)`.
+        fn call_stack(vm: &mut Vm) -> VmResult<NativeFuncReturn> {
+            let mut frames = 0;
+            let mut elided = 0;
+            vm.call_stack(|frame| {
+                frames += 1;
+                elided += frame.elided;
+            });
+            let values = vec![Value::from(frames as f64), Value::from(elided as f64)];
+            *vm.inner.reg_mut(0) = Value::Tuple { shape: 0, values: values.into() };
+            Ok(NativeFuncReturn::Reg(0))
+        }
+
+        let source = "
+            fn count(n):
+                if n == 0:
+                    return call_stack()
+                end
+                return count(n - 1)
+            end
+            check(count(1000))
+        ";
+        let mut vm = Vm::new();
+        vm.add_func("call_stack", FuncDesc {
+            code: FuncCode::Native(NativeFuncPtrEx(call_stack)),
+            constants: vec![],
+            tuple_shapes: vec![],
+            num_params: 0,
+            stack_size: 1,
+        }).unwrap();
+
+        let (funcs, items) = compile(source).unwrap();
+        let (result, checked) = run_in(vm, &funcs, &items);
+        assert_eq!(result, Ok(()));
+
+        // the module, `count` & `call_stack`.
+        assert_eq!(checked, ["(3, 1000)"]);
+    }
+
+    #[test]
+    fn wide_operands() {
+        // more live values than fit into 8 bit registers.
+        const N: usize = 300;
+        let mut source = String::from("fn f(x):\n");
+        for i in 0..N {
+            source.push_str(&format!("let v{i} = x + {i}\n"));
+        }
+        source.push_str("let l = [");
+        for i in 0..N {
+            source.push_str(&format!("v{i}, "));
+        }
+        source.push_str("]\nvar s = 0\nvar i = 0\n");
+        source.push_str(&format!("while i < {N}:\ns += l[i]\ni += 1\nend\n"));
+        source.push_str(&format!("return s + v{}\nend\ncheck(f(1))\n", N-1));
+
+        let (funcs, items) = compile(&source).unwrap();
+        let f = &funcs[1];
+        assert!(f.stack_size as usize > N);
+        let FuncCode::ByteCode(code) = &f.code else { unreachable!() };
+        assert!(code.iter().any(|word| word.opcode() as u8 == opcode::WIDE));
+        assert!(ByteCodeDecoder::decode(code).is_some());
+
+        let (result, checked) = run_in(Vm::new(), &funcs, &items);
+        assert_eq!(result, Ok(()));
+        assert_eq!(checked, [(N + N * (N - 1) / 2 + N).to_string()]);
+
+        // jump targets beyond 16 bits, see the `wide_jumps` fallback in codegen.
+        const M: usize = 22_000;
+        let mut source = String::from("fn g(x):\nvar s = 0\nvar i = 0\nwhile i < x:\n");
+        for _ in 0..M {
+            source.push_str("s = s * x * x + 1\n");
+        }
+        source.push_str("i += 1\nend\nif i > 0: s += 1 else: s = -1 end\nreturn s\nend\n");
+        source.push_str("check(g(1))\ncheck(g(0))\n");
+
+        let (funcs, items) = compile(&source).unwrap();
+        let FuncCode::ByteCode(code) = &funcs[1].code else { unreachable!() };
+        assert!(code.len() > u16::MAX as usize);
+        let instrs = ByteCodeDecoder::decode(code).unwrap();
+        assert!(instrs.iter().any(|instr| matches!(instr.data,
+            bytecode::InstrData::Jump { target } if target > u16::MAX as u32)));
+
+        let (result, checked) = run_in(Vm::new(), &funcs, &items);
+        assert_eq!(result, Ok(()));
+        assert_eq!(checked, [(M + 1).to_string(), "-1".into()]);
+    }
+
+    #[test]
+    fn verify_bytecode() {
+        fn desc(code: Vec<InstrWord>) -> FuncDesc {
+            FuncDesc {
+                code: FuncCode::ByteCode(code),
+                constants: vec![Constant::Nil, Constant::String { value: "a".into() }],
+                tuple_shapes: vec![vec!["a".into(), "b".into()]],
+                num_params: 1,
+                stack_size: 2,
+            }
+        }
+
+        fn verify(f: impl FnOnce(&mut ByteCodeBuilder)) -> Result<(), ByteCodeError> {
+            let mut b = ByteCodeBuilder::new();
+            f(&mut b);
+            desc(b.build()).verify()
+        }
+
+        assert!(verify(|b| { b.load_const(1, 0); b.add(0, 0, 1); b.ret(0) }).is_ok());
+
+        // operands out of bounds.
+        assert_eq!(verify(|b| { b.copy(2, 0); b.ret(0) }).unwrap_err().pc, 0);
+        assert_eq!(verify(|b| { b.load_nil(0); b.load_const(0, 2); b.ret(0) }).unwrap_err().pc, 1);
+        assert!(verify(|b| { b.tuple_new_named(0, 1, &[1, 0]); b.ret(0) }).is_err());
+        assert!(verify(|b| { b.read_path(0, bytecode::PathBase::ENV, &[bytecode::PathKey::Index { reg: 5 }]); b.ret(0) }).is_err());
+        assert!(verify(|b| { b.read_path(0, bytecode::PathBase::ENV, &[]); b.ret(0) }).is_err());
+        assert!(verify(|b| { b.load_global(0, 0); b.ret(0) }).is_err());
+
+        // field keys must be strings.
+        let field = |string| [bytecode::PathKey::Field { string }];
+        assert!(verify(|b| { b.read_path(0, bytecode::PathBase::reg(1), &field(1)); b.ret(0) }).is_ok());
+        assert!(verify(|b| { b.read_path(0, bytecode::PathBase::reg(1), &field(0)); b.ret(0) }).is_err());
+        assert!(verify(|b| { b.write_path_def(bytecode::PathBase::ENV, 1, &field(0)); b.ret(0) }).is_err());
+
+        // the env isn't accessible.
+        assert!(verify(|b| { b.load_env(0); b.ret(0) }).is_err());
+
+        // named tuples need a value per field.
+        assert!(verify(|b| { b.tuple_new_named(0, 0, &[1, 0]); b.ret(0) }).is_ok());
+        assert!(verify(|b| { b.tuple_new_named(0, 0, &[]); b.ret(0) }).is_err());
+        assert!(verify(|b| { b.tuple_new_named(0, 0, &[1]); b.ret(0) }).is_err());
+        assert!(verify(|b| { b.tuple_new_named(0, 0, &[1, 0, 1]); b.ret(0) }).is_err());
+
+        // jumps into the call's extras.
+        assert_eq!(verify(|b| { b.call(0, 1, &[1]); b.jump(2) }).unwrap_err().pc, 3);
+        assert!(verify(|b| { b.call(0, 1, &[1]); b.jump(0) }).is_ok());
+
+        // runs past the end.
+        assert!(verify(|b| { b.load_nil(0) }).is_err());
+        assert!(verify(|_| ()).is_err());
+
+        // extra word counts.
+        let mut b = ByteCodeBuilder::new();
+        b.call(0, 1, &[1, 0]);
+        b.ret(0);
+        let mut code = b.build();
+        code.remove(3);
+        let error = desc(code.clone()).verify().unwrap_err();
+        assert_eq!((error.pc, error.message.as_str()), (0, "call is missing extra words"));
+
+        code.insert(1, InstrWord::encode_extra(0));
+        assert!(desc(code).verify().is_err());
+
+        let mut vm = Vm::new();
+        assert!(vm.add_func("f", desc(vec![InstrWord::encode_op(0)])).is_err());
+        assert!(vm.add_func("f", desc(vec![InstrWord::encode_op(opcode::WIDE)])).is_err());
+        assert!(vm.add_func("f", desc(vec![InstrWord::encode_op(opcode::UNREACHABLE)])).is_ok());
+
+        // items are indexed by number.
+        let items = bytecode::PathBase::ITEMS;
+        assert!(verify(|b| { b.read_path(0, items, &field(1)); b.ret(0) }).is_err());
+        assert!(verify(|b| { b.write_path(items, 1, &field(1)); b.ret(0) }).is_err());
+        assert!(verify(|b| { b.delete_path(items, &[bytecode::PathKey::Field { string: 1 }, bytecode::PathKey::Index { reg: 1 }]); b.ret(0) }).is_err());
+    }
+
+    #[test]
+    fn item_paths() {
+        let index = [bytecode::PathKey::Index { reg: 1 }];
+        let read  = |b: &mut ByteCodeBuilder| b.read_path(0, bytecode::PathBase::ITEMS, &index);
+
+        // runs `f` as the crate's function `g`.
+        // `g` is recursive, so it isn't inlined.
+        let run_g = |f: &dyn Fn(&mut ByteCodeBuilder)| {
+            let (mut funcs, items) = compile("fn g(n): if n: g(n) end end\ng(false)").unwrap();
+            let mut b = ByteCodeBuilder::new();
+            f(&mut b);
+            funcs[1].code = FuncCode::ByteCode(b.build());
+            funcs[1].stack_size = 2;
+
+            let mut vm = Vm::new();
+            vm.load_crate(0, &funcs, &items).unwrap();
+            vm.call(0, 0, &[])
+        };
+        assert_eq!(run_g(&|b| { b.load_int(1, 1); read(b); b.ret(0) }), Ok(()));
+
+        // the index isn't a number.
+        assert_eq!(run_g(&|b| { b.load_nil(1); read(b); b.ret(0) }), Err(VmError::InvalidOperation));
+
+        // out of range.
+        assert_eq!(run_g(&|b| { b.load_int(1, 1000); read(b); b.ret(0) }), Err(VmError::InvalidOperation));
+        assert_eq!(run_g(&|b| { b.load_int(1, 1000); b.load_nil(0); b.write_path(bytecode::PathBase::ITEMS, 0, &index); b.ret(0) }),
+            Err(VmError::InvalidOperation));
+        assert_eq!(run_g(&|b| { b.load_int(1, 1000); b.delete_path(bytecode::PathBase::ITEMS, &[index[0], index[0]]); b.ret(0) }),
+            Err(VmError::InvalidOperation));
+
+        // functions added with `add_func` have no crate.
+        let mut vm = Vm::new();
+        let mut b = ByteCodeBuilder::new();
+        b.load_int(1, 0);
+        read(&mut b);
+        b.ret(0);
+        vm.add_func("f", FuncDesc {
+            code: FuncCode::ByteCode(b.build()),
+            constants: vec![],
+            tuple_shapes: vec![],
+            num_params: 0,
+            stack_size: 2,
+        }).unwrap();
+        let (funcs, items) = compile("f()").unwrap();
+        vm.load_crate(0, &funcs, &items).unwrap();
+        assert_eq!(vm.call(0, 0, &[]), Err(VmError::InvalidOperation));
+    }
 }
 
 